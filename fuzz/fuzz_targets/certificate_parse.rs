@@ -0,0 +1,12 @@
+//! Fuzzes parsing of PEM and Base64 DER client certificates.
+
+#![no_main]
+
+use jwks_service_app::crypto::certificate_thumbprint;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(certificate) = std::str::from_utf8(data) {
+        let _ = certificate_thumbprint(certificate);
+    }
+});
@@ -0,0 +1,14 @@
+//! Fuzzes DPoP proof validation, including the embedded public JWK.
+
+#![no_main]
+
+use jwks_service_app::dpop::validate_dpop_proof;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(proof) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    let _ = validate_dpop_proof(proof, "POST", "https://server.example.com/token", Some("token"), 1_700_000_000);
+});
@@ -0,0 +1,18 @@
+//! Fuzzes parsing of JWKs into OpenSSL public keys and their thumbprints.
+
+#![no_main]
+
+use jwks_service_app::crypto::{jwk_thumbprint, public_key_from_jwk, verify_with_jwk};
+use jwks_service_app::models::Jwk;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(jwk) = serde_json::from_slice::<Jwk>(data) else {
+        return;
+    };
+
+    let _ = jwk_thumbprint(&jwk);
+    if public_key_from_jwk(&jwk).is_ok() {
+        let _ = verify_with_jwk(&jwk, b"data", &[0u8; 64]);
+    }
+});
@@ -0,0 +1,18 @@
+//! Fuzzes the compact JWS parser used for tokens, DPoP proofs and token exchange.
+
+#![no_main]
+
+use jwks_service_app::jws::decode_jws;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(token) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    if let Ok(decoded) = decode_jws(token) {
+        let _ = decoded.header_str("alg");
+        let _ = decoded.header_str("kid");
+        let _ = decoded.claims();
+    }
+});
@@ -0,0 +1 @@
+ALTER TABLE jwks DROP COLUMN pub;
\ No newline at end of file
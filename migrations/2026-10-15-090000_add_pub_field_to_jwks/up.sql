@@ -0,0 +1 @@
+ALTER TABLE jwks ADD COLUMN pub TEXT;
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE jwks DROP COLUMN allowed_audiences;
+ALTER TABLE jwks DROP COLUMN allowed_issuers;
\ No newline at end of file
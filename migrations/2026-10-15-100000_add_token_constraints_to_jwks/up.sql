@@ -0,0 +1,2 @@
+ALTER TABLE jwks ADD COLUMN allowed_issuers TEXT[];
+ALTER TABLE jwks ADD COLUMN allowed_audiences TEXT[];
\ No newline at end of file
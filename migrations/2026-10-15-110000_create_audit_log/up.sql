@@ -0,0 +1,9 @@
+CREATE TABLE audit_log (
+  id UUID PRIMARY KEY,
+  key_id UUID NOT NULL,
+  action VARCHAR NOT NULL,
+  actor VARCHAR,
+  occurred_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX audit_log_key_id_idx ON audit_log (key_id);
@@ -0,0 +1 @@
+ALTER TABLE jwks DROP COLUMN predecessor_id;
@@ -0,0 +1,2 @@
+ALTER TABLE jwks ADD COLUMN predecessor_id UUID REFERENCES jwks (id);
+CREATE UNIQUE INDEX jwks_predecessor_id_idx ON jwks (predecessor_id);
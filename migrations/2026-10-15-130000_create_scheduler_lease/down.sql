@@ -0,0 +1 @@
+DROP TABLE scheduler_lease;
\ No newline at end of file
@@ -0,0 +1,5 @@
+CREATE TABLE scheduler_lease (
+  name VARCHAR PRIMARY KEY,
+  holder VARCHAR NOT NULL,
+  expires_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,4 @@
+DROP TRIGGER jwks_log_key_operation ON jwks;
+DROP FUNCTION log_key_operation();
+DROP TABLE key_operations;
+DROP FUNCTION reject_key_operation_change();
@@ -0,0 +1,39 @@
+-- Append-only log of every mutation of the jwks table, holding the row after the mutation
+CREATE TABLE key_operations (
+  seq BIGSERIAL PRIMARY KEY,
+  key_id UUID NOT NULL,
+  operation VARCHAR NOT NULL,
+  record JSONB,
+  occurred_at TIMESTAMP NOT NULL DEFAULT (CURRENT_TIMESTAMP AT TIME ZONE 'UTC')
+);
+
+CREATE INDEX key_operations_key_id_idx ON key_operations (key_id, seq);
+
+CREATE FUNCTION log_key_operation() RETURNS TRIGGER AS $$
+BEGIN
+  IF TG_OP = 'DELETE' THEN
+    INSERT INTO key_operations (key_id, operation, record) VALUES (OLD.id, TG_OP, NULL);
+  ELSE
+    INSERT INTO key_operations (key_id, operation, record) VALUES (NEW.id, TG_OP, to_jsonb(NEW));
+  END IF;
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER jwks_log_key_operation
+AFTER INSERT OR UPDATE OR DELETE ON jwks
+FOR EACH ROW EXECUTE FUNCTION log_key_operation();
+
+CREATE FUNCTION reject_key_operation_change() RETURNS TRIGGER AS $$
+BEGIN
+  RAISE EXCEPTION 'key_operations is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER key_operations_append_only
+BEFORE UPDATE OR DELETE ON key_operations
+FOR EACH ROW EXECUTE FUNCTION reject_key_operation_change();
+
+-- Existing keys are recorded as they are now, so they can be restored as well
+INSERT INTO key_operations (key_id, operation, record)
+SELECT id, 'SNAPSHOT', to_jsonb(jwks) FROM jwks;
@@ -0,0 +1,5 @@
+-- Time of the last change of a key, used to resolve concurrent changes between regions
+ALTER TABLE jwks ADD COLUMN updated_at TIMESTAMP;
+UPDATE jwks SET updated_at = COALESCE(deleted_at, created_at);
+ALTER TABLE jwks ALTER COLUMN updated_at SET NOT NULL;
+ALTER TABLE jwks ALTER COLUMN updated_at SET DEFAULT (CURRENT_TIMESTAMP AT TIME ZONE 'UTC');
@@ -0,0 +1 @@
+DROP TABLE replication_cursors;
\ No newline at end of file
@@ -0,0 +1,4 @@
+CREATE TABLE replication_cursors (
+  peer VARCHAR PRIMARY KEY,
+  cursor BIGINT NOT NULL
+);
@@ -0,0 +1 @@
+ALTER TABLE jwks DROP COLUMN frozen_at;
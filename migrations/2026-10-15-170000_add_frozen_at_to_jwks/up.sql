@@ -0,0 +1 @@
+ALTER TABLE jwks ADD COLUMN frozen_at TIMESTAMP;
@@ -0,0 +1,2 @@
+DROP INDEX jwks_primary_alg_idx;
+ALTER TABLE jwks DROP COLUMN is_primary;
@@ -0,0 +1,2 @@
+ALTER TABLE jwks ADD COLUMN is_primary BOOLEAN NOT NULL DEFAULT FALSE;
+CREATE UNIQUE INDEX jwks_primary_alg_idx ON jwks (alg) WHERE is_primary;
@@ -0,0 +1,8 @@
+CREATE TABLE key_aliases (
+  name VARCHAR PRIMARY KEY,
+  key_id UUID NOT NULL REFERENCES jwks (id),
+  created_at TIMESTAMP NOT NULL DEFAULT (CURRENT_TIMESTAMP AT TIME ZONE 'UTC'),
+  updated_at TIMESTAMP NOT NULL DEFAULT (CURRENT_TIMESTAMP AT TIME ZONE 'UTC')
+);
+
+CREATE INDEX key_aliases_key_id_idx ON key_aliases (key_id);
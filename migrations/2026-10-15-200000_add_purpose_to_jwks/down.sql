@@ -0,0 +1,2 @@
+DROP INDEX jwks_purpose_idx;
+ALTER TABLE jwks DROP COLUMN purpose;
@@ -0,0 +1,2 @@
+ALTER TABLE jwks ADD COLUMN purpose VARCHAR;
+CREATE INDEX jwks_purpose_idx ON jwks (purpose);
@@ -0,0 +1 @@
+DROP TABLE private_key_access_log;
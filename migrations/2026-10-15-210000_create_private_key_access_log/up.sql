@@ -0,0 +1,12 @@
+CREATE TABLE private_key_access_log (
+  id UUID PRIMARY KEY,
+  key_id UUID NOT NULL,
+  kid VARCHAR NOT NULL,
+  endpoint VARCHAR NOT NULL,
+  actor VARCHAR,
+  client_address VARCHAR,
+  accessed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX private_key_access_log_key_id_idx ON private_key_access_log (key_id);
+CREATE INDEX private_key_access_log_accessed_at_idx ON private_key_access_log (accessed_at);
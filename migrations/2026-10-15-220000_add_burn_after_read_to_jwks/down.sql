@@ -0,0 +1,2 @@
+ALTER TABLE jwks DROP COLUMN private_key_retrieved_at;
+ALTER TABLE jwks DROP COLUMN burn_after_read;
@@ -0,0 +1,2 @@
+ALTER TABLE jwks ADD COLUMN burn_after_read BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE jwks ADD COLUMN private_key_retrieved_at TIMESTAMP;
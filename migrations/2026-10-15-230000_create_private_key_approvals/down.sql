@@ -0,0 +1,3 @@
+DROP TABLE private_key_approvals;
+
+ALTER TABLE jwks DROP COLUMN sensitive;
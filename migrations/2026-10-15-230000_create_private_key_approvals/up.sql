@@ -0,0 +1,13 @@
+ALTER TABLE jwks ADD COLUMN sensitive BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE private_key_approvals (
+  id UUID PRIMARY KEY,
+  key_id UUID NOT NULL,
+  token_hash VARCHAR NOT NULL UNIQUE,
+  approved_by VARCHAR,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  expires_at TIMESTAMP NOT NULL,
+  used_at TIMESTAMP
+);
+
+CREATE INDEX private_key_approvals_key_id_idx ON private_key_approvals (key_id);
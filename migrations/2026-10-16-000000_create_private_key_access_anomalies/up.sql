@@ -0,0 +1,13 @@
+CREATE TABLE private_key_access_anomalies (
+  id UUID PRIMARY KEY,
+  access_id UUID NOT NULL,
+  key_id UUID NOT NULL,
+  kind VARCHAR NOT NULL,
+  detail VARCHAR NOT NULL,
+  actor VARCHAR,
+  client_address VARCHAR,
+  detected_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX private_key_access_anomalies_key_id_idx ON private_key_access_anomalies (key_id);
+CREATE INDEX private_key_access_anomalies_detected_at_idx ON private_key_access_anomalies (detected_at);
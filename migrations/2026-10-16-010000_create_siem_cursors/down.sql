@@ -0,0 +1,2 @@
+DROP INDEX audit_log_occurred_at_idx;
+DROP TABLE siem_cursors;
@@ -0,0 +1,7 @@
+CREATE TABLE siem_cursors (
+  sink VARCHAR PRIMARY KEY,
+  occurred_at TIMESTAMP NOT NULL,
+  event_id UUID NOT NULL
+);
+
+CREATE INDEX audit_log_occurred_at_idx ON audit_log (occurred_at, id);
@@ -0,0 +1,7 @@
+CREATE TABLE expiry_notifications (
+  key_id UUID NOT NULL,
+  event VARCHAR NOT NULL,
+  expires_at TIMESTAMP NOT NULL,
+  sent_at TIMESTAMP NOT NULL,
+  PRIMARY KEY (key_id, event, expires_at)
+);
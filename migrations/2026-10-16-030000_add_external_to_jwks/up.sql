@@ -0,0 +1 @@
+ALTER TABLE jwks ADD COLUMN external BOOLEAN NOT NULL DEFAULT FALSE;
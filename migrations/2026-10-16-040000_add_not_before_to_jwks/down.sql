@@ -0,0 +1 @@
+ALTER TABLE jwks DROP COLUMN not_before;
@@ -0,0 +1 @@
+ALTER TABLE jwks ADD COLUMN not_before TIMESTAMP;
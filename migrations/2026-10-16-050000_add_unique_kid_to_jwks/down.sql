@@ -0,0 +1 @@
+DROP INDEX jwks_kid_idx;
@@ -0,0 +1,10 @@
+-- Keys sharing a kid with an older key get the key ID appended, so the index can be built
+UPDATE jwks SET kid = kid || '-' || id
+WHERE id IN (
+  SELECT id FROM (
+    SELECT id, row_number() OVER (PARTITION BY kid ORDER BY created_at, id) AS position FROM jwks
+  ) AS numbered
+  WHERE position > 1
+);
+
+CREATE UNIQUE INDEX jwks_kid_idx ON jwks (kid);
@@ -0,0 +1,10 @@
+CREATE TABLE job_runs (
+  name VARCHAR PRIMARY KEY,
+  replica VARCHAR NOT NULL,
+  outcome VARCHAR NOT NULL,
+  started_at TIMESTAMP NOT NULL,
+  finished_at TIMESTAMP,
+  last_error TEXT,
+  last_error_at TIMESTAMP,
+  next_run_at TIMESTAMP
+);
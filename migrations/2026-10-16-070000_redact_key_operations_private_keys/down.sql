@@ -0,0 +1,11 @@
+-- Key material removed from the history is not brought back
+CREATE OR REPLACE FUNCTION log_key_operation() RETURNS TRIGGER AS $$
+BEGIN
+  IF TG_OP = 'DELETE' THEN
+    INSERT INTO key_operations (key_id, operation, record) VALUES (OLD.id, TG_OP, NULL);
+  ELSE
+    INSERT INTO key_operations (key_id, operation, record) VALUES (NEW.id, TG_OP, to_jsonb(NEW));
+  END IF;
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
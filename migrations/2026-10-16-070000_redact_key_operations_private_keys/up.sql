@@ -0,0 +1,25 @@
+-- The operations log only keeps key material encrypted with the key-encryption key: the
+-- private_key of rows stored unencrypted is left out of the logged record
+CREATE OR REPLACE FUNCTION log_key_operation() RETURNS TRIGGER AS $$
+BEGIN
+  IF TG_OP = 'DELETE' THEN
+    INSERT INTO key_operations (key_id, operation, record) VALUES (OLD.id, TG_OP, NULL);
+  ELSIF NEW.private_key = '' OR NEW.private_key LIKE 'sealed:v1:%' THEN
+    INSERT INTO key_operations (key_id, operation, record) VALUES (NEW.id, TG_OP, to_jsonb(NEW));
+  ELSE
+    INSERT INTO key_operations (key_id, operation, record) VALUES (NEW.id, TG_OP, to_jsonb(NEW) - 'private_key');
+  END IF;
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Unencrypted key material already logged is removed from the history, the only change ever
+-- made to the append-only log
+ALTER TABLE key_operations DISABLE TRIGGER key_operations_append_only;
+
+UPDATE key_operations SET record = record - 'private_key'
+WHERE record ? 'private_key'
+AND record->>'private_key' <> ''
+AND record->>'private_key' NOT LIKE 'sealed:v1:%';
+
+ALTER TABLE key_operations ENABLE TRIGGER key_operations_append_only;
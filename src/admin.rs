@@ -0,0 +1,94 @@
+//! This module configures the internal admin listener.
+//!
+//! With `ADMIN_PORT` set, the service listens on two ports: the public listener (`PORT`) serves
+//! only the well-known and public key routes, and the admin listener serves the full API together
+//! with `/metrics` and `/healthz`. The admin listener binds to `ADMIN_HOST` and, if
+//! `ADMIN_TOKEN` is set, requires it as bearer token on every request except `/healthz`, so
+//! liveness probes work without credentials.
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::HttpResponse;
+use dotenv::dotenv;
+use std::env;
+
+/// Path of the liveness probe, served without the admin token.
+pub const HEALTHZ_PATH: &str = "/healthz";
+
+/// Returns the port of the admin listener (`ADMIN_PORT`).
+///
+/// # Returns
+///
+/// `None` if the admin API is served on the public listener.
+///
+/// # Panics
+///
+/// This function will panic if `ADMIN_PORT` is not a valid port number.
+pub fn admin_port() -> Option<u16> {
+    dotenv().ok();
+
+    env::var("ADMIN_PORT")
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+        .map(|value| value.trim().parse().expect("ADMIN_PORT must be a valid port number"))
+}
+
+/// Returns the address the admin listener binds to (`ADMIN_HOST`, default `127.0.0.1`).
+pub fn admin_host() -> String {
+    dotenv().ok();
+
+    env::var("ADMIN_HOST").unwrap_or_else(|_| "127.0.0.1".to_string())
+}
+
+/// Returns the bearer token required on the admin listener (`ADMIN_TOKEN`).
+///
+/// # Returns
+///
+/// `None` if the admin listener is not authenticated.
+pub fn admin_token() -> Option<String> {
+    dotenv().ok();
+
+    env::var("ADMIN_TOKEN")
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+}
+
+/// Checks that a presented `Authorization` header carries the bearer token.
+pub fn is_authorized(authorization: Option<&str>, token: &str) -> bool {
+    authorization
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|presented| {
+            presented.len() == token.len()
+                && openssl::memcmp::eq(presented.as_bytes(), token.as_bytes())
+        })
+}
+
+/// Middleware rejecting requests to the admin listener without the `ADMIN_TOKEN` bearer token.
+pub async fn require_admin_token(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    if let Some(token) = admin_token() {
+        let authorization = req
+            .headers()
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok());
+        if req.path() != HEALTHZ_PATH && !is_authorized(authorization, &token) {
+            let response = HttpResponse::Unauthorized()
+                .insert_header(("WWW-Authenticate", "Bearer"))
+                .body("Invalid admin token");
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+    }
+
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
+
+#[test]
+fn test_is_authorized() {
+    assert!(is_authorized(Some("Bearer secret"), "secret"));
+    assert!(!is_authorized(Some("Bearer secreT"), "secret"));
+    assert!(!is_authorized(Some("Basic secret"), "secret"));
+    assert!(!is_authorized(None, "secret"));
+}
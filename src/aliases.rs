@@ -0,0 +1,236 @@
+//! This module provides named aliases for keys.
+//!
+//! An alias (e.g. `access-token-signing`) resolves to the current version of a logical key and
+//! moves to the new version when the key is rotated, so clients can reference a stable name
+//! instead of tracking key IDs.
+
+#[cfg(feature = "server")]
+use crate::db::establish_connection;
+#[cfg(feature = "server")]
+use crate::models::{KeyAlias, KeyAliasInput};
+#[cfg(feature = "server")]
+use crate::schema::jwks;
+use crate::schema::key_aliases;
+#[cfg(feature = "server")]
+use actix_web::{web, HttpResponse, Responder};
+use chrono::Utc;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+#[cfg(feature = "server")]
+use diesel::upsert::excluded;
+use uuid::Uuid;
+
+/// Maximum length of an alias name.
+pub const MAX_ALIAS_LENGTH: usize = 64;
+
+/// Checks that an alias name consists of lowercase letters, digits, `-`, `_` and `.`, starting
+/// with a letter or digit.
+///
+/// # Errors
+///
+/// Returns a message describing the invalid name.
+pub fn validate_alias_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_ALIAS_LENGTH {
+        return Err(format!("Alias must be 1 to {} characters long", MAX_ALIAS_LENGTH));
+    }
+    if !name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit()) {
+        return Err("Alias must start with a lowercase letter or digit".to_string());
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err("Alias may only contain lowercase letters, digits, '-', '_' and '.'".to_string());
+    }
+
+    Ok(())
+}
+
+/// Returns the key an alias currently resolves to.
+///
+/// # Returns
+///
+/// `None` if the alias does not exist.
+///
+/// # Errors
+///
+/// Returns an error if the alias cannot be loaded.
+pub fn alias_key_id(connection: &mut PgConnection, name: &str) -> QueryResult<Option<Uuid>> {
+    key_aliases::table
+        .find(name)
+        .select(key_aliases::key_id)
+        .first::<Uuid>(connection)
+        .optional()
+}
+
+/// Points every alias of a rotated key to its new version.
+///
+/// # Returns
+///
+/// The number of moved aliases.
+///
+/// # Errors
+///
+/// Returns an error if the aliases cannot be updated.
+pub fn move_aliases(connection: &mut PgConnection, from: Uuid, to: Uuid) -> QueryResult<usize> {
+    diesel::update(key_aliases::table.filter(key_aliases::key_id.eq(from)))
+        .set((key_aliases::key_id.eq(to), key_aliases::updated_at.eq(Utc::now().naive_utc())))
+        .execute(connection)
+}
+
+/// Handles the request to list every alias.
+///
+/// # Returns
+///
+/// A JSON response containing the aliases ordered by name.
+#[cfg(feature = "server")]
+#[utoipa::path(
+    get,
+    path = "/aliases",
+    responses(
+        (status = 200, description = "Aliases ordered by name", body = [KeyAlias]),
+        (status = 500, description = "Failed to load aliases", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn list_aliases_handler() -> impl Responder {
+    let connection = &mut establish_connection();
+
+    match key_aliases::table
+        .order(key_aliases::name.asc())
+        .load::<KeyAlias>(connection)
+    {
+        Ok(aliases) => HttpResponse::Ok().json(aliases),
+        Err(_) => HttpResponse::InternalServerError().body("Failed to load aliases"),
+    }
+}
+
+/// Handles the request to retrieve an alias.
+///
+/// # Returns
+///
+/// A JSON response containing the alias, or `404 Not Found` if it does not exist.
+#[cfg(feature = "server")]
+#[utoipa::path(
+    get,
+    path = "/aliases/{name}",
+    params(
+        ("name" = String, Path, description = "Name of the alias")
+    ),
+    responses(
+        (status = 200, description = "Alias found", body = KeyAlias),
+        (status = 404, description = "Alias not found", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn get_alias_handler(name: web::Path<String>) -> impl Responder {
+    let connection = &mut establish_connection();
+
+    match key_aliases::table
+        .find(name.into_inner())
+        .first::<KeyAlias>(connection)
+    {
+        Ok(alias) => HttpResponse::Ok().json(alias),
+        Err(_) => HttpResponse::NotFound().body("Alias not found"),
+    }
+}
+
+/// Handles the request to create an alias or point it to another key.
+///
+/// # Returns
+///
+/// A JSON response containing the alias or an error message.
+#[cfg(feature = "server")]
+#[utoipa::path(
+    put,
+    path = "/aliases/{name}",
+    params(
+        ("name" = String, Path, description = "Name of the alias (lowercase letters, digits, '-', '_' and '.')")
+    ),
+    request_body = KeyAliasInput,
+    responses(
+        (status = 200, description = "Alias points to the key", body = KeyAlias),
+        (status = 400, description = "Invalid alias name", body = String, content_type = "text/plain"),
+        (status = 404, description = "Key not found", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to store alias", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn put_alias_handler(
+    name: web::Path<String>,
+    input: web::Json<KeyAliasInput>,
+) -> impl Responder {
+    let name = name.into_inner();
+    if let Err(message) = validate_alias_name(&name) {
+        return HttpResponse::BadRequest().body(message);
+    }
+
+    let connection = &mut establish_connection();
+    let key_exists = jwks::table
+        .filter(jwks::id.eq(input.key_id))
+        .filter(jwks::deleted_at.is_null())
+        .select(jwks::id)
+        .first::<Uuid>(connection)
+        .is_ok();
+    if !key_exists {
+        return HttpResponse::NotFound().body("Key not found");
+    }
+
+    let now = Utc::now().naive_utc();
+    let alias = KeyAlias {
+        name,
+        key_id: input.key_id,
+        created_at: now,
+        updated_at: now,
+    };
+    let result = diesel::insert_into(key_aliases::table)
+        .values(&alias)
+        .on_conflict(key_aliases::name)
+        .do_update()
+        .set((
+            key_aliases::key_id.eq(excluded(key_aliases::key_id)),
+            key_aliases::updated_at.eq(excluded(key_aliases::updated_at)),
+        ))
+        .get_result::<KeyAlias>(connection);
+
+    match result {
+        Ok(alias) => HttpResponse::Ok().json(alias),
+        Err(_) => HttpResponse::InternalServerError().body("Failed to store alias"),
+    }
+}
+
+/// Handles the request to delete an alias. The key it resolves to is not affected.
+///
+/// # Returns
+///
+/// A response indicating success or failure.
+#[cfg(feature = "server")]
+#[utoipa::path(
+    delete,
+    path = "/aliases/{name}",
+    params(
+        ("name" = String, Path, description = "Name of the alias")
+    ),
+    responses(
+        (status = 204, description = "Alias deleted"),
+        (status = 404, description = "Alias not found", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to delete alias", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn delete_alias_handler(name: web::Path<String>) -> impl Responder {
+    let connection = &mut establish_connection();
+
+    match diesel::delete(key_aliases::table.find(name.into_inner())).execute(connection) {
+        Ok(0) => HttpResponse::NotFound().body("Alias not found"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(_) => HttpResponse::InternalServerError().body("Failed to delete alias"),
+    }
+}
+
+#[test]
+fn test_validate_alias_name() {
+    assert!(validate_alias_name("access-token-signing").is_ok());
+    assert!(validate_alias_name("partner.v2_signing").is_ok());
+    assert!(validate_alias_name("").is_err());
+    assert!(validate_alias_name("-signing").is_err());
+    assert!(validate_alias_name("Signing").is_err());
+    assert!(validate_alias_name("access token").is_err());
+    assert!(validate_alias_name(&"a".repeat(MAX_ALIAS_LENGTH + 1)).is_err());
+}
@@ -0,0 +1,366 @@
+//! This module detects anomalies in accesses to private keys.
+//!
+//! Every recorded access is compared with the earlier accesses to the same key (the baseline
+//! from the private key access log). An access is anomalous if it comes from a network the key
+//! was never accessed from, is part of a burst of retrievals, or touches a key that was not
+//! accessed for months. Anomalies are stored, logged and, if `ANOMALY_WEBHOOK_URL` is set,
+//! posted to a webhook.
+
+use crate::db::establish_connection;
+use crate::log_error;
+use crate::models::{AnomalyQuery, PrivateKeyAccess, PrivateKeyAccessAnomaly};
+use crate::pagination::{page_response, Page};
+use crate::schema::{private_key_access_anomalies, private_key_access_log};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::{NaiveDateTime, TimeDelta};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use dotenv::dotenv;
+use std::env;
+use std::net::IpAddr;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Anomaly kind of an access from a network the key was never accessed from.
+pub const ANOMALY_NEW_NETWORK: &str = "new_network";
+
+/// Anomaly kind of an access exceeding the burst threshold.
+pub const ANOMALY_BURST: &str = "burst";
+
+/// Anomaly kind of an access to a key that was not accessed for a long time.
+pub const ANOMALY_DORMANT_KEY: &str = "dormant_key";
+
+/// Maximum number of earlier accesses a new access is compared with.
+const BASELINE_SIZE: i64 = 1000;
+
+/// Thresholds of the anomaly detection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnomalySettings {
+    /// Number of accesses to a key within `burst_window` that is reported as a burst.
+    pub burst_threshold: usize,
+    /// Window accesses are counted in for burst detection.
+    pub burst_window: TimeDelta,
+    /// Time without access after which a key is considered dormant.
+    pub dormant_after: TimeDelta,
+}
+
+/// Returns whether private key accesses are checked for anomalies
+/// (`ANOMALY_DETECTION_ENABLED`, default `1`).
+pub fn anomaly_detection_enabled() -> bool {
+    dotenv().ok();
+
+    env::var("ANOMALY_DETECTION_ENABLED").map(|value| value != "0").unwrap_or(true)
+}
+
+/// Returns the thresholds of the anomaly detection: `ANOMALY_BURST_THRESHOLD` (default 5),
+/// `ANOMALY_BURST_WINDOW_SECONDS` (default 300) and `ANOMALY_DORMANT_DAYS` (default 90).
+///
+/// # Panics
+///
+/// This function will panic if a threshold is not a positive number.
+pub fn anomaly_settings() -> AnomalySettings {
+    dotenv().ok();
+
+    let positive = |name: &str, default: &str| -> i64 {
+        env::var(name)
+            .unwrap_or_else(|_| default.to_string())
+            .parse()
+            .ok()
+            .filter(|value| *value > 0)
+            .unwrap_or_else(|| panic!("{} must be a positive number", name))
+    };
+
+    AnomalySettings {
+        burst_threshold: positive("ANOMALY_BURST_THRESHOLD", "5") as usize,
+        burst_window: TimeDelta::seconds(positive("ANOMALY_BURST_WINDOW_SECONDS", "300")),
+        dormant_after: TimeDelta::days(positive("ANOMALY_DORMANT_DAYS", "90")),
+    }
+}
+
+/// Returns the webhook anomalies are posted to (`ANOMALY_WEBHOOK_URL`).
+pub fn anomaly_webhook_url() -> Option<String> {
+    dotenv().ok();
+
+    env::var("ANOMALY_WEBHOOK_URL")
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+}
+
+/// Returns the network of a client address: the /24 of an IPv4 address or the /48 of an IPv6
+/// address. Addresses that are not IP addresses are their own network.
+pub fn client_network(address: &str) -> String {
+    match address.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            let [a, b, c, _] = ip.octets();
+            format!("{}.{}.{}.0/24", a, b, c)
+        }
+        Ok(IpAddr::V6(ip)) => {
+            let segments = ip.segments();
+            format!("{:x}:{:x}:{:x}::/48", segments[0], segments[1], segments[2])
+        }
+        Err(_) => address.to_string(),
+    }
+}
+
+/// Detects the anomalies of an access to a private key.
+///
+/// # Arguments
+///
+/// * `access` - The access to check.
+/// * `baseline` - Earlier accesses to the same key, newest first.
+/// * `key_created_at` - Creation time of the key, the reference if it was never accessed.
+/// * `settings` - Thresholds of the detection.
+///
+/// # Returns
+///
+/// The kind and description of every detected anomaly.
+pub fn detect_anomalies(
+    access: &PrivateKeyAccess,
+    baseline: &[PrivateKeyAccess],
+    key_created_at: NaiveDateTime,
+    settings: &AnomalySettings,
+) -> Vec<(&'static str, String)> {
+    let mut anomalies = Vec::new();
+
+    // The first access has no network baseline
+    if let Some(address) = &access.client_address {
+        let network = client_network(address);
+        let known = baseline
+            .iter()
+            .filter_map(|earlier| earlier.client_address.as_deref())
+            .any(|earlier| client_network(earlier) == network);
+        if !baseline.is_empty() && !known {
+            anomalies.push((
+                ANOMALY_NEW_NETWORK,
+                format!("First access to key {} from network {}", access.kid, network),
+            ));
+        }
+    }
+
+    let window_start = access.accessed_at - settings.burst_window;
+    let recent = 1 + baseline
+        .iter()
+        .filter(|earlier| earlier.accessed_at > window_start)
+        .count();
+    if recent >= settings.burst_threshold {
+        anomalies.push((
+            ANOMALY_BURST,
+            format!(
+                "{} accesses to key {} within {} seconds",
+                recent,
+                access.kid,
+                settings.burst_window.num_seconds()
+            ),
+        ));
+    }
+
+    let last_touched = baseline
+        .first()
+        .map(|earlier| earlier.accessed_at)
+        .unwrap_or(key_created_at);
+    let idle = access.accessed_at - last_touched;
+    if idle >= settings.dormant_after {
+        anomalies.push((
+            ANOMALY_DORMANT_KEY,
+            format!("Key {} was not accessed for {} days", access.kid, idle.num_days()),
+        ));
+    }
+
+    anomalies
+}
+
+/// Posts an anomaly to the webhook in the background, so the access is not delayed.
+fn notify_webhook(url: String, anomaly: PrivateKeyAccessAnomaly) {
+    std::thread::spawn(move || {
+        let result = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .and_then(|client| client.post(&url).json(&anomaly).send())
+            .and_then(|response| response.error_for_status());
+        if let Err(error) = result {
+            log_error!("Failed to post anomaly {} to the webhook: {}", anomaly.id, error);
+        }
+    });
+}
+
+/// Checks a recorded access to a private key for anomalies, and stores, logs and posts every
+/// detected anomaly.
+///
+/// # Arguments
+///
+/// * `connection` - Database connection.
+/// * `access` - The recorded access.
+/// * `key_created_at` - Creation time of the accessed key.
+///
+/// # Returns
+///
+/// The detected anomalies.
+///
+/// # Errors
+///
+/// Returns an error if the baseline cannot be loaded or an anomaly cannot be stored.
+pub fn inspect_private_key_access(
+    connection: &mut PgConnection,
+    access: &PrivateKeyAccess,
+    key_created_at: NaiveDateTime,
+) -> QueryResult<Vec<PrivateKeyAccessAnomaly>> {
+    let baseline = private_key_access_log::table
+        .filter(private_key_access_log::key_id.eq(access.key_id))
+        .filter(private_key_access_log::id.ne(access.id))
+        .filter(private_key_access_log::accessed_at.le(access.accessed_at))
+        .order(private_key_access_log::accessed_at.desc())
+        .limit(BASELINE_SIZE)
+        .load::<PrivateKeyAccess>(connection)?;
+
+    let anomalies = detect_anomalies(access, &baseline, key_created_at, &anomaly_settings())
+        .into_iter()
+        .map(|(kind, detail)| PrivateKeyAccessAnomaly {
+            id: Uuid::new_v4(),
+            access_id: access.id,
+            key_id: access.key_id,
+            kind: kind.to_string(),
+            detail,
+            actor: access.actor.clone(),
+            client_address: access.client_address.clone(),
+            detected_at: access.accessed_at,
+        })
+        .collect::<Vec<_>>();
+    if anomalies.is_empty() {
+        return Ok(anomalies);
+    }
+
+    diesel::insert_into(private_key_access_anomalies::table)
+        .values(&anomalies)
+        .execute(connection)?;
+
+    let webhook = anomaly_webhook_url();
+    for anomaly in &anomalies {
+        log_error!(
+            "Private key access anomaly ({}): {} (actor: {}, client: {})",
+            anomaly.kind,
+            anomaly.detail,
+            anomaly.actor.as_deref().unwrap_or("unknown"),
+            anomaly.client_address.as_deref().unwrap_or("unknown")
+        );
+        if let Some(url) = &webhook {
+            notify_webhook(url.clone(), anomaly.clone());
+        }
+    }
+
+    Ok(anomalies)
+}
+
+/// Handles the request to list the anomalies detected in accesses to private keys.
+///
+/// # Arguments
+///
+/// * `req` - The request; the link to the next page is derived from it.
+/// * `query` - Optional key, kind and start time the anomalies are filtered by, and the requested page.
+///
+/// # Returns
+///
+/// A JSON response containing a page of the matching anomalies, newest first.
+#[utoipa::path(
+    get,
+    path = "/audit/anomalies",
+    params(AnomalyQuery),
+    responses(
+        (status = 200, description = "Detected anomalies, newest first", body = [PrivateKeyAccessAnomaly],
+            headers(("Link" = String, description = "URL of the next page (`rel=\"next\"`), if any"))),
+        (status = 400, description = "Invalid since, limit or offset", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to load the anomalies", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn anomalies_handler(req: HttpRequest, query: web::Query<AnomalyQuery>) -> impl Responder {
+    let since = match query.since_time() {
+        Ok(since) => since,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+    let page = match Page::new(query.limit, query.offset) {
+        Ok(page) => page,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+
+    let mut anomalies = private_key_access_anomalies::table.into_boxed();
+    if let Some(key_id) = query.key_id {
+        anomalies = anomalies.filter(private_key_access_anomalies::key_id.eq(key_id));
+    }
+    if let Some(kind) = &query.kind {
+        anomalies = anomalies.filter(private_key_access_anomalies::kind.eq(kind));
+    }
+    if let Some(since) = since {
+        anomalies = anomalies.filter(private_key_access_anomalies::detected_at.ge(since));
+    }
+
+    let connection = &mut establish_connection();
+    match anomalies
+        .order((private_key_access_anomalies::detected_at.desc(), private_key_access_anomalies::id.asc()))
+        .offset(page.offset)
+        .limit(page.fetch())
+        .load::<PrivateKeyAccessAnomaly>(connection)
+    {
+        Ok(anomalies) => page_response(&req, &page, anomalies),
+        Err(_) => HttpResponse::InternalServerError().body("Failed to load the anomalies"),
+    }
+}
+
+#[test]
+fn test_client_network() {
+    assert_eq!(client_network("203.0.113.7"), "203.0.113.0/24");
+    assert_eq!(client_network("2001:db8:1234:5678::1"), "2001:db8:1234::/48");
+    assert_eq!(client_network("unknown"), "unknown");
+}
+
+#[test]
+fn test_detect_anomalies() {
+    use chrono::NaiveDate;
+
+    let created = NaiveDate::from_ymd_opt(2026, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+    let settings = AnomalySettings {
+        burst_threshold: 3,
+        burst_window: TimeDelta::seconds(60),
+        dormant_after: TimeDelta::days(90),
+    };
+    let access = |address: &str, at: NaiveDateTime| PrivateKeyAccess {
+        id: Uuid::new_v4(),
+        key_id: Uuid::nil(),
+        kid: "key-1".to_string(),
+        endpoint: "/jwks/{id}".to_string(),
+        actor: None,
+        client_address: Some(address.to_string()),
+        accessed_at: at,
+    };
+    let kinds = |anomalies: Vec<(&'static str, String)>| {
+        anomalies.into_iter().map(|(kind, _)| kind).collect::<Vec<_>>()
+    };
+
+    // The first access establishes the baseline
+    let first = access("198.51.100.1", created + TimeDelta::days(1));
+    assert!(detect_anomalies(&first, &[], created, &settings).is_empty());
+
+    // Same network, different host
+    let second = access("198.51.100.2", created + TimeDelta::days(2));
+    assert!(detect_anomalies(&second, std::slice::from_ref(&first), created, &settings).is_empty());
+
+    let foreign = access("203.0.113.9", created + TimeDelta::days(3));
+    assert_eq!(
+        kinds(detect_anomalies(&foreign, &[second.clone(), first.clone()], created, &settings)),
+        vec![ANOMALY_NEW_NETWORK]
+    );
+
+    // Third access within a minute
+    let burst = [
+        access("198.51.100.1", created + TimeDelta::days(2) + TimeDelta::seconds(20)),
+        access("198.51.100.1", created + TimeDelta::days(2) + TimeDelta::seconds(10)),
+    ];
+    let third = access("198.51.100.1", created + TimeDelta::days(2) + TimeDelta::seconds(30));
+    assert_eq!(kinds(detect_anomalies(&third, &burst, created, &settings)), vec![ANOMALY_BURST]);
+
+    // Untouched since creation or since the last access
+    let late = access("198.51.100.1", created + TimeDelta::days(120));
+    assert_eq!(kinds(detect_anomalies(&late, &[], created, &settings)), vec![ANOMALY_DORMANT_KEY]);
+    assert_eq!(
+        kinds(detect_anomalies(&late, &[first], created, &settings)),
+        vec![ANOMALY_DORMANT_KEY]
+    );
+}
@@ -0,0 +1,203 @@
+//! This module provides step-up approval of private key retrieval for sensitive keys.
+//!
+//! Retrieving the private key of a key marked sensitive requires, on top of the regular API
+//! authentication, a one-time approval token in the `X-Approval-Token` header. Approval tokens
+//! are issued per key by an approver through `/jwks/{id}/approvals`, which is authenticated
+//! separately with the `APPROVAL_ADMIN_TOKEN` bearer token, and lapse after
+//! `APPROVAL_TOKEN_TTL_SECONDS`. Only the SHA-256 hash of a token is stored.
+
+use crate::audit::{record_event, request_actor, ACTION_APPROVE};
+use crate::db::establish_connection;
+use crate::log_error;
+use crate::models::{KeyApproval, KeyApprovalToken};
+use crate::schema::{jwks, private_key_approvals};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use dotenv::dotenv;
+use std::env;
+use uuid::Uuid;
+
+/// Request header carrying the approval token of a private key retrieval.
+pub const APPROVAL_HEADER: &str = "X-Approval-Token";
+
+/// Returns the bearer token authenticating approvers (`APPROVAL_ADMIN_TOKEN`).
+///
+/// # Returns
+///
+/// `None` if approvals cannot be issued.
+pub fn approval_admin_token() -> Option<String> {
+    dotenv().ok();
+
+    env::var("APPROVAL_ADMIN_TOKEN")
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+}
+
+/// Returns the lifetime of approval tokens in seconds (`APPROVAL_TOKEN_TTL_SECONDS`,
+/// default 300).
+///
+/// # Panics
+///
+/// This function will panic if `APPROVAL_TOKEN_TTL_SECONDS` is not a positive number.
+pub fn approval_ttl_seconds() -> i64 {
+    dotenv().ok();
+
+    env::var("APPROVAL_TOKEN_TTL_SECONDS")
+        .unwrap_or_else(|_| "300".to_string())
+        .parse()
+        .ok()
+        .filter(|seconds| *seconds > 0)
+        .expect("APPROVAL_TOKEN_TTL_SECONDS must be a positive number")
+}
+
+/// Returns the stored form of an approval token: its SHA-256 hash, base64url encoded.
+pub fn hash_approval_token(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(openssl::sha::sha256(token.as_bytes()))
+}
+
+/// Returns the approval token sent with a request.
+pub fn request_approval_token(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(APPROVAL_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+/// Uses an approval token to retrieve the private key of a key.
+///
+/// The approval is marked as used, so a token approves a single retrieval.
+///
+/// # Returns
+///
+/// `false` if the token does not approve the key, has expired or has already been used.
+///
+/// # Errors
+///
+/// Returns an error if the approval cannot be updated.
+pub fn consume_approval(
+    connection: &mut PgConnection,
+    key_id: Uuid,
+    token: &str,
+    now: NaiveDateTime,
+) -> QueryResult<bool> {
+    let used = diesel::update(
+        private_key_approvals::table
+            .filter(private_key_approvals::token_hash.eq(hash_approval_token(token)))
+            .filter(private_key_approvals::key_id.eq(key_id))
+            .filter(private_key_approvals::used_at.is_null())
+            .filter(private_key_approvals::expires_at.gt(now)),
+    )
+    .set(private_key_approvals::used_at.eq(now))
+    .execute(connection)?;
+
+    Ok(used > 0)
+}
+
+/// Checks that a request carries the approver bearer token.
+fn is_approver(req: &HttpRequest, token: &str) -> bool {
+    req.headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|presented| {
+            presented.len() == token.len()
+                && openssl::memcmp::eq(presented.as_bytes(), token.as_bytes())
+        })
+}
+
+/// Handles an approver's request for a one-time token to retrieve the private key of a
+/// sensitive key.
+///
+/// # Arguments
+///
+/// * `req` - The request; it must carry the approver bearer token, and its `X-Actor` header is
+///   recorded as the approver.
+/// * `key_id` - The unique identifier of the key.
+///
+/// # Returns
+///
+/// A JSON response containing the approval token, which is not shown again, or an error
+/// message.
+#[utoipa::path(
+    post,
+    path = "/jwks/{id}/approvals",
+    params(
+        ("id" = String, Path, description = "Unique key identifier")
+    ),
+    responses(
+        (status = 201, description = "Approval token issued", body = KeyApprovalToken),
+        (status = 400, description = "Key is not sensitive", body = String, content_type = "text/plain"),
+        (status = 401, description = "Missing or wrong approver token", body = String, content_type = "text/plain"),
+        (status = 404, description = "Approvals are not configured or key not found", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to store the approval", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn issue_approval_handler(req: HttpRequest, key_id: web::Path<Uuid>) -> impl Responder {
+    let admin_token = match approval_admin_token() {
+        Some(admin_token) => admin_token,
+        None => return HttpResponse::NotFound().body("Approvals are not configured"),
+    };
+    if !is_approver(&req, &admin_token) {
+        return HttpResponse::Unauthorized().body("Invalid approver token");
+    }
+
+    let connection = &mut establish_connection();
+    let key_id = key_id.into_inner();
+    let sensitive = jwks::table
+        .filter(jwks::id.eq(key_id))
+        .filter(jwks::deleted_at.is_null())
+        .select(jwks::sensitive)
+        .first::<bool>(connection);
+    match sensitive {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::BadRequest().body("Key is not sensitive"),
+        Err(_) => return HttpResponse::NotFound().body("Key not found"),
+    }
+
+    let mut bytes = [0u8; 32];
+    if openssl::rand::rand_bytes(&mut bytes).is_err() {
+        return HttpResponse::InternalServerError().body("Failed to generate approval token");
+    }
+    let token = URL_SAFE_NO_PAD.encode(bytes);
+
+    let now = Utc::now().naive_utc();
+    let approval = KeyApproval {
+        id: Uuid::new_v4(),
+        key_id,
+        token_hash: hash_approval_token(&token),
+        approved_by: request_actor(&req),
+        created_at: now,
+        expires_at: now + chrono::Duration::seconds(approval_ttl_seconds()),
+        used_at: None,
+    };
+    if diesel::insert_into(private_key_approvals::table)
+        .values(&approval)
+        .execute(connection)
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().body("Failed to store the approval");
+    }
+
+    if let Err(error) = record_event(connection, key_id, ACTION_APPROVE, approval.approved_by) {
+        log_error!("Failed to record audit event for key {}: {}", key_id, error);
+    }
+
+    HttpResponse::Created().json(KeyApprovalToken {
+        key_id,
+        token,
+        expires_at: approval.expires_at,
+    })
+}
+
+#[test]
+fn test_hash_approval_token() {
+    let hash = hash_approval_token("token");
+    assert_eq!(hash, hash_approval_token("token"));
+    assert_ne!(hash, hash_approval_token("other"));
+    assert!(!hash.contains("token"));
+}
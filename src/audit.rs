@@ -0,0 +1,296 @@
+//! This module provides the audit log of key lifecycle events.
+//!
+//! Every key creation, deletion, extension, freeze, unfreeze, primary designation, private key
+//! retrieval approval and burn-after-read retrieval (or refused repeated retrieval) is recorded
+//! with the calling actor, taken from the `X-Actor` request header (set by the authenticating
+//! proxy or the operator's tooling).
+//!
+//! Responses containing private key material are recorded separately in the private key access
+//! log, together with the actor and the client address, as the access trail for secrets.
+
+#[cfg(feature = "server")]
+use crate::db::establish_connection;
+#[cfg(feature = "server")]
+use crate::models::{JwkData, PrivateKeyAccess, PrivateKeyAccessQuery};
+#[cfg(feature = "server")]
+use crate::pagination::{page_response, Page};
+use crate::schema::audit_log;
+#[cfg(feature = "server")]
+use crate::schema::private_key_access_log;
+#[cfg(feature = "server")]
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Request header identifying the caller performing an action.
+pub const ACTOR_HEADER: &str = "X-Actor";
+
+/// Maximum length of a recorded actor.
+#[cfg(feature = "server")]
+const MAX_ACTOR_LENGTH: usize = 256;
+
+/// Audit action recorded when a key is created.
+pub const ACTION_CREATE: &str = "create";
+
+/// Audit action recorded when a key is deleted.
+pub const ACTION_DELETE: &str = "delete";
+
+/// Audit action recorded when the private key of a key is extended.
+pub const ACTION_EXTEND: &str = "extend";
+
+/// Audit action recorded when a key is frozen.
+pub const ACTION_FREEZE: &str = "freeze";
+
+/// Audit action recorded when a key is unfrozen.
+pub const ACTION_UNFREEZE: &str = "unfreeze";
+
+/// Audit action recorded when a key is designated as the primary key of its algorithm.
+pub const ACTION_SET_PRIMARY: &str = "set_primary";
+
+/// Audit action recorded when the primary designation of a key is removed.
+pub const ACTION_UNSET_PRIMARY: &str = "unset_primary";
+
+/// Audit action recorded when the private key of a burn-after-read key is retrieved.
+pub const ACTION_RETRIEVE: &str = "retrieve";
+
+/// Audit action recorded when a repeated retrieval of a burn-after-read private key is refused.
+pub const ACTION_RETRIEVE_REFUSED: &str = "retrieve_refused";
+
+/// Audit action recorded when an approval to retrieve the private key of a sensitive key is
+/// issued.
+pub const ACTION_APPROVE: &str = "approve";
+
+/// Audit action recorded when an SSH certificate is signed with an SSH CA key.
+pub const ACTION_SSH_CERTIFY: &str = "ssh_certify";
+
+/// Audit action recorded when an external key (public key only) is registered.
+pub const ACTION_REGISTER: &str = "register";
+
+/// Single audit log entry.
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Insertable, Selectable)]
+#[diesel(table_name = audit_log)]
+pub struct AuditEvent {
+    /// Unique event identifier.
+    pub id: Uuid,
+    /// Identifier of the affected key.
+    pub key_id: Uuid,
+    /// Performed action.
+    pub action: String,
+    /// Caller that performed the action, if known.
+    pub actor: Option<String>,
+    /// Time of the event.
+    pub occurred_at: NaiveDateTime,
+}
+
+/// Returns the actor of a request from the `X-Actor` header.
+///
+/// # Returns
+///
+/// `None` if the header is missing, empty, not valid UTF-8 or too long.
+#[cfg(feature = "server")]
+pub fn request_actor(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(ACTOR_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty() && value.len() <= MAX_ACTOR_LENGTH)
+        .map(str::to_string)
+}
+
+/// Records an audit event for a key.
+///
+/// # Errors
+///
+/// Returns an error if the event cannot be stored.
+pub fn record_event(
+    connection: &mut PgConnection,
+    key_id: Uuid,
+    action: &str,
+    actor: Option<String>,
+) -> QueryResult<()> {
+    let event = AuditEvent {
+        id: Uuid::new_v4(),
+        key_id,
+        action: action.to_string(),
+        actor,
+        occurred_at: Utc::now().naive_utc(),
+    };
+
+    diesel::insert_into(audit_log::table)
+        .values(&event)
+        .execute(connection)
+        .map(|_| ())
+}
+
+/// Returns the actor of the most recent deletion of each of the keys.
+///
+/// Keys deleted without a recorded actor are missing from the map.
+///
+/// # Errors
+///
+/// Returns an error if the audit log cannot be read.
+pub fn deleting_actors(
+    connection: &mut PgConnection,
+    key_ids: &[Uuid],
+) -> QueryResult<HashMap<Uuid, String>> {
+    let events = audit_log::table
+        .filter(audit_log::key_id.eq_any(key_ids))
+        .filter(audit_log::action.eq(ACTION_DELETE))
+        .order(audit_log::occurred_at.asc())
+        .select(AuditEvent::as_select())
+        .load(connection)?;
+
+    // Later deletions overwrite earlier ones
+    let mut actors = HashMap::new();
+    for event in events {
+        match event.actor {
+            Some(actor) => actors.insert(event.key_id, actor),
+            None => actors.remove(&event.key_id),
+        };
+    }
+
+    Ok(actors)
+}
+
+/// Returns the address of the client a request came from.
+///
+/// The `Forwarded` and `X-Forwarded-For` headers set by the proxy in front of the service take
+/// precedence over the peer address.
+#[cfg(feature = "server")]
+pub fn request_client_address(req: &HttpRequest) -> Option<String> {
+    req.connection_info().realip_remote_addr().map(str::to_string)
+}
+
+/// Records that a response contains the private key of a key.
+///
+/// # Arguments
+///
+/// * `connection` - Database connection.
+/// * `actor` - The caller, as given by [`request_actor`].
+/// * `client_address` - The address of the caller, as given by [`request_client_address`].
+/// * `jwk` - The accessed key.
+/// * `endpoint` - Endpoint returning the private key (e.g., "/jwks/{id}").
+///
+/// # Returns
+///
+/// The stored access record.
+///
+/// # Errors
+///
+/// Returns an error if the access cannot be stored.
+#[cfg(feature = "server")]
+pub fn record_private_key_access(
+    connection: &mut PgConnection,
+    actor: Option<String>,
+    client_address: Option<String>,
+    jwk: &JwkData,
+    endpoint: &str,
+) -> QueryResult<PrivateKeyAccess> {
+    let access = PrivateKeyAccess {
+        id: Uuid::new_v4(),
+        key_id: jwk.id,
+        kid: jwk.kid.clone(),
+        endpoint: endpoint.to_string(),
+        actor,
+        client_address,
+        accessed_at: Utc::now().naive_utc(),
+    };
+
+    diesel::insert_into(private_key_access_log::table)
+        .values(&access)
+        .execute(connection)?;
+
+    Ok(access)
+}
+
+/// Handles the request to list the recorded accesses to private keys.
+///
+/// # Arguments
+///
+/// * `req` - The request; the link to the next page is derived from it.
+/// * `query` - Optional key, actor and start time the accesses are filtered by, and the requested page.
+///
+/// # Returns
+///
+/// A JSON response containing a page of the matching accesses, newest first.
+#[cfg(feature = "server")]
+#[utoipa::path(
+    get,
+    path = "/audit/private-key-access",
+    params(PrivateKeyAccessQuery),
+    responses(
+        (status = 200, description = "Accesses to private keys, newest first", body = [PrivateKeyAccess],
+            headers(("Link" = String, description = "URL of the next page (`rel=\"next\"`), if any"))),
+        (status = 400, description = "Invalid since, limit or offset", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to load the access log", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn private_key_access_handler(req: HttpRequest, query: web::Query<PrivateKeyAccessQuery>) -> impl Responder {
+    let since = match query.since_time() {
+        Ok(since) => since,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+    let page = match Page::new(query.limit, query.offset) {
+        Ok(page) => page,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+
+    let mut accesses = private_key_access_log::table.into_boxed();
+    if let Some(key_id) = query.key_id {
+        accesses = accesses.filter(private_key_access_log::key_id.eq(key_id));
+    }
+    if let Some(actor) = &query.actor {
+        accesses = accesses.filter(private_key_access_log::actor.eq(actor));
+    }
+    if let Some(since) = since {
+        accesses = accesses.filter(private_key_access_log::accessed_at.ge(since));
+    }
+
+    let connection = &mut establish_connection();
+    match accesses
+        .order((private_key_access_log::accessed_at.desc(), private_key_access_log::id.asc()))
+        .offset(page.offset)
+        .limit(page.fetch())
+        .load::<PrivateKeyAccess>(connection)
+    {
+        Ok(accesses) => page_response(&req, &page, accesses),
+        Err(_) => HttpResponse::InternalServerError().body("Failed to load the access log"),
+    }
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_request_actor() {
+    use actix_web::test::TestRequest;
+
+    let req = TestRequest::default().insert_header((ACTOR_HEADER, " alice@example.com ")).to_http_request();
+    assert_eq!(request_actor(&req), Some("alice@example.com".to_string()));
+
+    let req = TestRequest::default().insert_header((ACTOR_HEADER, "")).to_http_request();
+    assert_eq!(request_actor(&req), None);
+
+    let req = TestRequest::default().insert_header((ACTOR_HEADER, "a".repeat(MAX_ACTOR_LENGTH + 1))).to_http_request();
+    assert_eq!(request_actor(&req), None);
+
+    assert_eq!(request_actor(&TestRequest::default().to_http_request()), None);
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_request_client_address() {
+    use actix_web::test::TestRequest;
+
+    let req = TestRequest::default()
+        .insert_header(("X-Forwarded-For", "203.0.113.7, 10.0.0.1"))
+        .to_http_request();
+    assert_eq!(request_client_address(&req), Some("203.0.113.7".to_string()));
+
+    let req = TestRequest::default()
+        .peer_addr("198.51.100.2:41000".parse().unwrap())
+        .to_http_request();
+    assert_eq!(request_client_address(&req), Some("198.51.100.2".to_string()));
+}
@@ -0,0 +1,180 @@
+//! This module provides fault injection for chaos testing.
+//!
+//! Available with the `chaos` feature, which is meant for development and test deployments only.
+//! Faults are configured at runtime through `/chaos/faults` and apply to every other request:
+//! added latency, injected database errors and forced private key expiry. They let client
+//! retry and cache behavior be verified under failure modes.
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Prefix of the fault configuration endpoints, which are never affected by faults.
+const CHAOS_PATH_PREFIX: &str = "/chaos/";
+
+/// Maximum latency that can be injected, in milliseconds.
+const MAX_LATENCY_MS: u64 = 60_000;
+
+/// Faults injected into request handling.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct FaultConfig {
+    /// Delay added to every request, in milliseconds.
+    #[serde(default)]
+    pub latency_ms: u64,
+    /// Share of requests (from 0.0 to 1.0) failing with an injected database error.
+    #[serde(default)]
+    pub db_error_rate: f64,
+    /// Whether every private key is treated as expired.
+    #[serde(default)]
+    pub force_key_expiry: bool,
+}
+
+impl FaultConfig {
+    /// Checks that the configuration is within the supported bounds.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message describing the invalid setting.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.latency_ms > MAX_LATENCY_MS {
+            return Err(format!("latency_ms must not exceed {}", MAX_LATENCY_MS));
+        }
+        if !(0.0..=1.0).contains(&self.db_error_rate) {
+            return Err("db_error_rate must be between 0.0 and 1.0".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Currently injected faults.
+static FAULTS: Mutex<FaultConfig> = Mutex::new(FaultConfig {
+    latency_ms: 0,
+    db_error_rate: 0.0,
+    force_key_expiry: false,
+});
+
+/// Returns the currently injected faults.
+pub fn current_faults() -> FaultConfig {
+    *FAULTS.lock().unwrap()
+}
+
+/// Replaces the injected faults.
+pub fn set_faults(faults: FaultConfig) {
+    *FAULTS.lock().unwrap() = faults;
+}
+
+/// Returns whether private keys are currently forced to be treated as expired.
+pub fn is_key_expiry_forced() -> bool {
+    current_faults().force_key_expiry
+}
+
+/// Decides whether a request fails with the given error rate.
+fn should_fail(rate: f64) -> bool {
+    if rate <= 0.0 {
+        return false;
+    }
+    if rate >= 1.0 {
+        return true;
+    }
+
+    let mut bytes = [0u8; 4];
+    if openssl::rand::rand_bytes(&mut bytes).is_err() {
+        return false;
+    }
+    (u32::from_be_bytes(bytes) as f64 / u32::MAX as f64) < rate
+}
+
+/// Middleware injecting the configured latency and database errors.
+pub async fn inject_faults(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    if !req.path().starts_with(CHAOS_PATH_PREFIX) {
+        let faults = current_faults();
+        if faults.latency_ms > 0 {
+            actix_web::rt::time::sleep(Duration::from_millis(faults.latency_ms)).await;
+        }
+        if should_fail(faults.db_error_rate) {
+            let response = HttpResponse::InternalServerError().body("Injected database error");
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+    }
+
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
+
+/// Returns the currently injected faults.
+pub async fn get_faults_handler() -> impl Responder {
+    HttpResponse::Ok().json(current_faults())
+}
+
+/// Replaces the injected faults.
+///
+/// # Returns
+///
+/// The new fault configuration, or `400 Bad Request` if it is out of bounds.
+pub async fn put_faults_handler(input: web::Json<FaultConfig>) -> impl Responder {
+    if let Err(message) = input.validate() {
+        return HttpResponse::BadRequest().body(message);
+    }
+
+    set_faults(input.into_inner());
+    HttpResponse::Ok().json(current_faults())
+}
+
+/// Removes every injected fault.
+pub async fn delete_faults_handler() -> impl Responder {
+    set_faults(FaultConfig::default());
+    HttpResponse::NoContent().finish()
+}
+
+#[test]
+fn test_fault_config_validate() {
+    assert!(FaultConfig::default().validate().is_ok());
+    assert!(FaultConfig { latency_ms: 500, db_error_rate: 0.5, force_key_expiry: true }.validate().is_ok());
+    assert!(FaultConfig { latency_ms: MAX_LATENCY_MS + 1, ..Default::default() }.validate().is_err());
+    assert!(FaultConfig { db_error_rate: 1.5, ..Default::default() }.validate().is_err());
+    assert!(FaultConfig { db_error_rate: -0.1, ..Default::default() }.validate().is_err());
+}
+
+#[test]
+fn test_should_fail_bounds() {
+    assert!(!should_fail(0.0));
+    assert!(should_fail(1.0));
+}
+
+#[cfg(test)]
+#[actix_rt::test]
+async fn test_inject_faults() {
+    use actix_web::{middleware::from_fn, test, App};
+    use std::time::Instant;
+
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(inject_faults))
+            .route("/ping", web::get().to(|| async { HttpResponse::Ok().body("pong") }))
+            .route("/chaos/faults", web::get().to(get_faults_handler)),
+    )
+    .await;
+
+    set_faults(FaultConfig { latency_ms: 50, db_error_rate: 1.0, force_key_expiry: true });
+    assert!(is_key_expiry_forced());
+
+    let started = Instant::now();
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/ping").to_request()).await;
+    assert_eq!(resp.status(), 500);
+    assert!(started.elapsed() >= Duration::from_millis(50));
+
+    // The fault configuration itself stays reachable
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/chaos/faults").to_request()).await;
+    assert_eq!(resp.status(), 200);
+
+    set_faults(FaultConfig::default());
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/ping").to_request()).await;
+    assert_eq!(resp.status(), 200);
+    assert!(!is_key_expiry_forced());
+}
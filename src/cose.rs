@@ -0,0 +1,409 @@
+//! This module provides COSE_Sign1 signing (RFC 9052) of CBOR Web Tokens (RFC 8392).
+//!
+//! Claims are converted from JSON to CBOR; registered claim names (`iss`, `sub`, `aud`, `exp`,
+//! `nbf`, `iat`, `cti`) are mapped to their integer keys. Tokens are encoded as a tagged
+//! COSE_Sign1 structure with the algorithm in the protected header and the key ID in the
+//! unprotected header.
+//!
+//! Public keys are also exported as COSE_Key structures (RFC 9052, section 7) for constrained
+//! verifiers that speak COSE rather than JOSE.
+
+use crate::crypto::sign_with_jwk;
+use crate::models::{Jwk, JwkData};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde_json::Value;
+use std::error::Error;
+
+/// CBOR tag of COSE_Sign1 structures.
+const COSE_SIGN1_TAG: u64 = 18;
+
+/// COSE header parameter of the algorithm.
+const HEADER_ALG: i64 = 1;
+
+/// COSE header parameter of the key ID.
+const HEADER_KID: i64 = 4;
+
+/// Media type of a COSE_Key structure.
+pub const COSE_KEY_CONTENT_TYPE: &str = "application/cose-key";
+
+/// Common COSE_Key parameters (RFC 9052, section 7.1).
+const KEY_KTY: i64 = 1;
+const KEY_KID: i64 = 2;
+const KEY_ALG: i64 = 3;
+
+/// Key type specific COSE_Key parameters: `crv`, `x`, `y` of EC2 and OKP keys (RFC 9053,
+/// section 7) and `n`, `e` of RSA keys (RFC 8230, section 4).
+const KEY_CRV: i64 = -1;
+const KEY_X: i64 = -2;
+const KEY_Y: i64 = -3;
+const KEY_N: i64 = -1;
+const KEY_E: i64 = -2;
+
+/// Returns the COSE algorithm identifier of a stored key, if the key can sign CWTs.
+///
+/// Only EC (ES256, ES384, ES512, ES256K) and OKP (EdDSA) keys are supported.
+pub fn cose_algorithm(alg: &str) -> Option<i64> {
+    match alg {
+        "ES256" => Some(-7),
+        "ES384" => Some(-35),
+        "ES512" => Some(-36),
+        "ES256K" => Some(-47),
+        "EdDSA" => Some(-8),
+        _ => None,
+    }
+}
+
+/// Returns the COSE algorithm identifier of a public key, including RSA algorithms
+/// (RFC 8812) that cannot sign CWTs here and the encryption algorithms of RFC 8230 and RFC 9053.
+fn cose_key_algorithm(alg: &str) -> Option<i64> {
+    match alg {
+        "RS256" => Some(-257),
+        "RS384" => Some(-258),
+        "RS512" => Some(-259),
+        "RSA-OAEP-256" => Some(-41),
+        "ECDH-ES+A128KW" => Some(-29),
+        _ => cose_algorithm(alg),
+    }
+}
+
+/// Returns the COSE elliptic curve identifier of a JWK curve (RFC 9053, section 7.1, and RFC 8812
+/// for secp256k1).
+fn cose_curve(crv: &str) -> Option<i64> {
+    match crv {
+        "P-256" => Some(1),
+        "P-384" => Some(2),
+        "P-521" => Some(3),
+        "X25519" => Some(4),
+        "X448" => Some(5),
+        "Ed25519" => Some(6),
+        "Ed448" => Some(7),
+        "secp256k1" => Some(8),
+        _ => None,
+    }
+}
+
+/// Returns the integer key of a registered CWT claim name (RFC 8392, section 4).
+fn cwt_claim_key(name: &str) -> Option<i64> {
+    match name {
+        "iss" => Some(1),
+        "sub" => Some(2),
+        "aud" => Some(3),
+        "exp" => Some(4),
+        "nbf" => Some(5),
+        "iat" => Some(6),
+        "cti" => Some(7),
+        _ => None,
+    }
+}
+
+/// Appends a CBOR data item head with the major type and argument.
+fn encode_head(out: &mut Vec<u8>, major: u8, argument: u64) {
+    let major = major << 5;
+    if argument < 24 {
+        out.push(major | argument as u8);
+    } else if argument <= u8::MAX as u64 {
+        out.push(major | 24);
+        out.push(argument as u8);
+    } else if argument <= u16::MAX as u64 {
+        out.push(major | 25);
+        out.extend_from_slice(&(argument as u16).to_be_bytes());
+    } else if argument <= u32::MAX as u64 {
+        out.push(major | 26);
+        out.extend_from_slice(&(argument as u32).to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&argument.to_be_bytes());
+    }
+}
+
+/// Appends a CBOR integer.
+fn encode_int(out: &mut Vec<u8>, value: i64) {
+    if value >= 0 {
+        encode_head(out, 0, value as u64);
+    } else {
+        encode_head(out, 1, (-1 - value) as u64);
+    }
+}
+
+/// Appends a CBOR byte string.
+fn encode_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    encode_head(out, 2, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+/// Appends a CBOR text string.
+fn encode_text(out: &mut Vec<u8>, text: &str) {
+    encode_head(out, 3, text.len() as u64);
+    out.extend_from_slice(text.as_bytes());
+}
+
+/// Appends a JSON value as CBOR.
+fn encode_json(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => out.push(0xf6),
+        Value::Bool(false) => out.push(0xf4),
+        Value::Bool(true) => out.push(0xf5),
+        Value::Number(number) => {
+            if let Some(value) = number.as_i64() {
+                encode_int(out, value);
+            } else if let Some(value) = number.as_u64() {
+                encode_head(out, 0, value);
+            } else {
+                out.push(0xfb);
+                out.extend_from_slice(&number.as_f64().unwrap_or_default().to_be_bytes());
+            }
+        }
+        Value::String(text) => encode_text(out, text),
+        Value::Array(values) => {
+            encode_head(out, 4, values.len() as u64);
+            for value in values {
+                encode_json(out, value);
+            }
+        }
+        Value::Object(members) => {
+            encode_head(out, 5, members.len() as u64);
+            for (name, value) in members {
+                encode_text(out, name);
+                encode_json(out, value);
+            }
+        }
+    }
+}
+
+/// Encodes a JSON claims set as a CWT claims set.
+///
+/// # Errors
+///
+/// Returns an error if the claims are not a JSON object.
+pub fn encode_cwt_claims(claims: &Value) -> Result<Vec<u8>, Box<dyn Error>> {
+    let members = claims.as_object().ok_or("Claims must be a JSON object")?;
+
+    let mut out = Vec::new();
+    encode_head(&mut out, 5, members.len() as u64);
+    for (name, value) in members {
+        match cwt_claim_key(name) {
+            Some(key) => encode_int(&mut out, key),
+            None => encode_text(&mut out, name),
+        }
+        encode_json(&mut out, value);
+    }
+
+    Ok(out)
+}
+
+/// Encodes the protected header (`{1: alg}`) of a COSE_Sign1 structure.
+fn encode_protected_header(alg: i64) -> Vec<u8> {
+    let mut out = Vec::new();
+    encode_head(&mut out, 5, 1);
+    encode_int(&mut out, HEADER_ALG);
+    encode_int(&mut out, alg);
+    out
+}
+
+/// Encodes the `Sig_structure` covered by a COSE_Sign1 signature.
+fn encode_sig_structure(protected: &[u8], external_aad: &[u8], payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    encode_head(&mut out, 4, 4);
+    encode_text(&mut out, "Signature1");
+    encode_bytes(&mut out, protected);
+    encode_bytes(&mut out, external_aad);
+    encode_bytes(&mut out, payload);
+    out
+}
+
+/// Signs the claims and returns a CWT as a tagged COSE_Sign1 structure.
+///
+/// # Arguments
+///
+/// * `jwk` - EC or OKP key used for signing, including its private part.
+/// * `claims` - JSON claims set.
+/// * `external_aad` - Externally supplied data bound to the signature.
+///
+/// # Errors
+///
+/// Returns an error if the key cannot sign CWTs, the claims are not a JSON object or signing
+/// fails.
+pub fn sign_cwt(jwk: &JwkData, claims: &Value, external_aad: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let alg = cose_algorithm(&jwk.alg).ok_or("Key cannot sign CBOR Web Tokens")?;
+
+    let protected = encode_protected_header(alg);
+    let payload = encode_cwt_claims(claims)?;
+    let signature = sign_with_jwk(jwk, &encode_sig_structure(&protected, external_aad, &payload))?;
+
+    let mut out = Vec::new();
+    encode_head(&mut out, 6, COSE_SIGN1_TAG);
+    encode_head(&mut out, 4, 4);
+    encode_bytes(&mut out, &protected);
+    encode_head(&mut out, 5, 1);
+    encode_int(&mut out, HEADER_KID);
+    encode_bytes(&mut out, jwk.kid.as_bytes());
+    encode_bytes(&mut out, &payload);
+    encode_bytes(&mut out, &signature);
+
+    Ok(out)
+}
+
+/// Encodes the public part of a JWK as a COSE_Key.
+///
+/// Map keys are written in the deterministic order of RFC 8949, section 4.2.1: `kty`, `kid`,
+/// `alg`, then the key type specific parameters.
+///
+/// # Errors
+///
+/// Returns an error if the key type or curve has no COSE representation (e.g., ML-DSA keys) or
+/// a key parameter is missing or not base64url encoded.
+pub fn encode_cose_key(jwk: &Jwk) -> Result<Vec<u8>, Box<dyn Error>> {
+    let parameter = |name: &str, value: &Option<String>| -> Result<Vec<u8>, Box<dyn Error>> {
+        let value = value.as_deref().ok_or(format!("Missing JWK parameter {}", name))?;
+        Ok(URL_SAFE_NO_PAD.decode(value)?)
+    };
+    let curve = || -> Result<i64, Box<dyn Error>> {
+        let crv = jwk.crv.as_deref().ok_or("Missing JWK parameter crv")?;
+        Ok(cose_curve(crv).ok_or("Curve has no COSE representation")?)
+    };
+
+    // Key type specific parameters, already encoded, and their number
+    let mut parameters = Vec::new();
+    let (kty, count) = match jwk.kty.as_str() {
+        "OKP" | "EC" => {
+            encode_int(&mut parameters, KEY_CRV);
+            encode_int(&mut parameters, curve()?);
+            encode_int(&mut parameters, KEY_X);
+            encode_bytes(&mut parameters, &parameter("x", &jwk.x)?);
+            if jwk.kty == "OKP" {
+                (1, 2)
+            } else {
+                encode_int(&mut parameters, KEY_Y);
+                encode_bytes(&mut parameters, &parameter("y", &jwk.y)?);
+                (2, 3)
+            }
+        }
+        "RSA" => {
+            encode_int(&mut parameters, KEY_N);
+            encode_bytes(&mut parameters, &parameter("n", &jwk.n)?);
+            encode_int(&mut parameters, KEY_E);
+            encode_bytes(&mut parameters, &parameter("e", &jwk.e)?);
+            (3, 2)
+        }
+        _ => return Err(Box::from("Key type has no COSE representation")),
+    };
+    let alg = cose_key_algorithm(&jwk.alg);
+
+    let mut out = Vec::new();
+    encode_head(&mut out, 5, 2 + alg.iter().len() as u64 + count);
+    encode_int(&mut out, KEY_KTY);
+    encode_int(&mut out, kty);
+    encode_int(&mut out, KEY_KID);
+    encode_bytes(&mut out, jwk.kid.as_bytes());
+    if let Some(alg) = alg {
+        encode_int(&mut out, KEY_ALG);
+        encode_int(&mut out, alg);
+    }
+    out.extend(parameters);
+
+    Ok(out)
+}
+
+#[test]
+fn test_encode_json_matches_rfc8949_examples() {
+    use serde_json::json;
+
+    let encode = |value: Value| {
+        let mut out = Vec::new();
+        encode_json(&mut out, &value);
+        out
+    };
+
+    assert_eq!(encode(json!(0)), vec![0x00]);
+    assert_eq!(encode(json!(23)), vec![0x17]);
+    assert_eq!(encode(json!(24)), vec![0x18, 0x18]);
+    assert_eq!(encode(json!(1000)), vec![0x19, 0x03, 0xe8]);
+    assert_eq!(encode(json!(1000000)), vec![0x1a, 0x00, 0x0f, 0x42, 0x40]);
+    assert_eq!(encode(json!(-1)), vec![0x20]);
+    assert_eq!(encode(json!(-1000)), vec![0x39, 0x03, 0xe7]);
+    assert_eq!(encode(json!(1.1)), vec![0xfb, 0x3f, 0xf1, 0x99, 0x99, 0x99, 0x99, 0x99, 0x9a]);
+    assert_eq!(encode(json!(true)), vec![0xf5]);
+    assert_eq!(encode(json!(null)), vec![0xf6]);
+    assert_eq!(encode(json!("a")), vec![0x61, 0x61]);
+    assert_eq!(encode(json!([1, 2, 3])), vec![0x83, 0x01, 0x02, 0x03]);
+    assert_eq!(encode(json!({"a": 1})), vec![0xa1, 0x61, 0x61, 0x01]);
+}
+
+#[test]
+fn test_encode_cwt_claims_maps_registered_claims() {
+    use serde_json::json;
+
+    let encoded = encode_cwt_claims(&json!({"iss": "a", "exp": 1000, "x": true})).unwrap();
+    // serde_json orders object members by name: exp, iss, x
+    assert_eq!(
+        encoded,
+        vec![0xa3, 0x04, 0x19, 0x03, 0xe8, 0x01, 0x61, 0x61, 0x61, 0x78, 0xf5]
+    );
+    assert!(encode_cwt_claims(&json!([1])).is_err());
+}
+
+#[test]
+fn test_sign_cwt_verifies_with_public_jwk() {
+    use crate::crypto::{generate_jwk_data, verify_with_jwk};
+    use crate::models::Jwk;
+    use serde_json::json;
+
+    let jwk = generate_jwk_data("ES256", 2048).unwrap();
+    let claims = json!({"iss": "device-issuer", "sub": "device-1", "exp": 2000000000});
+    let token = sign_cwt(&jwk, &claims, b"").unwrap();
+
+    // Tag 18 followed by a four-element array
+    assert_eq!(&token[..2], &[0xd2, 0x84]);
+
+    // The ES256 signature is the final 64-byte byte string
+    let (body, signature) = token.split_at(token.len() - 64);
+    assert_eq!(&body[body.len() - 2..], &[0x58, 0x40]);
+
+    let protected = encode_protected_header(-7);
+    let payload = encode_cwt_claims(&claims).unwrap();
+    let sig_structure = encode_sig_structure(&protected, b"", &payload);
+    assert!(verify_with_jwk(&Jwk::from(jwk.clone()), &sig_structure, signature).unwrap());
+
+    let rsa = generate_jwk_data("RS256", 2048).unwrap();
+    assert!(sign_cwt(&rsa, &claims, b"").is_err());
+}
+
+#[test]
+fn test_encode_cose_key() {
+    // RFC 9052, appendix C.7.1: public key "11" (P-256, with alg ES256 added)
+    let jwk = Jwk {
+        kty: "EC".to_string(),
+        use_: "sig".to_string(),
+        alg: "ES256".to_string(),
+        kid: "11".to_string(),
+        crv: Some("P-256".to_string()),
+        x: Some("usWxHK2PmfnHKwXPS54m0kTcGJ90UiglWiGahtagnv8".to_string()),
+        y: Some("IBOL-C3BttVivg-lSreASjpkttcsz-1rb7btKLv8EX4".to_string()),
+        n: None,
+        e: None,
+        x5c: None,
+        x5t: None,
+        pub_: None,
+    };
+    let mut expected = vec![0xa6, 0x01, 0x02, 0x02, 0x42, b'1', b'1', 0x03, 0x26, 0x20, 0x01, 0x21, 0x58, 0x20];
+    expected.extend(URL_SAFE_NO_PAD.decode(jwk.x.as_ref().unwrap()).unwrap());
+    expected.extend([0x22, 0x58, 0x20]);
+    expected.extend(URL_SAFE_NO_PAD.decode(jwk.y.as_ref().unwrap()).unwrap());
+    assert_eq!(encode_cose_key(&jwk).unwrap(), expected);
+
+    // OKP and RSA keys
+    let okp = Jwk::from(crate::crypto::generate_jwk_data("Ed25519", 2048).unwrap());
+    let encoded = encode_cose_key(&okp).unwrap();
+    assert_eq!(&encoded[..2], &[0xa5, 0x01]);
+    assert_eq!(encoded[2], 0x01);
+    let rsa = Jwk::from(crate::crypto::generate_jwk_data("RS256", 2048).unwrap());
+    let encoded = encode_cose_key(&rsa).unwrap();
+    assert_eq!(&encoded[..3], &[0xa5, 0x01, 0x03]);
+
+    let unsupported = Jwk {
+        kty: "AKP".to_string(),
+        ..jwk
+    };
+    assert!(encode_cose_key(&unsupported).is_err());
+}
@@ -1,29 +1,809 @@
 //! This module provides functionality for establishing a connection to the database.
+//!
+//! Request handlers check connections out of a pool ([`database_pool`], `DB_POOL_SIZE`
+//! connections, waiting up to `DB_POOL_TIMEOUT_SECONDS` for a free one). Pooled connections are
+//! checked before every checkout, so after a Postgres HA switchover broken connections and
+//! connections to the demoted primary are discarded and replaced by connections to the new
+//! primary without restarting the service. Other connections are established anew; attempts
+//! that fail while the switchover is in progress are retried with backoff, and connections that
+//! land on a read-only standby (e.g. through stale DNS) are rejected and retried. A change of the
+//! server behind `DATABASE_URL` is logged as a failover.
+//!
+//! The wait for a pooled connection and the latency of every query run on the connections of
+//! this module are recorded in the metrics (see [`crate::metrics`]).
+//!
+//! TLS to the database (including client certificates for mutual TLS) is configured with the
+//! `DB_SSLMODE`, `DB_SSLROOTCERT`, `DB_SSLCERT` and `DB_SSLKEY` variables, which are passed to
+//! libpq as the connection parameters of the same name.
 
+use crate::log_error;
+use crate::metrics::{record_pool_wait, record_query};
 use diesel::pg::PgConnection;
+use diesel::connection::{Instrumentation, InstrumentationEvent, SimpleConnection};
 use diesel::prelude::*;
+use diesel::r2d2::{self, HandleError, ManageConnection, Pool, PooledConnection, R2D2Connection};
+use diesel::result::ConnectionError;
+use diesel::sql_query;
+use diesel::sql_types::{Bool, Text};
 use dotenv::dotenv;
 use std::env;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
-/// Establishes a connection to the PostgreSQL database.
+/// Identity of the last database server a connection was established to.
+static DATABASE_SERVER: Mutex<Option<String>> = Mutex::new(None);
+
+/// Number of database failovers observed since startup.
+static DATABASE_FAILOVERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Connection pool of the process, created on first use.
+static DATABASE_POOL: OnceLock<DbPool> = OnceLock::new();
+
+/// Connection pool of the read replica (`DATABASE_READ_URL`), created on first use.
+static READ_DATABASE_POOL: OnceLock<DbPool> = OnceLock::new();
+
+/// Pool of database connections.
+pub type DbPool = Pool<PgConnectionManager>;
+
+/// Connection checked out of a [`DbPool`], returned to the pool when dropped.
+pub type PooledDbConnection = PooledConnection<PgConnectionManager>;
+
+/// State of the server a connection is established to.
+#[derive(QueryableByName)]
+struct ServerState {
+    #[diesel(sql_type = Bool)]
+    in_recovery: bool,
+    /// Address, port and start time of the server, distinguishing servers behind the same
+    /// address (e.g. a virtual IP).
+    #[diesel(sql_type = Text)]
+    server: String,
+}
+
+/// Returns the number of attempts to connect to the database (`DB_CONNECT_ATTEMPTS`,
+/// default 5).
+///
+/// # Panics
+///
+/// This function will panic if `DB_CONNECT_ATTEMPTS` is not a positive number.
+pub fn connect_attempts() -> u32 {
+    dotenv().ok();
+
+    env::var("DB_CONNECT_ATTEMPTS")
+        .unwrap_or_else(|_| "5".to_string())
+        .parse()
+        .ok()
+        .filter(|attempts| *attempts > 0)
+        .expect("DB_CONNECT_ATTEMPTS must be a positive number")
+}
+
+/// Returns the delay before the first connection retry (`DB_CONNECT_RETRY_DELAY_MS`,
+/// default 200); the delay doubles with every further retry.
+///
+/// # Panics
+///
+/// This function will panic if `DB_CONNECT_RETRY_DELAY_MS` is not a number.
+pub fn connect_retry_delay() -> Duration {
+    dotenv().ok();
+
+    let millis: u64 = env::var("DB_CONNECT_RETRY_DELAY_MS")
+        .unwrap_or_else(|_| "200".to_string())
+        .parse()
+        .expect("DB_CONNECT_RETRY_DELAY_MS must be a number");
+
+    Duration::from_millis(millis)
+}
+
+/// Longest delay between two connection attempts.
+const MAX_CONNECT_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// Returns the number of attempts to connect to the database at startup and in the `migrate`
+/// command (`DB_STARTUP_CONNECT_ATTEMPTS`, default 30), e.g. while docker-compose starts the
+/// database alongside the service.
+///
+/// # Panics
+///
+/// This function will panic if `DB_STARTUP_CONNECT_ATTEMPTS` is not a positive number.
+pub fn startup_connect_attempts() -> u32 {
+    dotenv().ok();
+
+    env::var("DB_STARTUP_CONNECT_ATTEMPTS")
+        .unwrap_or_else(|_| "30".to_string())
+        .parse()
+        .ok()
+        .filter(|attempts| *attempts > 0)
+        .expect("DB_STARTUP_CONNECT_ATTEMPTS must be a positive number")
+}
+
+/// Returns the delay before the first connection retry at startup
+/// (`DB_STARTUP_RETRY_DELAY_MS`, default 500); the delay doubles with every further retry.
+///
+/// # Panics
+///
+/// This function will panic if `DB_STARTUP_RETRY_DELAY_MS` is not a number.
+pub fn startup_retry_delay() -> Duration {
+    dotenv().ok();
+
+    let millis: u64 = env::var("DB_STARTUP_RETRY_DELAY_MS")
+        .unwrap_or_else(|_| "500".to_string())
+        .parse()
+        .expect("DB_STARTUP_RETRY_DELAY_MS must be a number");
+
+    Duration::from_millis(millis)
+}
+
+/// Returns the delay before a connection retry: `first_delay` doubled for every earlier retry,
+/// at most [`MAX_CONNECT_RETRY_DELAY`].
+fn retry_delay(first_delay: Duration, retry: u32) -> Duration {
+    first_delay
+        .checked_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+        .unwrap_or(MAX_CONNECT_RETRY_DELAY)
+        .min(MAX_CONNECT_RETRY_DELAY)
+}
+
+/// Valid values of the libpq `sslmode` connection parameter.
+const SSL_MODES: [&str; 6] = ["disable", "allow", "prefer", "require", "verify-ca", "verify-full"];
+
+/// TLS options of a database connection, passed to libpq as connection parameters.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DatabaseTls {
+    /// TLS mode (`sslmode`), e.g. `verify-full`.
+    pub sslmode: Option<String>,
+    /// CA bundle the server certificate is verified against (`sslrootcert`).
+    pub sslrootcert: Option<String>,
+    /// Client certificate for mutual TLS (`sslcert`).
+    pub sslcert: Option<String>,
+    /// Private key of the client certificate (`sslkey`).
+    pub sslkey: Option<String>,
+}
+
+/// Returns the TLS options of a database from the environment: `<prefix>_SSLMODE`,
+/// `<prefix>_SSLROOTCERT`, `<prefix>_SSLCERT` and `<prefix>_SSLKEY`.
+///
+/// # Arguments
+///
+/// * `prefix` - Prefix of the variables, e.g. `DB` for the key database.
+///
+/// # Panics
+///
+/// This function will panic if the TLS mode is not a libpq `sslmode`, or if only one of the
+/// client certificate and its key is set.
+pub fn database_tls(prefix: &str) -> DatabaseTls {
+    dotenv().ok();
+
+    let variable = |name: &str| {
+        env::var(format!("{}_{}", prefix, name))
+            .ok()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+
+    let tls = DatabaseTls {
+        sslmode: variable("SSLMODE"),
+        sslrootcert: variable("SSLROOTCERT"),
+        sslcert: variable("SSLCERT"),
+        sslkey: variable("SSLKEY"),
+    };
+    if let Some(sslmode) = &tls.sslmode {
+        if !SSL_MODES.contains(&sslmode.as_str()) {
+            panic!("{}_SSLMODE must be one of {}", prefix, SSL_MODES.join(", "));
+        }
+    }
+    if tls.sslcert.is_some() != tls.sslkey.is_some() {
+        panic!("{}_SSLCERT and {}_SSLKEY must be set together", prefix, prefix);
+    }
+
+    tls
+}
+
+/// Percent-encodes a connection parameter value for a connection URI.
+fn encode_uri_value(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Adds TLS options to a database connection string.
+///
+/// Connection URIs (`postgres://...`) get the options as query parameters, key/value connection
+/// strings as `key='value'` pairs. Parameters already present in the connection string take
+/// precedence over the options.
+///
+/// # Arguments
+///
+/// * `database_url` - Connection URI or key/value connection string.
+/// * `tls` - TLS options to add.
+pub fn with_tls_options(database_url: &str, tls: &DatabaseTls) -> String {
+    let options = [
+        ("sslmode", &tls.sslmode),
+        ("sslrootcert", &tls.sslrootcert),
+        ("sslcert", &tls.sslcert),
+        ("sslkey", &tls.sslkey),
+    ];
+    let is_uri = database_url.starts_with("postgres://") || database_url.starts_with("postgresql://");
+
+    let mut url = database_url.to_string();
+    for (name, value) in options {
+        let Some(value) = value else { continue };
+        let present = url
+            .split(['?', '&', ' '])
+            .any(|parameter| parameter.trim_start().starts_with(&format!("{}=", name)));
+        if present {
+            continue;
+        }
+        if is_uri {
+            let separator = if url.contains('?') { '&' } else { '?' };
+            url.push_str(&format!("{}{}={}", separator, name, encode_uri_value(value)));
+        } else {
+            let escaped = value.replace('\\', "\\\\").replace('\'', "\\'");
+            url.push_str(&format!(" {}='{}'", name, escaped));
+        }
+    }
+
+    url
+}
+
+/// Returns the number of database failovers observed since startup.
+pub fn database_failovers() -> usize {
+    DATABASE_FAILOVERS.load(Ordering::SeqCst)
+}
+
+/// Records the server a connection was established to, logging a failover if it changed.
 ///
 /// # Returns
 ///
-/// A `PgConnection` instance representing the database connection.
+/// `true` if the server differs from the previously recorded one.
+pub fn record_database_server(server: &str) -> bool {
+    let mut current = DATABASE_SERVER.lock().unwrap();
+    let failover = current.as_deref().is_some_and(|previous| previous != server);
+    if failover {
+        DATABASE_FAILOVERS.fetch_add(1, Ordering::SeqCst);
+        log_error!(
+            "Database failover detected: connected to {} instead of {}.",
+            server,
+            current.as_deref().unwrap_or_default()
+        );
+    }
+    *current = Some(server.to_string());
+
+    failover
+}
+
+/// Queries the state of the server a connection is established to.
+fn server_state(connection: &mut PgConnection) -> Result<ServerState, String> {
+    sql_query(
+        "SELECT pg_is_in_recovery() AS in_recovery, \
+         COALESCE(host(inet_server_addr()), 'local') || ':' || COALESCE(inet_server_port(), 0) \
+         || ' (started ' || pg_postmaster_start_time() || ')' AS server",
+    )
+    .get_result::<ServerState>(connection)
+    .map_err(|e| e.to_string())
+}
+
+/// Checks the server a connection is established to and records it.
+///
+/// # Errors
+///
+/// Returns a message if the query fails or the server is a standby and `allow_standby` is not set.
+fn check_server(connection: &mut PgConnection, allow_standby: bool) -> Result<(), String> {
+    let state = server_state(connection)?;
+    if state.in_recovery && !allow_standby {
+        return Err(format!("{} is a read-only standby", state.server));
+    }
+    record_database_server(&state.server);
+
+    Ok(())
+}
+
+/// Connects to the database once and checks that the server accepts writes.
+///
+/// # Arguments
+///
+/// * `database_url` - URL of the database.
+/// * `allow_standby` - Whether a read-only standby is acceptable.
+///
+/// # Errors
+///
+/// Returns a message if the connection fails or the server is a standby.
+fn connect(database_url: &str, allow_standby: bool) -> Result<PgConnection, String> {
+    let mut connection = establish(database_url)?;
+    check_server(&mut connection, allow_standby)?;
+
+    Ok(connection)
+}
+
+/// Connects to the database once, recording the latency of the queries run on the connection.
+fn establish(database_url: &str) -> Result<PgConnection, String> {
+    let mut connection = PgConnection::establish(database_url).map_err(|e| e.to_string())?;
+    connection.set_instrumentation(QueryTimer::default());
+
+    Ok(connection)
+}
+
+/// Records the latency of every query run on a connection.
+#[derive(Debug, Default)]
+struct QueryTimer {
+    started: Option<Instant>,
+}
+
+impl Instrumentation for QueryTimer {
+    fn on_connection_event(&mut self, event: InstrumentationEvent<'_>) {
+        match event {
+            InstrumentationEvent::StartQuery { .. } => self.started = Some(Instant::now()),
+            InstrumentationEvent::FinishQuery { query, error, .. } => {
+                if let Some(started) = self.started.take() {
+                    record_query(&query.to_string(), error.is_none(), started.elapsed());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Checks that a connection string addresses PostgreSQL, the only supported database.
+///
+/// Connection URIs must use the `postgres://` or `postgresql://` scheme; key/value connection
+/// strings are passed to libpq as they are.
+///
+/// # Errors
+///
+/// Returns a message naming the scheme of a URL for another database, e.g. `sqlite://` or
+/// `mysql://`, so that the service fails at startup instead of retrying the connection.
+pub fn check_database_url(database_url: &str) -> Result<(), String> {
+    let scheme = match database_url.split_once("://") {
+        Some((scheme, _)) if scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c)) => scheme,
+        _ => return Ok(()),
+    };
+    match scheme {
+        "postgres" | "postgresql" => Ok(()),
+        _ => Err(format!(
+            "Unsupported database URL scheme {}://, only PostgreSQL (postgres://) is supported",
+            scheme
+        )),
+    }
+}
+
+/// Returns the URL of the database (`DATABASE_URL`) and the connection string adding the TLS
+/// options of [`database_tls`].
 ///
 /// # Panics
 ///
-/// This function will panic if the `DATABASE_URL` environment variable is not set
-/// or if the connection to the database fails.
-pub fn establish_connection() -> PgConnection {
+/// This function will panic if the `DATABASE_URL` environment variable is not set or names
+/// another database than PostgreSQL.
+fn database_connection_string() -> (String, String) {
     // Load environment variables from the `.env` file (if it exists).
     dotenv().ok();
 
     // Retrieve the database URL from the environment variables.
     let database_url = env::var("DATABASE_URL")
         .expect("DATABASE_URL must be set in the environment variables or .env file");
+    if let Err(message) = check_database_url(&database_url) {
+        panic!("{}", message);
+    }
+    let connection_string = with_tls_options(&database_url, &database_tls("DB"));
+
+    (database_url, connection_string)
+}
+
+/// Returns the URL of the read replica (`DATABASE_READ_URL`), if set and not empty.
+///
+/// # Panics
+///
+/// This function will panic if the URL names another database than PostgreSQL.
+pub fn database_read_url() -> Option<String> {
+    dotenv().ok();
+
+    let database_read_url = env::var("DATABASE_READ_URL").ok().filter(|url| !url.is_empty())?;
+    if let Err(message) = check_database_url(&database_read_url) {
+        panic!("DATABASE_READ_URL: {}", message);
+    }
+
+    Some(database_read_url)
+}
+
+/// Establishes a connection to the PostgreSQL database, retrying with backoff while the
+/// database is unreachable or only a read-only standby answers.
+///
+/// Standbys are accepted in read-only mode, which only reads keys.
+///
+/// # Errors
+///
+/// Returns a message describing the last failure once all attempts are exhausted.
+///
+/// # Panics
+///
+/// This function will panic if the `DATABASE_URL` environment variable is not set.
+pub fn try_establish_connection() -> Result<PgConnection, String> {
+    connect_with_retries(connect_attempts(), connect_retry_delay())
+}
+
+/// Establishes a connection to the PostgreSQL database at startup, waiting for the database
+/// to come up: it retries for [`startup_connect_attempts`], starting with
+/// [`startup_retry_delay`].
+///
+/// # Errors
+///
+/// Returns a message describing the last failure once all attempts are exhausted.
+///
+/// # Panics
+///
+/// This function will panic if the `DATABASE_URL` environment variable is not set.
+pub fn wait_for_database() -> Result<PgConnection, String> {
+    connect_with_retries(startup_connect_attempts(), startup_retry_delay())
+}
+
+/// Connects to the database, retrying with exponential backoff (see [`retry_delay`]).
+fn connect_with_retries(attempts: u32, first_delay: Duration) -> Result<PgConnection, String> {
+    let (database_url, connection_string) = database_connection_string();
+    let allow_standby = crate::read_only_mode();
+
+    let mut attempt = 1;
+    loop {
+        match connect(&connection_string, allow_standby) {
+            Ok(connection) => return Ok(connection),
+            Err(message) if attempt >= attempts => {
+                return Err(format!("Error connecting to {}: {}", database_url, message))
+            }
+            Err(message) => {
+                log_error!(
+                    "Connection attempt {} of {} to the database failed, retrying: {}",
+                    attempt, attempts, message
+                );
+                std::thread::sleep(retry_delay(first_delay, attempt));
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Establishes a connection to the PostgreSQL database.
+///
+/// # Returns
+///
+/// A `PgConnection` instance representing the database connection.
+///
+/// # Panics
+///
+/// This function will panic if the `DATABASE_URL` environment variable is not set
+/// or if the connection to the database fails after all retries.
+pub fn establish_connection() -> PgConnection {
+    try_establish_connection().unwrap_or_else(|message| panic!("{}", message))
+}
+
+/// Returns the maximum number of pooled connections (`DB_POOL_SIZE`, default 10).
+///
+/// # Panics
+///
+/// This function will panic if `DB_POOL_SIZE` is not a positive number.
+pub fn pool_size() -> u32 {
+    dotenv().ok();
+
+    env::var("DB_POOL_SIZE")
+        .unwrap_or_else(|_| "10".to_string())
+        .parse()
+        .ok()
+        .filter(|size| *size > 0)
+        .expect("DB_POOL_SIZE must be a positive number")
+}
+
+/// Returns how long a checkout waits for a free pooled connection (`DB_POOL_TIMEOUT_SECONDS`,
+/// default 30).
+///
+/// # Panics
+///
+/// This function will panic if `DB_POOL_TIMEOUT_SECONDS` is not a positive number.
+pub fn pool_timeout() -> Duration {
+    dotenv().ok();
+
+    let seconds: u64 = env::var("DB_POOL_TIMEOUT_SECONDS")
+        .unwrap_or_else(|_| "30".to_string())
+        .parse()
+        .ok()
+        .filter(|seconds| *seconds > 0)
+        .expect("DB_POOL_TIMEOUT_SECONDS must be a positive number");
+
+    Duration::from_secs(seconds)
+}
+
+/// Establishes and checks the connections of a [`DbPool`].
+///
+/// New connections are rejected on a read-only standby like those of [`try_establish_connection`];
+/// the pool retries them until the checkout times out. Before every checkout the connection is
+/// checked again, so connections to a server demoted to standby are replaced.
+#[derive(Debug)]
+pub struct PgConnectionManager {
+    connection_string: String,
+    allow_standby: bool,
+    /// Whether the connections are made to a read replica, which is expected to be a standby and
+    /// is not recorded as the database server.
+    replica: bool,
+}
+
+impl PgConnectionManager {
+    /// Creates a manager connecting to `DATABASE_URL`.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the `DATABASE_URL` environment variable is not set.
+    pub fn new() -> PgConnectionManager {
+        PgConnectionManager {
+            connection_string: database_connection_string().1,
+            allow_standby: crate::read_only_mode(),
+            replica: false,
+        }
+    }
+
+    /// Creates a manager connecting to the read replica at `database_read_url`, with the TLS
+    /// options of [`database_tls`].
+    pub fn replica(database_read_url: &str) -> PgConnectionManager {
+        PgConnectionManager {
+            connection_string: with_tls_options(database_read_url, &database_tls("DB")),
+            allow_standby: true,
+            replica: true,
+        }
+    }
+}
+
+impl Default for PgConnectionManager {
+    fn default() -> Self {
+        PgConnectionManager::new()
+    }
+}
+
+impl ManageConnection for PgConnectionManager {
+    type Connection = PgConnection;
+    type Error = r2d2::Error;
+
+    fn connect(&self) -> Result<PgConnection, r2d2::Error> {
+        let connection = if self.replica {
+            establish(&self.connection_string)
+        } else {
+            connect(&self.connection_string, self.allow_standby)
+        };
+        connection.map_err(|message| r2d2::Error::ConnectionError(ConnectionError::BadConnection(message)))
+    }
+
+    fn is_valid(&self, connection: &mut PgConnection) -> Result<(), r2d2::Error> {
+        let checked = if self.replica {
+            server_state(connection).map(drop)
+        } else {
+            check_server(connection, self.allow_standby)
+        };
+        checked.map_err(|message| r2d2::Error::ConnectionError(ConnectionError::BadConnection(message)))
+    }
+
+    fn has_broken(&self, connection: &mut PgConnection) -> bool {
+        connection.is_broken()
+    }
+}
+
+/// Logs the failures of the pool to establish a connection.
+#[derive(Debug)]
+struct PoolErrorLogger;
+
+impl HandleError<r2d2::Error> for PoolErrorLogger {
+    fn handle_error(&self, error: r2d2::Error) {
+        log_error!("Failed to establish a pooled database connection: {}", error);
+    }
+}
+
+/// Returns the connection pool of the process, creating it on first use.
+///
+/// The pool is shared by all workers and listeners; connections are established as they are
+/// needed, up to [`pool_size`].
+///
+/// # Panics
+///
+/// This function will panic if the `DATABASE_URL` environment variable is not set or a pool
+/// setting is invalid.
+pub fn database_pool() -> DbPool {
+    DATABASE_POOL
+        .get_or_init(|| {
+            Pool::builder()
+                .max_size(pool_size())
+                .min_idle(Some(0))
+                .connection_timeout(pool_timeout())
+                .error_handler(Box::new(PoolErrorLogger))
+                .build_unchecked(PgConnectionManager::new())
+        })
+        .clone()
+}
+
+/// Returns the connection pool of the read replica (`DATABASE_READ_URL`), creating it on first
+/// use, or the pool of the primary ([`database_pool`]) if no replica is configured.
+///
+/// Reads served from the replica may lag behind the primary by its replication delay. The
+/// pool has the same settings as the pool of the primary.
+///
+/// # Panics
+///
+/// This function will panic if a database URL or a pool setting is invalid.
+pub fn read_database_pool() -> DbPool {
+    let database_read_url = match database_read_url() {
+        Some(database_read_url) => database_read_url,
+        None => return database_pool(),
+    };
+
+    READ_DATABASE_POOL
+        .get_or_init(|| {
+            Pool::builder()
+                .max_size(pool_size())
+                .min_idle(Some(0))
+                .connection_timeout(pool_timeout())
+                .error_handler(Box::new(PoolErrorLogger))
+                .build_unchecked(PgConnectionManager::replica(&database_read_url))
+        })
+        .clone()
+}
+
+/// Usage of a connection pool.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolUsage {
+    /// Name of the pool: `primary` or `replica`.
+    pub pool: &'static str,
+    /// Connections checked out of the pool.
+    pub in_use: u32,
+    /// Connections waiting in the pool.
+    pub idle: u32,
+    /// Maximum number of connections of the pool.
+    pub max_size: u32,
+}
+
+/// Returns the usage of the connection pools created so far.
+pub fn pool_usage() -> Vec<PoolUsage> {
+    [("primary", &DATABASE_POOL), ("replica", &READ_DATABASE_POOL)]
+        .into_iter()
+        .filter_map(|(name, pool)| {
+            let pool = pool.get()?;
+            let state = pool.state();
+            Some(PoolUsage {
+                pool: name,
+                in_use: state.connections - state.idle_connections,
+                idle: state.idle_connections,
+                max_size: pool.max_size(),
+            })
+        })
+        .collect()
+}
+
+/// Checks a connection out of the pool, waiting up to [`pool_timeout`] for a free one. The wait
+/// is recorded in the metrics.
+///
+/// # Errors
+///
+/// Returns a message if no connection becomes available in time.
+pub fn checkout(pool: &DbPool) -> Result<PooledDbConnection, String> {
+    let started = Instant::now();
+    let connection = pool.get();
+    record_pool_wait(started.elapsed());
+
+    connection.map_err(|e| format!("No database connection available: {}", e))
+}
+
+/// Loads the next batch of a listing, given the offset and the maximum number of items. A batch
+/// with fewer items than requested is the last one.
+pub type Batches<T> = Box<dyn FnMut(i64, i64) -> Result<Vec<T>, String> + Send>;
+
+/// Pooled connection with the read-only snapshot the batches of a listing are loaded from.
+///
+/// A snapshot still open when the connection is dropped (e.g. a streamed response was not sent
+/// to the end) is rolled back, so the connection returns to the pool outside of a transaction.
+struct Snapshot {
+    connection: PooledDbConnection,
+    open: bool,
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        if self.open {
+            self.connection.batch_execute("ROLLBACK").ok();
+        }
+    }
+}
+
+/// Loads a listing batch by batch from a read-only snapshot, so the batches are consistent with
+/// each other.
+///
+/// The snapshot is started with the first batch (offset 0) and committed after the last one; it
+/// is rolled back if a batch cannot be loaded or the batches are dropped before the last one.
+///
+/// # Arguments
+///
+/// * `connection` - Pooled connection the batches are loaded with; it is returned to the pool
+///   when the batches are dropped.
+/// * `load` - Loads the items of a batch, given the offset and the maximum number of rows, in a
+///   stable order.
+pub fn snapshot_batches<T, F>(connection: PooledDbConnection, mut load: F) -> Batches<T>
+where
+    F: FnMut(&mut PgConnection, i64, i64) -> QueryResult<Vec<T>> + Send + 'static,
+{
+    let mut snapshot = Snapshot { connection, open: false };
+    Box::new(move |offset, limit| {
+        let connection = &mut snapshot.connection;
+        let begin = if offset == 0 {
+            connection.batch_execute("BEGIN ISOLATION LEVEL REPEATABLE READ READ ONLY")
+        } else {
+            Ok(())
+        };
+        snapshot.open = begin.is_ok();
+        let items = begin.and_then(|_| load(connection, offset, limit)).and_then(|items| {
+            if (items.len() as i64) < limit {
+                connection.batch_execute("COMMIT")?;
+                snapshot.open = false;
+            }
+            Ok(items)
+        });
+        if items.is_err() && snapshot.open {
+            connection.batch_execute("ROLLBACK").ok();
+            snapshot.open = false;
+        }
+
+        items.map_err(|e| e.to_string())
+    })
+}
+
+#[test]
+fn test_record_database_server() {
+    let failovers = database_failovers();
+    record_database_server("10.0.0.1:5432 (started 2026-10-01 00:00:00+00)");
+    assert!(!record_database_server("10.0.0.1:5432 (started 2026-10-01 00:00:00+00)"));
+    assert!(record_database_server("10.0.0.2:5432 (started 2026-10-02 00:00:00+00)"));
+    assert!(database_failovers() > failovers);
+}
+
+#[test]
+fn test_retry_delay() {
+    let first_delay = Duration::from_millis(500);
+    assert_eq!(retry_delay(first_delay, 1), first_delay);
+    assert_eq!(retry_delay(first_delay, 2), Duration::from_secs(1));
+    assert_eq!(retry_delay(first_delay, 4), Duration::from_secs(4));
+    assert_eq!(retry_delay(first_delay, 6), MAX_CONNECT_RETRY_DELAY);
+    assert_eq!(retry_delay(first_delay, 100), MAX_CONNECT_RETRY_DELAY);
+}
+
+#[test]
+fn test_check_database_url() {
+    assert!(check_database_url("postgres://user:password@db:5432/jwk_db").is_ok());
+    assert!(check_database_url("postgresql://db1,db2/jwk_db?target_session_attrs=read-write").is_ok());
+    assert!(check_database_url("host=db dbname=jwk_db password='a://b'").is_ok());
+
+    let message = check_database_url("sqlite://jwks.db").unwrap_err();
+    assert!(message.contains("sqlite://"));
+    assert!(check_database_url("mysql://user@db/jwk_db").is_err());
+}
+
+#[test]
+fn test_with_tls_options() {
+    let tls = DatabaseTls {
+        sslmode: Some("verify-full".to_string()),
+        sslrootcert: Some("/etc/ssl/db ca.pem".to_string()),
+        sslcert: Some("/etc/ssl/client.pem".to_string()),
+        sslkey: Some("/etc/ssl/client.key".to_string()),
+    };
 
-    // Establish a connection to the database.
-    PgConnection::establish(&database_url)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
+    assert_eq!(
+        with_tls_options("postgres://user@db:5432/jwk_db", &tls),
+        "postgres://user@db:5432/jwk_db?sslmode=verify-full&sslrootcert=/etc/ssl/db%20ca.pem\
+         &sslcert=/etc/ssl/client.pem&sslkey=/etc/ssl/client.key"
+    );
+    // Parameters of the URL take precedence
+    assert_eq!(
+        with_tls_options("postgres://db/jwk_db?sslmode=require", &DatabaseTls {
+            sslmode: Some("verify-full".to_string()),
+            ..Default::default()
+        }),
+        "postgres://db/jwk_db?sslmode=require"
+    );
+    assert_eq!(
+        with_tls_options("host=db dbname=jwk_db", &tls),
+        "host=db dbname=jwk_db sslmode='verify-full' sslrootcert='/etc/ssl/db ca.pem' \
+         sslcert='/etc/ssl/client.pem' sslkey='/etc/ssl/client.key'"
+    );
+    assert_eq!(with_tls_options("postgres://db/jwk_db", &DatabaseTls::default()), "postgres://db/jwk_db");
 }
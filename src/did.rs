@@ -0,0 +1,145 @@
+//! This module publishes a did:web DID document at `/.well-known/did.json`.
+//!
+//! Every active key is listed as a `JsonWebKey2020` verification method, so a verifiable
+//! credentials stack can reference keys managed here by DID URL (`did:web:<domain>#<kid>`).
+//! Signing keys are authentication and assertion methods, encryption keys key agreement methods.
+
+use crate::log_error;
+use crate::manager::{run_blocking, KeyError};
+use crate::models::{DidDocument, DidVerificationMethod, Jwk};
+use crate::store::KeyStore;
+use actix_web::{web, HttpResponse, Responder};
+use dotenv::dotenv;
+use std::env;
+use std::sync::Arc;
+
+/// JSON-LD contexts of the DID document.
+const DID_CONTEXTS: [&str; 2] = [
+    "https://www.w3.org/ns/did/v1",
+    "https://w3id.org/security/suites/jws-2020/v1",
+];
+
+/// Verification method type of the published keys.
+const VERIFICATION_METHOD_TYPE: &str = "JsonWebKey2020";
+
+/// Returns the DID of the service (`DID_WEB_DOMAIN`), e.g. `did:web:keys.example.com`.
+///
+/// A port in the domain is percent-encoded as the did:web method requires
+/// (`keys.example.com:8443` becomes `did:web:keys.example.com%3A8443`).
+///
+/// # Returns
+///
+/// `None` if the variable is unset or empty (the DID document is disabled).
+pub fn did_web_id() -> Option<String> {
+    dotenv().ok();
+
+    env::var("DID_WEB_DOMAIN")
+        .ok()
+        .map(|domain| domain.trim().to_lowercase())
+        .filter(|domain| !domain.is_empty())
+        .map(|domain| format!("did:web:{}", domain.replace(':', "%3A")))
+}
+
+/// Percent-encodes a key ID for use as the fragment of a DID URL.
+fn encode_fragment(kid: &str) -> String {
+    kid.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Builds the DID document listing the keys.
+///
+/// # Arguments
+///
+/// * `did` - The DID of the service.
+/// * `keys` - Public keys to list as verification methods.
+pub fn did_document(did: &str, keys: Vec<Jwk>) -> DidDocument {
+    let mut document = DidDocument {
+        context: DID_CONTEXTS.iter().map(|context| context.to_string()).collect(),
+        id: did.to_string(),
+        verification_method: Vec::new(),
+        authentication: Vec::new(),
+        assertion_method: Vec::new(),
+        key_agreement: Vec::new(),
+    };
+
+    for key in keys {
+        let method_id = format!("{}#{}", did, encode_fragment(&key.kid));
+        if key.use_ == "enc" {
+            document.key_agreement.push(method_id.clone());
+        } else {
+            document.authentication.push(method_id.clone());
+            document.assertion_method.push(method_id.clone());
+        }
+        document.verification_method.push(DidVerificationMethod {
+            id: method_id,
+            type_: VERIFICATION_METHOD_TYPE.to_string(),
+            controller: did.to_string(),
+            public_key_jwk: key,
+        });
+    }
+
+    document
+}
+
+/// Handles the request to retrieve the did:web DID document of the service.
+///
+/// # Returns
+///
+/// The DID document listing the active keys, or `404 Not Found` if `DID_WEB_DOMAIN` is not set.
+#[utoipa::path(
+    get,
+    path = "/.well-known/did.json",
+    responses(
+        (status = 200, description = "DID document with the active keys as verification methods", body = DidDocument),
+        (status = 404, description = "DID document is not configured", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to load keys", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn did_document_handler(store: web::Data<Arc<dyn KeyStore>>) -> impl Responder {
+    let did = match did_web_id() {
+        Some(did) => did,
+        None => return HttpResponse::NotFound().body("DID document is not configured"),
+    };
+
+    // Same keys as /.well-known/jwks.json
+    let results = run_blocking(move || {
+        store.list_active(None, None).map_err(|error| {
+            log_error!("Failed to load keys: {}", error);
+            KeyError::Internal("Failed to load keys".to_string())
+        })
+    })
+    .await;
+
+    match results {
+        Ok(results) => HttpResponse::Ok().json(did_document(&did, results.into_iter().map(Jwk::from).collect())),
+        Err(error) => HttpResponse::from(error),
+    }
+}
+
+#[test]
+fn test_did_document() {
+    use crate::crypto::generate_jwk_data;
+
+    let mut signing = generate_jwk_data("ES256", 2048).unwrap();
+    signing.kid = "key 1".to_string();
+    let mut encryption = Jwk::from(generate_jwk_data("ES256", 2048).unwrap());
+    encryption.use_ = "enc".to_string();
+    let document = did_document("did:web:keys.example.com", vec![Jwk::from(signing), encryption]);
+
+    assert_eq!(document.context[0], "https://www.w3.org/ns/did/v1");
+    let method = &document.verification_method[0];
+    assert_eq!(method.id, "did:web:keys.example.com#key%201");
+    assert_eq!(method.type_, "JsonWebKey2020");
+    assert_eq!(method.controller, "did:web:keys.example.com");
+    assert_eq!(document.authentication, vec![method.id.clone()]);
+    assert_eq!(document.assertion_method, vec![method.id.clone()]);
+    assert_eq!(document.key_agreement, vec![document.verification_method[1].id.clone()]);
+
+    let json = serde_json::to_value(&document).unwrap();
+    assert!(json["verificationMethod"][0]["publicKeyJwk"].get("d").is_none());
+    assert_eq!(json["verificationMethod"][0]["publicKeyJwk"]["crv"], "P-256");
+}
@@ -0,0 +1,238 @@
+//! This module validates DPoP proofs (RFC 9449) on behalf of resource servers.
+//!
+//! A proof is a JWT with `typ: dpop+jwt` signed by the key carried in its `jwk` header. A valid
+//! proof binds the request method (`htm`) and URI (`htu`) to that key; the key's JWK SHA-256
+//! thumbprint is what access tokens are bound to (`cnf.jkt`).
+
+use crate::crypto::{curve_for_alg, jwk_thumbprint};
+use crate::jws::decode_jws;
+use crate::models::Jwk;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Maximum age of a proof, measured from its `iat` claim.
+pub const DPOP_PROOF_MAX_AGE_SECONDS: i64 = 300;
+
+/// Tolerated clock skew for proofs issued in the future.
+pub const DPOP_CLOCK_SKEW_SECONDS: i64 = 60;
+
+/// `jti` values of accepted proofs with their `iat`, kept until the proofs are too old to be replayed.
+static SEEN_PROOFS: Mutex<Option<HashMap<String, i64>>> = Mutex::new(None);
+
+/// Claims of a validated DPoP proof needed by the caller.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DpopProof {
+    /// JWK SHA-256 thumbprint of the proof key.
+    pub jkt: String,
+    /// Unique identifier of the proof.
+    pub jti: String,
+    /// Issue time of the proof.
+    pub iat: i64,
+}
+
+/// Removes the query and fragment from a URI, as required for `htu` comparison.
+fn strip_query_and_fragment(uri: &str) -> &str {
+    uri.split(['?', '#']).next().unwrap_or(uri)
+}
+
+/// Builds the public key carried in the `jwk` header of a proof.
+///
+/// # Errors
+///
+/// Returns a message if the header has no public key, contains private key material or the key
+/// does not match the algorithm.
+fn proof_key(header: &Value, alg: &str) -> Result<Jwk, String> {
+    let key = header
+        .get("jwk")
+        .and_then(Value::as_object)
+        .ok_or("Proof has no jwk header")?;
+    if key.contains_key("d") {
+        return Err("Proof jwk header must not contain a private key".to_string());
+    }
+
+    let member = |name: &str| key.get(name).and_then(Value::as_str).map(str::to_string);
+    let jwk = Jwk {
+        kty: member("kty").ok_or("Proof jwk header has no kty")?,
+        use_: "sig".to_string(),
+        alg: alg.to_string(),
+        kid: member("kid").unwrap_or_default(),
+        crv: member("crv"),
+        x: member("x"),
+        y: member("y"),
+        n: member("n"),
+        e: member("e"),
+        x5c: None,
+        x5t: None,
+        pub_: None,
+    };
+
+    let matches = match jwk.kty.as_str() {
+        "RSA" => alg.starts_with("RS"),
+        "EC" => curve_for_alg(alg).is_some() && curve_for_alg(alg) == jwk.crv.as_deref(),
+        "OKP" => alg == "EdDSA",
+        _ => false,
+    };
+    if !matches {
+        return Err("Proof algorithm does not match the jwk header".to_string());
+    }
+
+    Ok(jwk)
+}
+
+/// Validates a DPoP proof.
+///
+/// # Arguments
+///
+/// * `proof` - DPoP proof JWT from the `DPoP` request header.
+/// * `htm` - HTTP method of the request the proof was sent with.
+/// * `htu` - HTTP URI of the request the proof was sent with.
+/// * `access_token` - Access token sent with the proof, if any; the proof must then carry its hash (`ath`).
+/// * `now` - Current time in seconds since the Unix epoch.
+///
+/// # Errors
+///
+/// Returns a message describing why the proof is invalid.
+pub fn validate_dpop_proof(
+    proof: &str,
+    htm: &str,
+    htu: &str,
+    access_token: Option<&str>,
+    now: i64,
+) -> Result<DpopProof, String> {
+    let decoded = decode_jws(proof).map_err(|e| format!("Malformed proof: {}", e))?;
+
+    if decoded.header_str("typ") != Some("dpop+jwt") {
+        return Err("Proof typ must be dpop+jwt".to_string());
+    }
+    let alg = decoded.header_str("alg").ok_or("Proof has no alg header")?;
+    let jwk = proof_key(&decoded.header, alg)?;
+
+    match decoded.verify(&jwk) {
+        Ok(true) => {}
+        _ => return Err("Proof signature is invalid".to_string()),
+    }
+
+    let claims = decoded.claims().map_err(|e| format!("Malformed proof: {}", e))?;
+    let claim = |name: &str| claims.get(name).and_then(Value::as_str);
+
+    let jti = claim("jti").filter(|jti| !jti.is_empty()).ok_or("Proof has no jti claim")?;
+    if claim("htm") != Some(htm) {
+        return Err("Proof htm does not match the request method".to_string());
+    }
+    match claim("htu") {
+        Some(proof_htu) if strip_query_and_fragment(proof_htu) == strip_query_and_fragment(htu) => {}
+        _ => return Err("Proof htu does not match the request URI".to_string()),
+    }
+
+    let iat = claims
+        .get("iat")
+        .and_then(Value::as_i64)
+        .ok_or("Proof has no iat claim")?;
+    if iat > now + DPOP_CLOCK_SKEW_SECONDS {
+        return Err("Proof is issued in the future".to_string());
+    }
+    if iat < now - DPOP_PROOF_MAX_AGE_SECONDS {
+        return Err("Proof has expired".to_string());
+    }
+
+    if let Some(access_token) = access_token {
+        let expected = URL_SAFE_NO_PAD.encode(openssl::sha::sha256(access_token.as_bytes()));
+        if claim("ath") != Some(expected.as_str()) {
+            return Err("Proof ath does not match the access token".to_string());
+        }
+    }
+
+    Ok(DpopProof {
+        jkt: jwk_thumbprint(&jwk).map_err(|e| format!("Invalid proof jwk: {}", e))?,
+        jti: jti.to_string(),
+        iat,
+    })
+}
+
+/// Records the proof as used and rejects replays of an already accepted proof.
+///
+/// Entries older than the maximum proof age are pruned, since such proofs are rejected anyway.
+///
+/// # Errors
+///
+/// Returns a message if a proof with the same key and `jti` was already accepted.
+pub fn check_dpop_replay(proof: &DpopProof, now: i64) -> Result<(), String> {
+    let mut seen = SEEN_PROOFS.lock().unwrap();
+    let seen = seen.get_or_insert_with(HashMap::new);
+
+    seen.retain(|_, iat| *iat >= now - DPOP_PROOF_MAX_AGE_SECONDS);
+
+    let key = format!("{}:{}", proof.jkt, proof.jti);
+    if seen.contains_key(&key) {
+        return Err("Proof has already been used".to_string());
+    }
+    seen.insert(key, proof.iat);
+
+    Ok(())
+}
+
+#[cfg(test)]
+fn sign_test_proof(claims: &Value, typ: &str) -> (String, Jwk) {
+    use crate::crypto::{generate_jwk_data, sign_with_jwk};
+
+    let jwk = generate_jwk_data("ES256", 2048).unwrap();
+    let public = Jwk::from(jwk.clone());
+    let header = serde_json::json!({
+        "typ": typ,
+        "alg": "ES256",
+        "jwk": { "kty": "EC", "crv": "P-256", "x": public.x, "y": public.y }
+    });
+
+    let signing_input = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header).unwrap()),
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).unwrap())
+    );
+    let signature = sign_with_jwk(&jwk, signing_input.as_bytes()).unwrap();
+
+    (format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature)), public)
+}
+
+#[test]
+fn test_validate_dpop_proof() {
+    use serde_json::json;
+
+    let ath = URL_SAFE_NO_PAD.encode(openssl::sha::sha256(b"access-token"));
+    let claims = json!({
+        "jti": "proof-1",
+        "htm": "POST",
+        "htu": "https://api.example.com/resource",
+        "iat": 1_000,
+        "ath": ath
+    });
+    let (proof, public) = sign_test_proof(&claims, "dpop+jwt");
+    let uri = "https://api.example.com/resource?page=2";
+
+    let validated = validate_dpop_proof(&proof, "POST", uri, Some("access-token"), 1_010).unwrap();
+    assert_eq!(validated.jkt, jwk_thumbprint(&public).unwrap());
+    assert_eq!(validated.jti, "proof-1");
+
+    assert!(validate_dpop_proof(&proof, "GET", uri, None, 1_010).is_err());
+    assert!(validate_dpop_proof(&proof, "POST", "https://api.example.com/other", None, 1_010).is_err());
+    assert!(validate_dpop_proof(&proof, "POST", uri, Some("other-token"), 1_010).is_err());
+    assert!(validate_dpop_proof(&proof, "POST", uri, None, 1_000 + DPOP_PROOF_MAX_AGE_SECONDS + 1).is_err());
+    assert!(validate_dpop_proof(&proof, "POST", uri, None, 1_000 - DPOP_CLOCK_SKEW_SECONDS - 1).is_err());
+
+    let (wrong_typ, _) = sign_test_proof(&claims, "JWT");
+    assert!(validate_dpop_proof(&wrong_typ, "POST", uri, None, 1_010).is_err());
+}
+
+#[test]
+fn test_check_dpop_replay() {
+    let proof = DpopProof {
+        jkt: "test-check-dpop-replay".to_string(),
+        jti: "proof-1".to_string(),
+        iat: 1_000,
+    };
+
+    assert!(check_dpop_replay(&proof, 1_000).is_ok());
+    assert!(check_dpop_replay(&proof, 1_010).is_err());
+    assert!(check_dpop_replay(&proof, 1_000 + DPOP_PROOF_MAX_AGE_SECONDS + 1).is_ok());
+}
@@ -0,0 +1,256 @@
+//! This module provides the dual-write mode used to migrate the keystore between databases.
+//!
+//! With `DUAL_WRITE_DATABASE_URL` set, every key mutation committed to the primary database is
+//! mirrored to the target database. Keys created before dual writing started are copied with
+//! `POST /jwks/dual-write/sync`, and `GET /jwks/dual-write/report` compares both databases, so
+//! the switch to the target can be made once they are consistent.
+
+#[cfg(feature = "server")]
+use crate::db::establish_connection;
+use crate::db::{database_tls, with_tls_options};
+use crate::log_error;
+use crate::models::{DualWriteReport, JwkData};
+use crate::schema::jwks;
+#[cfg(feature = "server")]
+use actix_web::{web, HttpResponse, Responder};
+use chrono::Utc;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use dotenv::dotenv;
+use std::collections::HashMap;
+use std::env;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use uuid::Uuid;
+
+/// Number of key mutations that could not be mirrored to the target database.
+static MIRROR_FAILURES: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the URL of the dual-write target database (`DUAL_WRITE_DATABASE_URL`).
+///
+/// # Returns
+///
+/// `None` if dual writing is disabled.
+pub fn dual_write_database_url() -> Option<String> {
+    dotenv().ok();
+
+    env::var("DUAL_WRITE_DATABASE_URL")
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+}
+
+/// Connects to the dual-write target database, with the TLS options of the `DUAL_WRITE_DB_`
+/// variables (see [`database_tls`]).
+///
+/// # Returns
+///
+/// `None` if dual writing is disabled.
+pub fn establish_target_connection() -> Option<ConnectionResult<PgConnection>> {
+    dual_write_database_url()
+        .map(|url| PgConnection::establish(&with_tls_options(&url, &database_tls("DUAL_WRITE_DB"))))
+}
+
+/// Copies a key from the primary to the target database.
+///
+/// The key is inserted if it is missing; otherwise its lifecycle state (deletion, expiration,
+/// freezing and token constraints) is updated, as key material never changes.
+///
+/// # Errors
+///
+/// Returns an error if the key cannot be loaded from the primary or stored in the target.
+pub fn copy_key(
+    primary: &mut PgConnection,
+    target: &mut PgConnection,
+    key_id: Uuid,
+) -> QueryResult<()> {
+    let jwk = jwks::table.find(key_id).first::<JwkData>(primary)?;
+    upsert_key(target, &jwk)
+}
+
+/// Stores a key in a database, updating the lifecycle state of an existing key.
+fn upsert_key(connection: &mut PgConnection, jwk: &JwkData) -> QueryResult<()> {
+    diesel::insert_into(jwks::table)
+        .values(jwk.clone())
+        .on_conflict(jwks::id)
+        .do_update()
+        .set((
+            jwks::deleted_at.eq(excluded(jwks::deleted_at)),
+            jwks::private_key_expires_at.eq(excluded(jwks::private_key_expires_at)),
+            jwks::key_expires_at.eq(excluded(jwks::key_expires_at)),
+            jwks::allowed_issuers.eq(excluded(jwks::allowed_issuers)),
+            jwks::allowed_audiences.eq(excluded(jwks::allowed_audiences)),
+            jwks::updated_at.eq(excluded(jwks::updated_at)),
+            jwks::frozen_at.eq(excluded(jwks::frozen_at)),
+            jwks::is_primary.eq(excluded(jwks::is_primary)),
+            jwks::private_key_retrieved_at.eq(excluded(jwks::private_key_retrieved_at)),
+        ))
+        .execute(connection)
+        .map(|_| ())
+}
+
+/// Mirrors a mutated key to the target database if dual writing is enabled.
+///
+/// The primary database stays the source of truth: failures are logged and counted in the
+/// consistency report instead of failing the request.
+///
+/// # Arguments
+///
+/// * `primary` - Connection to the primary database, after the mutation was committed.
+/// * `key_id` - The unique identifier of the mutated key.
+pub fn mirror_key(primary: &mut PgConnection, key_id: Uuid) {
+    let result = match establish_target_connection() {
+        None => return,
+        Some(Ok(mut target)) => copy_key(primary, &mut target, key_id).map_err(|e| e.to_string()),
+        Some(Err(e)) => Err(e.to_string()),
+    };
+
+    if let Err(error) = result {
+        MIRROR_FAILURES.fetch_add(1, Ordering::SeqCst);
+        log_error!("Failed to mirror key {} to the dual-write target: {}", key_id, error);
+    }
+}
+
+/// Copies every key from the primary to the target database, oldest first so that
+/// predecessors of rotated keys exist before their successors.
+///
+/// # Returns
+///
+/// The number of copied keys.
+///
+/// # Errors
+///
+/// Returns an error if a key cannot be loaded or stored.
+pub fn sync_target(primary: &mut PgConnection, target: &mut PgConnection) -> QueryResult<usize> {
+    let keys = jwks::table
+        .order(jwks::created_at.asc())
+        .load::<JwkData>(primary)?;
+
+    target.transaction(|target| {
+        for jwk in &keys {
+            upsert_key(target, jwk)?;
+        }
+        Ok(keys.len())
+    })
+}
+
+/// Compares the keys of the primary and the target database.
+///
+/// # Errors
+///
+/// Returns an error if the keys cannot be loaded.
+pub fn consistency_report(
+    primary: &mut PgConnection,
+    target: &mut PgConnection,
+) -> QueryResult<DualWriteReport> {
+    let primary_keys = jwks::table.order(jwks::created_at.asc()).load::<JwkData>(primary)?;
+    let target_keys = jwks::table
+        .order(jwks::created_at.asc())
+        .load::<JwkData>(target)?
+        .into_iter()
+        .map(|jwk| (jwk.id, jwk))
+        .collect::<HashMap<_, _>>();
+
+    let mut missing = Vec::new();
+    let mut mismatched = Vec::new();
+    for jwk in &primary_keys {
+        match target_keys.get(&jwk.id) {
+            None => missing.push(jwk.id),
+            Some(copy) if copy != jwk => mismatched.push(jwk.id),
+            Some(_) => {}
+        }
+    }
+
+    let mut extra = target_keys
+        .keys()
+        .filter(|key_id| !primary_keys.iter().any(|jwk| jwk.id == **key_id))
+        .copied()
+        .collect::<Vec<_>>();
+    extra.sort();
+
+    Ok(DualWriteReport {
+        checked_at: Utc::now().naive_utc(),
+        primary_keys: primary_keys.len(),
+        target_keys: target_keys.len(),
+        consistent: missing.is_empty() && extra.is_empty() && mismatched.is_empty(),
+        missing,
+        extra,
+        mismatched,
+        mirror_failures: MIRROR_FAILURES.load(Ordering::SeqCst),
+    })
+}
+
+/// Runs an operation on the primary and the target database.
+///
+/// # Returns
+///
+/// `None` if dual writing is disabled, otherwise the result of the operation.
+#[cfg(feature = "server")]
+fn with_databases<T>(
+    operation: impl FnOnce(&mut PgConnection, &mut PgConnection) -> QueryResult<T>,
+) -> Option<Result<T, String>> {
+    let target = establish_target_connection()?;
+    Some(
+        target
+            .map_err(|e| e.to_string())
+            .and_then(|mut target| {
+                operation(&mut establish_connection(), &mut target).map_err(|e| e.to_string())
+            }),
+    )
+}
+
+/// Handles the request for the consistency report of the dual-write databases.
+///
+/// # Returns
+///
+/// A JSON response containing the report, or `404 Not Found` if dual writing is disabled.
+#[cfg(feature = "server")]
+#[utoipa::path(
+    get,
+    path = "/jwks/dual-write/report",
+    responses(
+        (status = 200, description = "Consistency of the primary and target databases", body = DualWriteReport),
+        (status = 404, description = "Dual writing is not configured", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to compare the databases", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn dual_write_report_handler() -> impl Responder {
+    let result = web::block(|| with_databases(consistency_report)).await;
+
+    match result {
+        Ok(None) => HttpResponse::NotFound().body("Dual writing is not configured"),
+        Ok(Some(Ok(report))) => HttpResponse::Ok().json(report),
+        _ => HttpResponse::InternalServerError().body("Failed to compare the databases"),
+    }
+}
+
+/// Handles the request to copy every key to the dual-write target database.
+///
+/// # Returns
+///
+/// A JSON response containing the consistency report after copying, or `404 Not Found` if dual
+/// writing is disabled.
+#[cfg(feature = "server")]
+#[utoipa::path(
+    post,
+    path = "/jwks/dual-write/sync",
+    responses(
+        (status = 200, description = "Keys copied, with the resulting consistency report", body = DualWriteReport),
+        (status = 404, description = "Dual writing is not configured", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to copy the keys", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn dual_write_sync_handler() -> impl Responder {
+    let result = web::block(|| {
+        with_databases(|primary, target| {
+            sync_target(primary, target)?;
+            consistency_report(primary, target)
+        })
+    })
+    .await;
+
+    match result {
+        Ok(None) => HttpResponse::NotFound().body("Dual writing is not configured"),
+        Ok(Some(Ok(report))) => HttpResponse::Ok().json(report),
+        _ => HttpResponse::InternalServerError().body("Failed to copy the keys"),
+    }
+}
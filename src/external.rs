@@ -0,0 +1,279 @@
+//! This module registers external keys.
+//!
+//! External keys are public keys whose private key is held by someone else, typically a partner
+//! that signs tokens this deployment's relying parties verify. They are registered from a public
+//! JWK or an X.509 certificate, published in the JWK Set next to the generated keys and follow
+//! the same lifecycle (expiration, freezing, deletion, audit log and expiry notifications), but
+//! can neither sign nor be rotated, extended or made primary.
+
+use crate::audit::{record_event, request_actor, ACTION_REGISTER};
+use crate::crypto::{jwk_thumbprint, key_details, public_jwk_data, public_key_from_jwk};
+use crate::db::establish_connection;
+use crate::dual_write::mirror_key;
+use crate::manager::{is_kid_conflict, kid_owner};
+use crate::log_error;
+use crate::models::{ExternalKeyInput, Jwk, JwkData};
+use crate::policy::{
+    allowed_algorithms, approved_curves, check_key_purpose, check_purpose_algorithm, key_lifetimes,
+    min_rsa_key_size,
+};
+use crate::schema::jwks;
+use crate::validation::{validate_jwk, validate_kid, ValidationPolicy, SEVERITY_ERROR};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine as _;
+use chrono::Utc;
+use diesel::prelude::*;
+use openssl::x509::X509;
+use serde_json::Value;
+use uuid::Uuid;
+
+/// Builds the stored public parameters of a JWK posted for registration.
+///
+/// `x5c` entries may use standard or URL-safe Base64 and are stored URL-safe like the
+/// certificates of generated keys.
+///
+/// # Errors
+///
+/// Returns a message if the key is invalid or `alg` does not fit it.
+pub fn external_jwk_data(value: &Value, alg: Option<&str>) -> Result<JwkData, String> {
+    let parameter = |name: &str| value.get(name).and_then(Value::as_str).map(str::to_string);
+    let public = Jwk {
+        kty: parameter("kty").unwrap_or_default(),
+        use_: String::new(),
+        alg: String::new(),
+        kid: String::new(),
+        crv: parameter("crv"),
+        x: parameter("x"),
+        y: parameter("y"),
+        n: parameter("n"),
+        e: parameter("e"),
+        x5c: None,
+        x5t: None,
+        pub_: None,
+    };
+    let pkey = public_key_from_jwk(&public).map_err(|e| e.to_string())?;
+    let alg = alg.map(str::to_string).or_else(|| parameter("alg"));
+    let mut jwk = public_jwk_data(&pkey, alg.as_deref()).map_err(|e| e.to_string())?;
+
+    let chain = value
+        .get("x5c")
+        .and_then(Value::as_array)
+        .map(|x5c| {
+            x5c.iter()
+                .map(|certificate| {
+                    let certificate = certificate.as_str().unwrap_or_default();
+                    STANDARD
+                        .decode(certificate)
+                        .or_else(|_| URL_SAFE_NO_PAD.decode(certificate))
+                        .map_err(|_| "x5c entries must be Base64 encoded DER certificates".to_string())
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?;
+    if let Some(chain) = chain.filter(|chain| !chain.is_empty()) {
+        set_certificate_chain(&mut jwk, &chain);
+    }
+    jwk.kid = parameter("kid").unwrap_or_default();
+
+    Ok(jwk)
+}
+
+/// Builds the stored public parameters of a key from its PEM encoded certificate chain, leaf
+/// certificate first.
+///
+/// # Errors
+///
+/// Returns a message if the chain cannot be parsed or the key is unsupported.
+pub fn certificate_jwk_data(pem: &str, alg: Option<&str>) -> Result<JwkData, String> {
+    let chain = X509::stack_from_pem(pem.as_bytes()).map_err(|_| "Invalid PEM certificate chain".to_string())?;
+    let leaf = chain.first().ok_or("Certificate chain is empty")?;
+
+    let pkey = leaf.public_key().map_err(|e| e.to_string())?;
+    let mut jwk = public_jwk_data(&pkey, alg).map_err(|e| e.to_string())?;
+    let chain = chain
+        .iter()
+        .map(|certificate| certificate.to_der())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    set_certificate_chain(&mut jwk, &chain);
+
+    Ok(jwk)
+}
+
+/// Stores a DER certificate chain as `x5c` and the SHA-1 thumbprint of its leaf as `x5t`.
+fn set_certificate_chain(jwk: &mut JwkData, chain: &[Vec<u8>]) {
+    jwk.x5c = Some(chain.iter().map(|der| URL_SAFE_NO_PAD.encode(der)).collect());
+    jwk.x5t = chain.first().map(|leaf| URL_SAFE_NO_PAD.encode(openssl::sha::sha1(leaf)));
+}
+
+/// Handles the request to register an external key.
+///
+/// The key is checked like `/jwks/validate` checks keys and must be permitted by the deployment
+/// policy. It is published until `expires_at`, the expiration of its certificate or the end of
+/// the key lifetimes of its algorithm and purpose. Its key ID must not be used by any other key,
+/// deleted and expired keys included.
+///
+/// # Arguments
+///
+/// * `req` - The request; its `X-Actor` header is recorded in the audit log.
+/// * `input` - The public JWK or certificate chain of the key.
+///
+/// # Returns
+///
+/// The public JWK of the registered key, with its location in the `Location` header.
+#[utoipa::path(
+    post,
+    path = "/jwks/external",
+    request_body(
+        content = ExternalKeyInput,
+        examples(
+            ("JWK" = (summary = "Public JWK of a partner", value = json!({
+                "jwk": {
+                    "kty": "EC",
+                    "crv": "P-256",
+                    "x": "Cs-csi67j2KIxtp-KaEn5RaLPh9wFUpGNpXFElvseO0",
+                    "y": "Hpqc2jQNsGk3ylHW6dX7pVkYyZLfOQ2nM3Vi5hlTp9A",
+                    "alg": "ES256"
+                },
+                "kid": "partner-2026-10",
+                "expires_at": "2027-10-15T00:00:00"
+            }))),
+            ("Certificate" = (summary = "Certificate of a partner", value = json!({
+                "certificate": "-----BEGIN CERTIFICATE-----\n...\n-----END CERTIFICATE-----\n",
+                "alg": "RS256"
+            })))
+        )
+    ),
+    responses(
+        (status = 201, description = "External key registered", body = Jwk),
+        (status = 400, description = "Neither or both of jwk and certificate, an unparsable key, or an unknown purpose", body = String, content_type = "text/plain"),
+        (status = 409, description = "Key ID is already in use", body = String, content_type = "text/plain"),
+        (status = 422, description = "Key is invalid, not permitted by policy or already expired", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to store the key", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn register_external_key_handler(
+    req: HttpRequest,
+    input: web::Json<ExternalKeyInput>,
+) -> impl Responder {
+    let input = input.into_inner();
+    let policy = ValidationPolicy {
+        allowed_algorithms: allowed_algorithms(),
+        min_rsa_key_size: min_rsa_key_size(),
+        approved_curves: approved_curves(),
+    };
+
+    // The posted JWK is validated as posted, so private parameters are rejected
+    let (key, validated) = match (&input.jwk, &input.certificate) {
+        (Some(value), None) => (external_jwk_data(value, input.alg.as_deref()), value.clone()),
+        (None, Some(pem)) => match certificate_jwk_data(pem, input.alg.as_deref()) {
+            Ok(key) => {
+                let value = serde_json::to_value(Jwk::from(key.clone())).unwrap_or_default();
+                (Ok(key), value)
+            }
+            Err(message) => (Err(message), Value::Null),
+        },
+        _ => return HttpResponse::BadRequest().body("Exactly one of jwk and certificate is required"),
+    };
+    let errors = validate_jwk(&validated, &policy)
+        .into_iter()
+        .filter(|finding| finding.severity == SEVERITY_ERROR)
+        .map(|finding| finding.message)
+        .collect::<Vec<_>>();
+    if !errors.is_empty() {
+        return HttpResponse::UnprocessableEntity().body(errors.join("; "));
+    }
+    let key = match key {
+        Ok(key) => key,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+
+    // Purposes are checked with the generation algorithm, which names the curve of EdDSA keys
+    if let Some(key_purpose) = &input.purpose {
+        let algorithm = if key.alg == "EdDSA" { key.crv.clone().unwrap_or_default() } else { key.alg.clone() };
+        if let Err(message) = check_key_purpose(key_purpose).and(check_purpose_algorithm(key_purpose, &algorithm)) {
+            return HttpResponse::BadRequest().body(message);
+        }
+    }
+
+    let kid = match input.kid.clone().filter(|kid| !kid.is_empty()) {
+        Some(kid) => kid,
+        None if !key.kid.is_empty() => key.kid.clone(),
+        None => match jwk_thumbprint(&Jwk::from(key.clone())) {
+            Ok(thumbprint) => thumbprint,
+            Err(_) => return HttpResponse::BadRequest().body("Failed to compute the key thumbprint"),
+        },
+    };
+    if let Err(message) = validate_kid(&kid) {
+        return HttpResponse::BadRequest().body(message);
+    }
+
+    let now = Utc::now().naive_utc();
+    let certificate_not_after = key_details(&key, now).ok().and_then(|details| details.certificate_not_after);
+    if certificate_not_after.is_some_and(|not_after| not_after <= now) {
+        return HttpResponse::UnprocessableEntity().body("Certificate has expired");
+    }
+    let expires_at = input.expires_at.or(certificate_not_after).unwrap_or_else(|| {
+        let (private_key_seconds, key_seconds) = key_lifetimes(&key.alg, input.purpose.as_deref());
+        now + chrono::Duration::seconds(private_key_seconds + key_seconds)
+    });
+    if expires_at <= now {
+        return HttpResponse::UnprocessableEntity().body("expires_at must be in the future");
+    }
+
+    let jwk = JwkData {
+        id: Uuid::new_v4(),
+        kid,
+        private_key: String::new(),
+        created_at: now,
+        private_key_expires_at: None,
+        key_expires_at: Some(expires_at),
+        updated_at: now,
+        purpose: input.purpose,
+        external: true,
+        ..key
+    };
+
+    let connection = &mut establish_connection();
+    match kid_owner(connection, &jwk.kid) {
+        Ok(None) => {}
+        Ok(Some(_)) => return HttpResponse::Conflict().body("Key ID is already in use"),
+        Err(_) => return HttpResponse::InternalServerError().body("Failed to store the key"),
+    }
+    // A concurrent registration can still take the key ID between the check and the insert
+    match diesel::insert_into(jwks::table).values(jwk.clone()).execute(connection) {
+        Ok(_) => {}
+        Err(error) if is_kid_conflict(&error) => return HttpResponse::Conflict().body("Key ID is already in use"),
+        Err(_) => return HttpResponse::InternalServerError().body("Failed to store the key"),
+    }
+    if let Err(error) = record_event(connection, jwk.id, ACTION_REGISTER, request_actor(&req)) {
+        log_error!("Failed to record audit event for key {}: {}", jwk.id, error);
+    }
+    mirror_key(connection, jwk.id);
+
+    HttpResponse::Created()
+        .insert_header(("Location", format!("/jwks/{}", jwk.id)))
+        .json(Jwk::from(jwk))
+}
+
+#[test]
+fn test_external_jwk_data() {
+    use crate::crypto::generate_jwk_data;
+
+    let generated = generate_jwk_data("RS256", 2048).unwrap();
+    let mut value = serde_json::to_value(Jwk::from(generated.clone())).unwrap();
+    let der = URL_SAFE_NO_PAD.decode(&generated.x5c.clone().unwrap()[0]).unwrap();
+    value["x5c"] = serde_json::json!([STANDARD.encode(&der)]);
+
+    let jwk = external_jwk_data(&value, None).unwrap();
+    assert_eq!((&jwk.kid, &jwk.alg, &jwk.n, &jwk.e), (&generated.kid, &generated.alg, &generated.n, &generated.e));
+    assert_eq!((&jwk.x5c, &jwk.x5t), (&generated.x5c, &generated.x5t));
+    assert!(jwk.private_key.is_empty());
+    assert!(external_jwk_data(&value, Some("ES256")).is_err());
+
+    let pem = String::from_utf8(X509::from_der(&der).unwrap().to_pem().unwrap()).unwrap();
+    let jwk = certificate_jwk_data(&pem, Some("RS384")).unwrap();
+    assert_eq!((jwk.alg.as_str(), &jwk.n, &jwk.x5t), ("RS384", &generated.n, &generated.x5t));
+    assert!(certificate_jwk_data("not a certificate", None).is_err());
+}
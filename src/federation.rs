@@ -0,0 +1,131 @@
+//! This module builds the signed JWK Set of OpenID Federation (`signed_jwks_uri`).
+//!
+//! The signed JWK Set is a JWT of type `jwk-set+jwt` carrying the active public keys in its
+//! `keys` claim. It is signed with a designated federation key, so consumers can verify the key
+//! set against the entity's federation keys instead of relying on TLS alone.
+
+use crate::jws::encode_jws;
+use crate::models::{Jwk, JwkData};
+use dotenv::dotenv;
+use serde_json::{json, Value};
+use std::env;
+use std::error::Error;
+use uuid::Uuid;
+
+/// Media type of the signed JWK Set, also used as its `typ` header parameter.
+pub const SIGNED_JWKS_TYPE: &str = "jwk-set+jwt";
+
+/// Configuration of the signed JWK Set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FederationConfig {
+    /// Entity identifier used as `iss` and `sub` (`FEDERATION_ENTITY_ID`).
+    pub entity_id: String,
+    /// ID of the key signing the JWK Set (`FEDERATION_KEY_ID`).
+    pub key_id: Uuid,
+    /// Validity of a signed JWK Set in seconds (`SIGNED_JWKS_LIFETIME_SECONDS`).
+    pub lifetime_seconds: i64,
+}
+
+/// Returns the signed JWK Set configuration from the environment.
+///
+/// # Returns
+///
+/// `None` if `FEDERATION_ENTITY_ID` or `FEDERATION_KEY_ID` is not set (the signed JWK Set is
+/// disabled).
+///
+/// # Panics
+///
+/// This function will panic if `FEDERATION_KEY_ID` is not a UUID or
+/// `SIGNED_JWKS_LIFETIME_SECONDS` is not a positive number.
+pub fn federation_config() -> Option<FederationConfig> {
+    dotenv().ok();
+
+    let entity_id = env::var("FEDERATION_ENTITY_ID")
+        .ok()
+        .filter(|value| !value.trim().is_empty())?;
+    let key_id = env::var("FEDERATION_KEY_ID")
+        .ok()
+        .filter(|value| !value.trim().is_empty())?
+        .trim()
+        .parse()
+        .expect("FEDERATION_KEY_ID must be a UUID");
+    let lifetime_seconds: i64 = env::var("SIGNED_JWKS_LIFETIME_SECONDS")
+        .unwrap_or_else(|_| "86400".to_string())
+        .parse()
+        .ok()
+        .filter(|seconds| *seconds > 0)
+        .expect("SIGNED_JWKS_LIFETIME_SECONDS must be a positive number");
+
+    Some(FederationConfig {
+        entity_id,
+        key_id,
+        lifetime_seconds,
+    })
+}
+
+/// Builds the claims set of a signed JWK Set.
+///
+/// # Arguments
+///
+/// * `config` - Signed JWK Set configuration.
+/// * `keys` - Public keys of the JWK Set.
+/// * `now` - Issue time as a Unix timestamp.
+pub fn signed_jwks_claims(config: &FederationConfig, keys: &[Jwk], now: i64) -> Value {
+    json!({
+        "iss": config.entity_id,
+        "sub": config.entity_id,
+        "iat": now,
+        "exp": now + config.lifetime_seconds,
+        "keys": keys,
+    })
+}
+
+/// Signs the JWK Set with the federation key.
+///
+/// # Arguments
+///
+/// * `signing_key` - Federation key, including its private part.
+/// * `config` - Signed JWK Set configuration.
+/// * `keys` - Public keys of the JWK Set.
+/// * `now` - Issue time as a Unix timestamp.
+///
+/// # Errors
+///
+/// Returns an error if the claims cannot be serialized or signing fails.
+pub fn sign_jwks(
+    signing_key: &JwkData,
+    config: &FederationConfig,
+    keys: &[Jwk],
+    now: i64,
+) -> Result<String, Box<dyn Error>> {
+    let claims = signed_jwks_claims(config, keys, now);
+    encode_jws(signing_key, SIGNED_JWKS_TYPE, &serde_json::to_vec(&claims)?)
+}
+
+#[test]
+fn test_sign_jwks() {
+    use crate::crypto::generate_jwk_data;
+    use crate::jws::decode_jws;
+
+    let signing_key = generate_jwk_data("ES256", 2048).unwrap();
+    let published = generate_jwk_data("Ed25519", 2048).unwrap();
+    let config = FederationConfig {
+        entity_id: "https://op.example.com".to_string(),
+        key_id: signing_key.id,
+        lifetime_seconds: 3600,
+    };
+
+    let token = sign_jwks(&signing_key, &config, &[Jwk::from(published.clone())], 1_700_000_000).unwrap();
+    let decoded = decode_jws(&token).unwrap();
+    assert_eq!(decoded.header_str("typ"), Some(SIGNED_JWKS_TYPE));
+    assert_eq!(decoded.header_str("kid"), Some(signing_key.kid.as_str()));
+    assert!(decoded.verify(&Jwk::from(signing_key)).unwrap());
+
+    let claims = decoded.claims().unwrap();
+    assert_eq!(claims["iss"], "https://op.example.com");
+    assert_eq!(claims["sub"], "https://op.example.com");
+    assert_eq!(claims["iat"], 1_700_000_000);
+    assert_eq!(claims["exp"], 1_700_003_600);
+    assert_eq!(claims["keys"][0]["kid"], published.kid);
+    assert!(claims["keys"][0].get("d").is_none());
+}
@@ -1,64 +1,392 @@
 //! This module contains the request handlers for the JWK microservice.
 
-use crate::crypto::{generate_ec_jwk_data, generate_eddsa_jwk_data, generate_rsa_jwk_data};
-use crate::db::establish_connection;
-use crate::models::{AlgorithmInput, Jwk, JwkData, Jwks};
+use crate::anomalies::{anomaly_detection_enabled, inspect_private_key_access};
+use crate::approvals::{consume_approval, request_approval_token};
+use crate::audit::{
+    record_event, record_private_key_access, request_actor, request_client_address,
+    ACTION_DELETE, ACTION_EXTEND, ACTION_FREEZE, ACTION_RETRIEVE, ACTION_RETRIEVE_REFUSED,
+};
+use crate::cose::{cose_algorithm, encode_cose_key, sign_cwt, COSE_KEY_CONTENT_TYPE};
+use crate::crypto::{
+    certificate_chain_pem, export_private_key, jwk_thumbprint, key_details, key_use_for_alg,
+    spki_fingerprint,
+};
+use crate::db::{checkout, DbPool, PooledDbConnection};
+use crate::dpop::{check_dpop_replay, validate_dpop_proof};
+use crate::dual_write::mirror_key;
+use crate::federation::{federation_config, sign_jwks};
+use crate::http_signatures::{jwks_signature_key_id, sign_response, ResponseSignature};
+use crate::jwe::{encrypt_to_jwk, RecipientKey, SecretDelivery, ENCRYPTION_KEY_HEADER, JOSE_CONTENT_TYPE};
+use crate::jws::{decode_jws, encode_jws, encode_jwt};
+use crate::log_error;
+use crate::manager::{
+    find_active_signing_jwk, find_private_jwk, find_signing_jwk,
+    key_set_selection, run_blocking, CreatedKey, KeyError, KeyManager,
+};
+use crate::models::{
+    AccessTokenInput, AlgorithmInput, BulkTransitionInput, BulkTransitionReport, CwtSignInput,
+    DeletedJwk, DpopValidationInput, DpopValidationOutput, ExpiringJwk, ExpiringQuery, ExportQuery,
+    ExtendKeyInput, Jwk, JwkData, JwkDetails, JwkFinding, JwkValidationReport, Jwks, JwksChanges,
+    JwksChangesQuery, JwksDiff, JwksDiffInput, JwksQuery, KeyExtension, KeyFingerprint, KeyVersion,
+    PageQuery, PasetoSignInput, RequestObjectInput, SdJwtIssueInput, SignInput, SignOutput,
+    SoftwareStatementInput, TokenExchangeInput, TokenExchangeOutput,
+};
+use crate::pagination::{page_response, Page};
+use crate::paseto::{is_v4_public_key, sign_v4_public};
+use crate::policy::{
+    allowed_algorithms, approved_curves, check_key_purpose, check_token_constraints,
+    extend_private_key_expiration, key_lifetimes, max_private_key_extension_seconds,
+    min_rsa_key_size,
+};
+use crate::public_only_mode;
+use crate::saml::{saml_metadata, saml_metadata_config};
 use crate::schema::jwks::dsl::*;
-use actix_web::{web, HttpResponse, Responder};
+use crate::sdjwt::{issue_sd_jwt, DEFAULT_SD_JWT_TYPE};
+use crate::store::{KeyStore, LifecycleChange};
+use crate::streaming::{stream_listing, Listing};
+use crate::tokens::{
+    access_token_claims, check_token_times, exchanged_token_claims,
+    request_object_claims, software_statement_claims, software_statement_key_id,
+    software_statement_template, DEFAULT_ACCESS_TOKEN_LIFETIME_SECONDS, GRANT_TYPE_TOKEN_EXCHANGE,
+    TOKEN_TYPE_ACCESS_TOKEN, TOKEN_TYPE_JWT,
+};
+use crate::validation::{validate_jwk, ValidationPolicy, SEVERITY_ERROR, SEVERITY_WARNING};
+use actix_web::{web, HttpRequest, HttpResponse, HttpResponseBuilder, Responder};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine as _;
 use chrono::Utc;
+use std::sync::Arc;
 use diesel::prelude::*;
-use dotenv::dotenv;
-use std::env;
 use uuid::Uuid;
 
 /// Handles the request to retrieve a list of active JWKs.
 ///
+/// With `at`, the key set active at that instant is reconstructed from the key lifecycle
+/// timestamps: keys created before it that were neither deleted nor expired at that time. With
+/// `purpose`, only the keys of that purpose are listed.
+///
+/// # Arguments
+///
+/// * `query` - The optional instant to reconstruct the key set for.
+///
 /// # Returns
 ///
 /// A JSON response containing the list of active JWKs.
 #[utoipa::path(
     get,
     path = "/.well-known/jwks.json",
+    params(JwksQuery),
     responses(
-        (status = 200, description = "Список JWK", body = Jwks)
+        (status = 200, description = "Список JWK", body = Jwks, example = json!({
+            "keys": [
+                {
+                    "kty": "EC",
+                    "use": "sig",
+                    "alg": "ES256",
+                    "kid": "884bd577-a3be-430c-b915-522124544ad0",
+                    "crv": "P-256",
+                    "x": "Cs-csi67j2KIxtp-KaEn5RaLPh9wFUpGNpXFElvseO0",
+                    "y": "Hpqc2jQNsGk3ylHW6dX7pVkYyZLfOQ2nM3Vi5hlTp9A"
+                },
+                {
+                    "kty": "OKP",
+                    "use": "sig",
+                    "alg": "EdDSA",
+                    "kid": "074a5fe1-fd38-4b60-9047-88b7f9192462",
+                    "crv": "Ed25519",
+                    "x": "sBh9QyOizHoYpyLEKORJQGDmCeDkuFkOGhOrw-pGjV8"
+                }
+            ]
+        }),
+            headers(("Content-Digest" = String, description = "SHA-256 digest of the body (RFC 9530), if responses are signed"),
+                ("Signature-Input" = String, description = "Parameters of the `jwks` HTTP message signature (RFC 9421), if responses are signed"),
+                ("Signature" = String, description = "The `jwks` HTTP message signature (RFC 9421), if responses are signed"))),
+        (status = 400, description = "Invalid at or unknown purpose", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to load the keys or sign the JWK Set response", body = String, content_type = "text/plain")
     )
 )]
-pub async fn jwks_handler() -> impl Responder {
-    let connection = &mut establish_connection();
+pub async fn jwks_handler(store: web::Data<Arc<dyn KeyStore>>, query: web::Query<JwksQuery>) -> HttpResponse {
+    // Signed responses cover the whole body, so they cannot be streamed
+    if let Some(signing_key_id) = jwks_signature_key_id() {
+        let jwks_list = match KeyManager::with_store(store.get_ref().clone(), None).list(&query).await {
+            Ok(jwks_list) => jwks_list,
+            Err(error) => return HttpResponse::from(error),
+        };
+        return match run_blocking(move || sign_jwks_response(&store, signing_key_id, &jwks_list)).await {
+            Ok((body, signed)) => HttpResponse::Ok()
+                .content_type("application/json")
+                .insert_header(("Content-Digest", signed.content_digest))
+                .insert_header(("Signature-Input", signed.signature_input))
+                .insert_header(("Signature", signed.signature))
+                .body(body),
+            Err(error) => HttpResponse::from(error),
+        };
+    }
 
-    // Return only active keys (deleted_at IS NULL and key_expires_at > NOW)
-    let results = jwks
-        .filter(deleted_at.is_null())
-        .filter(key_expires_at.gt(Utc::now().naive_utc()))
-        .load::<JwkData>(connection)
-        .expect("Error loading jwks");
+    let (at, key_purpose) = match key_set_selection(&query) {
+        Ok(selection) => selection,
+        Err(error) => return HttpResponse::from(error),
+    };
 
-    let public_jwks = results
-        .into_iter()
-        .map(|jwk| Jwk {
-            kty: jwk.kty,
-            use_: "sig".to_string(),
-            alg: jwk.alg,
-            kid: jwk.kid,
-            crv: jwk.crv,
-            x: jwk.x,
-            y: jwk.y,
-            n: jwk.n,
-            e: jwk.e,
-            x5c: jwk.x5c,
-            x5t: jwk.x5t,
+    let listing = Listing {
+        content_type: "application/json",
+        prefix: "{\"keys\":[".to_string(),
+        separator: ",",
+        suffix: "]}",
+    };
+    // The store opens its view of the keys on the blocking thread pool
+    let batches = run_blocking(move || {
+        store.active_batches(at, key_purpose).map_err(|error| {
+            log_error!("Failed to load keys: {}", error);
+            KeyError::Internal("Failed to load keys".to_string())
         })
-        .collect::<Vec<_>>();
+    })
+    .await;
+    let mut batches = match batches {
+        Ok(batches) => batches,
+        Err(error) => return HttpResponse::from(error),
+    };
+    let load = Box::new(move |offset: i64, limit: i64| {
+        batches(offset, limit).map(|results| {
+            results
+                .into_iter()
+                .map(|jwk| serde_json::to_string(&Jwk::from(jwk)).unwrap_or_default())
+                .collect()
+        })
+    });
+    stream_listing(listing, load)
+        .await
+        .unwrap_or_else(|_| HttpResponse::InternalServerError().body("Failed to load keys"))
+}
+
+/// Serializes the JWK Set and signs it with an HTTP message signature (RFC 9421) by the
+/// designated key.
+///
+/// # Errors
+///
+/// Returns [`KeyError::Internal`] if the designated key cannot sign, so that consumers never
+/// receive an unsigned key set they expect to be signed.
+fn sign_jwks_response(
+    store: &Arc<dyn KeyStore>,
+    signing_key_id: Uuid,
+    jwks_list: &Jwks,
+) -> Result<(Vec<u8>, ResponseSignature), KeyError> {
+    let failed = || KeyError::Internal("Failed to sign the JWK Set response".to_string());
+    let signing_key = match find_signing_jwk(store, signing_key_id) {
+        Ok(signing_key) if key_use_for_alg(&signing_key.alg) == "sig" => signing_key,
+        _ => {
+            log_error!("JWK Set signing key {} is not usable for signing", signing_key_id);
+            return Err(failed());
+        }
+    };
+
+    let body = serde_json::to_vec(jwks_list).expect("Error serializing jwks");
+    match sign_response(&signing_key, 200, "application/json", &body, Utc::now().timestamp()) {
+        Ok(signed) => Ok((body, signed)),
+        Err(error) => {
+            log_error!("Failed to sign the JWK Set response: {}", error);
+            Err(failed())
+        }
+    }
+}
+
+/// Handles the request to list the changes of the active key set since a point in time.
+///
+/// Pollers pass the returned cursor as `since` of the next request and apply the delta to
+/// their copy of `/.well-known/jwks.json`. Keys that were added and removed within the same
+/// interval are not reported. Published key parameters never change once a key is created, so
+/// `changed` is currently always empty.
+///
+/// # Arguments
+///
+/// * `query` - The time changes are reported since.
+///
+/// # Returns
+///
+/// A JSON response containing the changes and the next cursor, or an error message.
+#[utoipa::path(
+    get,
+    path = "/jwks/changes",
+    params(JwksChangesQuery),
+    responses(
+        (status = 200, description = "Changes since the given time", body = JwksChanges, example = json!({
+            "added": [
+                {
+                    "kty": "OKP",
+                    "use": "sig",
+                    "alg": "EdDSA",
+                    "kid": "074a5fe1-fd38-4b60-9047-88b7f9192462",
+                    "crv": "Ed25519",
+                    "x": "sBh9QyOizHoYpyLEKORJQGDmCeDkuFkOGhOrw-pGjV8"
+                }
+            ],
+            "removed": ["884bd577-a3be-430c-b915-522124544ad0"],
+            "changed": [],
+            "cursor": "2026-10-15T12:00:00.000000Z"
+        })),
+        (status = 400, description = "Invalid since", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to load keys", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn jwks_changes_handler(store: web::Data<Arc<dyn KeyStore>>, query: web::Query<JwksChangesQuery>) -> impl Responder {
+    let since = match query.since_time() {
+        Ok(since) => since,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+
+    let changes = run_blocking(move || {
+        let now = Utc::now().naive_utc();
+        match store.changes(since, now) {
+            Ok((added, removed)) => Ok(JwksChanges {
+                added: added.into_iter().map(Jwk::from).collect(),
+                removed,
+                changed: Vec::new(),
+                cursor: now.and_utc().to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
+            }),
+            Err(error) => {
+                log_error!("Failed to load key changes: {}", error);
+                Err(KeyError::Internal("Failed to load keys".to_string()))
+            }
+        }
+    })
+    .await;
+
+    match changes {
+        Ok(changes) => HttpResponse::Ok().json(changes),
+        Err(error) => HttpResponse::from(error),
+    }
+}
+
+/// Handles the request to compare a JWKS document with the active key set.
+///
+/// # Arguments
+///
+/// * `input` - The JWKS document, e.g. as served by a downstream cache or CDN.
+///
+/// # Returns
+///
+/// A JSON response listing the missing, extra and changed key IDs, or an error message.
+#[utoipa::path(
+    post,
+    path = "/jwks/diff",
+    request_body(
+        content = JwksDiffInput,
+        example = json!({
+            "keys": [
+                {
+                    "kty": "EC",
+                    "use": "sig",
+                    "alg": "ES256",
+                    "kid": "884bd577-a3be-430c-b915-522124544ad0",
+                    "crv": "P-256",
+                    "x": "Cs-csi67j2KIxtp-KaEn5RaLPh9wFUpGNpXFElvseO0",
+                    "y": "Hpqc2jQNsGk3ylHW6dX7pVkYyZLfOQ2nM3Vi5hlTp9A"
+                }
+            ]
+        })
+    ),
+    responses(
+        (status = 200, description = "Differences to the active key set", body = JwksDiff, example = json!({
+            "missing": ["074a5fe1-fd38-4b60-9047-88b7f9192462"],
+            "extra": [],
+            "changed": []
+        })),
+        (status = 400, description = "A key is not an object or has no kid", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to load keys", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn jwks_diff_handler(store: web::Data<Arc<dyn KeyStore>>, input: web::Json<JwksDiffInput>) -> impl Responder {
+    // Compare with the same set as published by /.well-known/jwks.json
+    let results = run_blocking(move || {
+        store.list_active(None, None).map_err(|error| {
+            log_error!("Failed to load keys: {}", error);
+            KeyError::Internal("Failed to load keys".to_string())
+        })
+    })
+    .await;
+    let results = match results {
+        Ok(results) => results,
+        Err(error) => return HttpResponse::from(error),
+    };
+
+    let active = results.into_iter().map(Jwk::from).collect::<Vec<_>>();
+    match JwksDiff::compare(active, &input.keys) {
+        Ok(diff) => HttpResponse::Ok().json(diff),
+        Err(message) => HttpResponse::BadRequest().body(message),
+    }
+}
+
+/// Handles the request to validate an externally supplied JWK.
+///
+/// The JWK is checked for RFC 7517/7518 compliance, weak parameters and violations of the
+/// deployment policy (`ALLOWED_ALGORITHMS`, `MIN_RSA_KEY_SIZE`, `APPROVED_CURVES`). A `kid`
+/// already used by a stored key is reported as a `kid_in_use` warning, since another key cannot
+/// be registered under it.
+///
+/// # Arguments
+///
+/// * `input` - The JWK to validate.
+///
+/// # Returns
+///
+/// A JSON response containing the validation report.
+#[utoipa::path(
+    post,
+    path = "/jwks/validate",
+    request_body(
+        content = Object,
+        description = "JWK to validate",
+        example = json!({
+            "kty": "EC",
+            "use": "sig",
+            "alg": "ES256",
+            "kid": "partner-key-1",
+            "crv": "P-256",
+            "x": "Cs-csi67j2KIxtp-KaEn5RaLPh9wFUpGNpXFElvseO0",
+            "y": "Hpqc2jQNsGk3ylHW6dX7pVkYyZLfOQ2nM3Vi5hlTp9A"
+        })
+    ),
+    responses(
+        (status = 200, description = "Validation report", body = JwkValidationReport, example = json!({
+            "valid": false,
+            "findings": [
+                {"severity": "error", "code": "weak_key", "message": "RSA key size 1024 is below the minimum of 2048 bits"},
+                {"severity": "warning", "code": "missing_kid", "message": "kid should be set so the key can be selected"}
+            ]
+        }))
+    )
+)]
+pub async fn validate_jwk_handler(store: web::Data<Arc<dyn KeyStore>>, input: web::Json<serde_json::Value>) -> impl Responder {
+    let policy = ValidationPolicy {
+        allowed_algorithms: allowed_algorithms(),
+        min_rsa_key_size: min_rsa_key_size(),
+        approved_curves: approved_curves(),
+    };
 
-    let jwks_list = Jwks { keys: public_jwks };
+    let mut findings = validate_jwk(&input, &policy);
+    if let Some(key_id) = input.get("kid").and_then(serde_json::Value::as_str) {
+        let key_id = key_id.to_string();
+        let owner = run_blocking(move || Ok(store.get_by_kid(&key_id).ok().flatten().map(|jwk| jwk.id))).await;
+        match owner {
+            Ok(Some(owner)) => findings.push(JwkFinding {
+                severity: SEVERITY_WARNING.to_string(),
+                code: "kid_in_use".to_string(),
+                message: format!("kid is already used by key {}", owner),
+            }),
+            Ok(None) => {}
+            Err(error) => return HttpResponse::from(error),
+        }
+    }
+    let valid = !findings.iter().any(|finding| finding.severity == SEVERITY_ERROR);
 
-    HttpResponse::Ok().json(jwks_list)
+    HttpResponse::Ok().json(JwkValidationReport { valid, findings })
 }
 
 /// Handles the request to add a new JWK.
 ///
 /// # Arguments
 ///
+/// * `req` - The request; its `X-Actor` header is recorded in the audit log.
 /// * `input` - The input data containing the algorithm for key generation.
 ///
 /// # Returns
@@ -67,76 +395,368 @@ pub async fn jwks_handler() -> impl Responder {
 #[utoipa::path(
     post,
     path = "/jwks",
-    request_body = AlgorithmInput,
+    request_body(
+        content = AlgorithmInput,
+        examples(
+            ("RS256" = (summary = "RSA key", value = json!({"alg": "RS256", "key_size": 3072}))),
+            ("ES256" = (summary = "EC key on P-256", value = json!({"alg": "ES256"}))),
+            ("EdDSA" = (summary = "Ed25519 key", value = json!({"alg": "EdDSA", "crv": "Ed25519"}))),
+            ("Constrained" = (summary = "Key restricted to an issuer and audience", value = json!({
+                "alg": "ES384",
+                "allowed_issuers": ["https://auth.example.com"],
+                "allowed_audiences": ["https://api.example.com"]
+            })))
+        )
+    ),
     responses(
-        (status = 201, description = "JWK successfully added", body = Jwk)
+        (status = 201, description = "JWK successfully added", body = Jwk, examples(
+            ("ES256" = (value = json!({
+                "kty": "EC",
+                "use": "sig",
+                "alg": "ES256",
+                "kid": "884bd577-a3be-430c-b915-522124544ad0",
+                "crv": "P-256",
+                "x": "Cs-csi67j2KIxtp-KaEn5RaLPh9wFUpGNpXFElvseO0",
+                "y": "Hpqc2jQNsGk3ylHW6dX7pVkYyZLfOQ2nM3Vi5hlTp9A"
+            }))),
+            ("EdDSA" = (value = json!({
+                "kty": "OKP",
+                "use": "sig",
+                "alg": "EdDSA",
+                "kid": "074a5fe1-fd38-4b60-9047-88b7f9192462",
+                "crv": "Ed25519",
+                "x": "sBh9QyOizHoYpyLEKORJQGDmCeDkuFkOGhOrw-pGjV8"
+            })))
+        )),
+        (status = 200, description = "Existing usable key returned because `reuse_active` is set (the public key for burn-after-read and sensitive keys)", body = JwkData),
+        (status = 400, description = "Unsupported algorithm or curve, or unknown purpose", body = String, content_type = "text/plain"),
+        (status = 422, description = "Algorithm or key strength is not permitted by policy", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to generate, verify or store the key", body = String, content_type = "text/plain")
     )
 )]
-pub async fn add_jwk_handler(input: web::Json<AlgorithmInput>) -> impl Responder {
-    dotenv().ok();
+pub async fn add_jwk_handler(store: web::Data<Arc<dyn KeyStore>>, req: HttpRequest, input: web::Json<AlgorithmInput>) -> impl Responder {
+    match KeyManager::with_store(store.get_ref().clone(), request_actor(&req)).create(&input).await {
+        Ok(CreatedKey { key, reused: true }) => key_response(HttpResponse::Ok(), key),
+        Ok(CreatedKey { key, reused: false }) => key_response(HttpResponse::Created(), key),
+        Err(error) => HttpResponse::from(error),
+    }
+}
+
+impl From<KeyError> for HttpResponse {
+    /// Responds with the status of the error and its message.
+    fn from(error: KeyError) -> HttpResponse {
+        let mut response = match error {
+            KeyError::Invalid(_) => HttpResponse::BadRequest(),
+            KeyError::Forbidden(_) => HttpResponse::Forbidden(),
+            KeyError::NotFound(_) => HttpResponse::NotFound(),
+            KeyError::Conflict(_) => HttpResponse::Conflict(),
+            KeyError::Gone(_) => HttpResponse::Gone(),
+            KeyError::Policy(_) => HttpResponse::UnprocessableEntity(),
+            KeyError::Frozen(_) => HttpResponse::Locked(),
+            KeyError::Unavailable(_) => HttpResponse::ServiceUnavailable(),
+            KeyError::Internal(_) => HttpResponse::InternalServerError(),
+        };
+        response.body(error.to_string())
+    }
+}
+
+/// Checks a connection out of the pool.
+///
+/// # Errors
+///
+/// Returns [`KeyError::Unavailable`] (`503 Service Unavailable`) if no connection becomes
+/// available in time.
+pub fn pooled_connection(pool: &DbPool) -> Result<PooledDbConnection, KeyError> {
+    checkout(pool).map_err(|error| {
+        log_error!("{}", error);
+        KeyError::Unavailable("Database unavailable".to_string())
+    })
+}
 
-    let algorithm = &input.alg;
+/// Loads a key published in the JWK Set from the key store.
+///
+/// # Errors
+///
+/// Returns [`KeyError::NotFound`] if the key does not exist or is not published.
+fn published_key(store: &Arc<dyn KeyStore>, key_id: Uuid) -> Result<JwkData, KeyError> {
+    let jwk = store.get_by_id(key_id).map_err(|error| {
+        log_error!("Failed to load key {}: {}", key_id, error);
+        KeyError::Internal("Failed to load key".to_string())
+    })?;
+    jwk.filter(|jwk| jwk.is_published(None, Utc::now().naive_utc()))
+        .ok_or_else(|| KeyError::NotFound("Key not found".to_string()))
+}
 
-    // Get expiration times from environment variables
-    let private_key_expiration_seconds: i64 = env::var("PRIVATE_KEY_EXPIRATION_SECONDS")
-        .unwrap_or_else(|_| "86400".to_string()) // По умолчанию 1 день
-        .parse()
-        .expect("PRIVATE_KEY_EXPIRATION_SECONDS must be a number");
+/// Loads a key that is not deleted from the key store.
+///
+/// # Errors
+///
+/// Returns [`KeyError::Internal`] if the key cannot be loaded.
+fn stored_key(store: &Arc<dyn KeyStore>, key_id: Uuid) -> Result<Option<JwkData>, KeyError> {
+    let jwk = store.get_private(key_id).map_err(|error| {
+        log_error!("Failed to load key {}: {}", key_id, error);
+        KeyError::Internal("Failed to load key".to_string())
+    })?;
+    Ok(jwk.filter(|jwk| jwk.deleted_at.is_none()))
+}
 
-    let key_expiration_seconds: i64 = env::var("KEY_EXPIRATION_SECONDS")
-        .unwrap_or_else(|_| "172800".to_string()) // По умолчанию 2 дня
-        .parse()
-        .expect("KEY_EXPIRATION_SECONDS must be a number");
+/// Builds the response of a created, reused or rotated key.
+///
+/// Burn-after-read and sensitive keys are returned without their private part, which is only
+/// delivered by [`get_jwk_by_id_handler`] or [`export_jwk_handler`] (once, or with an approval).
+/// In public-only mode, no key is returned with its private part.
+fn key_response(mut builder: HttpResponseBuilder, jwk: JwkData) -> HttpResponse {
+    if jwk.burn_after_read || jwk.sensitive || public_only_mode() {
+        builder.json(Jwk::from(jwk))
+    } else {
+        builder.json(jwk)
+    }
+}
 
-    // Generate keys based on the algorithm
-    let jwk_key = match algorithm.as_str() {
-        "RS256" | "RS384" | "RS512" => generate_rsa_jwk_data(2048, algorithm.as_str()).unwrap(),
-        "ES256" | "ES384" | "ES512" => generate_ec_jwk_data(algorithm.as_str()).unwrap(),
-        "Ed25519" | "Ed448" => generate_eddsa_jwk_data(algorithm.as_str()).unwrap(),
-        _ => return HttpResponse::BadRequest().body("Unsupported algorithm"),
+/// Handles the request to retrieve a JWK by its ID.
+/// (including private part)
+///
+/// Every successful response is recorded in the private key access log; the key is not returned
+/// if the access cannot be recorded. Sensitive keys require a one-time approval token in the
+/// `X-Approval-Token` header.
+///
+/// If the request carries a public JWK in the `X-Encryption-Key` header (typically an ephemeral
+/// key of the caller), the response is encrypted to it as a JWE, so the private key does not
+/// appear in plaintext in proxies and logs along the path. With `OCT_KEYS_REQUIRE_ENCRYPTION=1`,
+/// the secret of a symmetric key is only returned this way.
+///
+/// # Arguments
+///
+/// * `delivery` - Whether the secrets of symmetric keys must be encrypted.
+/// * `req` - The request; its actor and client address are recorded in the access log.
+/// * `key_id` - The unique identifier of the key.
+///
+/// # Returns
+///
+/// A JSON response containing the JWK, its JWE if an encryption key was supplied, or an error
+/// message.
+#[utoipa::path(
+    get,
+    path = "/jwks/{id}",
+    params(
+        ("id" = String, Path, description = "Unique key identifier"),
+        ("X-Encryption-Key" = Option<String>, Header, description = "Public EC (ECDH-ES) or RSA (RSA-OAEP-256) JWK, as JSON or base64url encoded JSON, the response is encrypted to")
+    ),
+    responses(
+        (status = 200, description = "Key found, with the information derived from it; encrypted as a compact JWE (A256GCM) if `X-Encryption-Key` is supplied", content(
+            ("application/json" = JwkDetails),
+            ("application/jose" = String)
+        )),
+        (status = 400, description = "Invalid or unsupported encryption key, or a symmetric key requested without `X-Encryption-Key` while `OCT_KEYS_REQUIRE_ENCRYPTION` is set", body = String, content_type = "text/plain"),
+        (status = 403, description = "Key is sensitive and the `X-Approval-Token` header is missing or invalid", body = String, content_type = "text/plain"),
+        (status = 404, description = "Key not found", body = String, content_type = "text/plain"),
+        (status = 409, description = "Private key of a burn-after-read key has already been retrieved, or the key is external", body = String, content_type = "text/plain"),
+        (status = 410, description = "Private key expired", body = String, content_type = "text/plain"),
+        (status = 423, description = "Key is frozen", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to decode the stored key or to record the access", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn get_jwk_by_id_handler(
+    store: web::Data<Arc<dyn KeyStore>>,
+    pool: Option<web::Data<DbPool>>,
+    delivery: web::Data<SecretDelivery>,
+    req: HttpRequest,
+    key_id: web::Path<Uuid>,
+) -> impl Responder {
+    let recipient = match req.headers().get(ENCRYPTION_KEY_HEADER) {
+        Some(value) => match value
+            .to_str()
+            .map_err(|_| "Invalid encryption key".to_string())
+            .and_then(RecipientKey::parse)
+        {
+            Ok(recipient) => Some(recipient),
+            Err(message) => return HttpResponse::BadRequest().body(message),
+        },
+        None => None,
     };
+    let requester = Requester::from(&req);
+    let key_id = key_id.into_inner();
 
-    // Current time
-    let now = Utc::now().naive_utc();
+    let released = run_blocking(move || {
+        let mut jwk_result = find_private_jwk(&store, key_id)?;
+        if jwk_result.is_symmetric() && delivery.require_encryption && recipient.is_none() {
+            return Err(KeyError::Invalid(format!(
+                "The secret of a symmetric key is only returned encrypted, supply the {} header",
+                ENCRYPTION_KEY_HEADER
+            )));
+        }
+        let details = key_details(&jwk_result, Utc::now().naive_utc())
+            .map_err(|_| KeyError::Internal("Failed to decode the stored key".to_string()))?;
+        // The secret of a symmetric key is returned once, as `k`
+        let k = jwk_result.is_symmetric().then(|| std::mem::take(&mut jwk_result.private_key));
+        let jwk_details = JwkDetails { jwk: jwk_result, k, details };
 
-    // Create a new JWK
-    let jwk = JwkData {
-        id: Uuid::new_v4(),
-        kty: jwk_key.kty,
-        alg: jwk_key.alg,
-        crv: jwk_key.crv,
-        kid: jwk_key.kid,
-        x: jwk_key.x,
-        y: jwk_key.y,
-        n: jwk_key.n,
-        e: jwk_key.e,
-        x5c: jwk_key.x5c,
-        x5t: jwk_key.x5t,
-        private_key: jwk_key.private_key,
-        created_at: now,
-        deleted_at: None,
-        private_key_expires_at: Some(
-            now + chrono::Duration::seconds(private_key_expiration_seconds),
-        ),
-        key_expires_at: Some(
-            now + chrono::Duration::seconds(
-                private_key_expiration_seconds + key_expiration_seconds,
+        // Encrypt before releasing, so a burn-after-read key is not used up by a failed encryption
+        let encrypted = match &recipient {
+            Some(recipient) => Some(
+                serde_json::to_vec(&jwk_details)
+                    .map_err(Box::from)
+                    .and_then(|payload| encrypt_to_jwk(recipient, &payload))
+                    .map_err(|_| KeyError::Internal("Failed to encrypt the response".to_string()))?,
             ),
-        ),
+            None => None,
+        };
+        release_private_key(pool.as_ref().map(|pool| pool.get_ref()), &store, &requester, &jwk_details.jwk, "/jwks/{id}")?;
+
+        Ok((jwk_details, encrypted))
+    })
+    .await;
+
+    match released {
+        Ok((_, Some(jwe))) => HttpResponse::Ok().content_type(JOSE_CONTENT_TYPE).body(jwe),
+        Ok((jwk_details, None)) => HttpResponse::Ok().json(jwk_details),
+        Err(error) => HttpResponse::from(error),
+    }
+}
+
+/// Caller of a request releasing a private key, taken from the request before the release is
+/// recorded on the blocking thread pool.
+struct Requester {
+    /// The `X-Actor` header of the request.
+    actor: Option<String>,
+    /// The address of the client.
+    client_address: Option<String>,
+    /// The `X-Approval-Token` header of the request.
+    approval_token: Option<String>,
+}
+
+impl From<&HttpRequest> for Requester {
+    fn from(req: &HttpRequest) -> Self {
+        Requester {
+            actor: request_actor(req),
+            client_address: request_client_address(req),
+            approval_token: request_approval_token(req).map(str::to_string),
+        }
+    }
+}
+
+/// Reason the private key of a key is not released.
+enum Refusal {
+    /// The key is sensitive and the request carries no valid approval token.
+    ApprovalRequired,
+    /// The private key of the burn-after-read key has already been retrieved.
+    AlreadyRetrieved,
+    /// The retrieval could not be recorded.
+    Database(diesel::result::Error),
+}
+
+impl From<diesel::result::Error> for Refusal {
+    fn from(error: diesel::result::Error) -> Self {
+        Refusal::Database(error)
+    }
+}
+
+/// Records a response containing the private key of a key in the access log.
+///
+/// The approval token of a sensitive key is used up and the single retrieval of a
+/// burn-after-read key is claimed in the same transaction, so that concurrent requests cannot
+/// both receive the private key; the retrieval and every refused later attempt of a
+/// burn-after-read key are recorded in the audit log. Recorded accesses are checked for
+/// anomalies (see [`crate::anomalies`]).
+///
+/// Without a database (in-memory storage), approvals and the access log are not available:
+/// sensitive keys are not released and the retrieval of a burn-after-read key is claimed in the
+/// store.
+///
+/// # Errors
+///
+/// Returns [`KeyError::Forbidden`] if the key is sensitive and the request carries no valid
+/// approval token, [`KeyError::Conflict`] if the private key of a burn-after-read key has already
+/// been retrieved, and [`KeyError::Internal`] if the access cannot be recorded, so that no
+/// private key is returned without an access record.
+fn release_private_key(
+    pool: Option<&DbPool>,
+    store: &Arc<dyn KeyStore>,
+    requester: &Requester,
+    jwk: &JwkData,
+    endpoint: &str,
+) -> Result<(), KeyError> {
+    let Some(pool) = pool else {
+        if jwk.sensitive {
+            return Err(KeyError::Forbidden("A valid approval token is required for this key".to_string()));
+        }
+        if jwk.burn_after_read {
+            let claimed = store.claim_retrieval(jwk.id).map_err(|error| {
+                log_error!("Failed to claim the retrieval of key {}: {}", jwk.id, error);
+                KeyError::Internal("Failed to record private key access".to_string())
+            })?;
+            if !claimed {
+                return Err(KeyError::Conflict("Private key has already been retrieved".to_string()));
+            }
+        }
+        return Ok(());
     };
+    let connection = &mut pooled_connection(pool)?;
+    let now = Utc::now().naive_utc();
 
-    // Save the JWK to the database
-    let connection = &mut establish_connection();
-    diesel::insert_into(jwks)
-        .values(&jwk)
-        .execute(connection)
-        .expect("Error saving new jwk");
+    let released = connection.transaction::<_, Refusal, _>(|connection| {
+        if jwk.sensitive {
+            let approved = match requester.approval_token.as_deref() {
+                Some(token) => consume_approval(connection, jwk.id, token, now)?,
+                None => false,
+            };
+            if !approved {
+                return Err(Refusal::ApprovalRequired);
+            }
+        }
+        if jwk.burn_after_read {
+            let claimed = diesel::update(
+                jwks.filter(id.eq(jwk.id)).filter(private_key_retrieved_at.is_null()),
+            )
+            .set((private_key_retrieved_at.eq(now), updated_at.eq(now)))
+            .execute(connection)?;
+            if claimed == 0 {
+                return Err(Refusal::AlreadyRetrieved);
+            }
+        }
+        Ok(record_private_key_access(
+            connection,
+            requester.actor.clone(),
+            requester.client_address.clone(),
+            jwk,
+            endpoint,
+        )?)
+    });
 
-    HttpResponse::Created().json(jwk)
+    match released {
+        Ok(access) => {
+            if anomaly_detection_enabled() {
+                if let Err(error) = inspect_private_key_access(connection, &access, jwk.created_at) {
+                    log_error!("Failed to check private key access {} for anomalies: {}", access.id, error);
+                }
+            }
+            if jwk.burn_after_read {
+                if let Err(error) = record_event(connection, jwk.id, ACTION_RETRIEVE, requester.actor.clone()) {
+                    log_error!("Failed to record audit event for key {}: {}", jwk.id, error);
+                }
+                mirror_key(connection, jwk.id);
+            }
+            Ok(())
+        }
+        Err(Refusal::ApprovalRequired) => {
+            Err(KeyError::Forbidden("A valid approval token is required for this key".to_string()))
+        }
+        Err(Refusal::AlreadyRetrieved) => {
+            if let Err(error) = record_event(connection, jwk.id, ACTION_RETRIEVE_REFUSED, requester.actor.clone()) {
+                log_error!("Failed to record audit event for key {}: {}", jwk.id, error);
+            }
+            Err(KeyError::Conflict("Private key has already been retrieved".to_string()))
+        }
+        Err(Refusal::Database(error)) => {
+            log_error!("Failed to record private key access for key {}: {}", jwk.id, error);
+            Err(KeyError::Internal("Failed to record private key access".to_string()))
+        }
+    }
 }
 
-/// Handles the request to retrieve a JWK by its ID.
-/// (including private part)
+/// Handles the request to retrieve the public part of a JWK by its ID.
+///
+/// Served at `/jwks/{id}` instead of [`get_jwk_by_id_handler`] in read-only mode.
 ///
 /// # Arguments
 ///
@@ -144,76 +764,1639 @@ pub async fn add_jwk_handler(input: web::Json<AlgorithmInput>) -> impl Responder
 ///
 /// # Returns
 ///
-/// A JSON response containing the JWK or an error message.
+/// A JSON response containing the public JWK, or `404 Not Found` if the key does not exist, is
+/// deleted or expired.
+pub async fn get_public_jwk_by_id_handler(store: web::Data<Arc<dyn KeyStore>>, key_id: web::Path<Uuid>) -> impl Responder {
+    let key_id = key_id.into_inner();
+    let jwk_result = run_blocking(move || published_key(&store, key_id))
+    .await;
+
+    match jwk_result {
+        Ok(jwk_result) => HttpResponse::Ok().json(Jwk::from(jwk_result)),
+        Err(error) => HttpResponse::from(error),
+    }
+}
+
+/// Handles the request to list the fingerprints of every active key.
+///
+/// # Returns
+///
+/// A JSON response containing the SPKI fingerprint and RFC 7638 thumbprint of each active key,
+/// oldest first. Fingerprints that cannot be computed for a key type are `null`.
 #[utoipa::path(
     get,
-    path = "/jwks/{id}",
+    path = "/jwks/fingerprints",
+    responses(
+        (status = 200, description = "Fingerprints of the active keys", body = [KeyFingerprint])
+    )
+)]
+pub async fn fingerprints_handler(store: web::Data<Arc<dyn KeyStore>>) -> impl Responder {
+    let results = run_blocking(move || {
+        let mut results = store.list_active(None, None).map_err(|error| {
+            log_error!("Failed to load keys: {}", error);
+            KeyError::Internal("Failed to load keys".to_string())
+        })?;
+        results.sort_by_key(|jwk| jwk.created_at);
+        Ok(results)
+    })
+    .await;
+    let results = match results {
+        Ok(results) => results,
+        Err(error) => return HttpResponse::from(error),
+    };
+
+    let fingerprints = results
+        .into_iter()
+        .map(|jwk_result| {
+            let public_jwk = Jwk::from(jwk_result.clone());
+            KeyFingerprint {
+                id: jwk_result.id,
+                kid: jwk_result.kid,
+                alg: jwk_result.alg,
+                spki_sha256: spki_fingerprint(&public_jwk).ok(),
+                thumbprint: jwk_thumbprint(&public_jwk).ok(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    HttpResponse::Ok().json(fingerprints)
+}
+
+/// Handles the request to retrieve the public part of the key currently used for an algorithm
+/// or alias.
+///
+/// The selector is resolved as an alias first. Otherwise it names an algorithm, and the key that
+/// signs by default for it is returned: its primary key, or the most recently created active key
+/// if it has none. The `Content-Location` header links to the key.
+///
+/// # Arguments
+///
+/// * `selector` - The algorithm (e.g., "ES256") or alias of the key.
+///
+/// # Returns
+///
+/// A JSON response containing the public JWK, or `404 Not Found` if there is no active key.
+#[utoipa::path(
+    get,
+    path = "/jwks/current/{selector}",
     params(
-        ("id" = String, Path, description = "Unique key identifier")
+        ("selector" = String, Path, description = "Algorithm (e.g., \"ES256\") or alias of the key")
     ),
     responses(
-        (status = 200, description = "Key found", body = JwkData),
-        (status = 404, description = "Key not found "),
-        (status = 410, description = "Private key expired")
+        (status = 200, description = "Public part of the current key", body = Jwk),
+        (status = 404, description = "No active key for the algorithm or alias", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to resolve alias", body = String, content_type = "text/plain")
     )
 )]
-pub async fn get_jwk_by_id_handler(key_id: web::Path<Uuid>) -> impl Responder {
-    let connection = &mut establish_connection();
+pub async fn current_jwk_handler(store: web::Data<Arc<dyn KeyStore>>, selector: web::Path<String>) -> impl Responder {
+    let selector = selector.into_inner();
+    let jwk_result = run_blocking(move || match store.resolve_alias(&selector) {
+        Ok(Some(key_id)) => Ok(published_key(&store, key_id).ok()),
+        Ok(None) => Ok(find_active_signing_jwk(&store, Some(&selector)).ok().filter(|jwk| !jwk.is_symmetric())),
+        Err(_) => Err(KeyError::Internal("Failed to resolve alias".to_string())),
+    })
+    .await;
 
-    // Find the key by ID
-    let result = jwks
-        .filter(id.eq(key_id.into_inner()))
-        .filter(deleted_at.is_null()) // Exclude deleted keys
-        .filter(key_expires_at.gt(Utc::now().naive_utc())) // Exclude expired keys
-        .first::<JwkData>(connection);
+    match jwk_result {
+        Ok(Some(jwk_result)) => HttpResponse::Ok()
+            .insert_header(("Content-Location", format!("/jwks/{}", jwk_result.id)))
+            .json(Jwk::from(jwk_result)),
+        Ok(None) => HttpResponse::NotFound().body("No active key for the algorithm or alias"),
+        Err(error) => HttpResponse::from(error),
+    }
+}
 
-    match result {
-        Ok(jwk_result) => {
-            // Check if the private key has expired
-            let now = Utc::now().naive_utc();
-            if let Some(expires_at) = jwk_result.private_key_expires_at {
-                if now > expires_at {
-                    return HttpResponse::Gone().body("Private key expired");
-                }
-            }
+/// Verifies a token signed by a stored key and returns its claims.
+///
+/// The key is selected by the `kid` header among keys that are neither deleted nor expired.
+///
+/// # Errors
+///
+/// Returns a message if the token is malformed, its key is unknown, its signature is invalid
+/// or it is outside its validity period.
+fn verify_stored_token(store: &Arc<dyn KeyStore>, token: &str) -> Result<serde_json::Value, String> {
+    let decoded = decode_jws(token).map_err(|err| format!("Malformed token: {}", err))?;
+    let token_kid = decoded.header_str("kid").ok_or("Token has no kid header")?;
 
-            HttpResponse::Ok().json(jwk_result)
-        }
-        Err(_) => HttpResponse::NotFound().body("Key not found"),
+    let now = Utc::now().naive_utc();
+    let jwk_result = store
+        .get_by_kid(token_kid)?
+        .filter(|jwk| jwk.deleted_at.is_none()) // Exclude deleted keys
+        .filter(|jwk| jwk.frozen_at.is_none()) // Exclude frozen keys
+        .filter(|jwk| jwk.key_expires_at.is_some_and(|expires_at| expires_at > now)) // Exclude expired keys
+        .filter(|jwk| jwk.not_before.is_none_or(|activation| activation <= now)) // Exclude keys not active yet
+        .ok_or("Token is signed by an unknown key")?;
+
+    let verified = if jwk_result.is_symmetric() {
+        decoded.verify_secret(&jwk_result)
+    } else {
+        decoded.verify(&Jwk::from(jwk_result))
+    };
+    match verified {
+        Ok(true) => {}
+        _ => return Err("Token signature is invalid".to_string()),
     }
+
+    let claims = decoded.claims().map_err(|err| format!("Malformed token: {}", err))?;
+    check_token_times(&claims, Utc::now().timestamp())?;
+
+    Ok(claims)
 }
 
-/// Handles the request to delete a JWK (soft delete).
+/// Handles the request to export the private key of a JWK in a selectable format.
+///
+/// Every successful export is recorded in the private key access log.
 ///
 /// # Arguments
 ///
+/// * `req` - The request; its actor and client address are recorded in the access log.
 /// * `key_id` - The unique identifier of the key.
+/// * `query` - The requested key format and encoding.
 ///
 /// # Returns
 ///
-/// A response indicating success or failure.
+/// The private key as PEM text or DER bytes, or an error message.
 #[utoipa::path(
-    delete,
-    path = "/jwks/{id}",
+    get,
+    path = "/jwks/{id}/export",
+    params(
+        ("id" = String, Path, description = "Unique key identifier"),
+        ExportQuery
+    ),
+    responses(
+        (status = 200, description = "Private key in the requested format", content_type = ["application/x-pem-file", "application/octet-stream"]),
+        (status = 400, description = "Unsupported export format for this key", body = String, content_type = "text/plain"),
+        (status = 403, description = "Key is sensitive and the `X-Approval-Token` header is missing or invalid", body = String, content_type = "text/plain"),
+        (status = 404, description = "Key not found", body = String, content_type = "text/plain"),
+        (status = 409, description = "Private key of a burn-after-read key has already been retrieved, or the key is external", body = String, content_type = "text/plain"),
+        (status = 410, description = "Private key expired", body = String, content_type = "text/plain"),
+        (status = 423, description = "Key is frozen", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to record the access", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn export_jwk_handler(
+    store: web::Data<Arc<dyn KeyStore>>,
+    pool: Option<web::Data<DbPool>>,
+    req: HttpRequest,
+    key_id: web::Path<Uuid>,
+    query: web::Query<ExportQuery>,
+) -> impl Responder {
+    let format = query.format.clone().unwrap_or_else(|| "pkcs8".to_string());
+    let encoding = query.encoding.clone().unwrap_or_else(|| "pem".to_string());
+    let content_type = match encoding.as_str() {
+        "pem" => "application/x-pem-file",
+        _ => "application/octet-stream",
+    };
+    let requester = Requester::from(&req);
+    let key_id = key_id.into_inner();
+
+    let exported = run_blocking(move || {
+        let jwk_result = find_private_jwk(&store, key_id)?;
+        let exported = export_private_key(&jwk_result, &format, &encoding)
+            .map_err(|_| KeyError::Invalid("Unsupported export format for this key".to_string()))?;
+        release_private_key(pool.as_ref().map(|pool| pool.get_ref()), &store, &requester, &jwk_result, "/jwks/{id}/export")?;
+        Ok(exported)
+    })
+    .await;
+
+    match exported {
+        Ok(exported) => HttpResponse::Ok().content_type(content_type).body(exported),
+        Err(error) => HttpResponse::from(error),
+    }
+}
+
+/// Handles the request to download the X.509 certificate chain of a JWK as PEM.
+///
+/// The certificates of `x5c` are returned leaf first, for proxies and truststores that are
+/// configured with raw certificates.
+///
+/// # Arguments
+///
+/// * `key_id` - The unique identifier of the key.
+///
+/// # Returns
+///
+/// The PEM encoded certificate chain, or `404 Not Found` if the key does not exist, is not
+/// active or has no certificate chain.
+#[utoipa::path(
+    get,
+    path = "/jwks/{id}/chain.pem",
     params(
         ("id" = String, Path, description = "Unique key identifier")
     ),
     responses(
-        (status = 204, description = "Key successfully deleted"),
-        (status = 404, description = "Key not found")
+        (status = 200, description = "Certificate chain, leaf certificate first", content_type = "application/x-pem-file"),
+        (status = 404, description = "Key not found or without certificate chain", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to decode the certificate chain", body = String, content_type = "text/plain")
     )
 )]
-pub async fn delete_jwk_handler(key_id: web::Path<Uuid>) -> impl Responder {
-    let connection = &mut establish_connection();
+pub async fn certificate_chain_handler(store: web::Data<Arc<dyn KeyStore>>, key_id: web::Path<Uuid>) -> impl Responder {
+    let key_id = key_id.into_inner();
+    let pem = run_blocking(move || {
+        let jwk_result = published_key(&store, key_id)?;
+        if jwk_result.x5c.as_ref().is_none_or(Vec::is_empty) {
+            return Err(KeyError::NotFound("Key has no certificate chain".to_string()));
+        }
 
-    // Set deleted_at to the current date and time
-    let result = diesel::update(jwks.filter(id.eq(key_id.into_inner())))
-        .set(deleted_at.eq(Some(Utc::now().naive_utc())))
-        .execute(connection);
+        certificate_chain_pem(&jwk_result)
+            .map_err(|_| KeyError::Internal("Failed to decode the certificate chain".to_string()))
+    })
+    .await;
 
-    match result {
-        Ok(0) => HttpResponse::NotFound().body("Key not found"),
-        Ok(_) => HttpResponse::NoContent().finish(),
-        Err(_) => HttpResponse::InternalServerError().body("Failed to delete key"),
+    match pem {
+        Ok(pem) => HttpResponse::Ok().content_type("application/x-pem-file").body(pem),
+        Err(error) => HttpResponse::from(error),
+    }
+}
+
+/// Handles the request to retrieve the public part of a JWK as a COSE_Key (RFC 9052).
+///
+/// # Arguments
+///
+/// * `key_id` - The unique identifier of the key.
+///
+/// # Returns
+///
+/// The CBOR encoded COSE_Key, or `404 Not Found` if the key does not exist or is not active.
+#[utoipa::path(
+    get,
+    path = "/jwks/{id}/cose",
+    params(
+        ("id" = String, Path, description = "Unique key identifier")
+    ),
+    responses(
+        (status = 200, description = "CBOR encoded COSE_Key of the public key", content_type = "application/cose-key"),
+        (status = 404, description = "Key not found", body = String, content_type = "text/plain"),
+        (status = 422, description = "Key type has no COSE representation", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn cose_key_handler(store: web::Data<Arc<dyn KeyStore>>, key_id: web::Path<Uuid>) -> impl Responder {
+    let key_id = key_id.into_inner();
+    let jwk_result = run_blocking(move || published_key(&store, key_id))
+    .await;
+    let jwk_result = match jwk_result {
+        Ok(jwk_result) => jwk_result,
+        Err(error) => return HttpResponse::from(error),
+    };
+
+    match encode_cose_key(&Jwk::from(jwk_result)) {
+        Ok(cose_key) => HttpResponse::Ok().content_type(COSE_KEY_CONTENT_TYPE).body(cose_key),
+        Err(error) => HttpResponse::UnprocessableEntity().body(error.to_string()),
+    }
+}
+
+/// Handles the request to rotate a JWK.
+///
+/// A new version of the logical key is created with the same algorithm, key size and
+/// constraints, linked to the rotated key as its predecessor. The rotated key stays published
+/// until it expires, so tokens it signed remain verifiable.
+///
+/// # Arguments
+///
+/// * `req` - The request; its `X-Actor` header is recorded in the audit log.
+/// * `key_id` - The unique identifier of the key to rotate.
+///
+/// # Returns
+///
+/// A JSON response containing the new key version or an error message.
+#[utoipa::path(
+    post,
+    path = "/jwks/{id}/rotate",
+    params(
+        ("id" = String, Path, description = "Unique key identifier")
+    ),
+    responses(
+        (status = 201, description = "New key version created (the public key for burn-after-read and sensitive keys)", body = JwkData),
+        (status = 404, description = "Key not found", body = String, content_type = "text/plain"),
+        (status = 409, description = "Key has already been rotated or is external", body = String, content_type = "text/plain"),
+        (status = 422, description = "Algorithm or key strength is no longer permitted by policy", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to generate, verify or store the key", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn rotate_jwk_handler(store: web::Data<Arc<dyn KeyStore>>, req: HttpRequest, key_id: web::Path<Uuid>) -> impl Responder {
+    match KeyManager::with_store(store.get_ref().clone(), request_actor(&req)).rotate(key_id.into_inner()).await {
+        Ok(jwk) => key_response(HttpResponse::Created(), jwk),
+        Err(error) => HttpResponse::from(error),
+    }
+}
+
+/// Handles the request to designate a JWK as the primary key of its algorithm.
+///
+/// The primary key is used by `/sign` when only an algorithm is given and by default for
+/// issued tokens, and is listed first in `/.well-known/jwks.json`. Each algorithm has at most
+/// one primary key; designating a key replaces the previous one.
+///
+/// # Arguments
+///
+/// * `req` - The request; its `X-Actor` header is recorded in the audit log.
+/// * `key_id` - The unique identifier of the key.
+///
+/// # Returns
+///
+/// A response indicating success or failure.
+#[utoipa::path(
+    post,
+    path = "/jwks/{id}/primary",
+    params(
+        ("id" = String, Path, description = "Unique key identifier")
+    ),
+    responses(
+        (status = 204, description = "Key designated as primary"),
+        (status = 404, description = "Key not found", body = String, content_type = "text/plain"),
+        (status = 409, description = "External key cannot sign", body = String, content_type = "text/plain"),
+        (status = 423, description = "Key is frozen", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to update key", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn set_primary_jwk_handler(store: web::Data<Arc<dyn KeyStore>>, req: HttpRequest, key_id: web::Path<Uuid>) -> impl Responder {
+    let actor = request_actor(&req);
+    let key_id = key_id.into_inner();
+
+    let result = run_blocking(move || {
+        let now = Utc::now().naive_utc();
+        let key = stored_key(&store, key_id)?
+            .filter(|key| key.key_expires_at.is_some_and(|expires_at| expires_at > now))
+            .ok_or_else(|| KeyError::NotFound("Key not found".to_string()))?;
+        if key.external {
+            return Err(KeyError::Conflict("External key cannot sign".to_string()));
+        }
+        if key.frozen_at.is_some() {
+            return Err(KeyError::Frozen("Key is frozen".to_string()));
+        }
+        if key.is_primary {
+            return Ok(());
+        }
+
+        store.designate_primary(&key, actor).map_err(|error| {
+            log_error!("Failed to designate key {} as primary: {}", key_id, error);
+            KeyError::Internal("Failed to update key".to_string())
+        })
+    })
+    .await;
+
+    match result {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(error) => HttpResponse::from(error),
+    }
+}
+
+/// Handles the request to remove the primary designation of a JWK.
+///
+/// # Arguments
+///
+/// * `req` - The request; its `X-Actor` header is recorded in the audit log.
+/// * `key_id` - The unique identifier of the key.
+///
+/// # Returns
+///
+/// A response indicating success or failure.
+#[utoipa::path(
+    delete,
+    path = "/jwks/{id}/primary",
+    params(
+        ("id" = String, Path, description = "Unique key identifier")
+    ),
+    responses(
+        (status = 204, description = "Primary designation removed"),
+        (status = 404, description = "Key not found", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to update key", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn unset_primary_jwk_handler(
+    store: web::Data<Arc<dyn KeyStore>>,
+    req: HttpRequest,
+    key_id: web::Path<Uuid>,
+) -> impl Responder {
+    let actor = request_actor(&req);
+    let key_id = key_id.into_inner();
+
+    let result = run_blocking(move || {
+        match store.update_lifecycle(key_id, LifecycleChange::UnsetPrimary, actor) {
+            Ok(true) => Ok(()),
+            // Keys that are not primary are left as they are
+            Ok(false) => match store.get_private(key_id) {
+                Ok(Some(_)) => Ok(()),
+                Ok(None) => Err(KeyError::NotFound("Key not found".to_string())),
+                Err(_) => Err(KeyError::Internal("Failed to update key".to_string())),
+            },
+            Err(error) => {
+                log_error!("Failed to update key {}: {}", key_id, error);
+                Err(KeyError::Internal("Failed to update key".to_string()))
+            }
+        }
+    })
+    .await;
+
+    match result {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(error) => HttpResponse::from(error),
+    }
+}
+
+/// Handles the request to extend the validity of a private key.
+///
+/// For cases where the rotation of a dependent service is delayed, the private key expiration
+/// is pushed out, in total at most `MAX_PRIVATE_KEY_EXTENSION_SECONDS` beyond the regular
+/// lifetime of the private key; the key expiration moves by the same amount.
+///
+/// # Arguments
+///
+/// * `req` - The request; its `X-Actor` header is recorded in the audit log.
+/// * `key_id` - The unique identifier of the key to extend.
+/// * `body` - The requested extension.
+///
+/// # Returns
+///
+/// A JSON response containing the new expiration dates or an error message.
+#[utoipa::path(
+    post,
+    path = "/jwks/{id}/extend",
+    params(
+        ("id" = String, Path, description = "Unique key identifier")
+    ),
+    request_body = ExtendKeyInput,
+    responses(
+        (status = 200, description = "Private key extended", body = KeyExtension),
+        (status = 404, description = "Key not found", body = String, content_type = "text/plain"),
+        (status = 409, description = "External key has no private key", body = String, content_type = "text/plain"),
+        (status = 422, description = "Private key has expired or the extension exceeds the policy bound", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to extend key", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn extend_jwk_handler(
+    store: web::Data<Arc<dyn KeyStore>>,
+    req: HttpRequest,
+    key_id: web::Path<Uuid>,
+    body: web::Json<ExtendKeyInput>,
+) -> impl Responder {
+    let actor = request_actor(&req);
+    let key_id = key_id.into_inner();
+    let seconds = body.seconds;
+
+    let extension = run_blocking(move || {
+        let now = Utc::now().naive_utc();
+
+        let extended = stored_key(&store, key_id)?.ok_or_else(|| KeyError::NotFound("Key not found".to_string()))?;
+        if extended.external {
+            return Err(KeyError::Conflict("External key has no private key".to_string()));
+        }
+
+        let (private_expires, key_expires) = extend_private_key_expiration(
+            &extended,
+            seconds,
+            key_lifetimes(&extended.alg, extended.purpose.as_deref()).0,
+            max_private_key_extension_seconds(),
+            now,
+        )
+        .map_err(KeyError::Policy)?;
+
+        let change = LifecycleChange::Extend { private_key_expires_at: private_expires, key_expires_at: key_expires };
+        match store.update_lifecycle(key_id, change, actor) {
+            Ok(true) => {}
+            Ok(false) => return Err(KeyError::NotFound("Key not found".to_string())),
+            Err(error) => {
+                log_error!("Failed to extend key {}: {}", key_id, error);
+                return Err(KeyError::Internal("Failed to extend key".to_string()));
+            }
+        }
+
+        Ok(KeyExtension {
+            id: key_id,
+            kid: extended.kid,
+            private_key_expires_at: private_expires,
+            key_expires_at: key_expires,
+        })
+    })
+    .await;
+
+    match extension {
+        Ok(extension) => HttpResponse::Ok().json(extension),
+        Err(error) => HttpResponse::from(error),
+    }
+}
+
+/// Freezes or unfreezes a key, recording the change in the audit log.
+///
+/// # Arguments
+///
+/// * `actor` - The caller recorded in the audit log.
+/// * `key_id` - The unique identifier of the key.
+/// * `frozen` - Whether the key should be frozen.
+///
+/// # Returns
+///
+/// `204 No Content`, also if the key already was in the requested state, or an error message.
+async fn set_key_frozen(store: web::Data<Arc<dyn KeyStore>>, actor: Option<String>, key_id: Uuid, frozen: bool) -> HttpResponse {
+    let result = run_blocking(move || {
+        let key = stored_key(&store, key_id)?.ok_or_else(|| KeyError::NotFound("Key not found".to_string()))?;
+        if key.frozen_at.is_some() == frozen {
+            return Ok(());
+        }
+
+        match store.update_lifecycle(key_id, LifecycleChange::Freeze(frozen), actor) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(KeyError::NotFound("Key not found".to_string())),
+            Err(error) => {
+                log_error!("Failed to update key {}: {}", key_id, error);
+                Err(KeyError::Internal("Failed to update key".to_string()))
+            }
+        }
+    })
+    .await;
+
+    match result {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(error) => HttpResponse::from(error),
+    }
+}
+
+/// Handles the request to freeze a JWK.
+///
+/// A frozen key is removed from the published key sets and rejected for signing without being
+/// deleted, e.g. while a suspected leak is investigated.
+///
+/// # Arguments
+///
+/// * `req` - The request; its `X-Actor` header is recorded in the audit log.
+/// * `key_id` - The unique identifier of the key.
+///
+/// # Returns
+///
+/// A response indicating success or failure.
+#[utoipa::path(
+    post,
+    path = "/jwks/{id}/freeze",
+    params(
+        ("id" = String, Path, description = "Unique key identifier")
+    ),
+    responses(
+        (status = 204, description = "Key frozen"),
+        (status = 404, description = "Key not found", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to update key", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn freeze_jwk_handler(store: web::Data<Arc<dyn KeyStore>>, req: HttpRequest, key_id: web::Path<Uuid>) -> impl Responder {
+    set_key_frozen(store, request_actor(&req), key_id.into_inner(), true).await
+}
+
+/// Handles the request to unfreeze a JWK, publishing it and allowing it to sign again.
+///
+/// # Arguments
+///
+/// * `req` - The request; its `X-Actor` header is recorded in the audit log.
+/// * `key_id` - The unique identifier of the key.
+///
+/// # Returns
+///
+/// A response indicating success or failure.
+#[utoipa::path(
+    post,
+    path = "/jwks/{id}/unfreeze",
+    params(
+        ("id" = String, Path, description = "Unique key identifier")
+    ),
+    responses(
+        (status = 204, description = "Key unfrozen"),
+        (status = 404, description = "Key not found", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to update key", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn unfreeze_jwk_handler(store: web::Data<Arc<dyn KeyStore>>, req: HttpRequest, key_id: web::Path<Uuid>) -> impl Responder {
+    set_key_frozen(store, request_actor(&req), key_id.into_inner(), false).await
+}
+
+/// Handles the request to apply a lifecycle transition to every key matching a filter.
+///
+/// `disable` freezes the matching keys, `revoke` deletes them and `extend` extends their private
+/// keys within the extension policy. Deleted keys never match; `disable` only matches keys that
+/// are not frozen yet and `extend` only keys whose private key has not expired. The transition
+/// is applied in a single transaction: if any key cannot be changed, none is.
+///
+/// # Arguments
+///
+/// * `req` - The request; its `X-Actor` header is recorded in the audit log.
+/// * `input` - The transition, the key filter and whether to only report the matching keys.
+///
+/// # Returns
+///
+/// A JSON response listing the affected keys or an error message.
+#[utoipa::path(
+    post,
+    path = "/jwks/bulk",
+    request_body = BulkTransitionInput,
+    responses(
+        (status = 200, description = "Transition applied, or the matching keys of a dry run", body = BulkTransitionReport),
+        (status = 400, description = "Unknown action or purpose, missing filter or seconds, or invalid created_before", body = String, content_type = "text/plain"),
+        (status = 422, description = "A matching key cannot be extended within the policy", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to apply the transition", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn bulk_transition_handler(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    input: web::Json<BulkTransitionInput>,
+) -> impl Responder {
+    let input = input.into_inner();
+    let filter = &input.filter;
+    if filter.alg.is_none() && filter.purpose.is_none() && filter.created_before.is_none() {
+        return HttpResponse::BadRequest().body("At least one filter criterion is required");
+    }
+    if let Some(Err(message)) = filter.purpose.as_deref().map(check_key_purpose) {
+        return HttpResponse::BadRequest().body(message);
+    }
+    let created_before = match filter.created_before_time() {
+        Ok(created_before) => created_before,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+
+    let now = Utc::now().naive_utc();
+    let mut query = jwks.filter(deleted_at.is_null()).into_boxed();
+    let event = match (input.action.as_str(), input.seconds) {
+        ("disable", _) => {
+            query = query.filter(frozen_at.is_null());
+            ACTION_FREEZE
+        }
+        ("revoke", _) => ACTION_DELETE,
+        ("extend", Some(_)) => {
+            query = query.filter(private_key_expires_at.gt(now));
+            ACTION_EXTEND
+        }
+        ("extend", None) => return HttpResponse::BadRequest().body("seconds is required for extend"),
+        (action, _) => {
+            return HttpResponse::BadRequest()
+                .body(format!("Unknown action {}, expected disable, revoke or extend", action))
+        }
+    };
+    if let Some(algorithm) = filter.alg.clone() {
+        query = query.filter(alg.eq(algorithm));
+    }
+    if let Some(key_purpose) = filter.purpose.clone() {
+        query = query.filter(purpose.eq(key_purpose));
+    }
+    if let Some(created_before) = created_before {
+        query = query.filter(created_at.lt(created_before));
+    }
+
+    let actor = request_actor(&req);
+    let report = run_blocking(move || {
+        let connection = &mut pooled_connection(&pool)?;
+        let matched = query
+            .order(created_at.asc())
+            .load::<JwkData>(connection)
+            .map_err(|_| KeyError::Internal("Failed to load keys".to_string()))?;
+
+        // Check every extension up front, so that a single policy violation rejects the transition
+        let extensions = match input.seconds.filter(|_| event == ACTION_EXTEND) {
+            Some(seconds) => matched
+                .iter()
+                .map(|key| {
+                    let lifetime_seconds = key_lifetimes(&key.alg, key.purpose.as_deref()).0;
+                    extend_private_key_expiration(
+                        key,
+                        seconds,
+                        lifetime_seconds,
+                        max_private_key_extension_seconds(),
+                        now,
+                    )
+                    .map_err(|message| KeyError::Policy(format!("Key {}: {}", key.id, message)))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            None => Vec::new(),
+        };
+
+        let report = BulkTransitionReport {
+            action: input.action.clone(),
+            dry_run: input.dry_run,
+            keys: matched.iter().map(|key| key.id).collect(),
+        };
+        if input.dry_run {
+            return Ok(report);
+        }
+
+        let result = connection.transaction::<_, diesel::result::Error, _>(|connection| {
+            for (index, key) in matched.iter().enumerate() {
+                let target = jwks.filter(id.eq(key.id)).filter(deleted_at.is_null());
+                match extensions.get(index) {
+                    Some((private_expires, key_expires)) => diesel::update(target)
+                        .set((
+                            private_key_expires_at.eq(Some(*private_expires)),
+                            key_expires_at.eq(*key_expires),
+                            updated_at.eq(now),
+                        ))
+                        .execute(connection)?,
+                    None if event == ACTION_FREEZE => diesel::update(target)
+                        .set((frozen_at.eq(Some(now)), updated_at.eq(now)))
+                        .execute(connection)?,
+                    None => diesel::update(target)
+                        .set((deleted_at.eq(Some(now)), updated_at.eq(now)))
+                        .execute(connection)?,
+                };
+                record_event(connection, key.id, event, actor.clone())?;
+            }
+            Ok(())
+        });
+        if result.is_err() {
+            return Err(KeyError::Internal("Failed to apply the transition".to_string()));
+        }
+
+        for key_id in &report.keys {
+            mirror_key(connection, *key_id);
+        }
+        Ok(report)
+    })
+    .await;
+
+    match report {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(error) => HttpResponse::from(error),
+    }
+}
+
+/// Handles the request to retrieve the version history of the logical key a JWK belongs to.
+///
+/// # Arguments
+///
+/// * `key_id` - The unique identifier of any version of the key.
+///
+/// # Returns
+///
+/// A JSON response containing every version, oldest first, or an error message.
+#[utoipa::path(
+    get,
+    path = "/jwks/{id}/history",
+    params(
+        ("id" = String, Path, description = "Unique identifier of any version of the key")
+    ),
+    responses(
+        (status = 200, description = "Versions of the logical key, oldest first", body = [KeyVersion]),
+        (status = 404, description = "Key not found", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to load key versions", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn key_history_handler(store: web::Data<Arc<dyn KeyStore>>, key_id: web::Path<Uuid>) -> impl Responder {
+    let key_id = key_id.into_inner();
+    let versions = run_blocking(move || {
+        let failed = || KeyError::Internal("Failed to load key versions".to_string());
+
+        let key = match store.get_private(key_id) {
+            Ok(Some(key)) => key,
+            Ok(None) => return Err(KeyError::NotFound("Key not found".to_string())),
+            Err(_) => return Err(failed()),
+        };
+
+        // Follow the predecessor links back to the first version, then the successors forward
+        let mut versions = vec![key];
+        while let Some(previous_id) = versions[0].predecessor_id {
+            let previous = store.get_private(previous_id).ok().flatten().ok_or_else(failed)?;
+            versions.insert(0, previous);
+        }
+        loop {
+            let last_id = versions[versions.len() - 1].id;
+            match store.successor(last_id) {
+                Ok(Some(next)) => versions.push(next),
+                Ok(None) => break,
+                Err(_) => return Err(failed()),
+            }
+        }
+        Ok(versions)
+    })
+    .await;
+    let versions = match versions {
+        Ok(versions) => versions,
+        Err(error) => return HttpResponse::from(error),
+    };
+
+    let now = Utc::now().naive_utc();
+    let successors = versions
+        .iter()
+        .skip(1)
+        .map(|next| Some((next.id, next.created_at)))
+        .chain(std::iter::once(None))
+        .collect::<Vec<_>>();
+    let history = versions
+        .into_iter()
+        .zip(successors)
+        .enumerate()
+        .map(|(index, (version, successor))| {
+            // A version signs until it is superseded, deleted or its private key expires
+            let active_until = [
+                successor.map(|(_, superseded_at)| superseded_at),
+                version.deleted_at,
+                version.private_key_expires_at,
+            ]
+            .into_iter()
+            .flatten()
+            .min();
+
+            KeyVersion {
+                version: index as u32 + 1,
+                status: version.lifecycle_status(now).to_string(),
+                id: version.id,
+                kid: version.kid,
+                alg: version.alg,
+                created_at: version.created_at,
+                active_from: version.created_at,
+                active_until,
+                deleted_at: version.deleted_at,
+                key_expires_at: version.key_expires_at,
+                predecessor_id: version.predecessor_id,
+                successor_id: successor.map(|(successor_id, _)| successor_id),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    HttpResponse::Ok().json(history)
+}
+
+/// Handles the request to delete a JWK (soft delete).
+///
+/// # Arguments
+///
+/// * `req` - The request; its `X-Actor` header is recorded in the audit log.
+/// * `key_id` - The unique identifier of the key.
+///
+/// # Returns
+///
+/// A response indicating success or failure.
+#[utoipa::path(
+    delete,
+    path = "/jwks/{id}",
+    params(
+        ("id" = String, Path, description = "Unique key identifier")
+    ),
+    responses(
+        (status = 204, description = "Key successfully deleted"),
+        (status = 404, description = "Key not found", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to delete key", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn delete_jwk_handler(store: web::Data<Arc<dyn KeyStore>>, req: HttpRequest, key_id: web::Path<Uuid>) -> impl Responder {
+    let actor = request_actor(&req);
+    let key_id = key_id.into_inner();
+
+    let result = run_blocking(move || match store.soft_delete(key_id, actor) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(KeyError::NotFound("Key not found".to_string())),
+        Err(error) => {
+            log_error!("Failed to delete key {}: {}", key_id, error);
+            Err(KeyError::Internal("Failed to delete key".to_string()))
+        }
+    })
+    .await;
+
+    match result {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(error) => HttpResponse::from(error),
+    }
+}
+
+/// Handles the request to list soft-deleted JWKs.
+///
+/// Deleted keys are listed newest deletion first, without private key material, together with
+/// the caller that deleted them according to the audit log.
+///
+/// # Arguments
+///
+/// * `req` - The request; the link to the next page is derived from it.
+/// * `query` - The requested page.
+///
+/// # Returns
+///
+/// A JSON response containing a page of deleted keys.
+#[utoipa::path(
+    get,
+    path = "/jwks/deleted",
+    params(PageQuery),
+    responses(
+        (status = 200, description = "Deleted keys", body = [DeletedJwk],
+            headers(("Link" = String, description = "URL of the next page (`rel=\"next\"`), if any"))),
+        (status = 400, description = "Invalid limit or offset", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to load deleted keys", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn deleted_jwks_handler(store: web::Data<Arc<dyn KeyStore>>, req: HttpRequest, query: web::Query<PageQuery>) -> impl Responder {
+    let page = match Page::new(query.limit, query.offset) {
+        Ok(page) => page,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+    let (offset, fetch) = (page.offset, page.fetch());
+
+    let loaded = run_blocking(move || {
+        store.deleted(offset, fetch).map_err(|error| {
+            log_error!("Failed to load deleted keys: {}", error);
+            KeyError::Internal("Failed to load deleted keys".to_string())
+        })
+    })
+    .await;
+    let results = match loaded {
+        Ok(results) => results,
+        Err(error) => return HttpResponse::from(error),
+    };
+
+    let deleted = results
+        .into_iter()
+        .filter_map(|(jwk, deleted_by)| {
+            Some(DeletedJwk {
+                deleted_by,
+                deleted_at: jwk.deleted_at?,
+                id: jwk.id,
+                kty: jwk.kty,
+                alg: jwk.alg,
+                kid: jwk.kid,
+                created_at: jwk.created_at,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    page_response(&req, &page, deleted)
+}
+
+/// Handles the request to list keys expiring soon.
+///
+/// A key is listed if its private key or the key itself expires within the window. Deleted and
+/// already expired keys are not listed.
+///
+/// # Arguments
+///
+/// * `req` - The request; the link to the next page is derived from it.
+/// * `query` - The reporting window and the requested page.
+///
+/// # Returns
+///
+/// A JSON response containing a page of the expiring keys, soonest expiry first.
+#[utoipa::path(
+    get,
+    path = "/jwks/expiring",
+    params(ExpiringQuery),
+    responses(
+        (status = 200, description = "Keys expiring within the window", body = [ExpiringJwk],
+            headers(("Link" = String, description = "URL of the next page (`rel=\"next\"`), if any"))),
+        (status = 400, description = "Invalid window, limit or offset", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to load keys", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn expiring_jwks_handler(store: web::Data<Arc<dyn KeyStore>>, req: HttpRequest, query: web::Query<ExpiringQuery>) -> impl Responder {
+    let window = match query.window() {
+        Ok(window) => window,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+    let page = match Page::new(query.limit, query.offset) {
+        Ok(page) => page,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+    let (offset, fetch) = (page.offset, page.fetch());
+
+    let now = Utc::now().naive_utc();
+    let until = now + window;
+
+    let results = run_blocking(move || {
+        store.expiring(until, offset, fetch).map_err(|error| {
+            log_error!("Failed to load keys: {}", error);
+            KeyError::Internal("Failed to load keys".to_string())
+        })
+    })
+    .await;
+    let results = match results {
+        Ok(results) => results,
+        Err(error) => return HttpResponse::from(error),
+    };
+
+    let expiring = results
+        .into_iter()
+        .map(|jwk| ExpiringJwk {
+            status: jwk.lifecycle_status(now).to_string(),
+            id: jwk.id,
+            kty: jwk.kty,
+            alg: jwk.alg,
+            kid: jwk.kid,
+            private_key_expires_at: jwk.private_key_expires_at,
+            key_expires_at: jwk.key_expires_at,
+        })
+        .collect::<Vec<_>>();
+
+    page_response(&req, &page, expiring)
+}
+
+/// Handles the request to sign a JWT with a managed key.
+///
+/// The key is selected by `id`, by `alias`, or by `alg` using the primary key of the algorithm
+/// (the most recently created active key if there is none). The claims must satisfy the issuer and
+/// audience constraints of the key. If a client
+/// certificate or its thumbprint is given, the token is bound to it with the `cnf.x5t#S256`
+/// confirmation claim (RFC 8705).
+///
+/// # Arguments
+///
+/// * `input` - The input data containing the key ID and the claims to sign.
+///
+/// # Returns
+///
+/// A JSON response containing the signed token or an error message.
+#[utoipa::path(
+    post,
+    path = "/sign",
+    request_body = SignInput,
+    responses(
+        (status = 200, description = "Token successfully signed", body = SignOutput),
+        (status = 400, description = "Neither id, alias nor alg is given, the key cannot be used for signing, or the client certificate or thumbprint is invalid", body = String, content_type = "text/plain"),
+        (status = 403, description = "Claims violate the key's issuer or audience constraints", body = String, content_type = "text/plain"),
+        (status = 404, description = "Key or alias not found", body = String, content_type = "text/plain"),
+        (status = 410, description = "Private key expired", body = String, content_type = "text/plain"),
+        (status = 409, description = "External key has no private key, or the key is not active yet", body = String, content_type = "text/plain"),
+        (status = 423, description = "Key is frozen", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to sign token", body = String, content_type = "text/plain"),
+        (status = 503, description = "No active signing key for the algorithm", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn sign_handler(store: web::Data<Arc<dyn KeyStore>>, input: web::Json<SignInput>) -> impl Responder {
+    match KeyManager::with_store(store.get_ref().clone(), None).sign(&input).await {
+        Ok(output) => HttpResponse::Ok().json(output),
+        Err(error) => HttpResponse::from(error),
+    }
+}
+
+/// Handles the request to issue a JWT access token (RFC 9068).
+///
+/// The token is signed with the requested key or, if none is given, with an active primary key
+/// or else the most recently created active signing key. Its header carries `typ: at+jwt`.
+///
+/// # Arguments
+///
+/// * `input` - The input data containing the access token claims.
+///
+/// # Returns
+///
+/// A JSON response containing the signed access token or an error message.
+#[utoipa::path(
+    post,
+    path = "/tokens/access",
+    request_body = AccessTokenInput,
+    responses(
+        (status = 200, description = "Access token successfully issued", body = SignOutput),
+        (status = 400, description = "Required claims are missing or invalid, or the key cannot be used for signing", body = String, content_type = "text/plain"),
+        (status = 403, description = "Claims violate the key's issuer or audience constraints", body = String, content_type = "text/plain"),
+        (status = 404, description = "Key not found", body = String, content_type = "text/plain"),
+        (status = 410, description = "Private key expired", body = String, content_type = "text/plain"),
+        (status = 409, description = "External key has no private key, or the key is not active yet", body = String, content_type = "text/plain"),
+        (status = 423, description = "Key is frozen", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to sign token", body = String, content_type = "text/plain"),
+        (status = 503, description = "No active signing key", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn access_token_handler(store: web::Data<Arc<dyn KeyStore>>, input: web::Json<AccessTokenInput>) -> impl Responder {
+    let input = input.into_inner();
+    let signed = run_blocking(move || {
+        let jwk_result = match input.id {
+            Some(key_id) => find_signing_jwk(&store, key_id)?,
+            None => find_active_signing_jwk(&store, None)?,
+        };
+
+        if key_use_for_alg(&jwk_result.alg) != "sig" {
+            return Err(KeyError::Invalid("Key cannot be used for signing".to_string()));
+        }
+
+        let claims = access_token_claims(
+            &input.claims,
+            Utc::now().timestamp(),
+            input.expires_in.unwrap_or(DEFAULT_ACCESS_TOKEN_LIFETIME_SECONDS),
+        )
+        .map_err(KeyError::Invalid)?;
+
+        check_token_constraints(&jwk_result, &claims).map_err(KeyError::Forbidden)?;
+
+        serde_json::to_vec(&claims)
+            .map_err(Box::from)
+            .and_then(|payload| encode_jws(&jwk_result, "at+jwt", &payload))
+            .map_err(|_| KeyError::Internal("Failed to sign token".to_string()))
+    })
+    .await;
+
+    match signed {
+        Ok(token) => HttpResponse::Ok().json(SignOutput { token }),
+        Err(error) => HttpResponse::from(error),
+    }
+}
+
+/// Handles the request to sign a software statement for dynamic client registration (RFC 7591).
+///
+/// The statement is signed with the key designated by `SOFTWARE_STATEMENT_KEY_ID` and its
+/// claims are completed from `SOFTWARE_STATEMENT_TEMPLATE`.
+///
+/// # Arguments
+///
+/// * `input` - The input data containing the software statement claims.
+///
+/// # Returns
+///
+/// A JSON response containing the signed software statement or an error message.
+#[utoipa::path(
+    post,
+    path = "/software-statements",
+    request_body = SoftwareStatementInput,
+    responses(
+        (status = 200, description = "Software statement successfully signed", body = SignOutput),
+        (status = 400, description = "Required claims are missing or invalid, or the key cannot be used for signing", body = String, content_type = "text/plain"),
+        (status = 403, description = "Claims violate the key's issuer or audience constraints", body = String, content_type = "text/plain"),
+        (status = 404, description = "Key not found", body = String, content_type = "text/plain"),
+        (status = 410, description = "Private key expired", body = String, content_type = "text/plain"),
+        (status = 409, description = "External key has no private key, or the key is not active yet", body = String, content_type = "text/plain"),
+        (status = 423, description = "Key is frozen", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to sign software statement", body = String, content_type = "text/plain"),
+        (status = 503, description = "No software statement signing key configured", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn software_statement_handler(store: web::Data<Arc<dyn KeyStore>>, input: web::Json<SoftwareStatementInput>) -> impl Responder {
+    let key_id = match software_statement_key_id() {
+        Some(key_id) => key_id,
+        None => {
+            return HttpResponse::ServiceUnavailable()
+                .body("No software statement signing key configured")
+        }
+    };
+
+    let input = input.into_inner();
+    let signed = run_blocking(move || {
+        let jwk_result = find_signing_jwk(&store, key_id)?;
+
+        if key_use_for_alg(&jwk_result.alg) != "sig" {
+            return Err(KeyError::Invalid("Key cannot be used for signing".to_string()));
+        }
+
+        let claims = software_statement_claims(
+            &input.claims,
+            software_statement_template().as_ref(),
+            Utc::now().timestamp(),
+        )
+        .map_err(KeyError::Invalid)?;
+
+        check_token_constraints(&jwk_result, &claims).map_err(KeyError::Forbidden)?;
+
+        encode_jwt(&jwk_result, &claims)
+            .map_err(|_| KeyError::Internal("Failed to sign software statement".to_string()))
+    })
+    .await;
+
+    match signed {
+        Ok(token) => HttpResponse::Ok().json(SignOutput { token }),
+        Err(error) => HttpResponse::from(error),
+    }
+}
+
+/// Handles a token exchange request (RFC 8693).
+///
+/// The subject token (and the actor token, if given) must be signed by a stored key. The issued
+/// token is signed with an active primary key or else the most recently created active signing
+/// key, and carries the mapped claims of the subject token.
+///
+/// # Arguments
+///
+/// * `input` - The token exchange request parameters.
+///
+/// # Returns
+///
+/// A JSON response containing the issued token or an error message.
+#[utoipa::path(
+    post,
+    path = "/tokens/exchange",
+    request_body(content = TokenExchangeInput, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Token successfully exchanged", body = TokenExchangeOutput),
+        (status = 400, description = "Invalid request, token type or subject/actor token", body = String, content_type = "text/plain"),
+        (status = 403, description = "Claims violate the signing key's issuer or audience constraints", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to sign token", body = String, content_type = "text/plain"),
+        (status = 503, description = "No active signing key", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn token_exchange_handler(
+    store: web::Data<Arc<dyn KeyStore>>,
+    input: web::Form<TokenExchangeInput>) -> impl Responder {
+    if input.grant_type != GRANT_TYPE_TOKEN_EXCHANGE {
+        return HttpResponse::BadRequest().body("Unsupported grant type");
+    }
+
+    let supported_token_type = |token_type: &str| {
+        token_type == TOKEN_TYPE_JWT || token_type == TOKEN_TYPE_ACCESS_TOKEN
+    };
+    if !supported_token_type(&input.subject_token_type) {
+        return HttpResponse::BadRequest().body("Unsupported subject token type");
+    }
+    let issued_token_type = input
+        .requested_token_type
+        .clone()
+        .unwrap_or_else(|| TOKEN_TYPE_ACCESS_TOKEN.to_string());
+    if !supported_token_type(&issued_token_type) {
+        return HttpResponse::BadRequest().body("Unsupported requested token type");
+    }
+
+    let input = input.into_inner();
+    let is_access_token = issued_token_type == TOKEN_TYPE_ACCESS_TOKEN;
+    let now = Utc::now().timestamp();
+    let exchanged = run_blocking(move || {
+        let subject = verify_stored_token(&store, &input.subject_token)
+            .map_err(|message| KeyError::Invalid(format!("Invalid subject token: {}", message)))?;
+        let actor = match (&input.actor_token, &input.actor_token_type) {
+            (None, _) => None,
+            (Some(_), None) => return Err(KeyError::Invalid("actor_token_type is required".to_string())),
+            (Some(_), Some(token_type)) if !supported_token_type(token_type) => {
+                return Err(KeyError::Invalid("Unsupported actor token type".to_string()))
+            }
+            (Some(token), Some(_)) => Some(
+                verify_stored_token(&store, token)
+                    .map_err(|message| KeyError::Invalid(format!("Invalid actor token: {}", message)))?,
+            ),
+        };
+
+        let claims = exchanged_token_claims(
+            &subject,
+            actor.as_ref(),
+            input.audience.as_deref(),
+            input.scope.as_deref(),
+            now,
+            DEFAULT_ACCESS_TOKEN_LIFETIME_SECONDS,
+        )
+        .map_err(KeyError::Invalid)?;
+
+        let jwk_result = find_active_signing_jwk(&store, None)?;
+
+        check_token_constraints(&jwk_result, &claims).map_err(KeyError::Forbidden)?;
+
+        let typ = if is_access_token { "at+jwt" } else { "JWT" };
+        let token = serde_json::to_vec(&claims)
+            .map_err(Box::from)
+            .and_then(|payload| encode_jws(&jwk_result, typ, &payload))
+            .map_err(|_| KeyError::Internal("Failed to sign token".to_string()))?;
+        Ok((token, claims["exp"].as_i64().unwrap_or(now) - now))
+    })
+    .await;
+
+    match exchanged {
+        Ok((token, expires_in)) => HttpResponse::Ok().json(TokenExchangeOutput {
+            access_token: token,
+            issued_token_type,
+            token_type: "Bearer".to_string(),
+            expires_in,
+        }),
+        Err(error) => HttpResponse::from(error),
+    }
+}
+
+/// Handles the request to validate a DPoP proof (RFC 9449).
+///
+/// # Arguments
+///
+/// * `input` - The proof and the request it was sent with.
+///
+/// # Returns
+///
+/// A JSON response containing the confirmed JWK thumbprint or an error message.
+#[utoipa::path(
+    post,
+    path = "/dpop/validate",
+    request_body = DpopValidationInput,
+    responses(
+        (status = 200, description = "Proof is valid", body = DpopValidationOutput),
+        (status = 400, description = "Proof is invalid or has already been used", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn dpop_validation_handler(input: web::Json<DpopValidationInput>) -> impl Responder {
+    let now = Utc::now().timestamp();
+
+    let proof = match validate_dpop_proof(
+        &input.proof,
+        &input.htm,
+        &input.htu,
+        input.access_token.as_deref(),
+        now,
+    ) {
+        Ok(proof) => proof,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+
+    if let Err(message) = check_dpop_replay(&proof, now) {
+        return HttpResponse::BadRequest().body(message);
+    }
+
+    HttpResponse::Ok().json(DpopValidationOutput { jkt: proof.jkt })
+}
+
+/// Handles the request to sign an authorization request object (RFC 9101) for FAPI clients.
+///
+/// # Arguments
+///
+/// * `input` - The input data containing the key ID and the authorization request parameters.
+///
+/// # Returns
+///
+/// A JSON response containing the signed request object or an error message.
+#[utoipa::path(
+    post,
+    path = "/request-objects",
+    request_body = RequestObjectInput,
+    responses(
+        (status = 200, description = "Request object successfully signed", body = SignOutput),
+        (status = 400, description = "Required claims are missing or invalid, or the key cannot be used for signing", body = String, content_type = "text/plain"),
+        (status = 403, description = "Claims violate the key's issuer or audience constraints", body = String, content_type = "text/plain"),
+        (status = 404, description = "Key not found", body = String, content_type = "text/plain"),
+        (status = 410, description = "Private key expired", body = String, content_type = "text/plain"),
+        (status = 409, description = "External key has no private key, or the key is not active yet", body = String, content_type = "text/plain"),
+        (status = 423, description = "Key is frozen", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to sign request object", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn request_object_handler(store: web::Data<Arc<dyn KeyStore>>, input: web::Json<RequestObjectInput>) -> impl Responder {
+    let input = input.into_inner();
+    let signed = run_blocking(move || {
+        let jwk_result = find_signing_jwk(&store, input.id)?;
+
+        if key_use_for_alg(&jwk_result.alg) != "sig" {
+            return Err(KeyError::Invalid("Key cannot be used for signing".to_string()));
+        }
+
+        let claims = request_object_claims(&input.claims, Utc::now().timestamp()).map_err(KeyError::Invalid)?;
+
+        check_token_constraints(&jwk_result, &claims).map_err(KeyError::Forbidden)?;
+
+        serde_json::to_vec(&claims)
+            .map_err(Box::from)
+            .and_then(|payload| encode_jws(&jwk_result, "oauth-authz-req+jwt", &payload))
+            .map_err(|_| KeyError::Internal("Failed to sign request object".to_string()))
+    })
+    .await;
+
+    match signed {
+        Ok(token) => HttpResponse::Ok().json(SignOutput { token }),
+        Err(error) => HttpResponse::from(error),
+    }
+}
+
+/// Handles the request to sign a PASETO `v4.public` token with a stored Ed25519 key.
+///
+/// The footer carries the `kid` of the key so that verifiers can select it from the JWK Set.
+///
+/// # Arguments
+///
+/// * `input` - The input data containing the key ID and the claims to sign.
+///
+/// # Returns
+///
+/// A JSON response containing the signed token or an error message.
+#[utoipa::path(
+    post,
+    path = "/paseto/sign",
+    request_body = PasetoSignInput,
+    responses(
+        (status = 200, description = "Token successfully signed", body = SignOutput),
+        (status = 400, description = "Key is not an Ed25519 key or the claims are not a JSON object", body = String, content_type = "text/plain"),
+        (status = 403, description = "Claims violate the key's issuer or audience constraints", body = String, content_type = "text/plain"),
+        (status = 404, description = "Key not found", body = String, content_type = "text/plain"),
+        (status = 410, description = "Private key expired", body = String, content_type = "text/plain"),
+        (status = 409, description = "External key has no private key, or the key is not active yet", body = String, content_type = "text/plain"),
+        (status = 423, description = "Key is frozen", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to sign token", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn paseto_sign_handler(store: web::Data<Arc<dyn KeyStore>>, input: web::Json<PasetoSignInput>) -> impl Responder {
+    let input = input.into_inner();
+    let signed = run_blocking(move || {
+        let jwk_result = find_signing_jwk(&store, input.id)?;
+
+        if !is_v4_public_key(&jwk_result.alg, jwk_result.crv.as_deref()) {
+            return Err(KeyError::Invalid("PASETO v4.public requires an Ed25519 key".to_string()));
+        }
+        if !input.claims.is_object() {
+            return Err(KeyError::Invalid("Claims must be a JSON object".to_string()));
+        }
+
+        check_token_constraints(&jwk_result, &input.claims).map_err(KeyError::Forbidden)?;
+
+        let failed = || KeyError::Internal("Failed to sign token".to_string());
+        let mut footer = input.footer.clone().unwrap_or_default();
+        footer.insert("kid".to_string(), jwk_result.kid.clone().into());
+
+        let (message, footer) = match (serde_json::to_vec(&input.claims), serde_json::to_vec(&footer)) {
+            (Ok(message), Ok(footer)) => (message, footer),
+            _ => return Err(failed()),
+        };
+        let implicit_assertion = input.implicit_assertion.as_deref().unwrap_or_default();
+
+        sign_v4_public(&jwk_result, &message, &footer, implicit_assertion.as_bytes()).map_err(|_| failed())
+    })
+    .await;
+
+    match signed {
+        Ok(token) => HttpResponse::Ok().json(SignOutput { token }),
+        Err(error) => HttpResponse::from(error),
+    }
+}
+
+/// Handles the request to sign a CBOR Web Token (RFC 8392) as COSE_Sign1 with a stored EC or
+/// OKP key.
+///
+/// # Arguments
+///
+/// * `input` - The input data containing the key ID and the claims to sign.
+///
+/// # Returns
+///
+/// A JSON response containing the Base64URL encoded COSE_Sign1 structure or an error message.
+#[utoipa::path(
+    post,
+    path = "/cwt/sign",
+    request_body = CwtSignInput,
+    responses(
+        (status = 200, description = "Token successfully signed", body = SignOutput),
+        (status = 400, description = "Key cannot sign CWTs, or the claims or external AAD are invalid", body = String, content_type = "text/plain"),
+        (status = 403, description = "Claims violate the key's issuer or audience constraints", body = String, content_type = "text/plain"),
+        (status = 404, description = "Key not found", body = String, content_type = "text/plain"),
+        (status = 410, description = "Private key expired", body = String, content_type = "text/plain"),
+        (status = 409, description = "External key has no private key, or the key is not active yet", body = String, content_type = "text/plain"),
+        (status = 423, description = "Key is frozen", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to sign token", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn cwt_sign_handler(store: web::Data<Arc<dyn KeyStore>>, input: web::Json<CwtSignInput>) -> impl Responder {
+    let input = input.into_inner();
+    let signed = run_blocking(move || {
+        let jwk_result = find_signing_jwk(&store, input.id)?;
+
+        if cose_algorithm(&jwk_result.alg).is_none() {
+            return Err(KeyError::Invalid("Key cannot sign CBOR Web Tokens".to_string()));
+        }
+        if !input.claims.is_object() {
+            return Err(KeyError::Invalid("Claims must be a JSON object".to_string()));
+        }
+
+        let external_aad = match input.external_aad.as_deref().map(|aad| URL_SAFE_NO_PAD.decode(aad)) {
+            None => Vec::new(),
+            Some(Ok(external_aad)) => external_aad,
+            Some(Err(_)) => return Err(KeyError::Invalid("external_aad must be Base64URL encoded".to_string())),
+        };
+
+        check_token_constraints(&jwk_result, &input.claims).map_err(KeyError::Forbidden)?;
+
+        sign_cwt(&jwk_result, &input.claims, &external_aad)
+            .map_err(|_| KeyError::Internal("Failed to sign token".to_string()))
+    })
+    .await;
+
+    match signed {
+        Ok(token) => HttpResponse::Ok().json(SignOutput {
+            token: URL_SAFE_NO_PAD.encode(token),
+        }),
+        Err(error) => HttpResponse::from(error),
+    }
+}
+
+/// Handles the request to retrieve the signed JWK Set (OpenID Federation `signed_jwks_uri`).
+///
+/// The active keys are published as a `jwk-set+jwt` JWT signed with the key designated by
+/// `FEDERATION_KEY_ID`, issued for the entity `FEDERATION_ENTITY_ID`.
+///
+/// # Returns
+///
+/// The signed JWK Set or an error message.
+#[utoipa::path(
+    get,
+    path = "/.well-known/signed-jwks.jwt",
+    responses(
+        (status = 200, description = "Signed JWK Set", body = String, content_type = "application/jwk-set+jwt"),
+        (status = 400, description = "Federation key cannot be used for signing", body = String, content_type = "text/plain"),
+        (status = 404, description = "Signed JWK Set is not configured, or the federation key was not found", body = String, content_type = "text/plain"),
+        (status = 410, description = "Federation private key expired", body = String, content_type = "text/plain"),
+        (status = 423, description = "Federation key is frozen", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to sign the JWK Set", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn signed_jwks_handler(store: web::Data<Arc<dyn KeyStore>>) -> impl Responder {
+    let config = match federation_config() {
+        Some(config) => config,
+        None => return HttpResponse::NotFound().body("Signed JWK Set is not configured"),
+    };
+
+    let signed = run_blocking(move || {
+        let signing_key = find_signing_jwk(&store, config.key_id)?;
+
+        if key_use_for_alg(&signing_key.alg) != "sig" {
+            return Err(KeyError::Invalid("Key cannot be used for signing".to_string()));
+        }
+
+        // Same keys as /.well-known/jwks.json
+        let results = store.list_active(None, None).map_err(|error| {
+            log_error!("Failed to load keys: {}", error);
+            KeyError::Internal("Failed to load keys".to_string())
+        })?;
+        let public_jwks = results.into_iter().map(Jwk::from).collect::<Vec<_>>();
+
+        sign_jwks(&signing_key, &config, &public_jwks, Utc::now().timestamp())
+            .map_err(|_| KeyError::Internal("Failed to sign the JWK Set".to_string()))
+    })
+    .await;
+
+    match signed {
+        Ok(token) => HttpResponse::Ok()
+            .content_type("application/jwk-set+jwt")
+            .body(token),
+        Err(error) => HttpResponse::from(error),
+    }
+}
+
+/// Handles the request to retrieve SAML 2.0 metadata with the active signing certificates.
+///
+/// Every active key with an X.509 certificate is listed as a signing `KeyDescriptor`.
+///
+/// # Returns
+///
+/// The SAML metadata XML document or an error message.
+#[utoipa::path(
+    get,
+    path = "/saml/metadata.xml",
+    responses(
+        (status = 200, description = "SAML 2.0 metadata document", content_type = "application/samlmetadata+xml"),
+        (status = 404, description = "SAML metadata is not configured", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn saml_metadata_handler(store: web::Data<Arc<dyn KeyStore>>) -> impl Responder {
+    let config = match saml_metadata_config() {
+        Some(config) => config,
+        None => return HttpResponse::NotFound().body("SAML metadata is not configured"),
+    };
+
+    let results = run_blocking(move || {
+        // Same keys as the JWK Set, oldest first
+        let mut results = store.list_active(None, None).map_err(|error| {
+            log_error!("Failed to load keys: {}", error);
+            KeyError::Internal("Failed to load keys".to_string())
+        })?;
+        results.sort_by_key(|jwk| jwk.created_at);
+        Ok(results)
+    })
+    .await;
+    let results = match results {
+        Ok(results) => results,
+        Err(error) => return HttpResponse::from(error),
+    };
+
+    // SAML expects standard Base64 DER certificates
+    let certificates = results
+        .into_iter()
+        .filter_map(|jwk| {
+            let der = URL_SAFE_NO_PAD.decode(jwk.x5c?.first()?).ok()?;
+            Some((jwk.kid, STANDARD.encode(der)))
+        })
+        .collect::<Vec<_>>();
+
+    HttpResponse::Ok()
+        .content_type("application/samlmetadata+xml")
+        .body(saml_metadata(&config, &certificates))
+}
+
+/// Handles the request to issue a Selective Disclosure JWT (SD-JWT).
+///
+/// # Arguments
+///
+/// * `input` - The input data containing the key ID, the claims and the disclosable claim names.
+///
+/// # Returns
+///
+/// A JSON response containing the SD-JWT with its disclosures or an error message.
+#[utoipa::path(
+    post,
+    path = "/sd-jwt/issue",
+    request_body = SdJwtIssueInput,
+    responses(
+        (status = 200, description = "SD-JWT successfully issued", body = SignOutput),
+        (status = 400, description = "Claims, disclosable claims or holder key are invalid, or the key cannot be used for signing", body = String, content_type = "text/plain"),
+        (status = 403, description = "Claims violate the key's issuer or audience constraints", body = String, content_type = "text/plain"),
+        (status = 404, description = "Key not found", body = String, content_type = "text/plain"),
+        (status = 410, description = "Private key expired", body = String, content_type = "text/plain"),
+        (status = 409, description = "External key has no private key, or the key is not active yet", body = String, content_type = "text/plain"),
+        (status = 423, description = "Key is frozen", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn sd_jwt_issue_handler(store: web::Data<Arc<dyn KeyStore>>, input: web::Json<SdJwtIssueInput>) -> impl Responder {
+    let input = input.into_inner();
+    let issued = run_blocking(move || {
+        let jwk_result = find_signing_jwk(&store, input.id)?;
+
+        if key_use_for_alg(&jwk_result.alg) != "sig" {
+            return Err(KeyError::Invalid("Key cannot be used for signing".to_string()));
+        }
+
+        check_token_constraints(&jwk_result, &input.claims).map_err(KeyError::Forbidden)?;
+
+        issue_sd_jwt(
+            &jwk_result,
+            &input.claims,
+            &input.disclosable,
+            input.holder_jwk.as_ref(),
+            input.typ.as_deref().unwrap_or(DEFAULT_SD_JWT_TYPE),
+        )
+        .map_err(|message| KeyError::Invalid(message.to_string()))
+    })
+    .await;
+
+    match issued {
+        Ok(token) => HttpResponse::Ok().json(SignOutput { token }),
+        Err(error) => HttpResponse::from(error),
     }
 }
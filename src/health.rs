@@ -0,0 +1,390 @@
+//! This module provides health checks for the JWK microservice.
+//!
+//! The crypto self-check verifies that the random number generator works and that a key can be
+//! generated, used for signing and verified for every enabled algorithm. Its latest result
+//! determines the readiness reported by `/readyz`.
+//!
+//! The stored key self-test loads every active key from the key store and runs a sign-verify
+//! round trip with it, so keys corrupted in storage (e.g. by a bad migration) are flagged before
+//! tokens stop validating.
+
+use crate::crypto::{
+    generate_jwk_data, key_details, key_use_for_alg, public_key_from_jwk, sign_with_jwk,
+    supported_algorithms, verify_with_jwk, verify_with_secret,
+};
+#[cfg(feature = "server")]
+use crate::db::database_failovers;
+#[cfg(feature = "server")]
+use crate::jobs::is_scheduler_leader;
+use crate::models::{Jwk, JwkData, StoredKeyFailure, StoredKeySelfTest};
+use crate::policy::{allowed_algorithms, default_rsa_key_size, is_algorithm_allowed};
+use crate::store::KeyStore;
+use crate::{log_error, log_info};
+#[cfg(feature = "server")]
+use actix_web::{web, HttpResponse, Responder};
+use chrono::Utc;
+use dotenv::dotenv;
+use std::env;
+#[cfg(feature = "server")]
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Latest crypto self-check result; `None` until the first check completes.
+static CRYPTO_SELF_CHECK: Mutex<Option<Result<(), String>>> = Mutex::new(None);
+
+/// Latest stored key self-test result; `None` until the first self-test completes.
+static STORED_KEY_SELF_TEST: Mutex<Option<StoredKeySelfTest>> = Mutex::new(None);
+
+/// Response header reporting whether the replica is the scheduler leader.
+pub const SCHEDULER_ROLE_HEADER: &str = "X-Scheduler-Role";
+
+/// Response header reporting the number of database failovers observed since startup.
+pub const DATABASE_FAILOVERS_HEADER: &str = "X-Database-Failovers";
+
+/// Checks that the OpenSSL random number generator produces usable output.
+///
+/// Two consecutive blocks are drawn; the check fails if OpenSSL reports an error, if a block
+/// is all zeros or if both blocks are identical.
+///
+/// # Errors
+///
+/// Returns a message describing the failure.
+pub fn check_rng() -> Result<(), String> {
+    let mut first = [0u8; 32];
+    let mut second = [0u8; 32];
+    openssl::rand::rand_bytes(&mut first).map_err(|e| format!("RNG failure: {}", e))?;
+    openssl::rand::rand_bytes(&mut second).map_err(|e| format!("RNG failure: {}", e))?;
+
+    if first.iter().all(|byte| *byte == 0) || second.iter().all(|byte| *byte == 0) {
+        return Err("RNG failure: produced an all-zero block".to_string());
+    }
+    if first == second {
+        return Err("RNG failure: produced identical consecutive blocks".to_string());
+    }
+
+    Ok(())
+}
+
+/// Runs a sign-verify round trip with a generated key pair.
+///
+/// The signature is verified with the published public JWK, so encoding errors in the public
+/// parameters are detected. MACs of symmetric keys are verified with their secret.
+/// Encryption-only keys are not checked.
+///
+/// # Errors
+///
+/// Returns a message describing the failure.
+pub fn verify_key_pair(jwk: &JwkData) -> Result<(), String> {
+    if key_use_for_alg(&jwk.alg) != "sig" {
+        return Ok(());
+    }
+
+    let control_data = b"CRYPTO_SELF_CHECK";
+    let signature = sign_with_jwk(jwk, control_data)
+        .map_err(|e| format!("signing failed: {}", e))?;
+    let verified = if jwk.is_symmetric() {
+        verify_with_secret(jwk, control_data, &signature)
+    } else {
+        verify_with_jwk(&Jwk::from(jwk.clone()), control_data, &signature)
+    }
+    .map_err(|e| format!("verification failed: {}", e))?;
+
+    if verified {
+        Ok(())
+    } else {
+        Err("signature did not verify".to_string())
+    }
+}
+
+/// Runs a generate-sign-verify round trip for a single algorithm.
+///
+/// Encryption-only algorithms are only checked for successful key generation.
+///
+/// # Errors
+///
+/// Returns a message describing the failure.
+pub fn check_algorithm(alg: &str, rsa_key_size: u32) -> Result<(), String> {
+    let jwk = generate_jwk_data(alg, rsa_key_size)
+        .map_err(|e| format!("{}: key generation failed: {}", alg, e))?;
+
+    verify_key_pair(&jwk).map_err(|message| format!("{}: {}", alg, message))
+}
+
+/// Runs the crypto self-check: RNG health plus a round trip for every enabled algorithm.
+///
+/// Enabled algorithms are the supported algorithms permitted by `ALLOWED_ALGORITHMS`.
+///
+/// # Errors
+///
+/// Returns the messages of every failed check joined with `; `.
+pub fn run_crypto_self_check() -> Result<(), String> {
+    let mut failures = Vec::new();
+
+    if let Err(message) = check_rng() {
+        failures.push(message);
+    }
+
+    let allowed = allowed_algorithms();
+    let rsa_key_size = default_rsa_key_size();
+    for alg in supported_algorithms() {
+        if !is_algorithm_allowed(alg, allowed.as_deref()) {
+            continue;
+        }
+        if let Err(message) = check_algorithm(alg, rsa_key_size) {
+            failures.push(message);
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures.join("; "))
+    }
+}
+
+/// Stores the result of a crypto self-check so it is reflected by `/readyz`.
+pub fn record_crypto_self_check(result: Result<(), String>) {
+    match &result {
+        Ok(()) => log_info!("Crypto self-check passed."),
+        Err(message) => log_error!("Crypto self-check failed: {}", message),
+    }
+
+    *CRYPTO_SELF_CHECK.lock().unwrap() = Some(result);
+}
+
+/// Returns the interval between periodic crypto self-checks
+/// (`CRYPTO_SELF_CHECK_INTERVAL_SECONDS`, default 300).
+///
+/// # Returns
+///
+/// `None` if periodic checks are disabled (interval set to `0`).
+///
+/// # Panics
+///
+/// This function will panic if `CRYPTO_SELF_CHECK_INTERVAL_SECONDS` is not a number.
+pub fn crypto_self_check_interval() -> Option<Duration> {
+    dotenv().ok();
+
+    let seconds: u64 = env::var("CRYPTO_SELF_CHECK_INTERVAL_SECONDS")
+        .unwrap_or_else(|_| "300".to_string())
+        .parse()
+        .expect("CRYPTO_SELF_CHECK_INTERVAL_SECONDS must be a number");
+
+    if seconds == 0 {
+        None
+    } else {
+        Some(Duration::from_secs(seconds))
+    }
+}
+
+/// Checks a stored key: its public parameters must decode and a sign-verify round trip with the
+/// published public JWK must succeed. External keys have no private key, so only their public
+/// key is decoded.
+///
+/// # Errors
+///
+/// Returns a message describing the failure.
+pub fn check_stored_key(jwk: &JwkData) -> Result<(), String> {
+    key_details(jwk, Utc::now().naive_utc())
+        .map_err(|e| format!("failed to decode the key: {}", e))?;
+
+    if jwk.external {
+        return public_key_from_jwk(&Jwk::from(jwk.clone()))
+            .map(|_| ())
+            .map_err(|e| format!("failed to decode the public key: {}", e));
+    }
+
+    verify_key_pair(jwk)
+}
+
+/// Runs the stored key self-test on every active (not deleted and not expired) key of `store`
+/// and stores the result for `/jwks/self-test`.
+///
+/// # Errors
+///
+/// Returns an error if the keys cannot be loaded.
+pub fn run_stored_key_self_test(store: &dyn KeyStore) -> Result<StoredKeySelfTest, String> {
+    let now = Utc::now().naive_utc();
+    let keys = store.live_keys()?;
+
+    let failures = keys
+        .iter()
+        .filter_map(|jwk| {
+            check_stored_key(jwk).err().map(|error| StoredKeyFailure {
+                id: jwk.id,
+                kid: jwk.kid.clone(),
+                alg: jwk.alg.clone(),
+                error,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for failure in &failures {
+        log_error!("Stored key self-test failed for key {}: {}", failure.id, failure.error);
+    }
+    log_info!(
+        "Stored key self-test checked {} keys, {} failed.",
+        keys.len(),
+        failures.len()
+    );
+
+    let report = StoredKeySelfTest {
+        checked_at: now,
+        checked: keys.len(),
+        failures,
+    };
+    *STORED_KEY_SELF_TEST.lock().unwrap() = Some(report.clone());
+
+    Ok(report)
+}
+
+/// Returns the latest stored key self-test result, if the self-test has run.
+pub fn last_stored_key_self_test() -> Option<StoredKeySelfTest> {
+    STORED_KEY_SELF_TEST.lock().unwrap().clone()
+}
+
+/// Returns whether the stored key self-test runs on startup (`STORED_KEY_SELF_TEST_ON_START`,
+/// default `0`).
+pub fn stored_key_self_test_on_start() -> bool {
+    dotenv().ok();
+
+    env::var("STORED_KEY_SELF_TEST_ON_START").map(|value| value == "1").unwrap_or(false)
+}
+
+/// Handles the liveness probe.
+///
+/// # Returns
+///
+/// `200 OK` as long as the process serves requests; unlike `/readyz`, the crypto self-check is not
+/// taken into account, so a failing check does not get the replica restarted.
+#[cfg(feature = "server")]
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    responses(
+        (status = 200, description = "Service is alive", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn healthz_handler() -> impl Responder {
+    HttpResponse::Ok().body("ok")
+}
+
+/// Handles the readiness probe.
+///
+/// The `X-Scheduler-Role` header reports whether the replica is the scheduler `leader` or a
+/// `follower`, and `X-Database-Failovers` the number of database failovers observed since startup;
+/// neither affects readiness.
+///
+/// # Returns
+///
+/// `200 OK` if the latest crypto self-check passed, `503 Service Unavailable` otherwise.
+#[cfg(feature = "server")]
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    responses(
+        (status = 200, description = "Service is ready", body = String, content_type = "text/plain",
+            headers(("X-Scheduler-Role" = String, description = "Scheduler role of the replica (`leader` or `follower`)"),
+                ("X-Database-Failovers" = String, description = "Number of database failovers observed since startup"))),
+        (status = 503, description = "Crypto self-check failed or has not completed yet", body = String, content_type = "text/plain",
+            headers(("X-Scheduler-Role" = String, description = "Scheduler role of the replica (`leader` or `follower`)"),
+                ("X-Database-Failovers" = String, description = "Number of database failovers observed since startup")))
+    )
+)]
+pub async fn readyz_handler() -> impl Responder {
+    let role = if is_scheduler_leader() { "leader" } else { "follower" };
+    let (mut response, body) = match &*CRYPTO_SELF_CHECK.lock().unwrap() {
+        Some(Ok(())) => (HttpResponse::Ok(), "ready".to_string()),
+        Some(Err(message)) => (
+            HttpResponse::ServiceUnavailable(),
+            format!("Crypto self-check failed: {}", message),
+        ),
+        None => (
+            HttpResponse::ServiceUnavailable(),
+            "Crypto self-check has not completed yet".to_string(),
+        ),
+    };
+
+    response
+        .insert_header((SCHEDULER_ROLE_HEADER, role))
+        .insert_header((DATABASE_FAILOVERS_HEADER, database_failovers().to_string()))
+        .body(body)
+}
+
+/// Handles the request for the latest stored key self-test result.
+///
+/// # Returns
+///
+/// A JSON response containing the result, or `404 Not Found` if the self-test has not run.
+#[cfg(feature = "server")]
+#[utoipa::path(
+    get,
+    path = "/jwks/self-test",
+    responses(
+        (status = 200, description = "Latest stored key self-test result", body = StoredKeySelfTest),
+        (status = 404, description = "Stored key self-test has not run", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn stored_key_self_test_handler() -> impl Responder {
+    match last_stored_key_self_test() {
+        Some(report) => HttpResponse::Ok().json(report),
+        None => HttpResponse::NotFound().body("Stored key self-test has not run"),
+    }
+}
+
+/// Handles the request to run the stored key self-test now.
+///
+/// # Returns
+///
+/// A JSON response containing the result.
+#[cfg(feature = "server")]
+#[utoipa::path(
+    post,
+    path = "/jwks/self-test",
+    responses(
+        (status = 200, description = "Stored key self-test result", body = StoredKeySelfTest),
+        (status = 500, description = "Failed to load keys", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn run_stored_key_self_test_handler(store: web::Data<Arc<dyn KeyStore>>) -> impl Responder {
+    match web::block(move || run_stored_key_self_test(store.as_ref().as_ref())).await {
+        Ok(Ok(report)) => HttpResponse::Ok().json(report),
+        _ => HttpResponse::InternalServerError().body("Failed to load keys"),
+    }
+}
+
+#[test]
+fn test_check_rng() {
+    assert!(check_rng().is_ok());
+}
+
+#[test]
+fn test_check_algorithm() {
+    assert!(check_algorithm("ES256", 2048).is_ok());
+    assert!(check_algorithm("Ed25519", 2048).is_ok());
+    assert!(check_algorithm("HS999", 2048).is_err());
+}
+
+#[test]
+fn test_check_stored_key_detects_corrupt_key() {
+    let jwk = generate_jwk_data("RS256", 2048).unwrap();
+    assert!(check_stored_key(&jwk).is_ok());
+
+    let corrupt = JwkData { private_key: "AAAA".to_string(), ..jwk.clone() };
+    assert!(check_stored_key(&corrupt).is_err());
+
+    let corrupt = JwkData { n: Some("!".to_string()), ..jwk };
+    assert!(check_stored_key(&corrupt).is_err());
+}
+
+#[test]
+fn test_verify_key_pair_detects_corrupt_public_key() {
+    let jwk = generate_jwk_data("ES256", 2048).unwrap();
+    assert!(verify_key_pair(&jwk).is_ok());
+
+    // Public coordinates that do not belong to the private key
+    let other = generate_jwk_data("ES256", 2048).unwrap();
+    let corrupt = JwkData { x: other.x, y: other.y, ..jwk };
+    assert!(verify_key_pair(&corrupt).is_err());
+}
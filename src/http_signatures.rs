@@ -0,0 +1,179 @@
+//! This module signs responses with HTTP Message Signatures (RFC 9421).
+//!
+//! When `JWKS_SIGNATURE_KEY_ID` designates a signing key, `/.well-known/jwks.json` responses
+//! carry a `Content-Digest` (RFC 9530) of the body and a `jwks` signature over the status, the
+//! content type and that digest. Consumers can thus verify the key set against the designated
+//! key even when TLS is terminated by a CDN in front of the service.
+
+use crate::crypto::sign_with_jwk;
+use crate::models::JwkData;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use dotenv::dotenv;
+use std::env;
+use std::error::Error;
+use uuid::Uuid;
+
+/// Label of the signature of JWK Set responses.
+pub const JWKS_SIGNATURE_LABEL: &str = "jwks";
+
+/// Components covered by response signatures.
+pub const COVERED_COMPONENTS: [&str; 3] = ["@status", "content-type", "content-digest"];
+
+/// Headers carrying a response signature.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseSignature {
+    /// Value of the `Content-Digest` header.
+    pub content_digest: String,
+    /// Value of the `Signature-Input` header.
+    pub signature_input: String,
+    /// Value of the `Signature` header.
+    pub signature: String,
+}
+
+/// Returns the ID of the key signing JWK Set responses (`JWKS_SIGNATURE_KEY_ID`).
+///
+/// # Returns
+///
+/// `None` if JWK Set responses are not signed.
+///
+/// # Panics
+///
+/// This function will panic if `JWKS_SIGNATURE_KEY_ID` is not a UUID.
+pub fn jwks_signature_key_id() -> Option<Uuid> {
+    dotenv().ok();
+
+    env::var("JWKS_SIGNATURE_KEY_ID")
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+        .map(|value| value.trim().parse().expect("JWKS_SIGNATURE_KEY_ID must be a UUID"))
+}
+
+/// Returns the `Content-Digest` of a body, a SHA-256 digest as a structured field byte sequence.
+pub fn content_digest(body: &[u8]) -> String {
+    format!("sha-256=:{}:", STANDARD.encode(openssl::sha::sha256(body)))
+}
+
+/// Returns the HTTP Signature Algorithms registry name of a key's algorithm.
+///
+/// # Returns
+///
+/// `None` for algorithms without a registered name; their signatures are JWS signatures
+/// (RFC 9421, section 3.3.7) and the `alg` parameter is omitted.
+pub fn signature_algorithm(jwk: &JwkData) -> Option<&'static str> {
+    match (jwk.alg.as_str(), jwk.crv.as_deref()) {
+        ("RS256", _) => Some("rsa-v1_5-sha256"),
+        ("ES256", _) => Some("ecdsa-p256-sha256"),
+        ("ES384", _) => Some("ecdsa-p384-sha384"),
+        ("EdDSA", Some("Ed25519")) => Some("ed25519"),
+        _ => None,
+    }
+}
+
+/// Returns the signature parameters (`@signature-params`) of a response signature.
+///
+/// # Arguments
+///
+/// * `kid` - Key ID of the signing key.
+/// * `alg` - Registered signature algorithm, if any.
+/// * `created` - Signature creation time as a Unix timestamp.
+pub fn signature_params(kid: &str, alg: Option<&str>, created: i64) -> String {
+    let components = COVERED_COMPONENTS
+        .iter()
+        .map(|component| format!("\"{}\"", component))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut params = format!("({});created={};keyid=\"{}\"", components, created, kid);
+    if let Some(alg) = alg {
+        params.push_str(&format!(";alg=\"{}\"", alg));
+    }
+    params
+}
+
+/// Builds the signature base (RFC 9421, section 2.5) of a response.
+///
+/// # Arguments
+///
+/// * `status` - Response status code.
+/// * `content_type` - Value of the `Content-Type` header.
+/// * `content_digest` - Value of the `Content-Digest` header.
+/// * `params` - Signature parameters, see [`signature_params`].
+pub fn signature_base(status: u16, content_type: &str, content_digest: &str, params: &str) -> String {
+    format!(
+        "\"@status\": {}\n\"content-type\": {}\n\"content-digest\": {}\n\"@signature-params\": {}",
+        status, content_type, content_digest, params
+    )
+}
+
+/// Signs a response.
+///
+/// # Arguments
+///
+/// * `signing_key` - Signing key, including its private part.
+/// * `status` - Response status code.
+/// * `content_type` - Value of the `Content-Type` header.
+/// * `body` - Response body.
+/// * `created` - Signature creation time as a Unix timestamp.
+///
+/// # Errors
+///
+/// Returns an error if the key cannot be used for signing.
+pub fn sign_response(
+    signing_key: &JwkData,
+    status: u16,
+    content_type: &str,
+    body: &[u8],
+    created: i64,
+) -> Result<ResponseSignature, Box<dyn Error>> {
+    let content_digest = content_digest(body);
+    let params = signature_params(&signing_key.kid, signature_algorithm(signing_key), created);
+    let base = signature_base(status, content_type, &content_digest, &params);
+    let signature = sign_with_jwk(signing_key, base.as_bytes())?;
+
+    Ok(ResponseSignature {
+        content_digest,
+        signature_input: format!("{}={}", JWKS_SIGNATURE_LABEL, params),
+        signature: format!("{}=:{}:", JWKS_SIGNATURE_LABEL, STANDARD.encode(signature)),
+    })
+}
+
+#[test]
+fn test_content_digest() {
+    // RFC 9530, appendix B.1
+    assert_eq!(
+        content_digest(b"{\"hello\": \"world\"}\n"),
+        "sha-256=:RK/0qy18MlBSVnWgjwz6lZEWjP/lF5HF9bvEF8FabDg=:"
+    );
+}
+
+#[test]
+fn test_sign_response() {
+    use crate::crypto::{generate_jwk_data, verify_with_jwk};
+    use crate::models::Jwk;
+
+    for alg in ["ES256", "Ed25519", "RS384"] {
+        let signing_key = generate_jwk_data(alg, 2048).unwrap();
+        let body = br#"{"keys":[]}"#;
+        let signed = sign_response(&signing_key, 200, "application/json", body, 1_700_000_000).unwrap();
+        assert_eq!(signed.content_digest, content_digest(body));
+
+        let params = signed
+            .signature_input
+            .strip_prefix("jwks=")
+            .unwrap();
+        assert!(params.starts_with("(\"@status\" \"content-type\" \"content-digest\");created=1700000000;"));
+        assert!(params.contains(&format!("keyid=\"{}\"", signing_key.kid)));
+        assert_eq!(
+            params.contains(";alg="),
+            signature_algorithm(&signing_key).is_some()
+        );
+
+        let signature = STANDARD
+            .decode(signed.signature.strip_prefix("jwks=:").unwrap().strip_suffix(':').unwrap())
+            .unwrap();
+        let base = signature_base(200, "application/json", &signed.content_digest, params);
+        assert!(verify_with_jwk(&Jwk::from(signing_key.clone()), base.as_bytes(), &signature).unwrap());
+        let tampered = signature_base(200, "application/json", &content_digest(b"{}"), params);
+        assert!(!verify_with_jwk(&Jwk::from(signing_key), tampered.as_bytes(), &signature).unwrap());
+    }
+}
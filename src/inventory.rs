@@ -0,0 +1,214 @@
+//! This module exports a flat inventory of the stored keys.
+//!
+//! The inventory lists one row per key with its identifiers, lifecycle status, dates, certificate
+//! expiry and labels (the aliases resolving to it), as CSV for compliance spreadsheets or as JSON
+//! lines for CMDB ingestion. It never contains key material.
+
+use crate::crypto::key_details;
+use crate::db::{snapshot_batches, DbPool};
+use crate::handlers::pooled_connection;
+use crate::models::{InventoryEntry, InventoryQuery, JwkData};
+use crate::schema::{jwks, key_aliases};
+use crate::streaming::{stream_listing, Listing};
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+/// Column names of the CSV inventory, in the order of [`csv_row`].
+pub const CSV_HEADER: [&str; 15] = [
+    "id",
+    "kid",
+    "kty",
+    "alg",
+    "purpose",
+    "status",
+    "primary",
+    "created_at",
+    "private_key_expires_at",
+    "key_expires_at",
+    "deleted_at",
+    "certificate_not_after",
+    "labels",
+    "external",
+    "not_before",
+];
+
+/// Builds the inventory row of a key.
+///
+/// # Arguments
+///
+/// * `jwk` - Stored key.
+/// * `labels` - Names of the aliases resolving to the key.
+/// * `now` - Time the lifecycle status is evaluated at.
+pub fn inventory_entry(jwk: &JwkData, labels: Vec<String>, now: NaiveDateTime) -> InventoryEntry {
+    InventoryEntry {
+        id: jwk.id,
+        kid: jwk.kid.clone(),
+        kty: jwk.kty.clone(),
+        alg: jwk.alg.clone(),
+        purpose: jwk.purpose.clone(),
+        status: jwk.lifecycle_status(now).to_string(),
+        primary: jwk.is_primary,
+        created_at: jwk.created_at,
+        private_key_expires_at: jwk.private_key_expires_at,
+        key_expires_at: jwk.key_expires_at,
+        deleted_at: jwk.deleted_at,
+        // A certificate that cannot be decoded is reported without expiry
+        certificate_not_after: key_details(jwk, now).ok().and_then(|details| details.certificate_not_after),
+        labels,
+        external: jwk.external,
+        not_before: jwk.not_before,
+    }
+}
+
+/// Escapes a CSV field (RFC 4180).
+///
+/// Fields starting with `=`, `+`, `-` or `@` are prefixed with `'`, so spreadsheets do not
+/// evaluate them as formulas.
+pub fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+/// Formats an inventory row as a CSV line, with the labels separated by `;`.
+pub fn csv_row(entry: &InventoryEntry) -> String {
+    let date = |value: Option<NaiveDateTime>| value.map(|value| value.format("%Y-%m-%dT%H:%M:%S").to_string());
+    [
+        Some(entry.id.to_string()),
+        Some(entry.kid.clone()),
+        Some(entry.kty.clone()),
+        Some(entry.alg.clone()),
+        entry.purpose.clone(),
+        Some(entry.status.clone()),
+        Some(entry.primary.to_string()),
+        date(Some(entry.created_at)),
+        date(entry.private_key_expires_at),
+        date(entry.key_expires_at),
+        date(entry.deleted_at),
+        date(entry.certificate_not_after),
+        Some(entry.labels.join(";")),
+        Some(entry.external.to_string()),
+        date(entry.not_before),
+    ]
+    .iter()
+    .map(|field| csv_field(field.as_deref().unwrap_or_default()))
+    .collect::<Vec<_>>()
+    .join(",")
+}
+
+/// Handles the request to export the key inventory.
+///
+/// # Arguments
+///
+/// * `query` - The output format and whether deleted keys are listed.
+///
+/// # Returns
+///
+/// The inventory as CSV with a header line or as JSON lines, oldest key first. Large inventories
+/// are streamed.
+#[utoipa::path(
+    get,
+    path = "/jwks/inventory",
+    params(InventoryQuery),
+    responses(
+        (status = 200, description = "Key inventory as CSV (text/csv) or JSON lines (application/x-ndjson)", body = [InventoryEntry]),
+        (status = 400, description = "Unknown format", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to load keys", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn inventory_handler(pool: web::Data<DbPool>, query: web::Query<InventoryQuery>) -> impl Responder {
+    let format = query.format.as_deref().unwrap_or("csv");
+    if format != "csv" && format != "jsonl" {
+        return HttpResponse::BadRequest().body(format!("Unknown format {}, expected csv or jsonl", format));
+    }
+
+    let include_deleted = query.include_deleted.unwrap_or(false);
+    let csv = format == "csv";
+    let load = Box::new(move |connection: &mut PgConnection, offset: i64, limit: i64| {
+        let mut keys_query = jwks::table.order((jwks::created_at.asc(), jwks::id.asc())).into_boxed();
+        if !include_deleted {
+            keys_query = keys_query.filter(jwks::deleted_at.is_null());
+        }
+        let keys = keys_query.offset(offset).limit(limit).load::<JwkData>(connection)?;
+        let aliases = key_aliases::table
+            .filter(key_aliases::key_id.eq_any(keys.iter().map(|jwk| jwk.id)))
+            .select((key_aliases::key_id, key_aliases::name))
+            .order(key_aliases::name.asc())
+            .load::<(Uuid, String)>(connection)?;
+
+        let now = Utc::now().naive_utc();
+        Ok(keys
+            .iter()
+            .map(|jwk| {
+                let labels = aliases
+                    .iter()
+                    .filter(|(key_id, _)| *key_id == jwk.id)
+                    .map(|(_, name)| name.clone())
+                    .collect();
+                let entry = inventory_entry(jwk, labels, now);
+                if csv {
+                    format!("{}\r\n", csv_row(&entry))
+                } else {
+                    format!("{}\n", serde_json::to_string(&entry).unwrap_or_default())
+                }
+            })
+            .collect())
+    });
+
+    // Large inventories are streamed in batches
+    let listing = if csv {
+        Listing {
+            content_type: "text/csv; charset=utf-8",
+            prefix: format!("{}\r\n", CSV_HEADER.join(",")),
+            separator: "",
+            suffix: "",
+        }
+    } else {
+        Listing {
+            content_type: "application/x-ndjson",
+            prefix: String::new(),
+            separator: "",
+            suffix: "",
+        }
+    };
+    let connection = match pooled_connection(&pool) {
+        Ok(connection) => connection,
+        Err(error) => return HttpResponse::from(error),
+    };
+    stream_listing(listing, snapshot_batches(connection, load))
+        .await
+        .unwrap_or_else(|_| HttpResponse::InternalServerError().body("Failed to load keys"))
+}
+
+#[test]
+fn test_csv_row() {
+    assert_eq!(csv_field("plain"), "plain");
+    assert_eq!(csv_field("a,b"), "\"a,b\"");
+    assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    assert_eq!(csv_field("=SUM(A1)"), "'=SUM(A1)");
+
+    let mut jwk = crate::crypto::generate_jwk_data("RS256", 2048).unwrap();
+    let now = Utc::now().naive_utc();
+    jwk.key_expires_at = Some(now + chrono::TimeDelta::days(1));
+    let entry = inventory_entry(&jwk, vec!["signing".to_string(), "legacy".to_string()], now);
+    assert_eq!(entry.status, "active");
+    assert!(entry.certificate_not_after.is_some());
+
+    let row = csv_row(&entry);
+    let fields = row.split(',').collect::<Vec<_>>();
+    assert_eq!(fields.len(), CSV_HEADER.len());
+    assert_eq!(fields[1], jwk.kid);
+    assert_eq!(fields[4], "");
+    assert_eq!(fields[12], "signing;legacy");
+    assert_eq!(fields[13], "false");
+    assert_eq!(fields[14], "");
+}
@@ -0,0 +1,530 @@
+//! This module runs scheduled background jobs.
+//!
+//! Every replica schedules the same jobs, so each run is guarded by a Postgres advisory lock
+//! derived from the job name: the first replica to take the lock performs the run and the
+//! others skip it.
+//!
+//! On top of the per-job locks, replicas elect a leader through a lease in the database: the
+//! leader renews the lease periodically and is the only replica running scheduled jobs. If it
+//! dies, the lease lapses and another replica takes over.
+//!
+//! The latest run of every job is recorded in the `job_runs` table, so `/jobs` reports the same
+//! last run, next run and last error whichever replica serves it. `POST /jobs/{name}/run` runs a
+//! job immediately, under the same lock as its scheduled runs.
+
+use crate::db::establish_connection;
+use crate::models::JobStatus;
+use crate::schema::job_runs;
+use crate::{log_error, log_info};
+#[cfg(feature = "server")]
+use actix_web::{rt, web, HttpResponse, Responder};
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Bool, Timestamp, Varchar};
+use dotenv::dotenv;
+use std::collections::HashMap;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Name of the lease owning the scheduler.
+pub const SCHEDULER_LEASE: &str = "scheduler";
+
+/// Whether this replica currently holds the scheduler lease.
+static SCHEDULER_LEADER: AtomicBool = AtomicBool::new(false);
+
+/// Scheduled job, run with a connection holding the job's lock.
+pub type Job = fn(&mut PgConnection) -> Result<(), String>;
+
+/// Outcome of a job run that has not finished.
+pub const OUTCOME_RUNNING: &str = "running";
+
+/// Outcome of a successful job run.
+pub const OUTCOME_COMPLETED: &str = "completed";
+
+/// Outcome of a failed job run.
+pub const OUTCOME_FAILED: &str = "failed";
+
+/// Jobs scheduled by this replica, with their intervals.
+static SCHEDULED_JOBS: Mutex<Option<HashMap<&'static str, (Duration, Job)>>> = Mutex::new(None);
+
+/// Latest recorded run of a job.
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = job_runs)]
+struct JobRun {
+    name: String,
+    replica: String,
+    outcome: String,
+    started_at: NaiveDateTime,
+    finished_at: Option<NaiveDateTime>,
+    last_error: Option<String>,
+    last_error_at: Option<NaiveDateTime>,
+    next_run_at: Option<NaiveDateTime>,
+}
+
+/// Result row of the advisory lock functions.
+#[derive(QueryableByName)]
+struct AdvisoryLock {
+    #[diesel(sql_type = Bool)]
+    locked: bool,
+}
+
+/// Returns the advisory lock key of a job: the first 8 bytes of the SHA-256 of its name.
+pub fn job_lock_key(job: &str) -> i64 {
+    let digest = openssl::sha::sha256(job.as_bytes());
+    let mut key = [0u8; 8];
+    key.copy_from_slice(&digest[..8]);
+    i64::from_be_bytes(key)
+}
+
+/// Runs `run` if no other session holds the lock of the job.
+///
+/// The lock is a session-level advisory lock, so it is also released if the connection is
+/// closed while the job runs.
+///
+/// # Arguments
+///
+/// * `connection` - Connection holding the lock while `run` executes.
+/// * `job` - Name of the job.
+/// * `run` - Work to perform.
+///
+/// # Returns
+///
+/// The result of `run`, or `None` if another session holds the lock.
+///
+/// # Errors
+///
+/// Returns an error if the lock cannot be acquired or released.
+pub fn run_exclusive<T>(
+    connection: &mut PgConnection,
+    job: &str,
+    run: impl FnOnce(&mut PgConnection) -> T,
+) -> QueryResult<Option<T>> {
+    let key = job_lock_key(job);
+    let acquired = sql_query("SELECT pg_try_advisory_lock($1) AS locked")
+        .bind::<BigInt, _>(key)
+        .get_result::<AdvisoryLock>(connection)?
+        .locked;
+    if !acquired {
+        return Ok(None);
+    }
+
+    let result = run(connection);
+    sql_query("SELECT pg_advisory_unlock($1) AS locked")
+        .bind::<BigInt, _>(key)
+        .get_result::<AdvisoryLock>(connection)?;
+
+    Ok(Some(result))
+}
+
+/// Runs `run` while holding the lock of the job, waiting for other sessions to release it.
+///
+/// # Errors
+///
+/// Returns an error if the lock cannot be acquired or released.
+pub fn run_locked<T>(
+    connection: &mut PgConnection,
+    job: &str,
+    run: impl FnOnce(&mut PgConnection) -> T,
+) -> QueryResult<T> {
+    let key = job_lock_key(job);
+    sql_query("SELECT pg_advisory_lock($1) IS NOT NULL AS locked")
+        .bind::<BigInt, _>(key)
+        .get_result::<AdvisoryLock>(connection)?;
+
+    let result = run(connection);
+    sql_query("SELECT pg_advisory_unlock($1) AS locked")
+        .bind::<BigInt, _>(key)
+        .get_result::<AdvisoryLock>(connection)?;
+
+    Ok(result)
+}
+
+/// Returns the identifier of this replica in leader election (`REPLICA_ID`, `HOSTNAME` or a
+/// random UUID generated at startup).
+pub fn replica_id() -> &'static str {
+    static REPLICA_ID: OnceLock<String> = OnceLock::new();
+
+    REPLICA_ID.get_or_init(|| {
+        dotenv().ok();
+
+        ["REPLICA_ID", "HOSTNAME"]
+            .iter()
+            .filter_map(|name| env::var(name).ok())
+            .map(|value| value.trim().to_string())
+            .find(|value| !value.is_empty())
+            .unwrap_or_else(|| Uuid::new_v4().to_string())
+    })
+}
+
+/// Returns the duration of the scheduler lease (`SCHEDULER_LEASE_SECONDS`, default 30).
+///
+/// The leader renews the lease every third of its duration.
+///
+/// # Panics
+///
+/// This function will panic if `SCHEDULER_LEASE_SECONDS` is not a positive number.
+pub fn scheduler_lease_duration() -> Duration {
+    dotenv().ok();
+
+    let seconds: u64 = env::var("SCHEDULER_LEASE_SECONDS")
+        .unwrap_or_else(|_| "30".to_string())
+        .parse()
+        .ok()
+        .filter(|seconds| *seconds > 0)
+        .expect("SCHEDULER_LEASE_SECONDS must be a positive number");
+
+    Duration::from_secs(seconds)
+}
+
+/// Acquires or renews a lease for a holder.
+///
+/// The lease is granted if it does not exist, is already held by the holder or has lapsed.
+///
+/// # Arguments
+///
+/// * `connection` - Database connection.
+/// * `lease` - Name of the lease.
+/// * `holder` - Replica requesting the lease.
+/// * `duration` - Time until the lease lapses unless renewed.
+///
+/// # Returns
+///
+/// `true` if the holder owns the lease afterwards.
+///
+/// # Errors
+///
+/// Returns an error if the lease cannot be stored.
+pub fn try_acquire_lease(
+    connection: &mut PgConnection,
+    lease: &str,
+    holder: &str,
+    duration: Duration,
+) -> QueryResult<bool> {
+    let now = Utc::now().naive_utc();
+    let lease_duration = TimeDelta::from_std(duration).unwrap_or(TimeDelta::MAX);
+    let expires_at = now.checked_add_signed(lease_duration).unwrap_or(now);
+
+    // A conflicting lease is only taken over if it is ours or has lapsed
+    let granted = sql_query(
+        "INSERT INTO scheduler_lease (name, holder, expires_at) VALUES ($1, $2, $3) \
+         ON CONFLICT (name) DO UPDATE SET holder = EXCLUDED.holder, expires_at = EXCLUDED.expires_at \
+         WHERE scheduler_lease.holder = EXCLUDED.holder OR scheduler_lease.expires_at < $4",
+    )
+    .bind::<Varchar, _>(lease)
+    .bind::<Varchar, _>(holder)
+    .bind::<Timestamp, _>(expires_at)
+    .bind::<Timestamp, _>(now)
+    .execute(connection)?;
+
+    Ok(granted == 1)
+}
+
+/// Returns whether this replica is the scheduler leader.
+pub fn is_scheduler_leader() -> bool {
+    SCHEDULER_LEADER.load(Ordering::SeqCst)
+}
+
+/// Records the outcome of a leader election round, logging leadership changes.
+pub fn record_scheduler_leadership(leader: bool) {
+    let was_leader = SCHEDULER_LEADER.swap(leader, Ordering::SeqCst);
+    if leader && !was_leader {
+        log_info!("Replica {} became the scheduler leader.", replica_id());
+    } else if !leader && was_leader {
+        log_info!("Replica {} is no longer the scheduler leader.", replica_id());
+    }
+}
+
+/// Takes part in scheduler leader election, renewing or acquiring the lease every third of its
+/// duration.
+///
+/// Leadership is given up if the lease cannot be renewed, so a replica that lost its database
+/// connection stops running jobs before another replica takes over.
+#[cfg(feature = "server")]
+pub fn spawn_scheduler_election() {
+    let duration = scheduler_lease_duration();
+    rt::spawn(async move {
+        let mut ticker = rt::time::interval(duration / 3);
+        loop {
+            ticker.tick().await;
+            let result = web::block(move || {
+                let connection = &mut establish_connection();
+                try_acquire_lease(connection, SCHEDULER_LEASE, replica_id(), duration)
+            })
+            .await;
+            match result {
+                Ok(Ok(leader)) => record_scheduler_leadership(leader),
+                Ok(Err(e)) => {
+                    log_error!("Scheduler leader election failed: {}", e);
+                    record_scheduler_leadership(false);
+                }
+                Err(e) => {
+                    log_error!("Scheduler leader election failed: {}", e);
+                    record_scheduler_leadership(false);
+                }
+            }
+        }
+    });
+}
+
+/// Performs a single run of a job unless another replica is running it, recording the run in
+/// `job_runs`.
+///
+/// # Arguments
+///
+/// * `name` - Name of the job.
+/// * `job` - Work to perform.
+/// * `next_run_at` - Time of the next scheduled run; `None` for runs triggered on demand, which
+///   keep the recorded one.
+///
+/// # Returns
+///
+/// `true` if this replica performed the run, `false` if it was skipped.
+///
+/// # Errors
+///
+/// Returns a message if the lock cannot be handled or the job fails.
+pub fn run_job(name: &str, job: Job, next_run_at: Option<NaiveDateTime>) -> Result<bool, String> {
+    let connection = &mut establish_connection();
+    let run = |connection: &mut PgConnection| {
+        if let Err(e) = record_run_started(connection, name) {
+            log_error!("Failed to record the start of job {}: {}", name, e);
+        }
+        let result = job(connection);
+        if let Err(e) = record_run_finished(connection, name, &result, next_run_at) {
+            log_error!("Failed to record the outcome of job {}: {}", name, e);
+        }
+        result
+    };
+    match run_exclusive(connection, name, run) {
+        Ok(Some(result)) => result.map(|_| true),
+        Ok(None) => Ok(false),
+        Err(e) => Err(format!("lock failure: {}", e)),
+    }
+}
+
+/// Records that this replica started a run of a job.
+fn record_run_started(connection: &mut PgConnection, name: &str) -> QueryResult<()> {
+    let now = Utc::now().naive_utc();
+    diesel::insert_into(job_runs::table)
+        .values((
+            job_runs::name.eq(name),
+            job_runs::replica.eq(replica_id()),
+            job_runs::outcome.eq(OUTCOME_RUNNING),
+            job_runs::started_at.eq(now),
+        ))
+        .on_conflict(job_runs::name)
+        .do_update()
+        .set((
+            job_runs::replica.eq(replica_id()),
+            job_runs::outcome.eq(OUTCOME_RUNNING),
+            job_runs::started_at.eq(now),
+            job_runs::finished_at.eq(None::<NaiveDateTime>),
+        ))
+        .execute(connection)
+        .map(|_| ())
+}
+
+/// Records the outcome of a run of a job; the error of a failed run is kept until the next
+/// failure.
+fn record_run_finished(
+    connection: &mut PgConnection,
+    name: &str,
+    result: &Result<(), String>,
+    next_run_at: Option<NaiveDateTime>,
+) -> QueryResult<()> {
+    let now = Utc::now().naive_utc();
+    let run = job_runs::table.find(name);
+    match result {
+        Ok(()) => diesel::update(run)
+            .set((job_runs::outcome.eq(OUTCOME_COMPLETED), job_runs::finished_at.eq(now)))
+            .execute(connection)?,
+        Err(message) => diesel::update(run)
+            .set((
+                job_runs::outcome.eq(OUTCOME_FAILED),
+                job_runs::finished_at.eq(now),
+                job_runs::last_error.eq(message),
+                job_runs::last_error_at.eq(now),
+            ))
+            .execute(connection)?,
+    };
+    if let Some(next_run_at) = next_run_at {
+        diesel::update(run).set(job_runs::next_run_at.eq(next_run_at)).execute(connection)?;
+    }
+
+    Ok(())
+}
+
+/// Registers a job scheduled by this replica, so its state is reported and it can be triggered.
+pub fn register_job(name: &'static str, interval: Duration, job: Job) {
+    SCHEDULED_JOBS
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(name, (interval, job));
+}
+
+/// Returns a job registered with [`register_job`].
+pub fn registered_job(name: &str) -> Option<(&'static str, Duration, Job)> {
+    SCHEDULED_JOBS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|jobs| jobs.get_key_value(name))
+        .map(|(name, (interval, job))| (*name, *interval, *job))
+}
+
+/// Returns the state of the registered jobs, ordered by name.
+///
+/// # Errors
+///
+/// Returns an error if the recorded runs cannot be loaded.
+pub fn job_statuses(connection: &mut PgConnection) -> QueryResult<Vec<JobStatus>> {
+    let mut scheduled = SCHEDULED_JOBS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|jobs| jobs.iter().map(|(name, (interval, _))| (*name, *interval)).collect::<Vec<_>>())
+        .unwrap_or_default();
+    scheduled.sort();
+
+    let names = scheduled.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+    let mut runs = job_runs::table
+        .filter(job_runs::name.eq_any(&names))
+        .select(JobRun::as_select())
+        .load(connection)?
+        .into_iter()
+        .map(|run| (run.name.clone(), run))
+        .collect::<HashMap<_, _>>();
+
+    Ok(scheduled
+        .into_iter()
+        .map(|(name, interval)| {
+            let run = runs.remove(name);
+            JobStatus {
+                name: name.to_string(),
+                interval_seconds: interval.as_secs(),
+                outcome: run.as_ref().map(|run| run.outcome.clone()),
+                replica: run.as_ref().map(|run| run.replica.clone()),
+                last_run_at: run.as_ref().map(|run| run.started_at),
+                last_finished_at: run.as_ref().and_then(|run| run.finished_at),
+                last_error: run.as_ref().and_then(|run| run.last_error.clone()),
+                last_error_at: run.as_ref().and_then(|run| run.last_error_at),
+                next_run_at: run.and_then(|run| run.next_run_at),
+            }
+        })
+        .collect())
+}
+
+/// Schedules a job to run every `interval`, starting immediately.
+///
+/// Runs are skipped while this replica is not the scheduler leader. Failures are logged and the
+/// job is retried at the next tick.
+#[cfg(feature = "server")]
+pub fn spawn_scheduled_job(name: &'static str, interval: Duration, job: Job) {
+    register_job(name, interval, job);
+    rt::spawn(async move {
+        let mut ticker = rt::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if !is_scheduler_leader() {
+                continue;
+            }
+            let now = Utc::now().naive_utc();
+            let next_run_at = now
+                .checked_add_signed(TimeDelta::from_std(interval).unwrap_or(TimeDelta::MAX))
+                .unwrap_or(now);
+            match web::block(move || run_job(name, job, Some(next_run_at))).await {
+                Ok(Ok(true)) => log_info!("Job {} completed.", name),
+                Ok(Ok(false)) => log_info!("Job {} skipped: running on another replica.", name),
+                Ok(Err(message)) => log_error!("Job {} failed: {}", name, message),
+                Err(e) => log_error!("Job {} failed: {}", name, e),
+            }
+        }
+    });
+}
+
+/// Handles the request to list the scheduled background jobs.
+///
+/// # Returns
+///
+/// A JSON response containing the state of every job scheduled by the deployment: its interval,
+/// the outcome and times of its latest run on any replica, its latest error and its next
+/// scheduled run.
+#[utoipa::path(
+    get,
+    path = "/jobs",
+    responses(
+        (status = 200, description = "Scheduled jobs, ordered by name", body = [JobStatus]),
+        (status = 500, description = "Failed to load the job runs", body = String, content_type = "text/plain")
+    )
+)]
+#[cfg(feature = "server")]
+pub async fn jobs_handler() -> impl Responder {
+    match web::block(|| job_statuses(&mut establish_connection())).await {
+        Ok(Ok(statuses)) => HttpResponse::Ok().json(statuses),
+        _ => HttpResponse::InternalServerError().body("Failed to load the job runs"),
+    }
+}
+
+/// Handles the request to run a scheduled job immediately.
+///
+/// The job runs on the replica serving the request, leader or not, under the same lock as its
+/// scheduled runs; its schedule is unchanged.
+///
+/// # Arguments
+///
+/// * `name` - The name of the job.
+///
+/// # Returns
+///
+/// A JSON response containing the state of the job after the run, or an error message.
+#[utoipa::path(
+    post,
+    path = "/jobs/{name}/run",
+    params(("name" = String, Path, description = "Name of the job")),
+    responses(
+        (status = 200, description = "Job completed", body = JobStatus),
+        (status = 404, description = "Unknown job", body = String, content_type = "text/plain"),
+        (status = 409, description = "Job is already running", body = String, content_type = "text/plain"),
+        (status = 500, description = "Job failed, with its error", body = String, content_type = "text/plain")
+    )
+)]
+#[cfg(feature = "server")]
+pub async fn run_job_handler(name: web::Path<String>) -> impl Responder {
+    let (name, _, job) = match registered_job(&name) {
+        Some(registered) => registered,
+        None => return HttpResponse::NotFound().body("Unknown job"),
+    };
+
+    log_info!("Job {} triggered on demand.", name);
+    match web::block(move || run_job(name, job, None)).await {
+        Ok(Ok(true)) => {}
+        Ok(Ok(false)) => return HttpResponse::Conflict().body("Job is already running"),
+        Ok(Err(message)) => {
+            log_error!("Job {} failed: {}", name, message);
+            return HttpResponse::InternalServerError().body(format!("Job failed: {}", message));
+        }
+        Err(_) => return HttpResponse::InternalServerError().body("Job failed"),
+    }
+
+    let status = web::block(|| job_statuses(&mut establish_connection()))
+        .await
+        .ok()
+        .and_then(Result::ok)
+        .and_then(|statuses| statuses.into_iter().find(|status| status.name == name));
+    match status {
+        Some(status) => HttpResponse::Ok().json(status),
+        None => HttpResponse::InternalServerError().body("Failed to load the job runs"),
+    }
+}
+
+#[test]
+fn test_job_lock_key() {
+    assert_eq!(job_lock_key("purge"), job_lock_key("purge"));
+    assert_ne!(job_lock_key("purge"), job_lock_key("rotation"));
+}
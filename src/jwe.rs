@@ -0,0 +1,331 @@
+//! This module provides JSON Web Encryption (JWE) of private key responses to a public key
+//! supplied by the caller.
+//!
+//! Responses use the JWE compact serialization with `A256GCM` content encryption. The content
+//! encryption key is agreed with `ECDH-ES` (RFC 7518, section 4.6) for `EC` recipient keys and
+//! wrapped with `RSA-OAEP-256` (RFC 7518, section 4.3) for `RSA` recipient keys, so the private
+//! key never appears in plaintext between the service and the holder of the recipient key.
+
+use crate::crypto::public_key_from_jwk;
+use crate::models::Jwk;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::derive::Deriver;
+use openssl::ec::EcKey;
+use openssl::encrypt::Encrypter;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Public};
+use openssl::rsa::Padding;
+use openssl::symm::{encrypt_aead, Cipher};
+use serde::Deserialize;
+use dotenv::dotenv;
+use serde_json::{json, Map, Value};
+use std::env;
+use std::error::Error;
+
+/// Request header carrying the public JWK responses are encrypted to.
+pub const ENCRYPTION_KEY_HEADER: &str = "X-Encryption-Key";
+
+/// Media type of JWE compact serialization responses.
+pub const JOSE_CONTENT_TYPE: &str = "application/jose";
+
+/// Content encryption algorithm of every JWE.
+const CONTENT_ENCRYPTION: &str = "A256GCM";
+
+/// Delivery policy of the secrets of symmetric (`oct`) keys, registered as application data.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SecretDelivery {
+    /// Whether the secret of an `oct` key is only returned encrypted to an `X-Encryption-Key`.
+    pub require_encryption: bool,
+}
+
+impl SecretDelivery {
+    /// Reads the policy from `OCT_KEYS_REQUIRE_ENCRYPTION` (`1` = encryption required).
+    pub fn from_env() -> Self {
+        dotenv().ok();
+
+        SecretDelivery {
+            require_encryption: env::var("OCT_KEYS_REQUIRE_ENCRYPTION").map(|value| value == "1").unwrap_or(false),
+        }
+    }
+}
+
+/// Public JWK a response is encrypted to.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RecipientKey {
+    /// Key type (`EC` or `RSA`).
+    pub kty: String,
+    /// Key management algorithm (`ECDH-ES` or `RSA-OAEP-256`); derived from `kty` if omitted.
+    pub alg: Option<String>,
+    /// Intended use of the key; must be `enc` if present.
+    #[serde(rename = "use")]
+    pub use_: Option<String>,
+    /// Key ID, returned in the JWE header.
+    pub kid: Option<String>,
+    /// Curve of an `EC` key.
+    pub crv: Option<String>,
+    /// X coordinate of an `EC` key.
+    pub x: Option<String>,
+    /// Y coordinate of an `EC` key.
+    pub y: Option<String>,
+    /// Modulus of an `RSA` key.
+    pub n: Option<String>,
+    /// Public exponent of an `RSA` key.
+    pub e: Option<String>,
+}
+
+impl RecipientKey {
+    /// Parses the recipient key of the `X-Encryption-Key` header: a public JWK as JSON or as
+    /// base64url encoded JSON.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if the value is not a JWK or the key is not usable for encryption.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        let json = if value.starts_with('{') {
+            value.as_bytes().to_vec()
+        } else {
+            URL_SAFE_NO_PAD
+                .decode(value)
+                .map_err(|_| "Encryption key must be a JWK or a base64url encoded JWK".to_string())?
+        };
+        let key: RecipientKey =
+            serde_json::from_slice(&json).map_err(|_| "Encryption key is not a valid JWK".to_string())?;
+
+        if key.use_.as_deref().is_some_and(|key_use| key_use != "enc") {
+            return Err("Encryption key must be an encryption key (use enc)".to_string());
+        }
+        key.algorithm()?;
+        key.public_key()
+            .map_err(|_| "Encryption key is not a valid public key".to_string())?;
+
+        Ok(key)
+    }
+
+    /// Returns the key management algorithm used with the key.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if the key type or algorithm is not supported.
+    pub fn algorithm(&self) -> Result<&str, String> {
+        match (self.kty.as_str(), self.alg.as_deref()) {
+            ("EC", None | Some("ECDH-ES")) => Ok("ECDH-ES"),
+            ("RSA", None | Some("RSA-OAEP-256")) => Ok("RSA-OAEP-256"),
+            _ => Err("Unsupported encryption key, expected an EC (ECDH-ES) or RSA (RSA-OAEP-256) key".to_string()),
+        }
+    }
+
+    /// Returns the public key.
+    fn public_key(&self) -> Result<PKey<Public>, Box<dyn Error>> {
+        public_key_from_jwk(&Jwk {
+            kty: self.kty.clone(),
+            use_: "enc".to_string(),
+            alg: String::new(),
+            kid: String::new(),
+            crv: self.crv.clone(),
+            x: self.x.clone(),
+            y: self.y.clone(),
+            n: self.n.clone(),
+            e: self.e.clone(),
+            x5c: None,
+            x5t: None,
+            pub_: None,
+        })
+    }
+}
+
+/// Derives the content encryption key from an ECDH shared secret with the Concat KDF
+/// (NIST SP 800-56A, as profiled by RFC 7518, section 4.6.2). Keys of up to 256 bits are
+/// derived with a single SHA-256 round.
+fn concat_kdf(shared_secret: &[u8], enc: &str, apu: &[u8], apv: &[u8], key_bits: u32) -> Vec<u8> {
+    let mut input = Vec::new();
+    input.extend_from_slice(&1u32.to_be_bytes());
+    input.extend_from_slice(shared_secret);
+    for field in [enc.as_bytes(), apu, apv] {
+        input.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        input.extend_from_slice(field);
+    }
+    input.extend_from_slice(&key_bits.to_be_bytes());
+
+    openssl::sha::sha256(&input)[..(key_bits / 8) as usize].to_vec()
+}
+
+/// Agrees a content encryption key with an `EC` recipient key through an ephemeral key pair.
+///
+/// # Returns
+///
+/// The content encryption key and the public JWK of the ephemeral key (`epk`).
+fn agree_content_key(recipient: &PKey<Public>, crv: &str) -> Result<(Vec<u8>, Value), Box<dyn Error>> {
+    let recipient_key = recipient.ec_key()?;
+    let group = recipient_key.group();
+    let ephemeral = EcKey::generate(group)?;
+    let ephemeral_pkey = PKey::from_ec_key(ephemeral.clone())?;
+
+    let mut deriver = Deriver::new(&ephemeral_pkey)?;
+    deriver.set_peer(recipient)?;
+    let shared_secret = deriver.derive_to_vec()?;
+
+    let size = (group.degree() as i32 + 7) / 8;
+    let mut x = BigNum::new()?;
+    let mut y = BigNum::new()?;
+    let mut ctx = BigNumContext::new()?;
+    ephemeral
+        .public_key()
+        .affine_coordinates(group, &mut x, &mut y, &mut ctx)?;
+    let epk = json!({
+        "kty": "EC",
+        "crv": crv,
+        "x": URL_SAFE_NO_PAD.encode(x.to_vec_padded(size)?),
+        "y": URL_SAFE_NO_PAD.encode(y.to_vec_padded(size)?),
+    });
+
+    Ok((concat_kdf(&shared_secret, CONTENT_ENCRYPTION, &[], &[], 256), epk))
+}
+
+/// Encrypts the payload to the recipient key and returns the JWE compact serialization.
+///
+/// # Errors
+///
+/// Returns an error if the recipient key is unsupported or encryption fails.
+pub fn encrypt_to_jwk(recipient: &RecipientKey, payload: &[u8]) -> Result<String, Box<dyn Error>> {
+    let algorithm = recipient.algorithm()?;
+    let public_key = recipient.public_key()?;
+
+    let mut header = Map::new();
+    header.insert("alg".to_string(), json!(algorithm));
+    header.insert("enc".to_string(), json!(CONTENT_ENCRYPTION));
+    if let Some(kid) = &recipient.kid {
+        header.insert("kid".to_string(), json!(kid));
+    }
+
+    let (content_key, encrypted_key) = if algorithm == "ECDH-ES" {
+        let crv = recipient.crv.as_deref().ok_or("Missing JWK parameter crv")?;
+        let (content_key, epk) = agree_content_key(&public_key, crv)?;
+        header.insert("epk".to_string(), epk);
+        // Direct key agreement: the JWE Encrypted Key is empty
+        (content_key, Vec::new())
+    } else {
+        let mut content_key = vec![0u8; 32];
+        openssl::rand::rand_bytes(&mut content_key)?;
+        let mut encrypter = Encrypter::new(&public_key)?;
+        encrypter.set_rsa_padding(Padding::PKCS1_OAEP)?;
+        encrypter.set_rsa_oaep_md(MessageDigest::sha256())?;
+        encrypter.set_rsa_mgf1_md(MessageDigest::sha256())?;
+        let mut encrypted_key = vec![0u8; encrypter.encrypt_len(&content_key)?];
+        let length = encrypter.encrypt(&content_key, &mut encrypted_key)?;
+        encrypted_key.truncate(length);
+        (content_key, encrypted_key)
+    };
+
+    let protected = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&Value::Object(header))?);
+    let mut iv = [0u8; 12];
+    openssl::rand::rand_bytes(&mut iv)?;
+    let mut tag = [0u8; 16];
+    let ciphertext = encrypt_aead(
+        Cipher::aes_256_gcm(),
+        &content_key,
+        Some(&iv),
+        protected.as_bytes(),
+        payload,
+        &mut tag,
+    )?;
+
+    Ok(format!(
+        "{}.{}.{}.{}.{}",
+        protected,
+        URL_SAFE_NO_PAD.encode(encrypted_key),
+        URL_SAFE_NO_PAD.encode(iv),
+        URL_SAFE_NO_PAD.encode(ciphertext),
+        URL_SAFE_NO_PAD.encode(tag)
+    ))
+}
+
+#[cfg(test)]
+fn decrypt_for_test(jwe: &str, recipient: &PKey<openssl::pkey::Private>) -> Vec<u8> {
+    use openssl::encrypt::Decrypter;
+
+    let parts = jwe.split('.').collect::<Vec<_>>();
+    let decode = |part: &str| URL_SAFE_NO_PAD.decode(part).unwrap();
+    let header: Value = serde_json::from_slice(&decode(parts[0])).unwrap();
+
+    let content_key = match header["alg"].as_str().unwrap() {
+        "ECDH-ES" => {
+            let epk: Jwk = serde_json::from_value(json!({
+                "use": "enc", "alg": "", "kid": "",
+                "kty": header["epk"]["kty"], "crv": header["epk"]["crv"],
+                "x": header["epk"]["x"], "y": header["epk"]["y"],
+            }))
+            .unwrap();
+            let ephemeral = public_key_from_jwk(&epk).unwrap();
+            let mut deriver = Deriver::new(recipient).unwrap();
+            deriver.set_peer(&ephemeral).unwrap();
+            concat_kdf(&deriver.derive_to_vec().unwrap(), CONTENT_ENCRYPTION, &[], &[], 256)
+        }
+        _ => {
+            let mut decrypter = Decrypter::new(recipient).unwrap();
+            decrypter.set_rsa_padding(Padding::PKCS1_OAEP).unwrap();
+            decrypter.set_rsa_oaep_md(MessageDigest::sha256()).unwrap();
+            decrypter.set_rsa_mgf1_md(MessageDigest::sha256()).unwrap();
+            let encrypted_key = decode(parts[1]);
+            let mut content_key = vec![0u8; decrypter.decrypt_len(&encrypted_key).unwrap()];
+            let length = decrypter.decrypt(&encrypted_key, &mut content_key).unwrap();
+            content_key.truncate(length);
+            content_key
+        }
+    };
+
+    openssl::symm::decrypt_aead(
+        Cipher::aes_256_gcm(),
+        &content_key,
+        Some(&decode(parts[2])),
+        parts[0].as_bytes(),
+        &decode(parts[3]),
+        &decode(parts[4]),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_concat_kdf() {
+    // RFC 7518, Appendix C
+    let z = [
+        158, 86, 217, 29, 129, 113, 53, 211, 114, 131, 66, 131, 191, 132, 38, 156, 251, 49, 110,
+        163, 218, 128, 106, 72, 246, 218, 167, 121, 140, 254, 144, 196,
+    ];
+    let key = concat_kdf(&z, "A128GCM", b"Alice", b"Bob", 128);
+    assert_eq!(URL_SAFE_NO_PAD.encode(key), "VqqN6vgjbSBcIijNcacQGg");
+
+    assert_eq!(concat_kdf(&z, "A256GCM", &[], &[], 256).len(), 32);
+}
+
+#[test]
+fn test_encrypt_to_ec_and_rsa_keys() {
+    use crate::crypto::{generate_ec_jwk_data, generate_rsa_jwk_data, private_key_from_jwk_data};
+
+    for jwk_data in [
+        generate_ec_jwk_data("ES256").unwrap(),
+        generate_ec_jwk_data("ES384").unwrap(),
+        generate_rsa_jwk_data(2048, "RS256").unwrap(),
+    ] {
+        let public = Jwk::from(jwk_data.clone());
+        let header = URL_SAFE_NO_PAD.encode(
+            json!({ "kty": public.kty, "crv": public.crv, "x": public.x, "y": public.y, "n": public.n, "e": public.e })
+                .to_string(),
+        );
+        let recipient = RecipientKey::parse(&header).unwrap();
+        let private = private_key_from_jwk_data(&jwk_data).unwrap();
+
+        let jwe = encrypt_to_jwk(&recipient, b"{\"d\":\"secret\"}").unwrap();
+        assert_eq!(jwe.split('.').count(), 5);
+        assert!(!jwe.contains("secret"));
+        assert_eq!(decrypt_for_test(&jwe, &private), b"{\"d\":\"secret\"}");
+    }
+}
+
+#[test]
+fn test_parse_recipient_key() {
+    assert!(RecipientKey::parse("not a key").is_err());
+    assert!(RecipientKey::parse(r#"{"kty": "oct", "k": "c2VjcmV0"}"#).is_err());
+    assert!(RecipientKey::parse(r#"{"kty": "EC", "crv": "P-256", "x": "AA", "y": "AA"}"#).is_err());
+}
@@ -0,0 +1,202 @@
+//! This module provides JSON Web Signature (JWS) encoding and decoding for tokens signed with
+//! managed keys.
+//!
+//! Tokens use the JWS compact serialization: `BASE64URL(header).BASE64URL(payload).BASE64URL(signature)`.
+//! The protected header carries the key's `alg` and `kid` so that verifiers can select the
+//! matching key from the published JWK Set.
+
+use crate::crypto::{sign_with_jwk, verify_with_jwk, verify_with_secret};
+use crate::models::{Jwk, JwkData};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use serde_json::{json, Value};
+use std::error::Error;
+
+/// Signs the payload and returns the token in JWS compact serialization.
+///
+/// # Arguments
+///
+/// * `jwk` - Key used for signing, including its private part.
+/// * `typ` - Media type placed in the `typ` header parameter (e.g., "JWT").
+/// * `payload` - Raw payload bytes.
+///
+/// # Errors
+///
+/// Returns an error if the header cannot be serialized or signing fails.
+pub fn encode_jws(jwk: &JwkData, typ: &str, payload: &[u8]) -> Result<String, Box<dyn Error>> {
+    let header = json!({
+        "alg": jwk.alg,
+        "kid": jwk.kid,
+        "typ": typ,
+    });
+
+    let signing_input = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+        URL_SAFE_NO_PAD.encode(payload)
+    );
+    let signature = sign_with_jwk(jwk, signing_input.as_bytes())?;
+
+    Ok(format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature)))
+}
+
+/// Signs the claims set and returns a JWT.
+///
+/// # Arguments
+///
+/// * `jwk` - Key used for signing, including its private part.
+/// * `claims` - JWT claims set.
+///
+/// # Errors
+///
+/// Returns an error if the claims cannot be serialized or signing fails.
+pub fn encode_jwt(jwk: &JwkData, claims: &Value) -> Result<String, Box<dyn Error>> {
+    encode_jws(jwk, "JWT", &serde_json::to_vec(claims)?)
+}
+
+/// A JWS in compact serialization split into its parts.
+#[derive(Debug, Clone)]
+pub struct DecodedJws {
+    /// Protected header.
+    pub header: Value,
+    /// Decoded payload bytes.
+    pub payload: Vec<u8>,
+    /// Signing input (`BASE64URL(header).BASE64URL(payload)`) covered by the signature.
+    pub signing_input: String,
+    /// Decoded signature bytes.
+    pub signature: Vec<u8>,
+}
+
+impl DecodedJws {
+    /// Returns a string header parameter (e.g., "alg", "kid", "typ").
+    pub fn header_str(&self, name: &str) -> Option<&str> {
+        self.header.get(name).and_then(Value::as_str)
+    }
+
+    /// Parses the payload as a JSON claims set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload is not a JSON object.
+    pub fn claims(&self) -> Result<Value, Box<dyn Error>> {
+        let claims: Value = serde_json::from_slice(&self.payload)?;
+        if !claims.is_object() {
+            return Err(Box::from("JWT claims must be a JSON object"));
+        }
+        Ok(claims)
+    }
+
+    /// Verifies the signature with the public key.
+    ///
+    /// The `alg` header must match the algorithm of the key.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the algorithms differ or the key cannot verify signatures.
+    pub fn verify(&self, jwk: &Jwk) -> Result<bool, Box<dyn Error>> {
+        if self.header_str("alg") != Some(jwk.alg.as_str()) {
+            return Err(Box::from("JWS algorithm does not match the key"));
+        }
+        verify_with_jwk(jwk, self.signing_input.as_bytes(), &self.signature)
+    }
+
+    /// Verifies the MAC with the secret of a symmetric key.
+    ///
+    /// The `alg` header must match the algorithm of the key.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the algorithms differ or the key is not a symmetric key.
+    pub fn verify_secret(&self, jwk: &JwkData) -> Result<bool, Box<dyn Error>> {
+        if self.header_str("alg") != Some(jwk.alg.as_str()) {
+            return Err(Box::from("JWS algorithm does not match the key"));
+        }
+        verify_with_secret(jwk, self.signing_input.as_bytes(), &self.signature)
+    }
+}
+
+/// Splits a JWS in compact serialization into its decoded parts without verifying it.
+///
+/// # Errors
+///
+/// Returns an error if the token is not three Base64URL encoded parts or the header is not a
+/// JSON object.
+pub fn decode_jws(token: &str) -> Result<DecodedJws, Box<dyn Error>> {
+    let parts = token.split('.').collect::<Vec<_>>();
+    if parts.len() != 3 {
+        return Err(Box::from("JWS must consist of three parts"));
+    }
+
+    let header: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(parts[0])?)?;
+    if !header.is_object() {
+        return Err(Box::from("JWS header must be a JSON object"));
+    }
+
+    Ok(DecodedJws {
+        header,
+        payload: URL_SAFE_NO_PAD.decode(parts[1])?,
+        signing_input: format!("{}.{}", parts[0], parts[1]),
+        signature: URL_SAFE_NO_PAD.decode(parts[2])?,
+    })
+}
+
+#[test]
+fn test_encode_jwt_verifies_with_public_jwk() {
+    use crate::crypto::generate_jwk_data;
+
+    for alg in ["RS256", "ES256", "Ed25519"] {
+        let jwk = generate_jwk_data(alg, 2048).unwrap();
+        let token = encode_jwt(&jwk, &json!({"sub": "user-1"})).unwrap();
+
+        let parts = token.split('.').collect::<Vec<_>>();
+        assert_eq!(parts.len(), 3);
+
+        let header: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(parts[0]).unwrap()).unwrap();
+        assert_eq!(header["alg"], jwk.alg.as_str());
+        assert_eq!(header["kid"], jwk.kid.as_str());
+        assert_eq!(header["typ"], "JWT");
+
+        let payload: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(parts[1]).unwrap()).unwrap();
+        assert_eq!(payload["sub"], "user-1");
+
+        let signing_input = format!("{}.{}", parts[0], parts[1]);
+        let signature = URL_SAFE_NO_PAD.decode(parts[2]).unwrap();
+        assert!(verify_with_jwk(&Jwk::from(jwk), signing_input.as_bytes(), &signature).unwrap());
+    }
+}
+
+#[test]
+fn test_decode_jws_verifies_signature() {
+    use crate::crypto::generate_jwk_data;
+
+    let jwk = generate_jwk_data("ES256", 2048).unwrap();
+    let other = generate_jwk_data("ES256", 2048).unwrap();
+    let token = encode_jwt(&jwk, &json!({"sub": "user-1"})).unwrap();
+
+    let decoded = decode_jws(&token).unwrap();
+    assert_eq!(decoded.header_str("kid"), Some(jwk.kid.as_str()));
+    assert_eq!(decoded.claims().unwrap()["sub"], "user-1");
+    assert!(decoded.verify(&Jwk::from(jwk.clone())).unwrap());
+    assert!(!decoded.verify(&Jwk::from(other)).unwrap());
+
+    let tampered = format!("{}.{}.{}", token.split('.').next().unwrap(), URL_SAFE_NO_PAD.encode(b"{}"), token.split('.').nth(2).unwrap());
+    assert!(!decode_jws(&tampered).unwrap().verify(&Jwk::from(jwk)).unwrap());
+
+    assert!(decode_jws("not-a-token").is_err());
+    assert!(decode_jws("a.b").is_err());
+}
+
+#[test]
+fn test_decode_jws_verifies_mac() {
+    use crate::crypto::generate_jwk_data;
+
+    let jwk = generate_jwk_data("HS256", 2048).unwrap();
+    let other = generate_jwk_data("HS256", 2048).unwrap();
+    let token = encode_jwt(&jwk, &json!({"sub": "user-1"})).unwrap();
+
+    let decoded = decode_jws(&token).unwrap();
+    assert_eq!(decoded.header_str("alg"), Some("HS256"));
+    assert!(decoded.verify_secret(&jwk).unwrap());
+    assert!(!decoded.verify_secret(&other).unwrap());
+    assert!(decoded.verify_secret(&generate_jwk_data("HS512", 2048).unwrap()).is_err());
+    assert!(decoded.verify(&Jwk::from(jwk)).is_err());
+}
@@ -0,0 +1,263 @@
+//! This module encrypts the private key material of the keys at rest.
+//!
+//! With `KEY_ENCRYPTION_KEY` set to a base64url encoded 256-bit key-encryption key (KEK), the
+//! `private_key` column of the `jwks` table (the private keys, and the secrets of symmetric keys)
+//! is encrypted with AES-256-GCM when a key is inserted and decrypted when it is loaded, so a copy
+//! of the `jwks` table, e.g. a backup, does not reveal the keys without the KEK. The column is
+//! mapped through [`SealedPrivateKey`], so every query loading or inserting
+//! [`crate::models::JwkData`] is covered.
+//!
+//! Values stored before the KEK was configured are loaded as they are; the `seal-keys` command
+//! ([`run_seal_keys`]) encrypts them. The operations log ([`crate::recovery`]) only records
+//! encrypted material: the `private_key` of a row stored unencrypted is left out of the logged
+//! record, so keys restored from the log need the KEK they were encrypted with.
+
+use crate::db::establish_connection;
+use crate::schema::jwks;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::prelude::*;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use dotenv::dotenv;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use std::env;
+use std::error::Error;
+use std::io::Write;
+use uuid::Uuid;
+
+/// Prefix of encrypted values, also authenticated as associated data.
+const SEALED_PREFIX: &str = "sealed:v1:";
+
+/// Length of the AES-256-GCM nonce in bytes.
+const NONCE_LENGTH: usize = 12;
+
+/// Length of the AES-256-GCM authentication tag in bytes.
+const TAG_LENGTH: usize = 16;
+
+/// Error of encrypting or decrypting private key material.
+type SealError = Box<dyn Error + Send + Sync>;
+
+/// Returns the key-encryption key (`KEY_ENCRYPTION_KEY`).
+///
+/// # Returns
+///
+/// `None` if private key material is stored unencrypted.
+///
+/// # Errors
+///
+/// Returns an error if the variable is not a base64url encoded 256-bit key.
+pub fn key_encryption_key() -> Result<Option<Vec<u8>>, String> {
+    dotenv().ok();
+
+    match env::var("KEY_ENCRYPTION_KEY") {
+        Ok(encoded) if !encoded.trim().is_empty() => parse_key_encryption_key(&encoded).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Decodes a base64url encoded 256-bit key-encryption key.
+fn parse_key_encryption_key(encoded: &str) -> Result<Vec<u8>, String> {
+    match URL_SAFE_NO_PAD.decode(encoded.trim().trim_end_matches('=')) {
+        Ok(key) if key.len() == 32 => Ok(key),
+        _ => Err("KEY_ENCRYPTION_KEY must be a base64url encoded 256-bit key".to_string()),
+    }
+}
+
+/// Checks that the secret of a symmetric key can be stored encrypted.
+///
+/// # Errors
+///
+/// Returns a message if no valid key-encryption key is configured.
+pub fn check_secret_storage() -> Result<(), String> {
+    match key_encryption_key()? {
+        Some(_) => Ok(()),
+        None => Err("Symmetric keys are only stored encrypted, KEY_ENCRYPTION_KEY is not set".to_string()),
+    }
+}
+
+/// Returns whether a stored value is encrypted.
+pub fn is_sealed(stored: &str) -> bool {
+    stored.starts_with(SEALED_PREFIX)
+}
+
+/// Encrypts private key material with the key-encryption key, if one is configured.
+///
+/// # Returns
+///
+/// The value to store: `sealed:v1:` followed by the base64url encoded nonce, ciphertext and tag,
+/// or the material itself without a key-encryption key.
+///
+/// # Errors
+///
+/// Returns an error if the key-encryption key is invalid or the encryption fails.
+pub fn seal(plaintext: &str) -> Result<String, SealError> {
+    match key_encryption_key()? {
+        Some(key) => seal_with_key(&key, plaintext),
+        None => Ok(plaintext.to_string()),
+    }
+}
+
+/// Encrypts private key material with `key`, see [`seal`].
+fn seal_with_key(key: &[u8], plaintext: &str) -> Result<String, SealError> {
+    let mut nonce = [0u8; NONCE_LENGTH];
+    openssl::rand::rand_bytes(&mut nonce)?;
+    let mut tag = [0u8; TAG_LENGTH];
+    let ciphertext = encrypt_aead(
+        Cipher::aes_256_gcm(),
+        key,
+        Some(&nonce),
+        SEALED_PREFIX.as_bytes(),
+        plaintext.as_bytes(),
+        &mut tag,
+    )?;
+
+    let sealed = [&nonce[..], &ciphertext, &tag].concat();
+    Ok(format!("{}{}", SEALED_PREFIX, URL_SAFE_NO_PAD.encode(sealed)))
+}
+
+/// Decrypts a stored value encrypted by [`seal`]; values stored unencrypted are returned as they
+/// are.
+///
+/// # Errors
+///
+/// Returns an error if the value is encrypted and no key-encryption key is configured, or it
+/// cannot be decrypted with the configured one.
+pub fn unseal(stored: &str) -> Result<String, SealError> {
+    if !is_sealed(stored) {
+        return Ok(stored.to_string());
+    }
+    unseal_with_key(key_encryption_key()?.as_deref(), stored)
+}
+
+/// Decrypts a stored value with `key`, see [`unseal`].
+fn unseal_with_key(key: Option<&[u8]>, stored: &str) -> Result<String, SealError> {
+    let Some(encoded) = stored.strip_prefix(SEALED_PREFIX) else {
+        return Ok(stored.to_string());
+    };
+    let key = key.ok_or("Private key is encrypted, but KEY_ENCRYPTION_KEY is not set")?;
+
+    let sealed = URL_SAFE_NO_PAD.decode(encoded)?;
+    if sealed.len() < NONCE_LENGTH + TAG_LENGTH {
+        return Err("Encrypted private key is truncated".into());
+    }
+    let (nonce, rest) = sealed.split_at(NONCE_LENGTH);
+    let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LENGTH);
+    let plaintext = decrypt_aead(Cipher::aes_256_gcm(), key, Some(nonce), SEALED_PREFIX.as_bytes(), ciphertext, tag)
+        .map_err(|_| "Failed to decrypt the private key, KEY_ENCRYPTION_KEY does not match")?;
+
+    Ok(String::from_utf8(plaintext)?)
+}
+
+/// Private key material as stored in the `private_key` column: encrypted with [`seal`] when it
+/// is written and decrypted with [`unseal`] when it is read.
+#[derive(Debug, Clone, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Text)]
+pub struct SealedPrivateKey(pub String);
+
+impl From<String> for SealedPrivateKey {
+    fn from(private_key: String) -> Self {
+        SealedPrivateKey(private_key)
+    }
+}
+
+impl From<SealedPrivateKey> for String {
+    fn from(private_key: SealedPrivateKey) -> Self {
+        private_key.0
+    }
+}
+
+impl ToSql<Text, Pg> for SealedPrivateKey {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(seal(&self.0)?.as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for SealedPrivateKey {
+    fn from_sql(value: PgValue<'_>) -> deserialize::Result<Self> {
+        let stored = <String as FromSql<Text, Pg>>::from_sql(value)?;
+        Ok(SealedPrivateKey(unseal(&stored)?))
+    }
+}
+
+/// Encrypts the private key material stored before the key-encryption key was configured.
+///
+/// # Returns
+///
+/// The number of encrypted keys.
+///
+/// # Errors
+///
+/// Returns an error if no key-encryption key is configured, or the keys cannot be loaded or
+/// updated.
+pub fn seal_stored_keys(connection: &mut PgConnection) -> Result<usize, SealError> {
+    if key_encryption_key()?.is_none() {
+        return Err("KEY_ENCRYPTION_KEY is not set".into());
+    }
+
+    connection.transaction(|connection| {
+        // The stored values are loaded as they are, without decrypting them
+        let stored = jwks::table
+            .select((jwks::id, jwks::private_key))
+            .filter(jwks::private_key.not_like(format!("{}%", SEALED_PREFIX)))
+            .filter(jwks::private_key.ne(""))
+            .for_update()
+            .load::<(Uuid, String)>(connection)?;
+
+        for (key_id, private_key) in &stored {
+            diesel::update(jwks::table.filter(jwks::id.eq(key_id)))
+                .set(jwks::private_key.eq(seal(private_key)?))
+                .execute(connection)?;
+        }
+        Ok(stored.len())
+    })
+}
+
+/// Runs the `seal-keys` command, which encrypts the private key material stored unencrypted.
+///
+/// # Errors
+///
+/// Returns an error if arguments are passed or the keys cannot be encrypted.
+pub fn run_seal_keys(args: &[String]) -> Result<(), Box<dyn Error>> {
+    if let Some(arg) = args.first() {
+        return Err(Box::from(format!("Unexpected argument {}, seal-keys takes none", arg)));
+    }
+
+    let sealed = seal_stored_keys(&mut establish_connection()).map_err(|error| error.to_string())?;
+    println!("Encrypted the private keys of {} keys.", sealed);
+    Ok(())
+}
+
+#[test]
+fn test_seal_and_unseal() {
+    let key = [7u8; 32];
+    let sealed = seal_with_key(&key, "secret").unwrap();
+    assert!(is_sealed(&sealed));
+    assert!(!sealed.contains("secret"));
+    assert_ne!(seal_with_key(&key, "secret").unwrap(), sealed);
+    assert_eq!(unseal_with_key(Some(&key), &sealed).unwrap(), "secret");
+
+    // Unencrypted values are read as they are
+    assert_eq!(unseal_with_key(Some(&key), "plain").unwrap(), "plain");
+    assert_eq!(unseal_with_key(None, "plain").unwrap(), "plain");
+
+    // Tampered values, other keys and a missing key are rejected
+    let mut tampered = sealed.clone().into_bytes();
+    let last = tampered.len() - 1;
+    tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+    assert!(unseal_with_key(Some(&key), &String::from_utf8(tampered).unwrap()).is_err());
+    assert!(unseal_with_key(Some(&[8u8; 32]), &sealed).is_err());
+    assert!(unseal_with_key(None, &sealed).is_err());
+}
+
+#[test]
+fn test_parse_key_encryption_key() {
+    let encoded = URL_SAFE_NO_PAD.encode([7u8; 32]);
+    assert_eq!(parse_key_encryption_key(&encoded).unwrap(), vec![7u8; 32]);
+    assert_eq!(parse_key_encryption_key(&format!("{}=", encoded)).unwrap(), vec![7u8; 32]);
+    assert!(parse_key_encryption_key("short").is_err());
+    assert!(parse_key_encryption_key(&URL_SAFE_NO_PAD.encode([7u8; 16])).is_err());
+}
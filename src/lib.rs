@@ -1,29 +1,189 @@
+// The original key generation tests predate the clippy gate and are kept as written
+#![cfg_attr(test, allow(clippy::bool_assert_comparison, clippy::explicit_auto_deref, clippy::needless_borrow))]
+
+#[cfg(feature = "server")]
 use crate::handlers::*;
+#[cfg(feature = "server")]
+use crate::health::{healthz_handler, readyz_handler, run_stored_key_self_test_handler, stored_key_self_test_handler};
+#[cfg(feature = "server")]
 use crate::models::*;
+#[cfg(feature = "server")]
+use actix_web::body::{EitherBody, MessageBody};
+#[cfg(feature = "server")]
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+#[cfg(feature = "server")]
+use actix_web::http::Method;
+#[cfg(feature = "server")]
+use actix_web::middleware::{from_fn, Condition, Next};
+#[cfg(feature = "server")]
 use actix_web::{web, HttpResponse, Responder};
 use diesel_migrations::{embed_migrations, EmbeddedMigrations};
+use dotenv::dotenv;
+use std::env;
+#[cfg(feature = "server")]
+use std::sync::Arc;
+#[cfg(feature = "server")]
 use utoipa::OpenApi;
 
+#[cfg(feature = "server")]
+pub mod admin;
+pub mod aliases;
+#[cfg(feature = "server")]
+pub mod anomalies;
+#[cfg(feature = "server")]
+pub mod approvals;
+pub mod audit;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod cose;
 pub mod crypto;
 pub mod db;
+#[cfg(feature = "server")]
+pub mod did;
+pub mod dpop;
+pub mod dual_write;
+#[cfg(feature = "server")]
+pub mod external;
+pub mod federation;
+#[cfg(feature = "server")]
 pub mod handlers;
+pub mod health;
+pub mod http_signatures;
+#[cfg(feature = "server")]
+pub mod inventory;
+pub mod jobs;
+pub mod jwe;
+pub mod jws;
+pub mod key_encryption;
+pub mod keygen;
+pub mod logging;
+pub mod manager;
+pub mod metrics;
+pub mod migrate;
 pub mod models;
+pub mod notifications;
+#[cfg(feature = "server")]
+pub mod pagination;
+pub mod paseto;
+pub mod policy;
+#[cfg(any(feature = "ml-dsa", feature = "ml-kem"))]
+pub mod pqc;
+pub mod recovery;
+#[cfg(feature = "server")]
+pub mod replication;
+#[cfg(feature = "server")]
+pub mod request_signing;
+pub mod saml;
 pub mod schema;
+#[cfg(any(test, feature = "seeded-keygen"))]
+pub mod seeded;
+pub mod sdjwt;
+#[cfg(feature = "server")]
+pub mod security_headers;
+pub mod siem;
+#[cfg(feature = "server")]
+pub mod ssh;
+pub mod statsd;
+pub mod store;
+#[cfg(feature = "server")]
+pub mod streaming;
+#[cfg(feature = "test-util")]
+pub mod test_support;
+pub mod tokens;
+#[cfg(feature = "server")]
+pub mod upstream;
+pub mod validation;
+#[cfg(feature = "server")]
+pub mod warmup;
+#[cfg(feature = "server")]
+pub mod webfinger;
+
+// Seeded keys are predictable and must never be generated by a release build
+#[cfg(all(feature = "seeded-keygen", not(debug_assertions)))]
+compile_error!("The `seeded-keygen` feature cannot be enabled in release builds");
 
 // Embedded migrations
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
 
+#[cfg(feature = "server")]
 /// OpenAPI documentation for the JWK microservice
 #[derive(OpenApi)]
 #[openapi(
     paths(
         jwks_handler,
+        jwks_diff_handler,
+        jwks_changes_handler,
+        validate_jwk_handler,
         get_jwk_by_id_handler,
+        current_jwk_handler,
+        fingerprints_handler,
         add_jwk_handler,
-        delete_jwk_handler
+        delete_jwk_handler,
+        rotate_jwk_handler,
+        extend_jwk_handler,
+        freeze_jwk_handler,
+        unfreeze_jwk_handler,
+        bulk_transition_handler,
+        set_primary_jwk_handler,
+        unset_primary_jwk_handler,
+        key_history_handler,
+        deleted_jwks_handler,
+        expiring_jwks_handler,
+        export_jwk_handler,
+        certificate_chain_handler,
+        cose_key_handler,
+        sign_handler,
+        access_token_handler,
+        software_statement_handler,
+        token_exchange_handler,
+        dpop_validation_handler,
+        request_object_handler,
+        paseto_sign_handler,
+        cwt_sign_handler,
+        saml_metadata_handler,
+        signed_jwks_handler,
+        sd_jwt_issue_handler,
+        crate::health::readyz_handler,
+        crate::health::healthz_handler,
+        crate::metrics::metrics_handler,
+        crate::health::stored_key_self_test_handler,
+        crate::health::run_stored_key_self_test_handler,
+        crate::dual_write::dual_write_report_handler,
+        crate::dual_write::dual_write_sync_handler,
+        crate::jobs::jobs_handler,
+        crate::jobs::run_job_handler,
+        crate::replication::replication_changes_handler,
+        crate::aliases::list_aliases_handler,
+        crate::aliases::get_alias_handler,
+        crate::aliases::put_alias_handler,
+        crate::aliases::delete_alias_handler,
+        crate::webfinger::webfinger_handler,
+        crate::did::did_document_handler,
+        crate::ssh::ssh_certificate_handler,
+        crate::ssh::authorized_keys_handler,
+        crate::ssh::ssh_public_key_handler,
+        crate::audit::private_key_access_handler,
+        crate::approvals::issue_approval_handler,
+        crate::anomalies::anomalies_handler,
+        crate::inventory::inventory_handler,
+        crate::external::register_external_key_handler,
+        crate::upstream::upstream_jwks_handler
     ),
     components(
-        schemas(Jwk, Jwks, AlgorithmInput)
+        schemas(
+            Jwk, Jwks, AlgorithmInput, SignInput, AccessTokenInput, SoftwareStatementInput,
+            TokenExchangeInput, TokenExchangeOutput, DpopValidationInput, DpopValidationOutput,
+            RequestObjectInput, PasetoSignInput, CwtSignInput, SdJwtIssueInput, SignOutput,
+            SshCertificateInput, SshCertificate,
+            JwkData, JwkDetails, KeyDetails, DeletedJwk, ExpiringJwk, ExtendKeyInput, KeyExtension,
+            BulkKeyFilter, BulkTransitionInput, BulkTransitionReport,
+            JwksDiffInput, JwksDiff, JwkFinding, JwkValidationReport,
+            JwksChanges, KeyVersion, StoredKeySelfTest, StoredKeyFailure, DualWriteReport,
+            ReplicatedKey, ReplicationBatch, KeyAlias, KeyAliasInput, KeyFingerprint,
+            WebFingerLink, WebFingerResponse, DidVerificationMethod, DidDocument,
+            PrivateKeyAccess, KeyApprovalToken, PrivateKeyAccessAnomaly, InventoryEntry,
+            ExternalKeyInput, JobStatus
+        )
     ),
     tags(
         (name = "JWK Service", description = "API for managing JSON Web Keys")
@@ -31,6 +191,7 @@ pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
 )]
 struct ApiDoc;
 
+#[cfg(feature = "server")]
 /// Endpoint to provide OpenAPI specification
 pub async fn openapi_spec() -> impl Responder {
     HttpResponse::Ok()
@@ -38,14 +199,306 @@ pub async fn openapi_spec() -> impl Responder {
         .body(ApiDoc::openapi().to_json().unwrap())
 }
 
+#[cfg(feature = "server")]
+/// Swagger UI page exploring the specification served by [`openapi_spec`].
+const SWAGGER_UI_HTML: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>JWK Service API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5.17.14/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5.17.14/swagger-ui-bundle.js" crossorigin></script>
+  <script>
+    window.onload = () => {
+      window.ui = SwaggerUIBundle({
+        url: window.location.pathname.replace(/\/?$/, "/openapi.json"),
+        dom_id: "#swagger-ui",
+      });
+    };
+  </script>
+</body>
+</html>
+"##;
+
+#[cfg(feature = "server")]
+/// Endpoint serving the interactive Swagger UI
+pub async fn swagger_ui() -> impl Responder {
+    let mut response = HttpResponse::Ok();
+    response.content_type("text/html; charset=utf-8");
+    if security_headers::security_headers_enabled() {
+        if let Some(csp) = security_headers::security_headers(SWAGGER_UI_HTML).swagger_ui_csp {
+            response.insert_header(("Content-Security-Policy", csp));
+        }
+    }
+    response.body(SWAGGER_UI_HTML)
+}
+
+/// Returns whether the Swagger UI is served at `/api-docs` (`SWAGGER_UI_ENABLED`, default `1`).
+pub fn swagger_ui_enabled() -> bool {
+    dotenv().ok();
+
+    env::var("SWAGGER_UI_ENABLED").map(|value| value != "0").unwrap_or(true)
+}
+
+/// Returns whether the service runs in read-only mode (`READ_ONLY_MODE`, default `0`).
+///
+/// A read-only deployment distributes public keys from a read replica: it serves the public
+/// GET endpoints only and rejects every other method.
+pub fn read_only_mode() -> bool {
+    dotenv().ok();
+
+    env::var("READ_ONLY_MODE").map(|value| value == "1").unwrap_or(false)
+}
+
+/// Returns whether the service runs in public-only mode (`PUBLIC_ONLY_MODE`, default `0`).
+///
+/// A public-only deployment never returns private key material: the private key endpoints
+/// (`/jwks/{id}` with the private part, `/jwks/{id}/export`, `/jwks/{id}/approvals` and the
+/// replication feed) are not served, and created keys are returned without their private part.
+pub fn public_only_mode() -> bool {
+    dotenv().ok();
+
+    env::var("PUBLIC_ONLY_MODE").map(|value| value == "1").unwrap_or(false)
+}
+
+#[cfg(feature = "server")]
+/// Middleware rejecting every request that is not a `GET`, `HEAD` or `OPTIONS` request.
+pub async fn reject_mutations(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    if ![Method::GET, Method::HEAD, Method::OPTIONS].contains(req.method()) {
+        let response = HttpResponse::MethodNotAllowed()
+            .insert_header(("Allow", "GET, HEAD, OPTIONS"))
+            .body("The service is in read-only mode");
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
+
+#[cfg(feature = "server")]
+/// Routes served by a listener.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Listener {
+    /// Every route, on a single listener.
+    Combined,
+    /// The well-known and public key routes only.
+    Public,
+    /// Every route, authenticated with `ADMIN_TOKEN` if it is set.
+    Admin,
+}
+
+#[cfg(feature = "server")]
 /// Configure the Actix Web application
 pub fn app_config(cfg: &mut web::ServiceConfig) {
-    cfg.service(
+    configure_listener(cfg, Listener::Combined);
+}
+
+#[cfg(feature = "server")]
+/// Configure the Actix Web application with `store` instead of the key store of the process, e.g.
+/// a [`store::MemoryKeyStore`] in the tests of a service embedding the crate
+///
+/// No database connections are registered, so the endpoints working on the keys of the store need
+/// no database; the endpoints of the features kept in PostgreSQL only (aliases, approvals, the
+/// audit and access logs, bulk transitions, the inventory) are not available, and sensitive keys
+/// are never released.
+pub fn app_config_with_store(store: Arc<dyn store::KeyStore>) -> impl FnOnce(&mut web::ServiceConfig) {
+    move |cfg| configure_routes(cfg, Listener::Combined, store, None)
+}
+
+#[cfg(feature = "server")]
+/// Configure the public listener, used instead of [`app_config`] if `ADMIN_PORT` is set
+pub fn public_app_config(cfg: &mut web::ServiceConfig) {
+    configure_listener(cfg, Listener::Public);
+}
+
+#[cfg(feature = "server")]
+/// Configure the internal admin listener on `ADMIN_PORT`
+pub fn admin_app_config(cfg: &mut web::ServiceConfig) {
+    configure_listener(cfg, Listener::Admin);
+}
+
+#[cfg(feature = "server")]
+/// Configure the routes and middleware of a listener
+pub fn configure_listener(cfg: &mut web::ServiceConfig, listener: Listener) {
+    // Database connections, shared by all workers and listeners; keys kept in memory need none
+    let pool = (!store::memory_storage()).then(db::database_pool);
+    configure_routes(cfg, listener, store::key_store(), pool);
+}
+
+#[cfg(feature = "server")]
+/// Configure the routes and middleware of a listener serving the keys of `store`
+///
+/// The endpoints of the features kept in PostgreSQL only are served if a `pool` is given.
+fn configure_routes(
+    cfg: &mut web::ServiceConfig,
+    listener: Listener,
+    store: Arc<dyn store::KeyStore>,
+    pool: Option<db::DbPool>,
+) {
+    let database = pool.is_some();
+    let read_only = read_only_mode();
+    let public_only = public_only_mode();
+    let scope = if read_only || listener == Listener::Public {
+        // Public key distribution only, without private key material or admin views
         web::scope("")
+            .route("/.well-known/jwks.json", web::get().to(jwks_handler))
+            .route("/jwks/changes", web::get().to(jwks_changes_handler))
+            .route("/jwks/current/{selector}", web::get().to(current_jwk_handler))
+            .route("/jwks/fingerprints", web::get().to(fingerprints_handler))
+            .route("/jwks/{id}", web::get().to(get_public_jwk_by_id_handler))
+            .route("/jwks/{id}/chain.pem", web::get().to(certificate_chain_handler))
+            .route("/jwks/{id}/cose", web::get().to(cose_key_handler))
+            .route("/jwks/{id}/ssh", web::get().to(ssh::ssh_public_key_handler))
+            .route("/ssh/authorized_keys", web::get().to(ssh::authorized_keys_handler))
+            .route("/saml/metadata.xml", web::get().to(saml_metadata_handler))
+            .route("/.well-known/webfinger", web::get().to(webfinger::webfinger_handler))
+            .route("/.well-known/did.json", web::get().to(did::did_document_handler))
+            .route("/.well-known/signed-jwks.jwt", web::get().to(signed_jwks_handler))
+            .route("/upstream/{name}/jwks.json", web::get().to(upstream::upstream_jwks_handler))
+            .route("/readyz", web::get().to(readyz_handler))
+            .route("/api-docs/openapi.json", web::get().to(openapi_spec))
+    } else {
+        let scope = web::scope("")
             .route("/.well-known/jwks.json", web::get().to(jwks_handler))
             .route("/jwks", web::post().to(add_jwk_handler))
-            .route("/jwks/{id}", web::get().to(get_jwk_by_id_handler))
+            .route("/jwks/deleted", web::get().to(deleted_jwks_handler))
+            .route("/jwks/expiring", web::get().to(expiring_jwks_handler))
+            .route("/jwks/diff", web::post().to(jwks_diff_handler))
+            .route("/jwks/changes", web::get().to(jwks_changes_handler))
+            .route("/jwks/validate", web::post().to(validate_jwk_handler))
+            .route("/jwks/self-test", web::get().to(stored_key_self_test_handler))
+            .route("/jwks/self-test", web::post().to(run_stored_key_self_test_handler));
+
+        // Features kept in PostgreSQL only, registered before /jwks/{id} captures their paths
+        let scope = if database {
+            scope
+                .route("/jwks/external", web::post().to(external::register_external_key_handler))
+                .route("/jwks/inventory", web::get().to(inventory::inventory_handler))
+                .route("/jwks/bulk", web::post().to(bulk_transition_handler))
+                .route("/jwks/dual-write/report", web::get().to(dual_write::dual_write_report_handler))
+                .route("/jwks/dual-write/sync", web::post().to(dual_write::dual_write_sync_handler))
+                .route("/jobs", web::get().to(jobs::jobs_handler))
+                .route("/jobs/{name}/run", web::post().to(jobs::run_job_handler))
+                .route("/aliases", web::get().to(aliases::list_aliases_handler))
+                .route("/aliases/{name}", web::get().to(aliases::get_alias_handler))
+                .route("/aliases/{name}", web::put().to(aliases::put_alias_handler))
+                .route("/aliases/{name}", web::delete().to(aliases::delete_alias_handler))
+                .route("/ssh/certificates", web::post().to(ssh::ssh_certificate_handler))
+                .route("/audit/private-key-access", web::get().to(audit::private_key_access_handler))
+                .route("/audit/anomalies", web::get().to(anomalies::anomalies_handler))
+        } else {
+            scope
+        };
+
+        scope
+            .route("/jwks/current/{selector}", web::get().to(current_jwk_handler))
+            .route("/jwks/fingerprints", web::get().to(fingerprints_handler))
+            .route(
+                "/jwks/{id}",
+                if public_only {
+                    web::get().to(get_public_jwk_by_id_handler)
+                } else {
+                    web::get().to(get_jwk_by_id_handler)
+                },
+            )
             .route("/jwks/{id}", web::delete().to(delete_jwk_handler))
-            .route("/api-docs/openapi.json", web::get().to(openapi_spec)),
-    );
+            .route("/jwks/{id}/chain.pem", web::get().to(certificate_chain_handler))
+            .route("/jwks/{id}/cose", web::get().to(cose_key_handler))
+            .route("/jwks/{id}/ssh", web::get().to(ssh::ssh_public_key_handler))
+            .route("/jwks/{id}/rotate", web::post().to(rotate_jwk_handler))
+            .route("/jwks/{id}/extend", web::post().to(extend_jwk_handler))
+            .route("/jwks/{id}/freeze", web::post().to(freeze_jwk_handler))
+            .route("/jwks/{id}/unfreeze", web::post().to(unfreeze_jwk_handler))
+            .route("/jwks/{id}/primary", web::post().to(set_primary_jwk_handler))
+            .route("/jwks/{id}/primary", web::delete().to(unset_primary_jwk_handler))
+            .route("/jwks/{id}/history", web::get().to(key_history_handler))
+            .route("/sign", web::post().to(sign_handler))
+            .route("/tokens/access", web::post().to(access_token_handler))
+            .route("/software-statements", web::post().to(software_statement_handler))
+            .route("/tokens/exchange", web::post().to(token_exchange_handler))
+            .route("/dpop/validate", web::post().to(dpop_validation_handler))
+            .route("/request-objects", web::post().to(request_object_handler))
+            .route("/paseto/sign", web::post().to(paseto_sign_handler))
+            .route("/cwt/sign", web::post().to(cwt_sign_handler))
+            .route("/ssh/authorized_keys", web::get().to(ssh::authorized_keys_handler))
+            .route("/saml/metadata.xml", web::get().to(saml_metadata_handler))
+            .route("/.well-known/webfinger", web::get().to(webfinger::webfinger_handler))
+            .route("/.well-known/did.json", web::get().to(did::did_document_handler))
+            .route("/.well-known/signed-jwks.jwt", web::get().to(signed_jwks_handler))
+            .route("/upstream/{name}/jwks.json", web::get().to(upstream::upstream_jwks_handler))
+            .route("/sd-jwt/issue", web::post().to(sd_jwt_issue_handler))
+            .route("/readyz", web::get().to(readyz_handler))
+            .route("/api-docs/openapi.json", web::get().to(openapi_spec))
+    };
+
+    // Endpoints returning private key material, never served in public-only mode
+    let scope = if read_only || public_only || listener == Listener::Public {
+        scope
+    } else if database {
+        scope
+            .route("/jwks/{id}/export", web::get().to(export_jwk_handler))
+            .route("/jwks/{id}/approvals", web::post().to(approvals::issue_approval_handler))
+            .route("/replication/changes", web::get().to(replication::replication_changes_handler))
+    } else {
+        scope.route("/jwks/{id}/export", web::get().to(export_jwk_handler))
+    };
+    let scope = scope.wrap(Condition::new(read_only, from_fn(reject_mutations)));
+
+    // Operational endpoints, kept off the public listener
+    let scope = if listener == Listener::Public {
+        scope
+    } else {
+        scope
+            .route("/metrics", web::get().to(metrics::metrics_handler))
+            .route(admin::HEALTHZ_PATH, web::get().to(healthz_handler))
+    };
+
+    // HMAC signatures on mutating requests, required once REQUEST_SIGNING_SECRET is set
+    let scope = scope.wrap(Condition::new(
+        request_signing::request_signing_secret().is_some(),
+        from_fn(request_signing::verify_request_signature),
+    ));
+
+    // Interactive API documentation, disabled with SWAGGER_UI_ENABLED=0
+    let scope = if swagger_ui_enabled() {
+        scope.route("/api-docs", web::get().to(swagger_ui))
+    } else {
+        scope
+    };
+
+    // HSTS, nosniff and referrer policy, disabled with SECURITY_HEADERS_ENABLED=0
+    let headers = security_headers::security_headers(SWAGGER_UI_HTML);
+    let scope = scope.wrap(Condition::new(
+        security_headers::security_headers_enabled(),
+        security_headers::default_headers(&headers),
+    ));
+
+    // Fault injection for chaos testing
+    #[cfg(feature = "chaos")]
+    let scope = scope
+        .wrap(actix_web::middleware::from_fn(chaos::inject_faults))
+        .route("/chaos/faults", web::get().to(chaos::get_faults_handler))
+        .route("/chaos/faults", web::put().to(chaos::put_faults_handler))
+        .route("/chaos/faults", web::delete().to(chaos::delete_faults_handler));
+
+    // Bearer token on the admin listener, required once ADMIN_TOKEN is set
+    let scope = scope.wrap(Condition::new(
+        listener == Listener::Admin,
+        from_fn(admin::require_admin_token),
+    ));
+
+    // Request counts and latencies
+    let scope = scope.wrap(from_fn(metrics::track_requests));
+
+    if let Some(pool) = pool {
+        cfg.app_data(web::Data::new(pool));
+    }
+    cfg.app_data(web::Data::new(store));
+    cfg.app_data(web::Data::new(jwe::SecretDelivery::from_env()));
+    cfg.service(scope);
 }
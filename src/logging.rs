@@ -0,0 +1,299 @@
+//! This module writes the application logs.
+//!
+//! Log messages go to standard output (informational) or standard error (errors) and, if
+//! `SYSLOG_ADDRESS` is set, additionally to a syslog server as RFC 5424 messages over UDP
+//! (RFC 5426), TCP (RFC 6587 octet counting) or TLS (RFC 5425). Messages are handed to a
+//! background thread, so a slow or unreachable syslog server never delays requests; if its queue
+//! is full, messages are dropped from the syslog sink only.
+//!
+//! Use the [`log_info!`](crate::log_info) and [`log_error!`](crate::log_error) macros.
+
+use chrono::{SecondsFormat, Utc};
+use dotenv::dotenv;
+use openssl::ssl::{SslConnector, SslMethod, SslStream};
+use std::env;
+use std::io::Write;
+use std::net::{TcpStream, UdpSocket};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Name of the application in syslog messages.
+const APP_NAME: &str = "jwks-service-app";
+
+/// Maximum number of messages waiting to be sent to the syslog server.
+const QUEUE_SIZE: usize = 1024;
+
+/// Delay before reconnecting to the syslog server after a failure.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Writes an informational message to the application log.
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        $crate::logging::log($crate::logging::Severity::Informational, &format!($($arg)*))
+    };
+}
+
+/// Writes an error message to the application log.
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => {
+        $crate::logging::log($crate::logging::Severity::Error, &format!($($arg)*))
+    };
+}
+
+/// Severity of a log message (RFC 5424, section 6.2.1).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    /// Error conditions.
+    Error = 3,
+    /// Informational messages.
+    Informational = 6,
+}
+
+/// Transport to the syslog server.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyslogProtocol {
+    /// One message per datagram.
+    Udp,
+    /// Octet-counted messages over TCP.
+    Tcp,
+    /// Octet-counted messages over TLS.
+    Tls,
+}
+
+/// Settings of the syslog sink.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyslogSettings {
+    /// Address of the server (`host:port`).
+    pub address: String,
+    /// Transport to the server.
+    pub protocol: SyslogProtocol,
+    /// Facility code (e.g., 3 for `daemon`).
+    pub facility: u8,
+    /// CA bundle the server certificate is verified against (TLS only); system roots if `None`.
+    pub ca_file: Option<String>,
+}
+
+/// Returns the facility code of a facility name (`kern` … `local7`).
+pub fn facility_code(name: &str) -> Option<u8> {
+    const FACILITIES: [&str; 24] = [
+        "kern", "user", "mail", "daemon", "auth", "syslog", "lpr", "news", "uucp", "cron",
+        "authpriv", "ftp", "ntp", "security", "console", "solaris-cron", "local0", "local1",
+        "local2", "local3", "local4", "local5", "local6", "local7",
+    ];
+    FACILITIES.iter().position(|facility| *facility == name).map(|code| code as u8)
+}
+
+/// Returns the settings of the syslog sink: `SYSLOG_ADDRESS`, `SYSLOG_PROTOCOL` (`udp`, `tcp`
+/// or `tls`, default `udp`), `SYSLOG_FACILITY` (default `daemon`) and `SYSLOG_TLS_CA_FILE`.
+///
+/// # Returns
+///
+/// `None` if `SYSLOG_ADDRESS` is not set, so logs are only written to the console.
+///
+/// # Panics
+///
+/// This function will panic if the protocol or the facility is unknown.
+pub fn syslog_settings() -> Option<SyslogSettings> {
+    dotenv().ok();
+
+    let address = env::var("SYSLOG_ADDRESS").ok().filter(|value| !value.trim().is_empty())?;
+    let protocol = match env::var("SYSLOG_PROTOCOL").unwrap_or_default().as_str() {
+        "" | "udp" => SyslogProtocol::Udp,
+        "tcp" => SyslogProtocol::Tcp,
+        "tls" => SyslogProtocol::Tls,
+        other => panic!("Unknown SYSLOG_PROTOCOL {}, expected udp, tcp or tls", other),
+    };
+    let facility = env::var("SYSLOG_FACILITY").unwrap_or_else(|_| "daemon".to_string());
+
+    Some(SyslogSettings {
+        address: address.trim().to_string(),
+        protocol,
+        facility: facility_code(&facility)
+            .unwrap_or_else(|| panic!("Unknown SYSLOG_FACILITY {}", facility)),
+        ca_file: env::var("SYSLOG_TLS_CA_FILE").ok().filter(|value| !value.trim().is_empty()),
+    })
+}
+
+/// Formats a log message as an RFC 5424 syslog message.
+///
+/// The structured data carries the software name and version (`origin`) and the replica.
+///
+/// # Arguments
+///
+/// * `facility` - Facility code.
+/// * `severity` - Severity of the message.
+/// * `hostname` - Host name of the sender.
+/// * `message` - The log message.
+pub fn format_syslog_message(facility: u8, severity: Severity, hostname: &str, message: &str) -> String {
+    // Parameter values escape '"', '\' and ']'
+    let escape = |value: &str| value.replace('\\', "\\\\").replace('"', "\\\"").replace(']', "\\]");
+    let hostname = if hostname.is_empty() { "-" } else { hostname };
+
+    format!(
+        "<{}>1 {} {} {} {} - [origin software=\"{}\" swVersion=\"{}\"][meta replica=\"{}\"] {}",
+        facility as u16 * 8 + severity as u16,
+        Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+        hostname.chars().filter(|c| c.is_ascii_graphic()).take(255).collect::<String>(),
+        APP_NAME,
+        std::process::id(),
+        APP_NAME,
+        env!("CARGO_PKG_VERSION"),
+        escape(crate::jobs::replica_id()),
+        message.trim_end()
+    )
+}
+
+/// Frames a syslog message for a stream transport (RFC 6587 octet counting).
+pub fn octet_counted(message: &str) -> Vec<u8> {
+    format!("{} {}", message.len(), message).into_bytes()
+}
+
+/// Open connection to the syslog server.
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    Tls(Box<SslStream<TcpStream>>),
+}
+
+impl Connection {
+    /// Connects to the syslog server.
+    fn open(settings: &SyslogSettings) -> Result<Connection, String> {
+        match settings.protocol {
+            SyslogProtocol::Udp => {
+                let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| e.to_string())?;
+                socket.connect(&settings.address).map_err(|e| e.to_string())?;
+                Ok(Connection::Udp(socket))
+            }
+            SyslogProtocol::Tcp => TcpStream::connect(&settings.address)
+                .map(Connection::Tcp)
+                .map_err(|e| e.to_string()),
+            SyslogProtocol::Tls => {
+                let mut builder = SslConnector::builder(SslMethod::tls_client()).map_err(|e| e.to_string())?;
+                if let Some(ca_file) = &settings.ca_file {
+                    builder.set_ca_file(ca_file).map_err(|e| e.to_string())?;
+                }
+                let host = settings.address.rsplit_once(':').map_or(settings.address.as_str(), |(host, _)| host);
+                let stream = TcpStream::connect(&settings.address).map_err(|e| e.to_string())?;
+                builder
+                    .build()
+                    .connect(host.trim_start_matches('[').trim_end_matches(']'), stream)
+                    .map(|stream| Connection::Tls(Box::new(stream)))
+                    .map_err(|e| e.to_string())
+            }
+        }
+    }
+
+    /// Sends a message.
+    fn send(&mut self, message: &str) -> std::io::Result<()> {
+        match self {
+            Connection::Udp(socket) => socket.send(message.as_bytes()).map(|_| ()),
+            Connection::Tcp(stream) => stream.write_all(&octet_counted(message)),
+            Connection::Tls(stream) => stream.write_all(&octet_counted(message)),
+        }
+    }
+}
+
+/// Sends the queued messages to the syslog server, reconnecting after failures.
+fn run_sink(settings: SyslogSettings, messages: Receiver<String>) {
+    let mut connection: Option<Connection> = None;
+    for message in messages {
+        if connection.is_none() {
+            match Connection::open(&settings) {
+                Ok(opened) => connection = Some(opened),
+                Err(error) => {
+                    eprintln!("Failed to connect to the syslog server {}: {}", settings.address, error);
+                    std::thread::sleep(RECONNECT_DELAY);
+                    continue;
+                }
+            }
+        }
+        if let Some(Err(error)) = connection.as_mut().map(|connection| connection.send(&message)) {
+            eprintln!("Failed to send a message to the syslog server {}: {}", settings.address, error);
+            connection = None;
+        }
+    }
+}
+
+/// Queue of the syslog sink with the fields shared by every message.
+struct SyslogSink {
+    facility: u8,
+    hostname: String,
+    queue: SyncSender<String>,
+}
+
+/// Returns the syslog sink, starting its thread on first use.
+fn syslog_sink() -> Option<&'static SyslogSink> {
+    static SINK: OnceLock<Option<SyslogSink>> = OnceLock::new();
+
+    SINK.get_or_init(|| {
+        let settings = syslog_settings()?;
+        let facility = settings.facility;
+        let (queue, messages) = sync_channel(QUEUE_SIZE);
+        std::thread::Builder::new()
+            .name("syslog".to_string())
+            .spawn(move || run_sink(settings, messages))
+            .ok()?;
+        Some(SyslogSink { facility, hostname: env::var("HOSTNAME").unwrap_or_default(), queue })
+    })
+    .as_ref()
+}
+
+/// Writes a message to the console and, if configured, to the syslog server.
+pub fn log(severity: Severity, message: &str) {
+    match severity {
+        Severity::Error => eprintln!("{}", message),
+        Severity::Informational => println!("{}", message),
+    }
+
+    if let Some(sink) = syslog_sink() {
+        let message = format_syslog_message(sink.facility, severity, &sink.hostname, message);
+        if let Err(TrySendError::Disconnected(_)) = sink.queue.try_send(message) {
+            eprintln!("The syslog sink has stopped");
+        }
+    }
+}
+
+#[test]
+fn test_format_syslog_message() {
+    let message = format_syslog_message(3, Severity::Error, "host-1", "Job purge failed: timeout\n");
+    assert!(message.starts_with("<27>1 "));
+    let fields = message.splitn(7, ' ').collect::<Vec<_>>();
+    assert!(fields[1].ends_with('Z'));
+    assert_eq!(fields[2], "host-1");
+    assert_eq!(fields[3], "jwks-service-app");
+    assert_eq!(fields[4], std::process::id().to_string());
+    assert_eq!(fields[5], "-");
+    assert!(fields[6].starts_with("[origin software=\"jwks-service-app\" swVersion=\""));
+    assert!(message.ends_with("] Job purge failed: timeout"));
+
+    assert!(format_syslog_message(16, Severity::Informational, "", "started").starts_with("<134>1 "));
+    assert_eq!(octet_counted("<27>1 x"), b"7 <27>1 x");
+    assert_eq!(facility_code("daemon"), Some(3));
+    assert_eq!(facility_code("local7"), Some(23));
+    assert_eq!(facility_code("nope"), None);
+}
+
+#[test]
+fn test_syslog_tcp_connection() {
+    use std::io::Read;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let settings = SyslogSettings {
+        address: listener.local_addr().unwrap().to_string(),
+        protocol: SyslogProtocol::Tcp,
+        facility: 3,
+        ca_file: None,
+    };
+
+    let mut connection = Connection::open(&settings).unwrap();
+    connection.send("<30>1 first").unwrap();
+    connection.send("<30>1 second").unwrap();
+    drop(connection);
+
+    let mut received = String::new();
+    listener.accept().unwrap().0.read_to_string(&mut received).unwrap();
+    assert_eq!(received, "11 <30>1 first12 <30>1 second");
+}
@@ -2,38 +2,100 @@
 
 use actix_cors::Cors;
 use actix_web::*;
-use diesel_migrations::MigrationHarness;
 use dotenv::dotenv;
-use jwks_service_app::{app_config, MIGRATIONS};
+use jwks_service_app::{
+    admin, admin_app_config, app_config, db, dual_write, health, jobs, key_encryption, keygen, log_error,
+    log_info, manager, migrate, notifications, policy, public_app_config, read_only_mode, recovery,
+    replication, siem, statsd, store, warmup,
+};
 use std::env;
 
-mod db;
 mod schema;
 
 #[actix_web::main]
 pub async fn main() -> std::io::Result<()> {
     dotenv().ok();
 
-    // Check if migrations need to be run
-    if env::var("RUN_MIGRATIONS_ON_START").unwrap_or_default() == "1" {
-        let connection = &mut db::establish_connection();
-        println!("Running migrations...");
+    // Generate a key offline without starting the server
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("keygen") {
+        if let Err(e) = keygen::run_keygen(&args[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+        return Ok(());
+    }
+
+    // Run the migrations, including contract migrations, and exit
+    if args.first().map(String::as_str) == Some("migrate") {
+        if let Err(e) = migrate::run_migrate(&args[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+        return Ok(());
+    }
+
+    // Restore the keystore to a point in time and exit
+    if args.first().map(String::as_str) == Some("restore") {
+        if let Err(e) = recovery::run_restore(&args[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+        return Ok(());
+    }
+
+    // Encrypt the private keys stored before a key-encryption key was configured and exit
+    if args.first().map(String::as_str) == Some("seal-keys") {
+        if let Err(e) = key_encryption::run_seal_keys(&args[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+        return Ok(());
+    }
+
+    // An invalid key-encryption key would fail every key read and creation
+    if let Err(e) = key_encryption::key_encryption_key() {
+        log_error!("{}", e);
+        std::process::exit(2);
+    }
+
+    // Keys kept in memory (demo and test deployments) need no database, so the steps and jobs
+    // working on the database are skipped
+    let database = !store::memory_storage();
+
+    // Check if migrations need to be run (a read-only deployment cannot write to its replica)
+    let read_only = read_only_mode();
+    if env::var("RUN_MIGRATIONS_ON_START").unwrap_or_default() == "1" && !read_only && database {
+        let connection = &mut db::wait_for_database().expect("Failed to connect to the database");
+        log_info!("Running migrations...");
 
-        // Run migrations
-        connection
-            .run_pending_migrations(MIGRATIONS)
-            .expect("Failed to run migrations");
+        // Run the expand migrations; contract migrations wait for `migrate --contract`
+        let plan = migrate::run_migrations(connection, false).expect("Failed to run migrations");
+        migrate::print_migration_plan(&plan);
 
-        // Get the list of applied migrations
-        let applied_migrations = connection
-            .applied_migrations()
-            .expect("Failed to get applied migrations");
-        println!("Applied migrations:");
-        for migration in applied_migrations {
-            println!("- {}", migration);
+        log_info!("Migrations completed.");
+
+        // Keep the dual-write target on the same schema
+        if let Some(target) = dual_write::establish_target_connection() {
+            log_info!("Running migrations on the dual-write target...");
+            let target = &mut target.expect("Failed to connect to the dual-write target");
+            migrate::run_migrations(target, false)
+                .expect("Failed to run migrations on the dual-write target");
         }
+    }
 
-        println!("Migrations completed.");
+    // Generate the first keys of a fresh deployment
+    let bootstrap_algorithms = policy::bootstrap_algorithms();
+    if !bootstrap_algorithms.is_empty() && !read_only && database {
+        let connection = &mut db::wait_for_database().expect("Failed to connect to the database");
+        match manager::bootstrap_keys(connection, &bootstrap_algorithms) {
+            Ok(keys) => {
+                for key in keys {
+                    log_info!("Bootstrapped {} key {}.", key.alg, key.kid);
+                }
+            }
+            Err(e) => log_error!("Key bootstrap failed: {}", e),
+        }
     }
 
     let host = env::var("HOST")
@@ -42,15 +104,108 @@ pub async fn main() -> std::io::Result<()> {
         .unwrap_or("8080".into())
         .parse::<u16>().unwrap();
 
+    // Check the crypto subsystem before accepting traffic and periodically afterwards
+    health::record_crypto_self_check(health::run_crypto_self_check());
+    if let Some(interval) = health::crypto_self_check_interval() {
+        rt::spawn(async move {
+            let mut ticker = rt::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                match web::block(health::run_crypto_self_check).await {
+                    Ok(result) => health::record_crypto_self_check(result),
+                    Err(e) => health::record_crypto_self_check(Err(e.to_string())),
+                }
+            }
+        });
+    }
+
+    // Optionally check every stored key before accepting traffic
+    if health::stored_key_self_test_on_start() && database {
+        if let Err(e) = health::run_stored_key_self_test(store::key_store().as_ref()) {
+            log_error!("Stored key self-test failed to load keys: {}", e);
+        }
+    }
+
+    // Connect to the database and serve the JWK Set once, so the first requests are not slow
+    if warmup::warmup_enabled() && database {
+        if let Err(e) = warmup::run_warmup().await {
+            log_error!("Warm-up failed: {}", e);
+        }
+    }
+
+    // Push the pool usage and key counts to StatsD; requests, key generation and queries are sent
+    // as they happen
+    if statsd::statsd_client().is_some() && database {
+        rt::spawn(async move {
+            let mut ticker = rt::time::interval(statsd::statsd_interval());
+            loop {
+                ticker.tick().await;
+                statsd::push_pool_usage();
+                match web::block(statsd::push_key_counts).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => log_error!("Failed to push key counts to StatsD: {}", e),
+                    Err(e) => log_error!("Failed to push key counts to StatsD: {}", e),
+                }
+            }
+        });
+    }
+
+    // Take part in scheduler leader election, so one replica owns the background jobs
+    if !read_only && database {
+        jobs::spawn_scheduler_election();
+
+        // Pull the keys of the other regions
+        if !replication::replication_peers().is_empty() {
+            jobs::spawn_scheduled_job(
+                replication::REPLICATION_JOB,
+                replication::replication_interval(),
+                replication::sync_from_peers,
+            );
+        }
+
+        // Forward the audit log to the SIEM
+        if siem::siem_settings().is_some() {
+            jobs::spawn_scheduled_job(siem::SIEM_JOB, siem::siem_interval(), siem::forward_audit_events);
+        }
+
+        // Warn about keys and certificates approaching expiry
+        if notifications::notification_settings().is_some() {
+            jobs::spawn_scheduled_job(
+                notifications::NOTIFICATION_JOB,
+                notifications::notification_interval(),
+                notifications::check_expiring_keys,
+            );
+        }
+    }
+
+    // Serve metrics and the admin API on a separate internal listener
+    let admin_port = admin::admin_port();
+    if let Some(admin_port) = admin_port {
+        let admin_server = HttpServer::new(|| App::new().configure(admin_app_config))
+            .bind((admin::admin_host(), admin_port))?
+            .run();
+        rt::spawn(async move {
+            if let Err(e) = admin_server.await {
+                log_error!("Admin listener failed: {}", e);
+            }
+        });
+    }
+
     // Start the web server
-    HttpServer::new(|| {
+    HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin() // Allow requests from any origin
             .allowed_methods(vec!["GET", "POST", "DELETE"]) // Allow GET, POST, and DELETE
             .allow_any_header() // Allow any headers
             .max_age(3600); // Set CORS cache time
 
-        App::new().wrap(cors).configure(app_config)
+        let app = App::new().wrap(cors);
+        if admin_port.is_some() {
+            app.configure(public_app_config)
+        } else {
+            app.configure(app_config)
+        }
     })
     .bind((host, port))?
     .run()
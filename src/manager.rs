@@ -0,0 +1,865 @@
+//! This module contains the key management operations, independent of the HTTP server.
+//!
+//! [`KeyManager`] creates, lists, rotates and signs with keys exactly like the corresponding
+//! endpoints, which are thin adapters over it, and reports failures as [`KeyError`]s instead of
+//! HTTP responses. Services embedding the key manager build the crate without the default
+//! `server` feature, which leaves out actix-web and every handler.
+//!
+//! The operations use a [`KeyStore`], by default the store of the process ([`key_store`]), like
+//! the handlers. Their blocking queries and key generation run on the blocking thread pool of the
+//! Tokio runtime (see [`run_blocking`]), so they must be awaited within a Tokio runtime.
+
+use crate::audit::{record_event, ACTION_SET_PRIMARY, ACTION_UNSET_PRIMARY};
+use crate::crypto::{
+    certificate_thumbprint, curve_for_alg, generate_jwk_data, key_details, key_use_for_alg,
+    supported_algorithms,
+};
+use crate::dual_write::mirror_key;
+use crate::health::verify_key_pair;
+use crate::jobs::run_locked;
+use crate::jws::encode_jwt;
+use crate::key_encryption::check_secret_storage;
+use crate::log_error;
+use crate::metrics::record_keygen;
+use crate::models::{AlgorithmInput, Jwk, JwkData, Jwks, JwksQuery, SignInput, SignOutput};
+use crate::notifications::notify_rotation_failure;
+use crate::policy::{
+    allowed_algorithms, approved_curves, check_key_purpose, check_key_strength,
+    check_purpose_algorithm, check_token_constraints, default_rsa_key_size, is_algorithm_allowed,
+    key_lifetimes, min_rsa_key_size, reuse_active_keys,
+};
+use crate::schema::jwks::dsl::*;
+use crate::store::{key_store, KeyStore, StoreError};
+use crate::schema::jwks::BoxedQuery;
+use crate::tokens::bind_certificate;
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use dotenv::dotenv;
+use std::fmt;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Name of the unique index on the key IDs (`kid`) of the keys.
+pub const KID_INDEX: &str = "jwks_kid_idx";
+
+/// Number of key IDs tried before giving up storing a generated key.
+const KID_ATTEMPTS: u32 = 3;
+
+/// Name of the advisory lock serializing the startup bootstrap of the replicas.
+pub const BOOTSTRAP_LOCK: &str = "bootstrap";
+
+/// Failure of a key management operation, with the message returned to the caller.
+///
+/// The server responds with the status noted on each variant.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyError {
+    /// The request is invalid (`400 Bad Request`).
+    Invalid(String),
+    /// The claims violate the constraints of the key (`403 Forbidden`).
+    Forbidden(String),
+    /// The key or alias does not exist (`404 Not Found`).
+    NotFound(String),
+    /// The key cannot be used in its current state (`409 Conflict`).
+    Conflict(String),
+    /// The private key has expired (`410 Gone`).
+    Gone(String),
+    /// The deployment policy does not permit the request (`422 Unprocessable Entity`).
+    Policy(String),
+    /// The key is frozen (`423 Locked`).
+    Frozen(String),
+    /// No key can serve the request (`503 Service Unavailable`).
+    Unavailable(String),
+    /// Key generation, signing or the database failed (`500 Internal Server Error`).
+    Internal(String),
+}
+
+impl KeyError {
+    /// Returns the message of the error.
+    pub fn message(&self) -> &str {
+        match self {
+            KeyError::Invalid(message)
+            | KeyError::Forbidden(message)
+            | KeyError::NotFound(message)
+            | KeyError::Conflict(message)
+            | KeyError::Gone(message)
+            | KeyError::Policy(message)
+            | KeyError::Frozen(message)
+            | KeyError::Unavailable(message)
+            | KeyError::Internal(message) => message,
+        }
+    }
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for KeyError {}
+
+/// Key returned by [`KeyManager::create`].
+#[derive(Debug, Clone)]
+pub struct CreatedKey {
+    /// The key, with its private part.
+    pub key: JwkData,
+    /// Whether an existing usable key was returned instead of a new one (`reuse_active`).
+    pub reused: bool,
+}
+
+/// Key management operations on behalf of an actor.
+///
+/// # Examples
+///
+/// ```no_run
+/// use jwks_service_app::manager::KeyManager;
+/// use jwks_service_app::models::{JwksQuery, SignInput};
+///
+/// # async fn example() -> Result<(), jwks_service_app::manager::KeyError> {
+/// let manager = KeyManager::new(Some("billing".to_string()));
+/// let input = serde_json::from_value(serde_json::json!({ "alg": "ES256" })).unwrap();
+/// let created = manager.create(&input).await?;
+///
+/// let signed = manager
+///     .sign(&SignInput {
+///         id: Some(created.key.id),
+///         alias: None,
+///         alg: None,
+///         claims: serde_json::json!({ "sub": "user-1" }),
+///         client_certificate: None,
+///         x5t_s256: None,
+///     })
+///     .await?;
+/// let key_set = manager.list(&JwksQuery { at: None, purpose: None }).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct KeyManager {
+    /// Caller recorded in the audit log, like the `X-Actor` header of the endpoints.
+    pub actor: Option<String>,
+    /// Store the keys are kept in.
+    pub store: Arc<dyn KeyStore>,
+}
+
+impl fmt::Debug for KeyManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyManager").field("actor", &self.actor).finish_non_exhaustive()
+    }
+}
+
+impl Default for KeyManager {
+    fn default() -> KeyManager {
+        KeyManager::new(None)
+    }
+}
+
+impl KeyManager {
+    /// Creates a key manager using the store of the process and recording `actor` in the audit
+    /// log.
+    pub fn new(actor: Option<String>) -> KeyManager {
+        KeyManager::with_store(key_store(), actor)
+    }
+
+    /// Creates a key manager using `store` and recording `actor` in the audit log.
+    pub fn with_store(store: Arc<dyn KeyStore>, actor: Option<String>) -> KeyManager {
+        KeyManager { actor, store }
+    }
+
+    /// Creates a key, like `POST /jwks`.
+    ///
+    /// With `reuse_active` (default `REUSE_ACTIVE_KEYS`), an existing usable key matching the
+    /// request is returned instead, unless the key is scheduled with `not_before`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyError::Invalid`] for unsupported algorithms or unknown purposes,
+    /// [`KeyError::Policy`] for policy violations and [`KeyError::Internal`] if key generation,
+    /// self-verification or storing the key fails.
+    pub async fn create(&self, input: &AlgorithmInput) -> Result<CreatedKey, KeyError> {
+        let (manager, input) = (self.clone(), input.clone());
+        run_blocking(move || manager.create_key(&input)).await
+    }
+
+    /// Blocking implementation of [`KeyManager::create`].
+    fn create_key(&self, input: &AlgorithmInput) -> Result<CreatedKey, KeyError> {
+        // Resolve the standard EdDSA form (alg + crv) to the curve used for key generation
+        let algorithm = input.generation_algorithm().map_err(KeyError::Invalid)?;
+
+        let constraints = (
+            non_empty(input.allowed_issuers.clone()),
+            non_empty(input.allowed_audiences.clone()),
+        );
+        let rsa_key_size = input.key_size.unwrap_or_else(default_rsa_key_size);
+        if let Some(key_purpose) = input.purpose.as_deref() {
+            check_key_purpose(key_purpose)
+                .and(check_purpose_algorithm(key_purpose, &algorithm))
+                .map_err(KeyError::Invalid)?;
+        }
+
+        // Singleton mode: retried deploy scripts get the existing key instead of another one;
+        // keys scheduled for a later activation are always new
+        if input.not_before.is_none() && input.reuse_active.unwrap_or_else(reuse_active_keys) {
+            if let Some(key) = find_reusable_jwk(
+                &self.store,
+                &algorithm,
+                rsa_key_size,
+                &constraints,
+                input.purpose.as_deref(),
+                (input.burn_after_read, input.sensitive),
+            )? {
+                return Ok(CreatedKey { key, reused: true });
+            }
+        }
+
+        let key = create_jwk(
+            &self.store,
+            &algorithm,
+            rsa_key_size,
+            constraints,
+            input.purpose.clone(),
+            (input.burn_after_read, input.sensitive),
+            None,
+            input.not_before,
+            self.actor.clone(),
+        )?;
+        Ok(CreatedKey { key, reused: false })
+    }
+
+    /// Lists the published keys, like `GET /.well-known/jwks.json`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyError::Invalid`] for an invalid `at` or unknown purpose and
+    /// [`KeyError::Internal`] if the keys cannot be loaded.
+    pub async fn list(&self, query: &JwksQuery) -> Result<Jwks, KeyError> {
+        let (manager, query) = (self.clone(), query.clone());
+        run_blocking(move || manager.list_keys(&query)).await
+    }
+
+    /// Blocking implementation of [`KeyManager::list`].
+    fn list_keys(&self, query: &JwksQuery) -> Result<Jwks, KeyError> {
+        let (at, key_purpose) = key_set_selection(query)?;
+        let results = self.store.list_active(at, key_purpose).map_err(|error| {
+            log_error!("Failed to load keys: {}", error);
+            KeyError::Internal("Failed to load keys".to_string())
+        })?;
+
+        Ok(Jwks { keys: results.into_iter().map(Jwk::from).collect() })
+    }
+
+    /// Rotates a key, like `POST /jwks/{id}/rotate`.
+    ///
+    /// A new version of the logical key is created with the same algorithm, key size,
+    /// constraints, purpose and delivery mode, linked to the rotated key as its predecessor. It
+    /// takes over the primary designation and the aliases of the rotated key, which stays
+    /// published until it expires.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyError::NotFound`] if the key does not exist, is deleted or expired,
+    /// [`KeyError::Conflict`] if it is external or already rotated, and the errors of
+    /// [`KeyManager::create`] if the new version cannot be created.
+    pub async fn rotate(&self, key_id: Uuid) -> Result<JwkData, KeyError> {
+        let manager = self.clone();
+        run_blocking(move || manager.rotate_key(key_id)).await
+    }
+
+    /// Blocking implementation of [`KeyManager::rotate`].
+    fn rotate_key(&self, key_id: Uuid) -> Result<JwkData, KeyError> {
+        let now = Utc::now().naive_utc();
+
+        let rotated = self
+            .store
+            .get_private(key_id)
+            .map_err(|error| {
+                log_error!("Failed to load key {}: {}", key_id, error);
+                KeyError::Internal("Failed to load key".to_string())
+            })?
+            .filter(|jwk| jwk.deleted_at.is_none())
+            .filter(|jwk| jwk.key_expires_at.is_some_and(|expires_at| expires_at > now))
+            .ok_or_else(|| KeyError::NotFound("Key not found".to_string()))?;
+        if rotated.external {
+            return Err(KeyError::Conflict(
+                "External keys are rotated by registering the new key".to_string(),
+            ));
+        }
+
+        // Versions form a chain: a key has at most one successor
+        match self.store.successor(key_id) {
+            Ok(None) => {}
+            Ok(Some(_)) => return Err(KeyError::Conflict("Key has already been rotated".to_string())),
+            Err(_) => return Err(KeyError::Internal("Failed to load key versions".to_string())),
+        }
+
+        // EdDSA keys are generated by curve name
+        let algorithm = match (rotated.alg.as_str(), rotated.crv.as_deref()) {
+            ("EdDSA", Some(curve)) => curve.to_string(),
+            (algorithm, _) => algorithm.to_string(),
+        };
+        let rsa_key_size = key_details(&rotated, now)
+            .ok()
+            .and_then(|details| details.modulus_bits)
+            .unwrap_or_else(default_rsa_key_size);
+        let constraints = (rotated.allowed_issuers.clone(), rotated.allowed_audiences.clone());
+
+        // The new version keeps the purpose and delivery mode and follows its rotation policy
+        let jwk = match create_jwk(
+            &self.store,
+            &algorithm,
+            rsa_key_size,
+            constraints,
+            rotated.purpose.clone(),
+            (rotated.burn_after_read, rotated.sensitive),
+            Some(key_id),
+            None,
+            self.actor.clone(),
+        ) {
+            Ok(jwk) => jwk,
+            Err(error) => {
+                let reason = format!("the new version could not be created ({})", error);
+                notify_rotation_failure(key_id, &rotated.kid, &reason);
+                return Err(error);
+            }
+        };
+
+        // The new version takes over the primary designation and the aliases of the rotated key
+        self.store.hand_over(&rotated, &jwk, self.actor.clone());
+
+        Ok(jwk)
+    }
+
+    /// Signs a JWT with a managed key, like `POST /sign`.
+    ///
+    /// The key is selected by `id`, by `alias`, or by `alg` using the primary key of the
+    /// algorithm (the most recently created active key if there is none). If a client
+    /// certificate or its thumbprint is given, the token is bound to it (RFC 8705).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyError::Invalid`] if no key is selected, the key cannot sign or the
+    /// certificate is invalid, [`KeyError::Forbidden`] if the claims violate the constraints of
+    /// the key, the errors of [`find_signing_jwk`] and [`find_active_signing_jwk`], and
+    /// [`KeyError::Internal`] if signing fails.
+    pub async fn sign(&self, input: &SignInput) -> Result<SignOutput, KeyError> {
+        let (manager, input) = (self.clone(), input.clone());
+        run_blocking(move || manager.sign_token(&input)).await
+    }
+
+    /// Blocking implementation of [`KeyManager::sign`].
+    fn sign_token(&self, input: &SignInput) -> Result<SignOutput, KeyError> {
+        let jwk_result = match (input.id, input.alias.as_deref(), input.alg.as_deref()) {
+            (Some(key_id), _, _) => find_signing_jwk(&self.store, key_id)?,
+            (None, Some(alias), _) => {
+                match self.store.resolve_alias(alias) {
+                    Ok(Some(key_id)) => find_signing_jwk(&self.store, key_id)?,
+                    Ok(None) => return Err(KeyError::NotFound("Alias not found".to_string())),
+                    Err(_) => return Err(KeyError::Internal("Failed to resolve alias".to_string())),
+                }
+            }
+            (None, None, Some(algorithm)) => find_active_signing_jwk(&self.store, Some(algorithm))?,
+            (None, None, None) => {
+                return Err(KeyError::Invalid("Either id, alias or alg is required".to_string()))
+            }
+        };
+
+        if key_use_for_alg(&jwk_result.alg) != "sig" {
+            return Err(KeyError::Invalid("Key cannot be used for signing".to_string()));
+        }
+
+        // Resolve the certificate the token is bound to (RFC 8705)
+        let thumbprint = match (&input.client_certificate, &input.x5t_s256) {
+            (None, None) => None,
+            (None, Some(thumbprint)) => Some(thumbprint.clone()),
+            (Some(certificate), supplied) => match certificate_thumbprint(certificate) {
+                Ok(thumbprint) if supplied.as_ref().is_none_or(|supplied| *supplied == thumbprint) => {
+                    Some(thumbprint)
+                }
+                Ok(_) => {
+                    return Err(KeyError::Invalid(
+                        "Certificate thumbprint does not match the client certificate".to_string(),
+                    ))
+                }
+                Err(_) => return Err(KeyError::Invalid("Invalid client certificate".to_string())),
+            },
+        };
+        let claims = match thumbprint {
+            Some(thumbprint) => bind_certificate(&input.claims, &thumbprint).map_err(KeyError::Invalid)?,
+            None => input.claims.clone(),
+        };
+
+        check_token_constraints(&jwk_result, &claims).map_err(KeyError::Forbidden)?;
+
+        encode_jwt(&jwk_result, &claims)
+            .map(|token| SignOutput { token })
+            .map_err(|_| KeyError::Internal("Failed to sign token".to_string()))
+    }
+}
+
+/// Runs blocking work (database queries, key generation, signing) on the blocking thread pool of
+/// the Tokio runtime, so it does not stall the async worker threads.
+///
+/// # Errors
+///
+/// Returns the error of the work, [`KeyError::Unavailable`] if it was cancelled because the
+/// runtime is shutting down and [`KeyError::Internal`] if it panicked.
+pub async fn run_blocking<T, F>(work: F) -> Result<T, KeyError>
+where
+    F: FnOnce() -> Result<T, KeyError> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(work).await.unwrap_or_else(|error| {
+        log_error!("Blocking work did not complete: {}", error);
+        if error.is_cancelled() {
+            Err(KeyError::Unavailable("The service is shutting down".to_string()))
+        } else {
+            Err(KeyError::Internal("The request could not be completed".to_string()))
+        }
+    })
+}
+
+/// Parses the instant and purpose a key set is requested for.
+///
+/// # Errors
+///
+/// Returns [`KeyError::Invalid`] for an invalid `at` or unknown purpose.
+pub fn key_set_selection(query: &JwksQuery) -> Result<(Option<NaiveDateTime>, Option<String>), KeyError> {
+    let at = query.at_time().map_err(KeyError::Invalid)?;
+
+    // Publish a separate key set per purpose
+    if let Some(key_purpose) = &query.purpose {
+        check_key_purpose(key_purpose).map_err(KeyError::Invalid)?;
+    }
+
+    Ok((at, query.purpose.clone()))
+}
+
+/// Returns the query of the keys published in the JWK Set.
+///
+/// # Arguments
+///
+/// * `at` - Instant to reconstruct the key set for, or `None` for the current key set.
+/// * `key_purpose` - Purpose the key set is restricted to, if any.
+pub fn published_keys(at: Option<NaiveDateTime>, key_purpose: Option<String>) -> BoxedQuery<'static, Pg> {
+    let mut keys = match at {
+        // Return only active keys (deleted_at IS NULL, frozen_at IS NULL, key_expires_at > NOW
+        // and not_before unset or reached)
+        None => jwks
+            .filter(deleted_at.is_null())
+            .filter(frozen_at.is_null())
+            .filter(key_expires_at.gt(Utc::now().naive_utc()))
+            .filter(not_before.is_null().or(not_before.le(Utc::now().naive_utc())))
+            .into_boxed(),
+        // Return the keys active at the requested instant
+        Some(at) => jwks
+            .filter(created_at.le(at))
+            .filter(deleted_at.is_null().or(deleted_at.gt(at)))
+            .filter(key_expires_at.gt(at))
+            .filter(not_before.is_null().or(not_before.le(at)))
+            .into_boxed(),
+    };
+    if let Some(key_purpose) = key_purpose {
+        keys = keys.filter(purpose.eq(key_purpose));
+    }
+    // Symmetric keys are secret and never published
+    keys.filter(kty.ne("oct"))
+}
+
+/// Loads the most recently created usable key matching a key creation request.
+///
+/// A key matches if it has the same algorithm (and curve), RSA key size, purpose, delivery mode
+/// and issuer and audience constraints, is neither deleted nor expired and its private key is
+/// still valid. Burn-after-read keys whose private key was already retrieved never match.
+///
+/// # Errors
+///
+/// Returns [`KeyError::Internal`] if the keys cannot be loaded.
+fn find_reusable_jwk(
+    store: &Arc<dyn KeyStore>,
+    algorithm: &str,
+    rsa_key_size: u32,
+    constraints: &(Option<Vec<String>>, Option<Vec<String>>),
+    key_purpose: Option<&str>,
+    delivery: (bool, bool),
+) -> Result<Option<JwkData>, KeyError> {
+    let now = Utc::now().naive_utc();
+
+    // EdDSA keys are generated by curve name but stored as alg EdDSA
+    let (stored_alg, stored_crv) = match algorithm {
+        "Ed25519" | "Ed448" => ("EdDSA", Some(algorithm)),
+        _ => (algorithm, curve_for_alg(algorithm)),
+    };
+
+    let mut candidates = store
+        .signing_keys(Some(stored_alg))
+        .map_err(|_| KeyError::Internal("Failed to load keys".to_string()))?;
+    candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.created_at));
+
+    Ok(candidates.into_iter().find(|candidate| {
+        let modulus_bits = key_details(candidate, now).ok().and_then(|details| details.modulus_bits);
+        (candidate.burn_after_read, candidate.sensitive) == delivery
+            && candidate.private_key_retrieved_at.is_none()
+            && !candidate.external
+            && candidate.crv.as_deref() == stored_crv
+            && (candidate.kty != "RSA" || modulus_bits == Some(rsa_key_size))
+            && candidate.allowed_issuers == constraints.0
+            && candidate.allowed_audiences == constraints.1
+            && candidate.purpose.as_deref() == key_purpose
+    }))
+}
+
+/// Generates, stores and audits a new key after applying the deployment policy.
+///
+/// # Arguments
+///
+/// * `store` - Store the key is kept in.
+/// * `algorithm` - Generation algorithm from [`supported_algorithms`].
+/// * `rsa_key_size` - RSA key size in bits; only used for RSA algorithms.
+/// * `constraints` - Allowed issuers and audiences of the key.
+/// * `key_purpose` - Purpose of the key, whose lifetimes apply.
+/// * `delivery` - Whether the private key can only be retrieved once, and whether retrieving it
+///   requires an approval.
+/// * `predecessor` - Key rotated by the new key, if any.
+/// * `activation` - Activation time of the key; its lifetimes start at the later of now and
+///   the activation time.
+/// * `actor` - Caller recorded in the audit log.
+///
+/// # Errors
+///
+/// Returns [`KeyError::Invalid`] for unsupported algorithms, [`KeyError::Policy`] for policy
+/// violations, including symmetric keys without a key-encryption key, and [`KeyError::Internal`]
+/// if key generation, self-verification or storing the key fails.
+#[allow(clippy::too_many_arguments)]
+fn create_jwk(
+    store: &Arc<dyn KeyStore>,
+    algorithm: &str,
+    rsa_key_size: u32,
+    constraints: (Option<Vec<String>>, Option<Vec<String>>),
+    key_purpose: Option<String>,
+    delivery: (bool, bool),
+    predecessor: Option<Uuid>,
+    activation: Option<NaiveDateTime>,
+    actor: Option<String>,
+) -> Result<JwkData, KeyError> {
+    dotenv().ok();
+
+    if !supported_algorithms().contains(&algorithm) {
+        return Err(KeyError::Invalid("Unsupported algorithm".to_string()));
+    }
+
+    // Reject algorithms forbidden by the deployment policy
+    if !is_algorithm_allowed(algorithm, allowed_algorithms().as_deref()) {
+        return Err(KeyError::Policy("Algorithm is not permitted by policy".to_string()));
+    }
+
+    // Reject weak keys according to the minimum key strength policy
+    check_key_strength(
+        algorithm,
+        rsa_key_size,
+        min_rsa_key_size(),
+        approved_curves().as_deref(),
+    )
+    .map_err(KeyError::Policy)?;
+
+    // Generate keys based on the algorithm
+    let started = std::time::Instant::now();
+    let generated = generate_jwk_data(algorithm, rsa_key_size);
+    record_keygen(algorithm, started.elapsed());
+    let jwk_key = generated.map_err(|_| KeyError::Internal("Failed to generate key".to_string()))?;
+
+    // Secrets of symmetric keys are never stored unencrypted
+    if jwk_key.is_symmetric() && store.stores_at_rest() {
+        check_secret_storage().map_err(KeyError::Policy)?;
+    }
+
+    // Never publish a key whose public part does not match its private part
+    if verify_key_pair(&jwk_key).is_err() {
+        return Err(KeyError::Internal("Generated key failed self-verification".to_string()));
+    }
+
+    // Get expiration times of the algorithm and purpose from environment variables
+    let (private_key_expiration_seconds, key_expiration_seconds) =
+        key_lifetimes(&jwk_key.alg, key_purpose.as_deref());
+
+    // Current time; the lifetimes of scheduled keys start at their activation
+    let now = Utc::now().naive_utc();
+    let activates_at = activation.map_or(now, |activation| activation.max(now));
+
+    // Create a new JWK
+    let (issuers, audiences) = constraints;
+    let mut jwk = JwkData {
+        id: Uuid::new_v4(),
+        created_at: now,
+        deleted_at: None,
+        private_key_expires_at: Some(
+            activates_at + chrono::Duration::seconds(private_key_expiration_seconds),
+        ),
+        key_expires_at: Some(
+            activates_at + chrono::Duration::seconds(
+                private_key_expiration_seconds + key_expiration_seconds,
+            ),
+        ),
+        not_before: activation,
+        allowed_issuers: issuers,
+        allowed_audiences: audiences,
+        predecessor_id: predecessor,
+        updated_at: now,
+        purpose: key_purpose,
+        burn_after_read: delivery.0,
+        sensitive: delivery.1,
+        ..jwk_key
+    };
+
+    // Save the JWK; a generated kid that is already taken is regenerated
+    let mut attempt = 1;
+    loop {
+        match store.insert(&jwk, actor.clone()) {
+            Ok(()) => break,
+            Err(StoreError::KidConflict) if attempt < KID_ATTEMPTS => {
+                log_error!("Key ID {} is already in use, generating another one", jwk.kid);
+                jwk.kid = Uuid::new_v4().to_string();
+                attempt += 1;
+            }
+            Err(_) => return Err(KeyError::Internal("Failed to store key".to_string())),
+        }
+    }
+
+    Ok(jwk)
+}
+
+/// Returns whether a statement failed because the key ID is already used by another key.
+pub fn is_kid_conflict(error: &diesel::result::Error) -> bool {
+    matches!(
+        error,
+        diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, info)
+            if info.constraint_name() == Some(KID_INDEX)
+    )
+}
+
+/// Returns the ID of the key using a key ID, deleted and expired keys included.
+///
+/// # Errors
+///
+/// Returns an error if the keys cannot be queried.
+pub fn kid_owner(connection: &mut PgConnection, key_id: &str) -> QueryResult<Option<Uuid>> {
+    jwks.filter(kid.eq(key_id)).select(id).first::<Uuid>(connection).optional()
+}
+
+/// Treats an empty constraint list as no constraint.
+fn non_empty(values: Option<Vec<String>>) -> Option<Vec<String>> {
+    values.filter(|values| !values.is_empty())
+}
+
+/// Generates the initial keys of a fresh deployment.
+///
+/// Keys are only generated if the key table is empty (deleted keys count as keys), so restarts
+/// and replicas starting at the same time do not add keys. The keys follow the deployment policy
+/// like keys created with `POST /jwks` and are recorded in the audit log with the actor
+/// `bootstrap`.
+///
+/// # Arguments
+///
+/// * `connection` - Connection holding the bootstrap lock.
+/// * `algorithms` - Generation algorithms from [`supported_algorithms`], one key each.
+///
+/// # Returns
+///
+/// The generated keys; none if the table already contains keys.
+///
+/// # Errors
+///
+/// Returns a message if the table cannot be checked or a key cannot be created.
+pub fn bootstrap_keys(connection: &mut PgConnection, algorithms: &[String]) -> Result<Vec<JwkData>, String> {
+    if algorithms.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    run_locked(connection, BOOTSTRAP_LOCK, |connection| {
+        let stored = jwks.count().get_result::<i64>(connection).map_err(|error| error.to_string())?;
+        if stored > 0 {
+            return Ok(Vec::new());
+        }
+
+        algorithms
+            .iter()
+            .map(|algorithm| {
+                create_jwk(
+                    &key_store(),
+                    algorithm,
+                    default_rsa_key_size(),
+                    (None, None),
+                    None,
+                    (false, false),
+                    None,
+                    None,
+                    Some("bootstrap".to_string()),
+                )
+                .map_err(|error| format!("Failed to bootstrap a {} key ({})", algorithm, error))
+            })
+            .collect()
+    })
+    .map_err(|error| error.to_string())?
+}
+
+/// Loads an active key whose private part can still be used.
+///
+/// # Arguments
+///
+/// * `store` - Store the key is kept in.
+/// * `key_id` - The unique identifier of the key.
+///
+/// # Errors
+///
+/// Returns [`KeyError::NotFound`] if the key does not exist, is deleted or expired,
+/// [`KeyError::Conflict`] if it is an external key, [`KeyError::Frozen`] if it is frozen,
+/// [`KeyError::Gone`] if its private key has expired and [`KeyError::Internal`] if the key cannot
+/// be loaded.
+pub fn find_private_jwk(store: &Arc<dyn KeyStore>, key_id: Uuid) -> Result<JwkData, KeyError> {
+    let now = Utc::now().naive_utc();
+
+    // Find the key by ID, excluding deleted and expired keys
+    let jwk_result = store
+        .get_private(key_id)
+        .map_err(|error| {
+            log_error!("Failed to load key {}: {}", key_id, error);
+            KeyError::Internal("Failed to load key".to_string())
+        })?
+        .filter(|jwk| jwk.deleted_at.is_none())
+        .filter(|jwk| jwk.key_expires_at.is_some_and(|expires_at| expires_at > now))
+        .ok_or_else(|| KeyError::NotFound("Key not found".to_string()))?;
+
+    // External keys are only published; their private key is held by the partner
+    if jwk_result.external {
+        return Err(KeyError::Conflict("External key has no private key".to_string()));
+    }
+
+    // Frozen keys must not sign until they are unfrozen
+    if jwk_result.frozen_at.is_some() {
+        return Err(KeyError::Frozen("Key is frozen".to_string()));
+    }
+
+    #[cfg(feature = "chaos")]
+    if crate::chaos::is_key_expiry_forced() {
+        return Err(KeyError::Gone("Private key expired".to_string()));
+    }
+
+    // Check if the private key has expired
+    if let Some(expires_at) = jwk_result.private_key_expires_at {
+        if Utc::now().naive_utc() > expires_at {
+            return Err(KeyError::Gone("Private key expired".to_string()));
+        }
+    }
+
+    Ok(jwk_result)
+}
+
+/// Loads a key that can sign now: an active key whose private part can still be used and whose
+/// activation time, if any, has been reached.
+///
+/// # Errors
+///
+/// Returns the errors of [`find_private_jwk`], and [`KeyError::Conflict`] if the key is not
+/// active yet.
+pub fn find_signing_jwk(store: &Arc<dyn KeyStore>, key_id: Uuid) -> Result<JwkData, KeyError> {
+    let jwk_result = find_private_jwk(store, key_id)?;
+
+    // Scheduled keys sign only from their activation time on
+    if jwk_result.not_before.is_some_and(|activation| Utc::now().naive_utc() < activation) {
+        return Err(KeyError::Conflict("Key is not active yet".to_string()));
+    }
+
+    Ok(jwk_result)
+}
+
+/// Loads the key that currently signs tokens by default: a primary key if one is active,
+/// otherwise the most recently created key that can sign.
+///
+/// # Arguments
+///
+/// * `store` - Store the keys are kept in.
+/// * `algorithm` - Algorithm the key must use, or `None` for any signing algorithm.
+///
+/// # Errors
+///
+/// Returns [`KeyError::Unavailable`] if no active signing key exists and [`KeyError::Internal`]
+/// if the keys cannot be loaded.
+pub fn find_active_signing_jwk(store: &Arc<dyn KeyStore>, algorithm: Option<&str>) -> Result<JwkData, KeyError> {
+    #[cfg(feature = "chaos")]
+    if crate::chaos::is_key_expiry_forced() {
+        return Err(KeyError::Unavailable("No active signing key".to_string()));
+    }
+
+    let results = store.signing_keys(algorithm).map_err(|error| {
+        log_error!("Failed to load signing keys: {}", error);
+        KeyError::Internal("Failed to load keys".to_string())
+    })?;
+
+    results
+        .into_iter()
+        .next()
+        .ok_or_else(|| KeyError::Unavailable("No active signing key".to_string()))
+}
+
+/// Designates a key as the primary key of its algorithm, replacing the previous primary key.
+///
+/// Designations are local to the deployment and not replicated, so the change time of the keys
+/// is kept.
+///
+/// # Errors
+///
+/// Returns an error if the designation cannot be stored.
+pub fn designate_primary(
+    connection: &mut PgConnection,
+    key: &JwkData,
+    actor: Option<String>,
+) -> QueryResult<()> {
+    let replaced = connection.transaction(|connection| {
+        let replaced = diesel::update(
+            jwks.filter(alg.eq(&key.alg))
+                .filter(is_primary.eq(true))
+                .filter(id.ne(key.id)),
+        )
+        .set(is_primary.eq(false))
+        .returning(id)
+        .get_results::<Uuid>(connection)?;
+        diesel::update(jwks.filter(id.eq(key.id)))
+            .set(is_primary.eq(true))
+            .execute(connection)?;
+        Ok::<_, diesel::result::Error>(replaced)
+    })?;
+
+    for replaced_id in replaced {
+        let replaced_actor = actor.clone();
+        if let Err(error) = record_event(connection, replaced_id, ACTION_UNSET_PRIMARY, replaced_actor) {
+            log_error!("Failed to record audit event for key {}: {}", replaced_id, error);
+        }
+        mirror_key(connection, replaced_id);
+    }
+    if let Err(error) = record_event(connection, key.id, ACTION_SET_PRIMARY, actor) {
+        log_error!("Failed to record audit event for key {}: {}", key.id, error);
+    }
+    mirror_key(connection, key.id);
+
+    Ok(())
+}
+
+#[test]
+fn test_key_error_message() {
+    let error = KeyError::NotFound("Key not found".to_string());
+    assert_eq!(error.message(), "Key not found");
+    assert_eq!(error.to_string(), "Key not found");
+}
+
+#[cfg(test)]
+#[actix_rt::test]
+async fn test_run_blocking() {
+    assert_eq!(run_blocking(|| Ok(7)).await.unwrap(), 7);
+
+    let error = run_blocking(|| Err::<(), _>(KeyError::NotFound("Key not found".to_string()))).await;
+    assert!(matches!(error, Err(KeyError::NotFound(_))));
+
+    // A panic is reported as an internal error instead of taking down the worker
+    let error = run_blocking::<(), _>(|| panic!("blocking work failed")).await;
+    assert!(matches!(error, Err(KeyError::Internal(_))));
+}
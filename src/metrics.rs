@@ -0,0 +1,388 @@
+//! This module collects the metrics of the JWK microservice and serves them at `/metrics` in the
+//! Prometheus text exposition format.
+//!
+//! Request counts and latencies are recorded per method, route pattern and status by the
+//! [`track_requests`] middleware, key generation durations per algorithm by the key creation,
+//! and the latency of database queries per statement and the wait for pooled connections by
+//! [`crate::db`]. The usage of the connection pools is read on every scrape.
+//! Key counts per lifecycle status are queried from the database on every scrape (keys kept in
+//! memory are not counted). If a StatsD server is configured, every recorded metric is also sent
+//! to it.
+
+#[cfg(feature = "server")]
+use crate::db::establish_connection;
+#[cfg(feature = "server")]
+use crate::log_error;
+use crate::db::pool_usage;
+use crate::statsd::{emit, MetricKind};
+#[cfg(feature = "server")]
+use crate::store::memory_storage;
+#[cfg(feature = "server")]
+use actix_web::body::MessageBody;
+#[cfg(feature = "server")]
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+#[cfg(feature = "server")]
+use actix_web::middleware::Next;
+#[cfg(feature = "server")]
+use actix_web::{HttpResponse, Responder};
+use chrono::NaiveDateTime;
+#[cfg(feature = "server")]
+use chrono::Utc;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Timestamp, Varchar};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+#[cfg(feature = "server")]
+use std::time::Instant;
+
+/// Content type of the Prometheus text exposition format.
+pub const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Upper bounds of the duration histogram buckets in seconds.
+const BUCKETS: [f64; 12] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Distribution of durations.
+#[derive(Debug, Default, Clone)]
+struct Histogram {
+    /// Number of observations per bucket (not cumulative).
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+/// Metrics recorded since startup.
+#[derive(Debug, Default)]
+struct Registry {
+    /// Request counts by method, route and status.
+    requests: BTreeMap<(String, String, u16), u64>,
+    /// Request latencies by method and route.
+    request_durations: BTreeMap<(String, String), Histogram>,
+    /// Key generation durations by algorithm.
+    keygen_durations: BTreeMap<String, Histogram>,
+    /// Query latencies by statement.
+    query_durations: BTreeMap<&'static str, Histogram>,
+    /// Failed query counts by statement.
+    query_errors: BTreeMap<&'static str, u64>,
+    /// Waits for a pooled database connection.
+    pool_waits: Histogram,
+}
+
+static REGISTRY: Mutex<Option<Registry>> = Mutex::new(None);
+
+/// Runs `update` with the registry.
+fn with_registry<T>(update: impl FnOnce(&mut Registry) -> T) -> T {
+    let mut registry = REGISTRY.lock().unwrap();
+    update(registry.get_or_insert_with(Registry::default))
+}
+
+/// Records a handled request.
+///
+/// # Arguments
+///
+/// * `method` - HTTP method.
+/// * `route` - Route pattern (e.g., "/jwks/{id}"), so key IDs do not create new series.
+/// * `status` - Response status code.
+/// * `duration` - Time taken to produce the response.
+pub fn record_request(method: &str, route: &str, status: u16, duration: Duration) {
+    with_registry(|registry| {
+        *registry
+            .requests
+            .entry((method.to_string(), route.to_string(), status))
+            .or_default() += 1;
+        registry
+            .request_durations
+            .entry((method.to_string(), route.to_string()))
+            .or_default()
+            .observe(duration.as_secs_f64());
+    });
+
+    let status = status.to_string();
+    let labels = [("method", method), ("route", route), ("status", status.as_str())];
+    emit("http.requests", &labels, 1.0, MetricKind::Counter);
+    emit("http.request_duration", &labels[..2], duration.as_secs_f64() * 1000.0, MetricKind::Timer);
+}
+
+/// Records the generation of a key.
+pub fn record_keygen(alg: &str, duration: Duration) {
+    with_registry(|registry| {
+        registry
+            .keygen_durations
+            .entry(alg.to_string())
+            .or_default()
+            .observe(duration.as_secs_f64());
+    });
+
+    emit("keygen.duration", &[("alg", alg)], duration.as_secs_f64() * 1000.0, MetricKind::Timer);
+}
+
+/// Returns the statement of a query (`select`, `insert`, `update` or `delete`, `other` for any
+/// other statement), so queries do not create new series.
+fn statement(sql: &str) -> &'static str {
+    let keyword = sql.split_whitespace().next().unwrap_or_default().to_ascii_lowercase();
+    match keyword.as_str() {
+        "select" => "select",
+        "insert" => "insert",
+        "update" => "update",
+        "delete" => "delete",
+        _ => "other",
+    }
+}
+
+/// Records a database query.
+///
+/// # Arguments
+///
+/// * `sql` - SQL of the query.
+/// * `succeeded` - Whether the query succeeded.
+/// * `duration` - Time taken to run the query.
+pub fn record_query(sql: &str, succeeded: bool, duration: Duration) {
+    let statement = statement(sql);
+    with_registry(|registry| {
+        registry
+            .query_durations
+            .entry(statement)
+            .or_default()
+            .observe(duration.as_secs_f64());
+        if !succeeded {
+            *registry.query_errors.entry(statement).or_default() += 1;
+        }
+    });
+
+    let labels = [("statement", statement)];
+    emit("db.query_duration", &labels, duration.as_secs_f64() * 1000.0, MetricKind::Timer);
+    if !succeeded {
+        emit("db.query_errors", &labels, 1.0, MetricKind::Counter);
+    }
+}
+
+/// Records the wait for a pooled database connection, including waits that timed out.
+pub fn record_pool_wait(duration: Duration) {
+    with_registry(|registry| registry.pool_waits.observe(duration.as_secs_f64()));
+
+    emit("db.pool_wait", &[], duration.as_secs_f64() * 1000.0, MetricKind::Timer);
+}
+
+/// Middleware recording the count and latency of every request.
+#[cfg(feature = "server")]
+pub async fn track_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let started = Instant::now();
+    let method = req.method().to_string();
+    let route = req.match_pattern().unwrap_or_else(|| "unmatched".to_string());
+
+    let response = next.call(req).await?;
+    record_request(&method, &route, response.status().as_u16(), started.elapsed());
+    Ok(response)
+}
+
+/// Number of keys in a lifecycle status.
+#[derive(QueryableByName)]
+struct StatusCount {
+    #[diesel(sql_type = Varchar)]
+    status: String,
+    #[diesel(sql_type = BigInt)]
+    keys: i64,
+}
+
+/// Counts the stored keys per lifecycle status, as determined by `JwkData::lifecycle_status`.
+///
+/// # Errors
+///
+/// Returns an error if the keys cannot be counted.
+pub fn key_counts(connection: &mut PgConnection, now: NaiveDateTime) -> QueryResult<Vec<(String, i64)>> {
+    let counts = sql_query(
+        "SELECT CASE \
+           WHEN deleted_at IS NOT NULL THEN 'deleted' \
+           WHEN key_expires_at < $1 THEN 'expired' \
+           WHEN frozen_at IS NOT NULL THEN 'frozen' \
+           WHEN private_key_expires_at < $1 THEN 'private_key_expired' \
+           ELSE 'active' END AS status, COUNT(*) AS keys \
+         FROM jwks GROUP BY 1 ORDER BY 1",
+    )
+    .bind::<Timestamp, _>(now)
+    .load::<StatusCount>(connection)?;
+
+    Ok(counts.into_iter().map(|count| (count.status, count.keys)).collect())
+}
+
+/// Escapes a label value of the exposition format.
+fn label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Writes a histogram with cumulative buckets; `labels` may be empty.
+fn write_histogram(out: &mut String, name: &str, labels: &str, histogram: &Histogram) {
+    let (bucket_labels, labels) = match labels {
+        "" => (String::new(), String::new()),
+        labels => (format!("{},", labels), format!("{{{}}}", labels)),
+    };
+    let mut cumulative = 0;
+    for (bound, count) in BUCKETS.iter().zip(histogram.buckets) {
+        cumulative += count;
+        let _ = writeln!(out, "{}_bucket{{{}le=\"{}\"}} {}", name, bucket_labels, bound, cumulative);
+    }
+    let _ = writeln!(out, "{}_bucket{{{}le=\"+Inf\"}} {}", name, bucket_labels, histogram.count);
+    let _ = writeln!(out, "{}_sum{} {}", name, labels, histogram.sum);
+    let _ = writeln!(out, "{}_count{} {}", name, labels, histogram.count);
+}
+
+/// Renders the recorded metrics, the usage of the connection pools and the key counts in the
+/// Prometheus text exposition format.
+///
+/// # Arguments
+///
+/// * `key_counts` - Number of keys per lifecycle status; the gauge is left out if `None`.
+pub fn render_metrics(key_counts: Option<&[(String, i64)]>) -> String {
+    let mut out = String::new();
+
+    with_registry(|registry| {
+        out.push_str("# HELP jwks_http_requests_total Number of handled HTTP requests.\n");
+        out.push_str("# TYPE jwks_http_requests_total counter\n");
+        for ((method, route, status), count) in &registry.requests {
+            let _ = writeln!(
+                out,
+                "jwks_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                label(method),
+                label(route),
+                status,
+                count
+            );
+        }
+
+        out.push_str("# HELP jwks_http_request_duration_seconds Latency of HTTP requests.\n");
+        out.push_str("# TYPE jwks_http_request_duration_seconds histogram\n");
+        for ((method, route), histogram) in &registry.request_durations {
+            let labels = format!("method=\"{}\",route=\"{}\"", label(method), label(route));
+            write_histogram(&mut out, "jwks_http_request_duration_seconds", &labels, histogram);
+        }
+
+        out.push_str("# HELP jwks_keygen_duration_seconds Duration of key generation.\n");
+        out.push_str("# TYPE jwks_keygen_duration_seconds histogram\n");
+        for (alg, histogram) in &registry.keygen_durations {
+            let labels = format!("alg=\"{}\"", label(alg));
+            write_histogram(&mut out, "jwks_keygen_duration_seconds", &labels, histogram);
+        }
+
+        out.push_str("# HELP jwks_db_query_duration_seconds Latency of database queries.\n");
+        out.push_str("# TYPE jwks_db_query_duration_seconds histogram\n");
+        for (statement, histogram) in &registry.query_durations {
+            let labels = format!("statement=\"{}\"", statement);
+            write_histogram(&mut out, "jwks_db_query_duration_seconds", &labels, histogram);
+        }
+
+        out.push_str("# HELP jwks_db_query_errors_total Number of failed database queries.\n");
+        out.push_str("# TYPE jwks_db_query_errors_total counter\n");
+        for (statement, count) in &registry.query_errors {
+            let _ = writeln!(out, "jwks_db_query_errors_total{{statement=\"{}\"}} {}", statement, count);
+        }
+
+        out.push_str("# HELP jwks_db_pool_wait_seconds Wait for a pooled database connection.\n");
+        out.push_str("# TYPE jwks_db_pool_wait_seconds histogram\n");
+        write_histogram(&mut out, "jwks_db_pool_wait_seconds", "", &registry.pool_waits);
+    });
+
+    out.push_str("# HELP jwks_db_pool_connections Connections of the database connection pools by state.\n");
+    out.push_str("# TYPE jwks_db_pool_connections gauge\n");
+    let pools = pool_usage();
+    for usage in &pools {
+        let _ = writeln!(out, "jwks_db_pool_connections{{pool=\"{}\",state=\"in_use\"}} {}", usage.pool, usage.in_use);
+        let _ = writeln!(out, "jwks_db_pool_connections{{pool=\"{}\",state=\"idle\"}} {}", usage.pool, usage.idle);
+    }
+    out.push_str("# HELP jwks_db_pool_max_connections Maximum number of connections of the database connection pools.\n");
+    out.push_str("# TYPE jwks_db_pool_max_connections gauge\n");
+    for usage in &pools {
+        let _ = writeln!(out, "jwks_db_pool_max_connections{{pool=\"{}\"}} {}", usage.pool, usage.max_size);
+    }
+
+    if let Some(key_counts) = key_counts {
+        out.push_str("# HELP jwks_keys Number of stored keys by lifecycle status.\n");
+        out.push_str("# TYPE jwks_keys gauge\n");
+        for (status, count) in key_counts {
+            let _ = writeln!(out, "jwks_keys{{status=\"{}\"}} {}", label(status), count);
+        }
+    }
+
+    out
+}
+
+/// Handles the request for the metrics in the Prometheus text exposition format.
+///
+/// # Returns
+///
+/// The metrics. If the keys cannot be counted, the key count gauge is left out.
+#[cfg(feature = "server")]
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text exposition format", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn metrics_handler() -> impl Responder {
+    // Keys kept in memory are not counted
+    let key_counts = if memory_storage() {
+        None
+    } else {
+        match key_counts(&mut establish_connection(), Utc::now().naive_utc()) {
+            Ok(key_counts) => Some(key_counts),
+            Err(error) => {
+                log_error!("Failed to count keys for the metrics: {}", error);
+                None
+            }
+        }
+    };
+
+    HttpResponse::Ok()
+        .content_type(METRICS_CONTENT_TYPE)
+        .body(render_metrics(key_counts.as_deref()))
+}
+
+#[test]
+fn test_render_metrics() {
+    record_request("GET", "/test/{id}", 200, Duration::from_millis(20));
+    record_request("GET", "/test/{id}", 200, Duration::from_secs(60));
+    record_keygen("TEST256", Duration::from_millis(3));
+
+    let out = render_metrics(Some(&[("active".to_string(), 3)]));
+    assert!(out.contains("jwks_http_requests_total{method=\"GET\",route=\"/test/{id}\",status=\"200\"} 2\n"));
+    assert!(out.contains("jwks_http_request_duration_seconds_bucket{method=\"GET\",route=\"/test/{id}\",le=\"0.025\"} 1\n"));
+    assert!(out.contains("jwks_http_request_duration_seconds_bucket{method=\"GET\",route=\"/test/{id}\",le=\"30\"} 1\n"));
+    assert!(out.contains("jwks_http_request_duration_seconds_bucket{method=\"GET\",route=\"/test/{id}\",le=\"+Inf\"} 2\n"));
+    assert!(out.contains("jwks_keygen_duration_seconds_count{alg=\"TEST256\"} 1\n"));
+    assert!(out.contains("jwks_keys{status=\"active\"} 3\n"));
+    assert!(!render_metrics(None).contains("jwks_keys{"));
+
+    assert_eq!(label("a\"b\\c"), "a\\\"b\\\\c");
+}
+
+#[test]
+fn test_render_database_metrics() {
+    record_query("SELECT \"jwks\".\"id\" FROM \"jwks\" -- binds: []", true, Duration::from_millis(2));
+    record_query("  update jwks SET kid = $1", false, Duration::from_millis(40));
+    record_query("BEGIN REPEATABLE READ", true, Duration::from_millis(1));
+    record_pool_wait(Duration::from_millis(1));
+
+    let out = render_metrics(None);
+    assert!(out.contains("jwks_db_query_duration_seconds_bucket{statement=\"select\",le=\"0.005\"} "));
+    assert!(out.contains("jwks_db_query_duration_seconds_count{statement=\"update\"} "));
+    assert!(out.contains("jwks_db_query_duration_seconds_count{statement=\"other\"} "));
+    assert!(out.contains("jwks_db_query_errors_total{statement=\"update\"} "));
+    assert!(out.contains("jwks_db_pool_wait_seconds_bucket{le=\"+Inf\"} "));
+    assert!(out.contains("jwks_db_pool_wait_seconds_count "));
+}
@@ -0,0 +1,324 @@
+//! This module runs the database migrations safely while other replicas are serving traffic.
+//!
+//! Migrations follow the expand/contract pattern. Expand migrations only add to the schema and
+//! are compatible with the previous release, so they run on startup while old replicas keep
+//! serving. Contract migrations (named `*_contract`) remove what the previous release still
+//! uses; they are only run by the `migrate --contract` command once every replica is upgraded.
+//!
+//! The runner holds an advisory lock, so replicas starting simultaneously migrate one after the
+//! other, and refuses to migrate a database whose migration history does not match the release.
+
+use crate::db::wait_for_database;
+use crate::dual_write::establish_target_connection;
+use crate::jobs::run_locked;
+use crate::MIGRATIONS;
+use diesel::migration::MigrationSource;
+use diesel::pg::{Pg, PgConnection};
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{Integer, Text};
+use diesel_migrations::MigrationHarness;
+use std::error::Error;
+
+/// Usage of the `migrate` command.
+pub const MIGRATE_USAGE: &str = "\
+Usage: jwks-service-app migrate [OPTIONS]
+
+Options:
+  --contract           Also run contract migrations (after every replica is upgraded)
+  -h, --help           Print this help";
+
+/// Name suffix of contract migrations.
+pub const CONTRACT_SUFFIX: &str = "_contract";
+
+/// Advisory lock serializing migration runs.
+const MIGRATION_LOCK: &str = "schema-migrations";
+
+/// Oldest supported PostgreSQL version (`server_version_num`).
+const MIN_SERVER_VERSION: i32 = 110000;
+
+/// Versions of migrations that were released with invalid timestamps (hours 24 to 30) and their
+/// current versions.
+const RENAMED_VERSIONS: [(&str, &str); 7] = [
+    ("20261015240000", "20261016000000"),
+    ("20261015250000", "20261016010000"),
+    ("20261015260000", "20261016020000"),
+    ("20261015270000", "20261016030000"),
+    ("20261015280000", "20261016040000"),
+    ("20261015290000", "20261016050000"),
+    ("20261015300000", "20261016060000"),
+];
+
+/// Migration as seen by the planner.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationInfo {
+    /// Version of the migration (its timestamp without separators).
+    pub version: String,
+    /// Full name of the migration.
+    pub name: String,
+}
+
+/// Migrations run and deferred by a migration run.
+#[derive(Debug, Default, PartialEq)]
+pub struct MigrationPlan {
+    /// Migrations to run, in order.
+    pub run: Vec<String>,
+    /// Pending contract migrations (and the migrations after them) left for `migrate --contract`.
+    pub deferred: Vec<String>,
+}
+
+/// Returns whether a migration is a contract migration.
+pub fn is_contract_migration(name: &str) -> bool {
+    name.ends_with(CONTRACT_SUFFIX)
+}
+
+/// Plans a migration run from the migrations of the release and the applied versions.
+///
+/// # Arguments
+///
+/// * `known` - Migrations of the release, in order.
+/// * `applied` - Versions applied to the database.
+/// * `contract` - Whether contract migrations may run.
+///
+/// # Errors
+///
+/// Returns a message if the database has migrations unknown to the release while migrations of
+/// the release are still pending, i.e. the histories diverged.
+pub fn plan_migrations(
+    known: &[MigrationInfo],
+    applied: &[String],
+    contract: bool,
+) -> Result<MigrationPlan, String> {
+    let pending = known
+        .iter()
+        .filter(|migration| !applied.contains(&migration.version))
+        .collect::<Vec<_>>();
+    let unknown = applied
+        .iter()
+        .filter(|version| !known.iter().any(|migration| &migration.version == *version))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if !unknown.is_empty() && !pending.is_empty() {
+        return Err(format!(
+            "The database has migrations unknown to this release ({}) while {} are pending",
+            unknown.join(", "),
+            pending
+                .iter()
+                .map(|migration| migration.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    // Migrations run in order, so everything after a deferred contract migration waits as well
+    let split = pending
+        .iter()
+        .position(|migration| !contract && is_contract_migration(&migration.name))
+        .unwrap_or(pending.len());
+    let names = |migrations: &[&MigrationInfo]| {
+        migrations
+            .iter()
+            .map(|migration| migration.name.clone())
+            .collect::<Vec<_>>()
+    };
+
+    Ok(MigrationPlan {
+        run: names(&pending[..split]),
+        deferred: names(&pending[split..]),
+    })
+}
+
+/// Records migrations applied under their former versions with their current versions, so they
+/// are not run again.
+///
+/// # Errors
+///
+/// Returns an error if the migration history cannot be updated.
+pub fn rename_applied_versions(connection: &mut PgConnection) -> QueryResult<()> {
+    // The history table is created by the first migration run otherwise (same definition)
+    sql_query(
+        "CREATE TABLE IF NOT EXISTS __diesel_schema_migrations (\
+         version VARCHAR(50) PRIMARY KEY NOT NULL, \
+         run_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP)",
+    )
+    .execute(connection)?;
+
+    for (old, new) in RENAMED_VERSIONS {
+        sql_query("UPDATE __diesel_schema_migrations SET version = $1 WHERE version = $2")
+            .bind::<Text, _>(new)
+            .bind::<Text, _>(old)
+            .execute(connection)?;
+    }
+    Ok(())
+}
+
+/// Result row of the server version query.
+#[derive(QueryableByName)]
+struct ServerVersion {
+    #[diesel(sql_type = Integer)]
+    version: i32,
+}
+
+/// Checks that the database server supports the migrations.
+///
+/// # Errors
+///
+/// Returns an error if the server version cannot be read or is too old.
+fn check_server_version(connection: &mut PgConnection) -> Result<(), Box<dyn Error>> {
+    let server = sql_query("SELECT current_setting('server_version_num')::integer AS version")
+        .get_result::<ServerVersion>(connection)?;
+
+    if server.version < MIN_SERVER_VERSION {
+        return Err(Box::from(format!(
+            "PostgreSQL {} or later is required, the server runs {}",
+            MIN_SERVER_VERSION / 10000,
+            server.version
+        )));
+    }
+
+    Ok(())
+}
+
+/// Runs the pending migrations after the pre-flight checks, holding the migration lock.
+///
+/// # Arguments
+///
+/// * `connection` - Database connection.
+/// * `contract` - Whether contract migrations may run.
+///
+/// # Returns
+///
+/// The migrations that were run and the deferred contract migrations.
+///
+/// # Errors
+///
+/// Returns an error if a pre-flight check or a migration fails.
+pub fn run_migrations(
+    connection: &mut PgConnection,
+    contract: bool,
+) -> Result<MigrationPlan, Box<dyn Error>> {
+    check_server_version(connection)?;
+
+    run_locked(connection, MIGRATION_LOCK, |connection| {
+        rename_applied_versions(connection).map_err(|e| e.to_string())?;
+        let migrations = MigrationSource::<Pg>::migrations(&MIGRATIONS).map_err(|e| e.to_string())?;
+        let known = migrations
+            .iter()
+            .map(|migration| MigrationInfo {
+                version: migration.name().version().to_string(),
+                name: migration.name().to_string(),
+            })
+            .collect::<Vec<_>>();
+        let applied = connection
+            .applied_migrations()
+            .map_err(|e| e.to_string())?
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+
+        let plan = plan_migrations(&known, &applied, contract)?;
+        for migration in &migrations {
+            if plan.run.contains(&migration.name().to_string()) {
+                connection
+                    .run_migration(migration.as_ref())
+                    .map_err(|e| format!("Migration {} failed: {}", migration.name(), e))?;
+            }
+        }
+
+        Ok::<_, String>(plan)
+    })?
+    .map_err(Box::from)
+}
+
+/// Runs the `migrate` command with the arguments following it.
+///
+/// # Errors
+///
+/// Returns an error if the arguments are invalid or the migration run fails.
+pub fn run_migrate(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut contract = false;
+    for arg in args {
+        match arg.as_str() {
+            "--contract" => contract = true,
+            "-h" | "--help" => return Err(Box::from(MIGRATE_USAGE)),
+            _ => return Err(Box::from(format!("Unknown argument {}\n\n{}", arg, MIGRATE_USAGE))),
+        }
+    }
+
+    let plan = run_migrations(&mut wait_for_database()?, contract)?;
+    print_migration_plan(&plan);
+
+    if let Some(target) = establish_target_connection() {
+        println!("Dual-write target:");
+        print_migration_plan(&run_migrations(&mut target?, contract)?);
+    }
+
+    Ok(())
+}
+
+/// Prints the migrations run and deferred by a migration run.
+pub fn print_migration_plan(plan: &MigrationPlan) {
+    if plan.run.is_empty() {
+        println!("No pending migrations.");
+    }
+    for name in &plan.run {
+        println!("- {}", name);
+    }
+    for name in &plan.deferred {
+        println!("Deferred until `migrate --contract`: {}", name);
+    }
+}
+
+#[test]
+fn test_plan_migrations() {
+    let migration = |version: &str, name: &str| MigrationInfo {
+        version: version.to_string(),
+        name: format!("{}_{}", version, name),
+    };
+    let known = vec![
+        migration("1", "create_jwks"),
+        migration("2", "add_column"),
+        migration("3", "drop_old_column_contract"),
+        migration("4", "add_table"),
+    ];
+    let applied = |versions: &[&str]| versions.iter().map(|version| version.to_string()).collect::<Vec<_>>();
+
+    // Expand migrations run; a contract migration defers itself and everything after it
+    let plan = plan_migrations(&known, &applied(&["1"]), false).unwrap();
+    assert_eq!(plan.run, vec!["2_add_column"]);
+    assert_eq!(plan.deferred, vec!["3_drop_old_column_contract", "4_add_table"]);
+
+    let plan = plan_migrations(&known, &applied(&["1", "2"]), true).unwrap();
+    assert_eq!(plan.run, vec!["3_drop_old_column_contract", "4_add_table"]);
+    assert!(plan.deferred.is_empty());
+
+    // A newer release already migrated the database
+    let plan = plan_migrations(&known[..2], &applied(&["1", "2", "3"]), false).unwrap();
+    assert_eq!(plan, MigrationPlan::default());
+
+    // Diverged histories
+    assert!(plan_migrations(&known, &applied(&["1", "5"]), false).is_err());
+}
+
+#[test]
+fn test_migration_versions_are_timestamps() {
+    let migrations = MigrationSource::<Pg>::migrations(&MIGRATIONS).unwrap();
+    let versions = migrations
+        .iter()
+        .map(|migration| migration.name().version().to_string())
+        .collect::<Vec<_>>();
+
+    for version in &versions {
+        assert!(
+            chrono::NaiveDateTime::parse_from_str(version, "%Y%m%d%H%M%S").is_ok(),
+            "invalid migration version {}",
+            version
+        );
+    }
+    assert!(versions.windows(2).all(|pair| pair[0] < pair[1]));
+    for (old, new) in RENAMED_VERSIONS {
+        assert!(!versions.iter().any(|version| version == old));
+        assert!(versions.iter().any(|version| version == new));
+    }
+}
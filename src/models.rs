@@ -1,13 +1,14 @@
 //! This module defines the data models used in the JWK microservice.
 
+use crate::crypto::{curve_for_alg, key_use_for_alg};
 use chrono::NaiveDateTime;
 use diesel::{Insertable, Queryable, Selectable};
 use serde::*;
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
 /// Input data for the `/jwks` endpoint.
-#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct AlgorithmInput {
     /// Algorithm name:
     ///
@@ -17,9 +18,102 @@ pub struct AlgorithmInput {
     /// - `ES256`
     /// - `ES384`
     /// - `ES512`
-    /// - `Ed25519`
+    /// - `ES256K` (secp256k1)
+    /// - `EdDSA` (requires `crv`)
+    /// - `Ed25519`, `Ed448` (legacy form of `EdDSA` with the curve passed as the algorithm)
+    /// - `HS256`, `HS384`, `HS512` (symmetric `oct` keys, never published)
+    /// - `RSA-OAEP-256`, `ECDH-ES+A128KW` (P-256) encryption keys
+    /// - `ML-DSA-44`, `ML-DSA-65`, `ML-DSA-87` (requires the `ml-dsa` feature)
+    /// - `ML-KEM-512`, `ML-KEM-768`, `ML-KEM-1024` (requires the `ml-kem` feature)
     #[schema(example = "RS256")]
     pub alg: String,
+    /// Curve name. Required for `EdDSA` (`Ed25519` or `Ed448`); for other curve based
+    /// algorithms it must match the curve implied by `alg`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "Ed25519")]
+    pub crv: Option<String>,
+    /// Intended use of the key: `sig` for signature keys or `enc` for encryption keys. Must match
+    /// the algorithm; implied by it if omitted.
+    #[serde(default, rename = "use", skip_serializing_if = "Option::is_none")]
+    #[schema(example = "sig")]
+    pub use_: Option<String>,
+    /// RSA key size in bits. Only used for RSA algorithms; defaults to `RSA_KEY_SIZE`.
+    #[serde(default)]
+    #[schema(example = 2048)]
+    pub key_size: Option<u32>,
+    /// Issuers (`iss` claim) the key may sign tokens for. Any issuer is allowed if omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = json!(["https://auth.example.com"]))]
+    pub allowed_issuers: Option<Vec<String>>,
+    /// Audiences (`aud` claim) the key may sign tokens for. Any audience is allowed if omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = json!(["https://api.example.com"]))]
+    pub allowed_audiences: Option<Vec<String>>,
+    /// Return the most recent usable key with the same algorithm, key size and constraints
+    /// instead of creating a new one, if such a key exists. Defaults to `REUSE_ACTIVE_KEYS`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = true)]
+    pub reuse_active: Option<bool>,
+    /// Purpose of the key: `access-token`, `refresh-token`, `id-token`, `webhook-signing`, `ssh`
+    /// (Ed25519 or RSA only) or `ssh-ca` (Ed25519 only). The key lifetimes of the purpose apply;
+    /// general purpose key if omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "access-token")]
+    pub purpose: Option<String>,
+    /// Deliver the private key exactly once: only the public key is returned on creation, and
+    /// the private key can be retrieved from `/jwks/{id}` or `/jwks/{id}/export` a single time.
+    #[serde(default)]
+    pub burn_after_read: bool,
+    /// Require a one-time approval token (`X-Approval-Token`) to retrieve the private key; only
+    /// the public key is returned on creation.
+    #[serde(default)]
+    pub sensitive: bool,
+    /// Activation time (UTC). The key is created now but neither published nor used for signing
+    /// before it, and its lifetimes start at it. Active immediately if omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, example = "2026-11-01T00:00:00")]
+    pub not_before: Option<NaiveDateTime>,
+}
+
+impl AlgorithmInput {
+    /// Resolves the algorithm used for key generation.
+    ///
+    /// The standard form `alg: "EdDSA"` with `crv` resolves to the curve name (`Ed25519` or
+    /// `Ed448`); every other algorithm, including the legacy curve names, is returned as is.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if `crv` is missing or unsupported for `EdDSA`, or does not match
+    /// the curve implied by `alg`, or if `use` is unknown or does not match the algorithm.
+    pub fn generation_algorithm(&self) -> Result<String, String> {
+        let algorithm = if self.alg == "EdDSA" {
+            match self.crv.as_deref() {
+                Some(crv @ ("Ed25519" | "Ed448")) => crv.to_string(),
+                Some(crv) => return Err(format!("Unsupported curve {} for EdDSA", crv)),
+                None => return Err("crv is required for EdDSA".to_string()),
+            }
+        } else {
+            if let Some(crv) = self.crv.as_deref() {
+                if curve_for_alg(&self.alg) != Some(crv) {
+                    return Err(format!("Curve {} does not match algorithm {}", crv, self.alg));
+                }
+            }
+            self.alg.clone()
+        };
+
+        match self.use_.as_deref() {
+            None => {}
+            Some(key_use) if key_use != "sig" && key_use != "enc" => {
+                return Err(format!("Unsupported use {}, expected sig or enc", key_use));
+            }
+            Some(key_use) if key_use_for_alg(&algorithm) != key_use => {
+                return Err(format!("Algorithm {} cannot be used with use {}", self.alg, key_use));
+            }
+            Some(_) => {}
+        }
+
+        Ok(algorithm)
+    }
 }
 
 /// Represents a single JWK (JSON Web Key).
@@ -27,7 +121,7 @@ pub struct AlgorithmInput {
 pub struct Jwk {
     /// Key type (e.g., "RSA").
     pub kty: String,
-    /// How the key was meant to be used; sig represents the signature.
+    /// How the key was meant to be used; sig represents the signature, enc represents the encryption.
     #[serde(rename = "use")]
     pub use_: String,
     /// Algorithm used with the key (e.g., "RS256").
@@ -56,10 +150,13 @@ pub struct Jwk {
     /// The thumbprint of the x.509 cert (SHA-1 thumbprint).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub x5t: Option<String>,
+    /// Contain the public key of an Algorithm Key Pair (e.g., ML-DSA) encoded using base64url.
+    #[serde(rename = "pub", skip_serializing_if = "Option::is_none")]
+    pub pub_: Option<String>,
 }
 
 /// Represents a single JWK (JSON Web Key) with additional
-#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, ToSchema)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, Queryable, Insertable, Selectable, ToSchema)]
 #[diesel(table_name = crate::schema::jwks)]
 pub struct JwkData {
     /// Unique key identifier.
@@ -86,7 +183,12 @@ pub struct JwkData {
     pub x5c: Option<Vec<String>>,
     /// The thumbprint of the x.509 cert (SHA-1 thumbprint).
     pub x5t: Option<String>,
-    /// Private key in Base64 format.
+    /// Private key in Base64 format; the secret (the JWK `k` parameter) of symmetric keys.
+    /// Encrypted in the database if a key-encryption key is configured (see
+    /// [`crate::key_encryption`]). Empty for external keys, and in `/jwks/{id}` responses of
+    /// symmetric keys, which carry the secret as `k`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    #[diesel(serialize_as = crate::key_encryption::SealedPrivateKey, deserialize_as = crate::key_encryption::SealedPrivateKey)]
     pub private_key: String,
     /// Key creation date.
     #[schema(value_type = String)] // Indicates that NaiveDateTime is serialized as a string
@@ -100,6 +202,180 @@ pub struct JwkData {
     /// Key expiration date.
     #[serde(skip_serializing)] // Field will not be returned in API responses
     pub key_expires_at: Option<NaiveDateTime>,
+    /// Contain the public key of an Algorithm Key Pair (e.g., ML-DSA) encoded using base64url.
+    #[serde(rename = "pub", skip_serializing_if = "Option::is_none")]
+    pub pub_: Option<String>,
+    /// Issuers (`iss` claim) the key may sign tokens for. If `None`, any issuer is allowed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_issuers: Option<Vec<String>>,
+    /// Audiences (`aud` claim) the key may sign tokens for. If `None`, any audience is allowed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_audiences: Option<Vec<String>>,
+    /// Key this key rotated, if it is a later version of a logical key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    pub predecessor_id: Option<Uuid>,
+    /// Time of the last change of the key.
+    #[serde(default, skip_serializing)] // Field will not be returned in API responses
+    #[schema(value_type = String)]
+    pub updated_at: NaiveDateTime,
+    /// Time the key was frozen. If set, the key is neither published nor used for signing.
+    #[serde(default, skip_serializing)] // Field will not be returned in API responses
+    #[schema(value_type = Option<String>)]
+    pub frozen_at: Option<NaiveDateTime>,
+    /// Whether the key is the primary key of its algorithm, used by default for signing.
+    #[serde(default, skip_serializing)] // Field will not be returned in API responses
+    pub is_primary: bool,
+    /// Purpose the key is used for (e.g., "access-token"), with its own rotation policy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub purpose: Option<String>,
+    /// Whether the private key can only be retrieved once.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub burn_after_read: bool,
+    /// Time the private key of a burn-after-read key was retrieved.
+    #[serde(default, skip_serializing)] // Field will not be returned in API responses
+    #[schema(value_type = Option<String>)]
+    pub private_key_retrieved_at: Option<NaiveDateTime>,
+    /// Whether retrieving the private key requires a one-time approval token.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sensitive: bool,
+    /// Whether the key was registered from outside (e.g., a partner verification key). External
+    /// keys have no private key and are only published.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub external: bool,
+    /// Activation time. If set, the key is neither published nor used for signing before it.
+    #[serde(default, skip_serializing)] // Field will not be returned in API responses
+    #[schema(value_type = Option<String>)]
+    pub not_before: Option<NaiveDateTime>,
+}
+
+impl From<JwkData> for Jwk {
+    /// Converts stored key data into its public JWK representation.
+    fn from(jwk: JwkData) -> Self {
+        Jwk {
+            kty: jwk.kty,
+            use_: key_use_for_alg(&jwk.alg).to_string(),
+            alg: jwk.alg,
+            kid: jwk.kid,
+            crv: jwk.crv,
+            x: jwk.x,
+            y: jwk.y,
+            n: jwk.n,
+            e: jwk.e,
+            x5c: jwk.x5c,
+            x5t: jwk.x5t,
+            pub_: jwk.pub_,
+        }
+    }
+}
+
+impl JwkData {
+    /// Returns the lifecycle status of the key at the given time: `deleted`, `expired`, `frozen`,
+    /// `pending` (before its activation time), `private_key_expired` or `active`.
+    pub fn lifecycle_status(&self, now: NaiveDateTime) -> &'static str {
+        if self.deleted_at.is_some() {
+            "deleted"
+        } else if self.key_expires_at.is_some_and(|expires_at| now > expires_at) {
+            "expired"
+        } else if self.frozen_at.is_some() {
+            "frozen"
+        } else if self.not_before.is_some_and(|not_before| now < not_before) {
+            "pending"
+        } else if self.private_key_expires_at.is_some_and(|expires_at| now > expires_at) {
+            "private_key_expired"
+        } else {
+            "active"
+        }
+    }
+
+    /// Returns whether the key is a symmetric (`oct`) key, whose secret must never be published.
+    pub fn is_symmetric(&self) -> bool {
+        self.kty == "oct"
+    }
+
+    /// Returns whether the key is published in the JWK Set at the instant `at`, or currently
+    /// (at `now`) if `at` is `None`; the same keys as [`crate::manager::published_keys`].
+    pub fn is_published(&self, at: Option<NaiveDateTime>, now: NaiveDateTime) -> bool {
+        if self.is_symmetric() {
+            return false;
+        }
+
+        match at {
+            None => {
+                self.deleted_at.is_none()
+                    && self.frozen_at.is_none()
+                    && self.key_expires_at.is_some_and(|expires_at| expires_at > now)
+                    && self.not_before.is_none_or(|not_before| not_before <= now)
+            }
+            Some(at) => {
+                self.created_at <= at
+                    && self.deleted_at.is_none_or(|deleted| deleted > at)
+                    && self.key_expires_at.is_some_and(|expires_at| expires_at > at)
+                    && self.not_before.is_none_or(|not_before| not_before <= at)
+            }
+        }
+    }
+}
+
+/// Information derived from the stored key material.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct KeyDetails {
+    /// Lifecycle status: `active`, `pending`, `private_key_expired`, `frozen`, `expired` or
+    /// `deleted`.
+    pub status: String,
+    /// Whether the key is the primary key of its algorithm.
+    pub primary: bool,
+    /// Activation time, before which the key is neither published nor used for signing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    pub not_before: Option<NaiveDateTime>,
+    /// Private key expiration date.
+    #[schema(value_type = Option<String>)]
+    pub private_key_expires_at: Option<NaiveDateTime>,
+    /// Key expiration date.
+    #[schema(value_type = Option<String>)]
+    pub key_expires_at: Option<NaiveDateTime>,
+    /// Bit length of the RSA modulus.
+    pub modulus_bits: Option<u32>,
+    /// Curve name (e.g., "P-256" or "Ed25519").
+    pub curve: Option<String>,
+    /// JWK SHA-256 thumbprint (RFC 7638).
+    pub thumbprint: Option<String>,
+    /// End of the validity period of the certificate.
+    #[schema(value_type = Option<String>)]
+    pub certificate_not_after: Option<NaiveDateTime>,
+    /// SHA-256 fingerprint of the certificate (`x5t#S256`).
+    #[serde(rename = "x5t#S256")]
+    pub x5t_s256: Option<String>,
+}
+
+/// Stored key together with the information derived from it, as returned by `/jwks/{id}`.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct JwkDetails {
+    /// Stored key data.
+    #[serde(flatten)]
+    pub jwk: JwkData,
+    /// Secret of a symmetric key (the JWK `k` parameter), which is only released here.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub k: Option<String>,
+    /// Derived key information.
+    pub details: KeyDetails,
+}
+
+/// Fingerprints of an active key, as returned by `/jwks/fingerprints`.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct KeyFingerprint {
+    /// Unique key identifier.
+    #[schema(value_type = String)]
+    pub id: Uuid,
+    /// Key ID.
+    pub kid: String,
+    /// Algorithm of the key.
+    pub alg: String,
+    /// SHA-256 fingerprint of the SubjectPublicKeyInfo in standard Base64 (`pin-sha256`).
+    pub spki_sha256: Option<String>,
+    /// JWK SHA-256 thumbprint (RFC 7638).
+    pub thumbprint: Option<String>,
 }
 
 /// Represents a set of JWKs.
@@ -108,3 +384,1084 @@ pub struct Jwks {
     /// List of JWKs.
     pub keys: Vec<Jwk>,
 }
+
+/// JWKS document compared by the `/jwks/diff` endpoint, e.g. a downstream cache or CDN copy.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct JwksDiffInput {
+    /// Keys of the document; every key must have a `kid`.
+    #[schema(value_type = Vec<Object>)]
+    pub keys: Vec<serde_json::Value>,
+}
+
+/// Differences between a JWKS document and the active key set, as key IDs.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct JwksDiff {
+    /// Active keys missing from the document.
+    pub missing: Vec<String>,
+    /// Keys in the document that are not active.
+    pub extra: Vec<String>,
+    /// Keys whose parameters in the document differ from the active key.
+    pub changed: Vec<String>,
+}
+
+impl JwksDiff {
+    /// Compares a JWKS document with the active key set by `kid`.
+    ///
+    /// A key is changed if any of its parameters differ; `null` parameters in the document are
+    /// treated as absent. Key IDs are listed in sorted order.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if a key of the document is not an object or has no string `kid`.
+    pub fn compare(active: Vec<Jwk>, document: &[serde_json::Value]) -> Result<Self, String> {
+        let mut supplied = std::collections::BTreeMap::new();
+        for key in document {
+            let mut key = key.as_object().cloned().ok_or("Every key must be a JSON object")?;
+            key.retain(|_, value| !value.is_null());
+            let kid = key
+                .get("kid")
+                .and_then(|kid| kid.as_str())
+                .ok_or("Every key must have a kid")?
+                .to_string();
+            supplied.insert(kid, serde_json::Value::Object(key));
+        }
+
+        let mut diff = JwksDiff::default();
+        for jwk in active {
+            let kid = jwk.kid.clone();
+            match supplied.remove(&kid) {
+                None => diff.missing.push(kid),
+                Some(key) if serde_json::to_value(&jwk).ok().as_ref() != Some(&key) => diff.changed.push(kid),
+                Some(_) => {}
+            }
+        }
+        diff.extra = supplied.into_keys().collect();
+
+        diff.missing.sort();
+        diff.changed.sort();
+        Ok(diff)
+    }
+}
+
+/// Single problem found by the `/jwks/validate` endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct JwkFinding {
+    /// `error` if the JWK must not be imported, `warning` for a weakness.
+    pub severity: String,
+    /// Machine-readable finding code (e.g., `weak_key` or `alg_mismatch`).
+    pub code: String,
+    /// Human-readable description.
+    pub message: String,
+}
+
+/// Result of the `/jwks/validate` endpoint.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct JwkValidationReport {
+    /// Whether the JWK has no findings of severity `error`.
+    pub valid: bool,
+    /// Every finding.
+    pub findings: Vec<JwkFinding>,
+}
+
+/// Query parameters of the `/jwks/changes` endpoint.
+#[derive(Debug, Serialize, Deserialize, IntoParams)]
+pub struct JwksChangesQuery {
+    /// Cursor of the previous response, or any RFC 3339 timestamp or Unix timestamp in seconds.
+    pub since: String,
+}
+
+impl JwksChangesQuery {
+    /// Parses the time changes are reported since.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if `since` is neither an RFC 3339 nor a Unix timestamp.
+    pub fn since_time(&self) -> Result<NaiveDateTime, String> {
+        parse_timestamp(&self.since)
+            .ok_or_else(|| format!("Invalid since {}, expected an RFC 3339 or Unix timestamp", self.since))
+    }
+}
+
+/// Parses an RFC 3339 timestamp or a Unix timestamp in seconds into UTC.
+pub(crate) fn parse_timestamp(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim();
+    let parsed = match value.parse::<i64>() {
+        Ok(seconds) => chrono::DateTime::from_timestamp(seconds, 0),
+        Err(_) => chrono::DateTime::parse_from_rfc3339(value).ok().map(|time| time.to_utc()),
+    };
+
+    parsed.map(|time| time.naive_utc())
+}
+
+/// Changes of the active key set since a point in time.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct JwksChanges {
+    /// Keys published since then, including unfrozen keys.
+    pub added: Vec<Jwk>,
+    /// Key IDs of keys deleted, expired or frozen since then.
+    pub removed: Vec<String>,
+    /// Keys whose published parameters changed since then.
+    pub changed: Vec<Jwk>,
+    /// Value of `since` for the next poll.
+    pub cursor: String,
+}
+
+/// Single version of a logical key, as listed by the `/jwks/{id}/history` endpoint.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct KeyVersion {
+    /// Version number, starting at 1 for the first key.
+    pub version: u32,
+    /// Unique key identifier.
+    #[schema(value_type = String)]
+    pub id: Uuid,
+    /// Key ID.
+    pub kid: String,
+    /// Algorithm used with the key (e.g., "RS256").
+    pub alg: String,
+    /// Lifecycle status: `active`, `private_key_expired`, `frozen`, `expired` or `deleted`.
+    pub status: String,
+    /// Key creation date.
+    #[schema(value_type = String)]
+    pub created_at: NaiveDateTime,
+    /// Start of the period in which this version was the signing key.
+    #[schema(value_type = String)]
+    pub active_from: NaiveDateTime,
+    /// End of the period in which this version was the signing key: when it was superseded,
+    /// deleted or its private key expired, whichever came first.
+    #[schema(value_type = Option<String>)]
+    pub active_until: Option<NaiveDateTime>,
+    /// Key deletion date.
+    #[schema(value_type = Option<String>)]
+    pub deleted_at: Option<NaiveDateTime>,
+    /// Key expiration date.
+    #[schema(value_type = Option<String>)]
+    pub key_expires_at: Option<NaiveDateTime>,
+    /// Previous version.
+    #[schema(value_type = Option<String>)]
+    pub predecessor_id: Option<Uuid>,
+    /// Next version.
+    #[schema(value_type = Option<String>)]
+    pub successor_id: Option<Uuid>,
+}
+
+/// Soft-deleted JWK as listed by the `/jwks/deleted` endpoint.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DeletedJwk {
+    /// Unique key identifier.
+    #[schema(value_type = String)]
+    pub id: Uuid,
+    /// Key type (e.g., "RSA").
+    pub kty: String,
+    /// Algorithm used with the key (e.g., "RS256").
+    pub alg: String,
+    /// Key ID.
+    pub kid: String,
+    /// Key creation date.
+    #[schema(value_type = String)]
+    pub created_at: NaiveDateTime,
+    /// Key deletion date.
+    #[schema(value_type = String)]
+    pub deleted_at: NaiveDateTime,
+    /// Caller that deleted the key, if recorded in the audit log.
+    pub deleted_by: Option<String>,
+}
+
+/// Query parameters of the `/.well-known/jwks.json` endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize, IntoParams)]
+pub struct JwksQuery {
+    /// Reconstruct the key set active at this instant (RFC 3339 or Unix timestamp) instead of now.
+    pub at: Option<String>,
+    /// Only list keys of this purpose (e.g., "access-token").
+    pub purpose: Option<String>,
+}
+
+impl JwksQuery {
+    /// Parses the instant the key set is requested for.
+    ///
+    /// # Returns
+    ///
+    /// `None` if no instant is requested.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if `at` is neither an RFC 3339 nor a Unix timestamp.
+    pub fn at_time(&self) -> Result<Option<NaiveDateTime>, String> {
+        match &self.at {
+            Some(at) => parse_timestamp(at)
+                .map(Some)
+                .ok_or_else(|| format!("Invalid at {}, expected an RFC 3339 or Unix timestamp", at)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Input data for the `/sign` endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SignInput {
+    /// Unique identifier of the key used for signing. If omitted, the key `alias` resolves to is
+    /// used, or else the primary key of `alg` (the most recently created active key of `alg` if it
+    /// has no primary key).
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub id: Option<Uuid>,
+    /// Alias of the key used for signing when `id` is omitted (e.g., "access-token-signing").
+    #[serde(default)]
+    #[schema(example = "access-token-signing")]
+    pub alias: Option<String>,
+    /// Algorithm of the key used for signing when `id` and `alias` are omitted (e.g., "ES256").
+    #[serde(default)]
+    #[schema(example = "ES256")]
+    pub alg: Option<String>,
+    /// JWT claims set to sign.
+    #[schema(value_type = Object, example = json!({"iss": "https://auth.example.com", "aud": "https://api.example.com", "sub": "user-1"}))]
+    pub claims: serde_json::Value,
+    /// Client certificate (PEM or Base64 DER) the token is bound to via `cnf.x5t#S256`.
+    #[serde(default)]
+    pub client_certificate: Option<String>,
+    /// SHA-256 thumbprint of the client certificate the token is bound to via `cnf.x5t#S256`.
+    #[serde(default, rename = "x5t#S256")]
+    pub x5t_s256: Option<String>,
+}
+
+/// Input data for the `/tokens/access` endpoint.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AccessTokenInput {
+    /// Key used for signing. An active primary key, or else the most recently created active
+    /// signing key, is used if omitted.
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub id: Option<Uuid>,
+    /// Access token claims; `iss`, `sub`, `aud` and `client_id` are required.
+    #[schema(value_type = Object, example = json!({"iss": "https://auth.example.com", "sub": "user-1", "aud": "https://api.example.com", "client_id": "client-1", "scope": "read"}))]
+    pub claims: serde_json::Value,
+    /// Token lifetime in seconds used when `exp` is not supplied (default 3600).
+    #[serde(default)]
+    #[schema(example = 3600)]
+    pub expires_in: Option<i64>,
+}
+
+/// Input data for the `/software-statements` endpoint.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SoftwareStatementInput {
+    /// Software statement claims, completed from `SOFTWARE_STATEMENT_TEMPLATE`.
+    #[schema(value_type = Object, example = json!({"software_id": "partner-app", "client_name": "Partner App", "redirect_uris": ["https://partner.example.com/callback"]}))]
+    pub claims: serde_json::Value,
+}
+
+/// Input data for the `/tokens/exchange` endpoint (RFC 8693), sent as a form.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TokenExchangeInput {
+    /// Must be `urn:ietf:params:oauth:grant-type:token-exchange`.
+    pub grant_type: String,
+    /// Token representing the subject, signed by a stored key.
+    pub subject_token: String,
+    /// Type of the subject token (`urn:ietf:params:oauth:token-type:jwt` or `...:access_token`).
+    pub subject_token_type: String,
+    /// Token representing the acting party, signed by a stored key.
+    #[serde(default)]
+    pub actor_token: Option<String>,
+    /// Type of the actor token; required if `actor_token` is given.
+    #[serde(default)]
+    pub actor_token_type: Option<String>,
+    /// Audience of the issued token. Defaults to the audience of the subject token.
+    #[serde(default)]
+    pub audience: Option<String>,
+    /// Scope of the issued token. Defaults to the scope of the subject token.
+    #[serde(default)]
+    pub scope: Option<String>,
+    /// Type of the issued token (default `urn:ietf:params:oauth:token-type:access_token`).
+    #[serde(default)]
+    pub requested_token_type: Option<String>,
+}
+
+/// Output data of the `/tokens/exchange` endpoint (RFC 8693).
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TokenExchangeOutput {
+    /// Issued token.
+    pub access_token: String,
+    /// Type of the issued token.
+    pub issued_token_type: String,
+    /// Always `Bearer`.
+    pub token_type: String,
+    /// Lifetime of the issued token in seconds.
+    pub expires_in: i64,
+}
+
+/// Input data for the `/request-objects` endpoint.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RequestObjectInput {
+    /// Unique identifier of the key used for signing.
+    #[schema(value_type = String)]
+    pub id: Uuid,
+    /// Authorization request parameters; `iss`, `aud`, `client_id`, `response_type`,
+    /// `redirect_uri` and `scope` are required.
+    #[schema(value_type = Object, example = json!({"iss": "client-1", "client_id": "client-1", "aud": "https://auth.example.com", "response_type": "code", "redirect_uri": "https://client.example.com/callback", "scope": "openid accounts"}))]
+    pub claims: serde_json::Value,
+}
+
+/// Input data for the `/paseto/sign` endpoint.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PasetoSignInput {
+    /// Unique identifier of the Ed25519 key used for signing.
+    #[schema(value_type = String)]
+    pub id: Uuid,
+    /// Token claims.
+    #[schema(value_type = Object, example = json!({"iss": "https://auth.example.com", "sub": "user-1", "exp": "2030-01-01T00:00:00+00:00"}))]
+    pub claims: serde_json::Value,
+    /// Additional footer members; the footer always carries the `kid` of the key.
+    #[serde(default)]
+    #[schema(value_type = Option<Object>)]
+    pub footer: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Implicit assertion bound to the signature but not included in the token.
+    #[serde(default)]
+    pub implicit_assertion: Option<String>,
+}
+
+/// Input data for the `/cwt/sign` endpoint.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CwtSignInput {
+    /// Unique identifier of the EC or OKP key used for signing.
+    #[schema(value_type = String)]
+    pub id: Uuid,
+    /// CWT claims; registered claim names are mapped to their integer keys.
+    #[schema(value_type = Object, example = json!({"iss": "https://auth.example.com", "sub": "device-1", "exp": 1893456000}))]
+    pub claims: serde_json::Value,
+    /// Base64URL encoded external data bound to the signature.
+    #[serde(default)]
+    pub external_aad: Option<String>,
+}
+
+/// Input data for the `/ssh/certificates` endpoint.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SshCertificateInput {
+    /// OpenSSH public key to certify (`ssh-ed25519`, `ssh-rsa` or `ecdsa-sha2-nistp*`).
+    #[schema(example = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIHLv64DJzq/ZgpOZKvoMUF7a92uqLo5OGtXc+gFZ/JTy alice@laptop")]
+    pub public_key: String,
+    /// Certificate type, `user` (default) or `host`.
+    #[serde(default)]
+    pub cert_type: Option<String>,
+    /// Key identity of the certificate, logged by the SSH server (e.g., "alice@example.com").
+    pub identity: String,
+    /// User names (`user`) or host names (`host`) the certificate is valid for.
+    pub principals: Vec<String>,
+    /// Validity of the certificate in seconds (default `SSH_CERTIFICATE_TTL_SECONDS`).
+    #[serde(default)]
+    pub ttl_seconds: Option<i64>,
+    /// Extensions of a user certificate (default: the `ssh-keygen` defaults, e.g. `permit-pty`).
+    #[serde(default)]
+    pub extensions: Option<Vec<String>>,
+}
+
+/// SSH certificate issued by `/ssh/certificates`.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SshCertificate {
+    /// The certificate in OpenSSH format (`<type>-cert-v01@openssh.com <base64> <identity>`).
+    pub certificate: String,
+    /// Serial number of the certificate.
+    pub serial: u64,
+    /// Start of the validity of the certificate.
+    #[schema(value_type = String)]
+    pub valid_after: NaiveDateTime,
+    /// End of the validity of the certificate.
+    #[schema(value_type = String)]
+    pub valid_before: NaiveDateTime,
+    /// Key ID of the CA key that signed the certificate.
+    pub ca_kid: String,
+}
+
+/// Input data for the `/sd-jwt/issue` endpoint.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SdJwtIssueInput {
+    /// Unique identifier of the key used for signing.
+    #[schema(value_type = String)]
+    pub id: Uuid,
+    /// Claims of the SD-JWT.
+    #[schema(value_type = Object, example = json!({"iss": "https://issuer.example.com", "vct": "https://credentials.example.com/identity", "given_name": "Erika", "birthdate": "1963-08-12"}))]
+    pub claims: serde_json::Value,
+    /// Names of the top-level claims that are selectively disclosable.
+    #[serde(default)]
+    #[schema(example = json!(["given_name", "birthdate"]))]
+    pub disclosable: Vec<String>,
+    /// Public JWK of the holder for key binding (`cnf.jwk`).
+    #[serde(default)]
+    #[schema(value_type = Option<Object>)]
+    pub holder_jwk: Option<serde_json::Value>,
+    /// Value of the `typ` header (default `dc+sd-jwt`).
+    #[serde(default)]
+    pub typ: Option<String>,
+}
+
+/// Input data for the `/dpop/validate` endpoint.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DpopValidationInput {
+    /// DPoP proof JWT from the `DPoP` request header.
+    pub proof: String,
+    /// HTTP method of the request the proof was sent with.
+    #[schema(example = "GET")]
+    pub htm: String,
+    /// HTTP URI of the request the proof was sent with.
+    #[schema(example = "https://api.example.com/resource")]
+    pub htu: String,
+    /// Access token sent with the proof, if any; the proof must then carry its hash (`ath`).
+    #[serde(default)]
+    pub access_token: Option<String>,
+}
+
+/// Output data of the `/dpop/validate` endpoint.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DpopValidationOutput {
+    /// JWK SHA-256 thumbprint of the proof key, to be matched against the `cnf.jkt` claim.
+    pub jkt: String,
+}
+
+/// Output data of the token signing endpoints.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SignOutput {
+    /// Signed token in JWS compact serialization.
+    pub token: String,
+}
+
+/// Query parameters of the private key export endpoint.
+#[derive(Debug, Serialize, Deserialize, IntoParams)]
+pub struct ExportQuery {
+    /// Key format: `pkcs8` (default, any key), `pkcs1` (RSA keys) or `sec1` (EC keys).
+    pub format: Option<String>,
+    /// Output encoding: `pem` (default) or `der`.
+    pub encoding: Option<String>,
+}
+
+/// Query parameters of the paginated listings without filters.
+#[derive(Debug, Serialize, Deserialize, IntoParams)]
+pub struct PageQuery {
+    /// Maximum number of entries (default 100, at most 1000).
+    pub limit: Option<i64>,
+    /// Number of entries skipped (default 0).
+    pub offset: Option<i64>,
+}
+
+/// Query parameters of the `/jwks/expiring` endpoint.
+#[derive(Debug, Serialize, Deserialize, IntoParams)]
+pub struct ExpiringQuery {
+    /// Reporting window: a number followed by `s`, `m`, `h`, `d` or `w` (default `7d`).
+    pub within: Option<String>,
+    /// Maximum number of entries (default 100, at most 1000).
+    pub limit: Option<i64>,
+    /// Number of entries skipped (default 0).
+    pub offset: Option<i64>,
+}
+
+impl ExpiringQuery {
+    /// Parses the reporting window.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if the window is not a positive number with a supported unit.
+    pub fn window(&self) -> Result<chrono::Duration, String> {
+        let within = self.within.as_deref().unwrap_or("7d").trim();
+        let invalid = || format!("Invalid window {}, expected e.g. 12h or 7d", within);
+
+        let unit_start = within.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+        let amount: i64 = within[..unit_start].parse().map_err(|_| invalid())?;
+        let seconds_per_unit = match &within[unit_start..] {
+            "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            "d" => 86400,
+            "w" => 604800,
+            _ => return Err(invalid()),
+        };
+
+        if amount == 0 {
+            return Err(invalid());
+        }
+        amount
+            .checked_mul(seconds_per_unit)
+            .and_then(chrono::Duration::try_seconds)
+            .ok_or_else(invalid)
+    }
+}
+
+/// Key whose private key or key expires within the requested window.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ExpiringJwk {
+    /// Unique key identifier.
+    #[schema(value_type = String)]
+    pub id: Uuid,
+    /// Key type (e.g., "RSA").
+    pub kty: String,
+    /// Algorithm used with the key (e.g., "RS256").
+    pub alg: String,
+    /// Key ID.
+    pub kid: String,
+    /// Lifecycle status: `active`, `frozen` or `private_key_expired`.
+    pub status: String,
+    /// Private key expiration date.
+    #[schema(value_type = Option<String>)]
+    pub private_key_expires_at: Option<NaiveDateTime>,
+    /// Key expiration date.
+    #[schema(value_type = Option<String>)]
+    pub key_expires_at: Option<NaiveDateTime>,
+}
+
+/// Query parameters of the `/jwks/inventory` endpoint.
+#[derive(Debug, Serialize, Deserialize, IntoParams)]
+pub struct InventoryQuery {
+    /// Output format: `csv` (default) or `jsonl` (one JSON object per line).
+    pub format: Option<String>,
+    /// Whether deleted keys are listed too (default `false`).
+    pub include_deleted: Option<bool>,
+}
+
+/// Row of the key inventory exported at `/jwks/inventory`.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct InventoryEntry {
+    /// Unique key identifier.
+    #[schema(value_type = String)]
+    pub id: Uuid,
+    /// Key ID.
+    pub kid: String,
+    /// Key type (e.g., "RSA").
+    pub kty: String,
+    /// Algorithm used with the key (e.g., "RS256").
+    pub alg: String,
+    /// Purpose of the key, if dedicated to one.
+    pub purpose: Option<String>,
+    /// Lifecycle status: `active`, `pending`, `private_key_expired`, `frozen`, `expired` or
+    /// `deleted`.
+    pub status: String,
+    /// Whether the key is the primary key of its algorithm.
+    pub primary: bool,
+    /// Key creation date.
+    #[schema(value_type = String)]
+    pub created_at: NaiveDateTime,
+    /// Private key expiration date.
+    #[schema(value_type = Option<String>)]
+    pub private_key_expires_at: Option<NaiveDateTime>,
+    /// Key expiration date.
+    #[schema(value_type = Option<String>)]
+    pub key_expires_at: Option<NaiveDateTime>,
+    /// Key deletion date.
+    #[schema(value_type = Option<String>)]
+    pub deleted_at: Option<NaiveDateTime>,
+    /// End of the validity period of the certificate.
+    #[schema(value_type = Option<String>)]
+    pub certificate_not_after: Option<NaiveDateTime>,
+    /// Labels of the key: the names of the aliases resolving to it.
+    pub labels: Vec<String>,
+    /// Whether the key was registered from outside with its public part only.
+    pub external: bool,
+    /// Activation time, before which the key is neither published nor used for signing.
+    #[schema(value_type = Option<String>)]
+    pub not_before: Option<NaiveDateTime>,
+}
+
+/// Input data for the `/jwks/{id}/extend` endpoint.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ExtendKeyInput {
+    /// Number of seconds to push the private key expiration out by. All extensions of a key
+    /// together must not exceed `MAX_PRIVATE_KEY_EXTENSION_SECONDS`.
+    #[schema(example = 3600)]
+    pub seconds: i64,
+}
+
+/// Expiration dates of a key after its private key was extended.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct KeyExtension {
+    /// Unique key identifier.
+    #[schema(value_type = String)]
+    pub id: Uuid,
+    /// Key ID.
+    pub kid: String,
+    /// New private key expiration date.
+    #[schema(value_type = String)]
+    pub private_key_expires_at: NaiveDateTime,
+    /// New key expiration date.
+    #[schema(value_type = Option<String>)]
+    pub key_expires_at: Option<NaiveDateTime>,
+}
+
+/// Keys selected by a bulk lifecycle transition. Every given criterion must match.
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct BulkKeyFilter {
+    /// Algorithm of the keys (e.g., "RS256").
+    #[serde(default)]
+    pub alg: Option<String>,
+    /// Purpose of the keys (e.g., "access-token").
+    #[serde(default)]
+    pub purpose: Option<String>,
+    /// Only keys created before this instant (RFC 3339 or Unix timestamp).
+    #[serde(default)]
+    #[schema(example = "2026-06-01T00:00:00Z")]
+    pub created_before: Option<String>,
+}
+
+impl BulkKeyFilter {
+    /// Parses the instant keys must have been created before.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if `created_before` is neither an RFC 3339 nor a Unix timestamp.
+    pub fn created_before_time(&self) -> Result<Option<NaiveDateTime>, String> {
+        match &self.created_before {
+            Some(value) => parse_timestamp(value).map(Some).ok_or_else(|| {
+                format!("Invalid created_before {}, expected an RFC 3339 or Unix timestamp", value)
+            }),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Input data for the `/jwks/bulk` endpoint.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BulkTransitionInput {
+    /// Transition applied to every matching key: `disable` (freeze), `revoke` (delete) or
+    /// `extend` (extend the private key by `seconds`).
+    #[schema(example = "disable")]
+    pub action: String,
+    /// Number of seconds to extend the private keys by. Required for `extend`.
+    #[serde(default)]
+    #[schema(example = 3600)]
+    pub seconds: Option<i64>,
+    /// Keys the transition applies to. At least one criterion is required.
+    pub filter: BulkKeyFilter,
+    /// Only report the matching keys without changing them.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Result of a bulk lifecycle transition.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BulkTransitionReport {
+    /// Applied transition.
+    pub action: String,
+    /// Whether the keys were only reported.
+    pub dry_run: bool,
+    /// Keys the transition applies (or, in a dry run, would apply) to.
+    #[schema(value_type = Vec<String>)]
+    pub keys: Vec<Uuid>,
+}
+
+/// Named alias resolving to the current version of a key.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Queryable, Insertable, Selectable, ToSchema)]
+#[diesel(table_name = crate::schema::key_aliases)]
+pub struct KeyAlias {
+    /// Name of the alias (e.g., "access-token-signing").
+    pub name: String,
+    /// Key the alias currently resolves to.
+    #[schema(value_type = String)]
+    pub key_id: Uuid,
+    /// Time the alias was created.
+    #[schema(value_type = String)]
+    pub created_at: NaiveDateTime,
+    /// Time the alias was last pointed to a key.
+    #[schema(value_type = String)]
+    pub updated_at: NaiveDateTime,
+}
+
+/// Input data for the `PUT /aliases/{name}` endpoint.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct KeyAliasInput {
+    /// Key the alias resolves to.
+    #[schema(value_type = String)]
+    pub key_id: Uuid,
+}
+
+/// Record of a response containing the private key of a key.
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Insertable, Selectable, ToSchema)]
+#[diesel(table_name = crate::schema::private_key_access_log)]
+pub struct PrivateKeyAccess {
+    /// Unique access record identifier.
+    #[schema(value_type = String)]
+    pub id: Uuid,
+    /// Identifier of the accessed key.
+    #[schema(value_type = String)]
+    pub key_id: Uuid,
+    /// Key ID of the accessed key.
+    pub kid: String,
+    /// Endpoint that returned the private key (e.g., "/jwks/{id}").
+    pub endpoint: String,
+    /// Caller that accessed the key, from the `X-Actor` header.
+    pub actor: Option<String>,
+    /// Address of the client the request came from.
+    pub client_address: Option<String>,
+    /// Time of the access.
+    #[schema(value_type = String)]
+    pub accessed_at: NaiveDateTime,
+}
+
+/// Anomaly detected in an access to a private key.
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Insertable, Selectable, ToSchema)]
+#[diesel(table_name = crate::schema::private_key_access_anomalies)]
+pub struct PrivateKeyAccessAnomaly {
+    /// Unique anomaly identifier.
+    #[schema(value_type = String)]
+    pub id: Uuid,
+    /// Access record the anomaly was detected in.
+    #[schema(value_type = String)]
+    pub access_id: Uuid,
+    /// Identifier of the accessed key.
+    #[schema(value_type = String)]
+    pub key_id: Uuid,
+    /// Kind of anomaly: `new_network`, `burst` or `dormant_key`.
+    #[schema(example = "new_network")]
+    pub kind: String,
+    /// Description of the anomaly.
+    pub detail: String,
+    /// Caller that accessed the key, from the `X-Actor` header.
+    pub actor: Option<String>,
+    /// Address of the client the access came from.
+    pub client_address: Option<String>,
+    /// Time the anomaly was detected.
+    #[schema(value_type = String)]
+    pub detected_at: NaiveDateTime,
+}
+
+/// Query parameters of the `/audit/anomalies` endpoint.
+#[derive(Debug, Serialize, Deserialize, IntoParams)]
+pub struct AnomalyQuery {
+    /// Only list anomalies of accesses to this key.
+    #[param(value_type = Option<String>)]
+    pub key_id: Option<Uuid>,
+    /// Only list anomalies of this kind.
+    pub kind: Option<String>,
+    /// Only list anomalies detected at or after this instant (RFC 3339 or Unix timestamp).
+    pub since: Option<String>,
+    /// Maximum number of entries (default 100, at most 1000).
+    pub limit: Option<i64>,
+    /// Number of entries skipped (default 0).
+    pub offset: Option<i64>,
+}
+
+impl AnomalyQuery {
+    /// Parses the instant anomalies are listed since.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if `since` is neither an RFC 3339 nor a Unix timestamp.
+    pub fn since_time(&self) -> Result<Option<NaiveDateTime>, String> {
+        match &self.since {
+            Some(since) => parse_timestamp(since)
+                .map(Some)
+                .ok_or_else(|| format!("Invalid since {}, expected an RFC 3339 or Unix timestamp", since)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// One-time approval to retrieve the private key of a sensitive key.
+#[derive(Debug, Clone, Queryable, Insertable, Selectable)]
+#[diesel(table_name = crate::schema::private_key_approvals)]
+pub struct KeyApproval {
+    /// Unique approval identifier.
+    pub id: Uuid,
+    /// Identifier of the approved key.
+    pub key_id: Uuid,
+    /// SHA-256 hash of the approval token; the token itself is never stored.
+    pub token_hash: String,
+    /// Approver that issued the approval, from the `X-Actor` header.
+    pub approved_by: Option<String>,
+    /// Time the approval was issued.
+    pub created_at: NaiveDateTime,
+    /// Time the approval lapses unless used.
+    pub expires_at: NaiveDateTime,
+    /// Time the approval was used.
+    pub used_at: Option<NaiveDateTime>,
+}
+
+/// Approval token issued for a sensitive key.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct KeyApprovalToken {
+    /// Identifier of the approved key.
+    #[schema(value_type = String)]
+    pub key_id: Uuid,
+    /// One-time token to send in the `X-Approval-Token` header when retrieving the private key.
+    pub token: String,
+    /// Time the token lapses unless used.
+    #[schema(value_type = String)]
+    pub expires_at: NaiveDateTime,
+}
+
+/// Query parameters of the `/audit/private-key-access` endpoint.
+#[derive(Debug, Serialize, Deserialize, IntoParams)]
+pub struct PrivateKeyAccessQuery {
+    /// Only list accesses to this key.
+    #[param(value_type = Option<String>)]
+    pub key_id: Option<Uuid>,
+    /// Only list accesses by this actor.
+    pub actor: Option<String>,
+    /// Only list accesses at or after this instant (RFC 3339 or Unix timestamp).
+    pub since: Option<String>,
+    /// Maximum number of entries (default 100, at most 1000).
+    pub limit: Option<i64>,
+    /// Number of entries skipped (default 0).
+    pub offset: Option<i64>,
+}
+
+impl PrivateKeyAccessQuery {
+    /// Parses the instant accesses are listed since.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if `since` is neither an RFC 3339 nor a Unix timestamp.
+    pub fn since_time(&self) -> Result<Option<NaiveDateTime>, String> {
+        match &self.since {
+            Some(since) => parse_timestamp(since)
+                .map(Some)
+                .ok_or_else(|| format!("Invalid since {}, expected an RFC 3339 or Unix timestamp", since)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Link of a WebFinger response (RFC 7033, section 4.4.4).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct WebFingerLink {
+    /// Link relation type.
+    pub rel: String,
+    /// Media type of the target.
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub type_: Option<String>,
+    /// Target URL.
+    pub href: String,
+}
+
+/// JSON Resource Descriptor returned by `/.well-known/webfinger` (RFC 7033, section 4.4).
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WebFingerResponse {
+    /// Queried resource.
+    pub subject: String,
+    /// Links of the resource.
+    pub links: Vec<WebFingerLink>,
+}
+
+/// Verification method of a DID document (W3C DID Core, section 5.2).
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DidVerificationMethod {
+    /// DID URL of the method: the DID with the key ID as fragment.
+    pub id: String,
+    /// Verification method type (`JsonWebKey2020`).
+    #[serde(rename = "type")]
+    pub type_: String,
+    /// DID controlling the key.
+    pub controller: String,
+    /// Public key of the method.
+    #[serde(rename = "publicKeyJwk")]
+    pub public_key_jwk: Jwk,
+}
+
+/// DID document returned by `/.well-known/did.json` (W3C DID Core, did:web method).
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DidDocument {
+    /// JSON-LD contexts of the document.
+    #[serde(rename = "@context")]
+    pub context: Vec<String>,
+    /// The DID (e.g., "did:web:keys.example.com").
+    pub id: String,
+    /// Active keys of the service.
+    #[serde(rename = "verificationMethod")]
+    pub verification_method: Vec<DidVerificationMethod>,
+    /// Methods of the signing keys, for authentication.
+    pub authentication: Vec<String>,
+    /// Methods of the signing keys, for issuing verifiable credentials.
+    #[serde(rename = "assertionMethod")]
+    pub assertion_method: Vec<String>,
+    /// Methods of the encryption keys.
+    #[serde(rename = "keyAgreement", skip_serializing_if = "Vec::is_empty", default)]
+    pub key_agreement: Vec<String>,
+}
+
+/// Consistency of the primary and the target database in dual-write mode.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DualWriteReport {
+    /// Time the databases were compared.
+    #[schema(value_type = String)]
+    pub checked_at: NaiveDateTime,
+    /// Number of keys in the primary database.
+    pub primary_keys: usize,
+    /// Number of keys in the target database.
+    pub target_keys: usize,
+    /// Whether both databases hold the same keys.
+    pub consistent: bool,
+    /// Keys missing from the target database.
+    #[schema(value_type = Vec<String>)]
+    pub missing: Vec<Uuid>,
+    /// Keys only present in the target database.
+    #[schema(value_type = Vec<String>)]
+    pub extra: Vec<Uuid>,
+    /// Keys that differ between the databases.
+    #[schema(value_type = Vec<String>)]
+    pub mismatched: Vec<Uuid>,
+    /// Key mutations that could not be mirrored since the service started.
+    pub mirror_failures: usize,
+}
+
+/// Query parameters of the replication change feed.
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ReplicationChangesQuery {
+    /// Sequence number of the last operation already replicated (default: from the start).
+    pub since: Option<i64>,
+}
+
+/// Key replicated between regional deployments, including its private key and lifecycle state.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ReplicatedKey {
+    /// Key material; its lifecycle dates are not serialized and are carried below.
+    #[serde(flatten)]
+    pub key: JwkData,
+    /// Key deletion date.
+    #[schema(value_type = Option<String>)]
+    pub deleted_at: Option<NaiveDateTime>,
+    /// Private key expiration date.
+    #[schema(value_type = Option<String>)]
+    pub private_key_expires_at: Option<NaiveDateTime>,
+    /// Key expiration date.
+    #[schema(value_type = Option<String>)]
+    pub key_expires_at: Option<NaiveDateTime>,
+    /// Time of the last change of the key.
+    #[schema(value_type = String)]
+    pub updated_at: NaiveDateTime,
+    /// Time the key was frozen.
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub frozen_at: Option<NaiveDateTime>,
+    /// Time the private key of a burn-after-read key was retrieved.
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub private_key_retrieved_at: Option<NaiveDateTime>,
+    /// Activation time of the key.
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub not_before: Option<NaiveDateTime>,
+}
+
+impl ReplicatedKey {
+    /// Wraps a stored key for replication.
+    pub fn new(jwk: JwkData) -> Self {
+        ReplicatedKey {
+            deleted_at: jwk.deleted_at,
+            private_key_expires_at: jwk.private_key_expires_at,
+            key_expires_at: jwk.key_expires_at,
+            updated_at: jwk.updated_at,
+            frozen_at: jwk.frozen_at,
+            private_key_retrieved_at: jwk.private_key_retrieved_at,
+            not_before: jwk.not_before,
+            key: jwk,
+        }
+    }
+
+    /// Returns the key as stored, including its lifecycle state.
+    pub fn to_jwk_data(&self) -> JwkData {
+        JwkData {
+            deleted_at: self.deleted_at,
+            private_key_expires_at: self.private_key_expires_at,
+            key_expires_at: self.key_expires_at,
+            updated_at: self.updated_at,
+            frozen_at: self.frozen_at,
+            private_key_retrieved_at: self.private_key_retrieved_at,
+            not_before: self.not_before,
+            ..self.key.clone()
+        }
+    }
+}
+
+/// Batch of the replication change feed.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReplicationBatch {
+    /// Changed keys, in the order of their last change.
+    pub keys: Vec<ReplicatedKey>,
+    /// Value of `since` for the next request.
+    pub cursor: i64,
+}
+
+/// Stored key that failed the stored key self-test.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StoredKeyFailure {
+    /// Unique key identifier.
+    #[schema(value_type = String)]
+    pub id: Uuid,
+    /// Key ID.
+    pub kid: String,
+    /// Algorithm used with the key (e.g., "RS256").
+    pub alg: String,
+    /// Description of the failure.
+    pub error: String,
+}
+
+/// Result of the stored key self-test.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StoredKeySelfTest {
+    /// Time the self-test ran.
+    #[schema(value_type = String)]
+    pub checked_at: NaiveDateTime,
+    /// Number of checked keys.
+    pub checked: usize,
+    /// Keys that failed the self-test.
+    pub failures: Vec<StoredKeyFailure>,
+}
+
+/// Input data for the `/jwks/external` endpoint. Exactly one of `jwk` and `certificate` is
+/// required.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ExternalKeyInput {
+    /// Public JWK of the key. Private parameters are rejected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub jwk: Option<serde_json::Value>,
+    /// PEM encoded X.509 certificate chain of the key, leaf certificate first. Published as
+    /// `x5c`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<String>,
+    /// Algorithm of the key. Taken from the JWK or derived from the curve if omitted; RSA keys
+    /// default to `RS256`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "ES256")]
+    pub alg: Option<String>,
+    /// Key ID. Taken from the JWK if omitted, else the RFC 7638 thumbprint of the key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "partner-2026-10")]
+    pub kid: Option<String>,
+    /// Purpose of the key (e.g., `access-token`), used to filter the JWK Set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub purpose: Option<String>,
+    /// Time the key stops being published. Defaults to the expiration of the certificate, else
+    /// to the key lifetimes of the algorithm and purpose.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, example = "2027-10-15T00:00:00")]
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+/// State of a scheduled background job, returned by `/jobs`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct JobStatus {
+    /// Name of the job.
+    #[schema(example = "expiry-notifications")]
+    pub name: String,
+    /// Time between two scheduled runs in seconds.
+    pub interval_seconds: u64,
+    /// Outcome of the latest run: `running`, `completed` or `failed`; missing if the job never
+    /// ran.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<String>,
+    /// Replica that performed the latest run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replica: Option<String>,
+    /// Time the latest run started.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    pub last_run_at: Option<NaiveDateTime>,
+    /// Time the latest run finished.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    pub last_finished_at: Option<NaiveDateTime>,
+    /// Error of the latest failed run, kept after later successful runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// Time of the latest failed run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    pub last_error_at: Option<NaiveDateTime>,
+    /// Time of the next scheduled run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    pub next_run_at: Option<NaiveDateTime>,
+}
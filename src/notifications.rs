@@ -0,0 +1,563 @@
+//! This module notifies operators of expiring keys and failed rotations.
+//!
+//! A scheduled job looks for keys whose public key, private key or certificate expires within
+//! `NOTIFY_EXPIRY_WARNING_DAYS` and sends one warning per key, kind of expiry and expiry date, so
+//! humans find out before tokens start failing. Failed rotations are notified as they happen.
+//! Notifications are sent by email (SMTP), to a Slack incoming webhook and/or as JSON to a generic
+//! webhook, depending on which channels are configured.
+
+use crate::crypto::key_details;
+use crate::log_error;
+use crate::models::JwkData;
+use crate::schema::{expiry_notifications, jwks};
+use base64::Engine;
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use dotenv::dotenv;
+use openssl::ssl::{SslConnector, SslMethod, SslStream};
+use serde::Serialize;
+use serde_json::json;
+use std::env;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Name of the scheduled expiry notification job.
+pub const NOTIFICATION_JOB: &str = "expiry-notifications";
+
+/// The public key expires, so tokens it signed stop verifying.
+pub const EVENT_KEY_EXPIRING: &str = "key_expiring";
+/// The private key expires, so the key can no longer sign.
+pub const EVENT_PRIVATE_KEY_EXPIRING: &str = "private_key_expiring";
+/// The certificate (`x5c`) of the key expires.
+pub const EVENT_CERTIFICATE_EXPIRING: &str = "certificate_expiring";
+/// A rotation failed to create the new key version.
+pub const EVENT_ROTATION_FAILED: &str = "rotation_failed";
+
+/// Timeout of the requests to the notification channels.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Security of the connection to the SMTP server.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SmtpSecurity {
+    /// Plain connection upgraded with `STARTTLS`.
+    StartTls,
+    /// Implicit TLS (SMTPS).
+    Tls,
+    /// Unencrypted connection, for local relays only.
+    None,
+}
+
+/// Settings of the email channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmtpSettings {
+    /// Host name of the SMTP server.
+    pub host: String,
+    /// Port of the SMTP server.
+    pub port: u16,
+    /// Security of the connection.
+    pub security: SmtpSecurity,
+    /// User name and password for `AUTH PLAIN`, if the server requires authentication.
+    pub credentials: Option<(String, String)>,
+    /// Sender address.
+    pub from: String,
+    /// Recipient addresses.
+    pub to: Vec<String>,
+}
+
+/// Settings of the notifications.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NotificationSettings {
+    /// Email channel.
+    pub smtp: Option<SmtpSettings>,
+    /// Slack incoming webhook URL.
+    pub slack_webhook_url: Option<String>,
+    /// Generic webhook URL receiving the notifications as JSON.
+    pub webhook_url: Option<String>,
+    /// How long before an expiry the warning is sent.
+    pub warning_period: TimeDelta,
+}
+
+/// Notification sent to the configured channels.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Notification {
+    /// Kind of notification (e.g., "key_expiring").
+    pub event: String,
+    /// Identifier of the key.
+    pub key_id: Uuid,
+    /// Key ID (`kid`) of the key.
+    pub kid: String,
+    /// Expiry date, for expiry warnings.
+    pub expires_at: Option<NaiveDateTime>,
+    /// One-line summary, used as email subject.
+    pub subject: String,
+    /// Description of the problem and what to do.
+    pub message: String,
+}
+
+/// Returns the value of an environment variable, if set and not empty.
+fn optional_var(name: &str) -> Option<String> {
+    env::var(name).ok().map(|value| value.trim().to_string()).filter(|value| !value.is_empty())
+}
+
+/// Returns the settings of the notifications: the email channel (`NOTIFY_SMTP_HOST`,
+/// `NOTIFY_SMTP_PORT`, `NOTIFY_SMTP_SECURITY`, `NOTIFY_SMTP_USERNAME`, `NOTIFY_SMTP_PASSWORD`,
+/// `NOTIFY_EMAIL_FROM`, `NOTIFY_EMAIL_TO`), `NOTIFY_SLACK_WEBHOOK_URL`, `NOTIFY_WEBHOOK_URL` and
+/// `NOTIFY_EXPIRY_WARNING_DAYS` (default 14).
+///
+/// # Returns
+///
+/// `None` if no channel is configured.
+///
+/// # Panics
+///
+/// This function will panic if a setting is invalid or the email channel is incomplete.
+pub fn notification_settings() -> Option<NotificationSettings> {
+    dotenv().ok();
+
+    let smtp = optional_var("NOTIFY_SMTP_HOST").map(|host| {
+        let security = match optional_var("NOTIFY_SMTP_SECURITY").as_deref() {
+            None | Some("starttls") => SmtpSecurity::StartTls,
+            Some("tls") => SmtpSecurity::Tls,
+            Some("none") => SmtpSecurity::None,
+            Some(other) => panic!("Unknown NOTIFY_SMTP_SECURITY {}, expected starttls, tls or none", other),
+        };
+        let default_port = if security == SmtpSecurity::Tls { "465" } else { "587" };
+        let to = optional_var("NOTIFY_EMAIL_TO")
+            .expect("NOTIFY_EMAIL_TO must be set with NOTIFY_SMTP_HOST")
+            .split(',')
+            .map(|address| address.trim().to_string())
+            .filter(|address| !address.is_empty())
+            .collect();
+
+        SmtpSettings {
+            host,
+            port: optional_var("NOTIFY_SMTP_PORT")
+                .unwrap_or_else(|| default_port.to_string())
+                .parse()
+                .expect("NOTIFY_SMTP_PORT must be a valid port number"),
+            security,
+            credentials: optional_var("NOTIFY_SMTP_USERNAME")
+                .map(|username| (username, env::var("NOTIFY_SMTP_PASSWORD").unwrap_or_default())),
+            from: optional_var("NOTIFY_EMAIL_FROM").expect("NOTIFY_EMAIL_FROM must be set with NOTIFY_SMTP_HOST"),
+            to,
+        }
+    });
+    let slack_webhook_url = optional_var("NOTIFY_SLACK_WEBHOOK_URL");
+    let webhook_url = optional_var("NOTIFY_WEBHOOK_URL");
+    if smtp.is_none() && slack_webhook_url.is_none() && webhook_url.is_none() {
+        return None;
+    }
+
+    let days: i64 = optional_var("NOTIFY_EXPIRY_WARNING_DAYS")
+        .unwrap_or_else(|| "14".to_string())
+        .parse()
+        .ok()
+        .filter(|days| *days > 0)
+        .expect("NOTIFY_EXPIRY_WARNING_DAYS must be a positive number");
+
+    Some(NotificationSettings {
+        smtp,
+        slack_webhook_url,
+        webhook_url,
+        warning_period: TimeDelta::days(days),
+    })
+}
+
+/// Returns the interval between expiry checks (`NOTIFY_INTERVAL_SECONDS`, default 3600).
+///
+/// # Panics
+///
+/// This function will panic if `NOTIFY_INTERVAL_SECONDS` is not a positive number.
+pub fn notification_interval() -> Duration {
+    dotenv().ok();
+
+    let seconds: u64 = env::var("NOTIFY_INTERVAL_SECONDS")
+        .unwrap_or_else(|_| "3600".to_string())
+        .parse()
+        .ok()
+        .filter(|seconds| *seconds > 0)
+        .expect("NOTIFY_INTERVAL_SECONDS must be a positive number");
+
+    Duration::from_secs(seconds)
+}
+
+/// Returns the expiry warnings due for a key.
+///
+/// # Arguments
+///
+/// * `jwk` - Stored key.
+/// * `now` - Current time; dates already passed are not warned about.
+/// * `warning_period` - How long before an expiry the warning is due.
+pub fn expiry_warnings(jwk: &JwkData, now: NaiveDateTime, warning_period: TimeDelta) -> Vec<Notification> {
+    // A certificate that cannot be decoded is not warned about
+    let certificate_not_after = key_details(jwk, now).ok().and_then(|details| details.certificate_not_after);
+    let expiries = [
+        (EVENT_KEY_EXPIRING, "public key", jwk.key_expires_at, "Tokens it signed will stop verifying"),
+        (EVENT_PRIVATE_KEY_EXPIRING, "private key", jwk.private_key_expires_at, "It will no longer sign"),
+        (EVENT_CERTIFICATE_EXPIRING, "certificate", certificate_not_after, "Relying parties checking x5c will reject it"),
+    ];
+
+    expiries
+        .into_iter()
+        .filter_map(|(event, what, expires_at, consequence)| {
+            let expires_at = expires_at.filter(|expires_at| *expires_at > now && *expires_at <= now + warning_period)?;
+            Some(Notification {
+                event: event.to_string(),
+                key_id: jwk.id,
+                kid: jwk.kid.clone(),
+                expires_at: Some(expires_at),
+                subject: format!("The {} of key {} expires on {} UTC", what, jwk.kid, expires_at.format("%Y-%m-%d %H:%M")),
+                message: format!(
+                    "The {} of the {} key {} ({}) expires on {} UTC. {}; rotate or extend the key before then.",
+                    what,
+                    jwk.alg,
+                    jwk.kid,
+                    jwk.id,
+                    expires_at.format("%Y-%m-%d %H:%M:%S"),
+                    consequence
+                ),
+            })
+        })
+        .collect()
+}
+
+/// Posts a JSON body to a webhook.
+fn post_json(url: &str, body: &serde_json::Value) -> Result<(), String> {
+    reqwest::blocking::Client::builder()
+        .timeout(TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?
+        .post(url)
+        .json(body)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Formats a notification as an email message (RFC 5322), with CRLF line endings and the lines
+/// starting with a dot escaped for the SMTP `DATA` command.
+pub fn format_email(from: &str, to: &[String], notification: &Notification) -> String {
+    let header = |value: &str| value.replace(['\r', '\n'], " ");
+    let body = notification
+        .message
+        .lines()
+        .map(|line| if line.starts_with('.') { format!(".{}", line) } else { line.to_string() })
+        .collect::<Vec<_>>()
+        .join("\r\n");
+
+    format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\n\
+         Content-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n{}\r\n",
+        header(from),
+        header(&to.join(", ")),
+        header(&notification.subject),
+        Utc::now().to_rfc2822(),
+        body
+    )
+}
+
+/// Connection to an SMTP server.
+enum SmtpStream {
+    Plain(TcpStream),
+    Tls(Box<SslStream<TcpStream>>),
+}
+
+impl Read for SmtpStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            SmtpStream::Plain(stream) => stream.read(buf),
+            SmtpStream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for SmtpStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            SmtpStream::Plain(stream) => stream.write(buf),
+            SmtpStream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            SmtpStream::Plain(stream) => stream.flush(),
+            SmtpStream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Reads an SMTP reply, following continuation lines, and checks its status class.
+///
+/// The reply is read byte by byte, so nothing after it is consumed before a `STARTTLS` upgrade.
+fn expect_reply(stream: &mut SmtpStream, expected: u16) -> Result<(), String> {
+    loop {
+        let mut line = Vec::new();
+        let mut byte = [0u8; 1];
+        while !line.ends_with(b"\n") {
+            match stream.read(&mut byte).map_err(|e| e.to_string())? {
+                0 => return Err("SMTP server closed the connection".to_string()),
+                _ => line.push(byte[0]),
+            }
+        }
+        let line = String::from_utf8_lossy(&line);
+        let code: u16 = line.get(..3).and_then(|code| code.parse().ok()).ok_or("Invalid SMTP reply")?;
+        if code / 100 != expected / 100 {
+            return Err(format!("SMTP server replied {}", line.trim_end()));
+        }
+        if line.as_bytes().get(3) != Some(&b'-') {
+            return Ok(());
+        }
+    }
+}
+
+/// Sends an SMTP command and checks the status class of the reply.
+fn command(stream: &mut SmtpStream, line: &str, expected: u16) -> Result<(), String> {
+    stream.write_all(format!("{}\r\n", line).as_bytes()).map_err(|e| e.to_string())?;
+    expect_reply(stream, expected)
+}
+
+/// Sends a notification by email.
+///
+/// # Errors
+///
+/// Returns a message if the server cannot be reached or rejects the message.
+pub fn send_email(settings: &SmtpSettings, notification: &Notification) -> Result<(), String> {
+    let tls = |stream: TcpStream| -> Result<SmtpStream, String> {
+        let connector = SslConnector::builder(SslMethod::tls_client()).map_err(|e| e.to_string())?.build();
+        let stream = connector.connect(&settings.host, stream).map_err(|e| e.to_string())?;
+        Ok(SmtpStream::Tls(Box::new(stream)))
+    };
+
+    let tcp = TcpStream::connect((settings.host.as_str(), settings.port)).map_err(|e| e.to_string())?;
+    tcp.set_read_timeout(Some(TIMEOUT)).map_err(|e| e.to_string())?;
+    tcp.set_write_timeout(Some(TIMEOUT)).map_err(|e| e.to_string())?;
+    let mut stream = match settings.security {
+        SmtpSecurity::Tls => tls(tcp)?,
+        _ => SmtpStream::Plain(tcp),
+    };
+
+    expect_reply(&mut stream, 220)?;
+    command(&mut stream, "EHLO jwks-service-app", 250)?;
+    if settings.security == SmtpSecurity::StartTls {
+        command(&mut stream, "STARTTLS", 220)?;
+        if let SmtpStream::Plain(tcp) = stream {
+            stream = tls(tcp)?;
+        }
+        command(&mut stream, "EHLO jwks-service-app", 250)?;
+    }
+    if let Some((username, password)) = &settings.credentials {
+        let token = base64::engine::general_purpose::STANDARD.encode(format!("\0{}\0{}", username, password));
+        command(&mut stream, &format!("AUTH PLAIN {}", token), 235)?;
+    }
+
+    command(&mut stream, &format!("MAIL FROM:<{}>", settings.from), 250)?;
+    for recipient in &settings.to {
+        command(&mut stream, &format!("RCPT TO:<{}>", recipient), 250)?;
+    }
+    command(&mut stream, "DATA", 354)?;
+    let message = format_email(&settings.from, &settings.to, notification);
+    stream.write_all(message.as_bytes()).map_err(|e| e.to_string())?;
+    command(&mut stream, ".", 250)?;
+    let _ = command(&mut stream, "QUIT", 221);
+
+    Ok(())
+}
+
+/// Sends a notification to every configured channel.
+///
+/// # Errors
+///
+/// Returns the failures of the channels that could not be notified; the others are notified.
+pub fn notify(settings: &NotificationSettings, notification: &Notification) -> Result<(), String> {
+    let mut errors = Vec::new();
+
+    if let Some(smtp) = &settings.smtp {
+        if let Err(error) = send_email(smtp, notification) {
+            errors.push(format!("email: {}", error));
+        }
+    }
+    if let Some(url) = &settings.slack_webhook_url {
+        let text = format!("*{}*\n{}", notification.subject, notification.message);
+        if let Err(error) = post_json(url, &json!({ "text": text })) {
+            errors.push(format!("Slack: {}", error));
+        }
+    }
+    if let Some(url) = &settings.webhook_url {
+        if let Err(error) = post_json(url, &json!(notification)) {
+            errors.push(format!("webhook: {}", error));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("; "))
+    }
+}
+
+/// Sends the expiry warnings that are due and have not been sent yet.
+///
+/// A warning is recorded once it was delivered to every channel; if a channel fails, the warning
+/// is sent again at the next run, so channels that succeeded may receive it twice.
+///
+/// # Errors
+///
+/// Returns a message if the keys cannot be loaded or a warning could not be sent.
+pub fn check_expiring_keys(connection: &mut PgConnection) -> Result<(), String> {
+    let Some(settings) = notification_settings() else {
+        return Ok(());
+    };
+    let now = Utc::now().naive_utc();
+
+    let keys = jwks::table
+        .filter(jwks::deleted_at.is_null())
+        .load::<JwkData>(connection)
+        .map_err(|e| e.to_string())?;
+    let sent = expiry_notifications::table
+        .select((expiry_notifications::key_id, expiry_notifications::event, expiry_notifications::expires_at))
+        .load::<(Uuid, String, NaiveDateTime)>(connection)
+        .map_err(|e| e.to_string())?;
+
+    let mut failures = 0;
+    for warning in keys.iter().flat_map(|jwk| expiry_warnings(jwk, now, settings.warning_period)) {
+        let Some(expires_at) = warning.expires_at else { continue };
+        if sent.contains(&(warning.key_id, warning.event.clone(), expires_at)) {
+            continue;
+        }
+
+        if let Err(error) = notify(&settings, &warning) {
+            log_error!("Failed to send {} notification for key {}: {}", warning.event, warning.key_id, error);
+            failures += 1;
+            continue;
+        }
+        diesel::insert_into(expiry_notifications::table)
+            .values((
+                expiry_notifications::key_id.eq(warning.key_id),
+                expiry_notifications::event.eq(&warning.event),
+                expiry_notifications::expires_at.eq(expires_at),
+                expiry_notifications::sent_at.eq(now),
+            ))
+            .on_conflict_do_nothing()
+            .execute(connection)
+            .map_err(|e| e.to_string())?;
+    }
+
+    if failures > 0 {
+        return Err(format!("{} expiry notifications could not be sent", failures));
+    }
+    Ok(())
+}
+
+/// Notifies the failed rotation of a key in the background, if notifications are configured.
+///
+/// # Arguments
+///
+/// * `key_id` - Identifier of the key that was to be rotated.
+/// * `kid` - Key ID of the key.
+/// * `reason` - Why the new version could not be created.
+pub fn notify_rotation_failure(key_id: Uuid, kid: &str, reason: &str) {
+    let Some(settings) = notification_settings() else {
+        return;
+    };
+    let notification = Notification {
+        event: EVENT_ROTATION_FAILED.to_string(),
+        key_id,
+        kid: kid.to_string(),
+        expires_at: None,
+        subject: format!("Rotation of key {} failed", kid),
+        message: format!(
+            "The rotation of key {} ({}) failed: {}. The key stays in use until it is rotated successfully.",
+            kid, key_id, reason
+        ),
+    };
+
+    std::thread::spawn(move || {
+        if let Err(error) = notify(&settings, &notification) {
+            log_error!("Failed to send rotation failure notification for key {}: {}", key_id, error);
+        }
+    });
+}
+
+#[test]
+fn test_expiry_warnings() {
+    let now = Utc::now().naive_utc();
+    let mut jwk = crate::crypto::generate_jwk_data("ES256", 2048).unwrap();
+    jwk.private_key_expires_at = Some(now + TimeDelta::days(3));
+    jwk.key_expires_at = Some(now + TimeDelta::days(30));
+
+    let warnings = expiry_warnings(&jwk, now, TimeDelta::days(14));
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].event, EVENT_PRIVATE_KEY_EXPIRING);
+    assert_eq!(warnings[0].expires_at, jwk.private_key_expires_at);
+    assert!(warnings[0].subject.contains(&jwk.kid));
+
+    // Passed dates are not warned about
+    jwk.private_key_expires_at = Some(now - TimeDelta::days(1));
+    assert!(expiry_warnings(&jwk, now, TimeDelta::days(14)).is_empty());
+    assert_eq!(expiry_warnings(&jwk, now, TimeDelta::days(31)).len(), 1);
+}
+
+#[test]
+fn test_send_email() {
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        let mut transcript = Vec::new();
+        writer.write_all(b"220 mail.example.com ESMTP\r\n").unwrap();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap() == 0 {
+                break;
+            }
+            let reply: &[u8] = match line.trim_end() {
+                "EHLO jwks-service-app" => b"250-mail.example.com\r\n250 AUTH PLAIN\r\n",
+                "DATA" => b"354 Go ahead\r\n",
+                "." => b"250 Queued\r\n",
+                "QUIT" => b"221 Bye\r\n",
+                command if command.starts_with("AUTH PLAIN ") => b"235 Authenticated\r\n",
+                command if command.starts_with("MAIL FROM:") || command.starts_with("RCPT TO:") => b"250 OK\r\n",
+                _ => b"",
+            };
+            writer.write_all(reply).unwrap();
+            transcript.push(line.trim_end().to_string());
+        }
+        transcript
+    });
+
+    let settings = SmtpSettings {
+        host: "127.0.0.1".to_string(),
+        port,
+        security: SmtpSecurity::None,
+        credentials: Some(("jwks".to_string(), "secret".to_string())),
+        from: "jwks@example.com".to_string(),
+        to: vec!["ops@example.com".to_string(), "sec@example.com".to_string()],
+    };
+    let notification = Notification {
+        event: EVENT_ROTATION_FAILED.to_string(),
+        key_id: Uuid::new_v4(),
+        kid: "kid-1".to_string(),
+        expires_at: None,
+        subject: "Rotation of key kid-1 failed".to_string(),
+        message: "First line\n.dot line".to_string(),
+    };
+    send_email(&settings, &notification).unwrap();
+
+    let transcript = server.join().unwrap();
+    assert_eq!(transcript[1], "AUTH PLAIN AGp3a3MAc2VjcmV0");
+    assert_eq!(transcript[2], "MAIL FROM:<jwks@example.com>");
+    assert_eq!(transcript[4], "RCPT TO:<sec@example.com>");
+    assert!(transcript.contains(&"Subject: Rotation of key kid-1 failed".to_string()));
+    assert!(transcript.contains(&"..dot line".to_string()));
+    assert_eq!(transcript.last().unwrap(), "QUIT");
+}
@@ -0,0 +1,96 @@
+//! This module paginates the admin listings.
+//!
+//! Listings return at most `limit` entries (default [`DEFAULT_PAGE_SIZE`], at most
+//! [`MAX_PAGE_SIZE`]) starting at `offset`. If more entries follow, the response carries a
+//! `Link` header (RFC 8288) with the URL of the next page.
+
+use actix_web::{HttpRequest, HttpResponse};
+use serde::Serialize;
+
+/// Number of entries per page if the request does not set `limit`.
+pub const DEFAULT_PAGE_SIZE: i64 = 100;
+
+/// Maximum number of entries per page.
+pub const MAX_PAGE_SIZE: i64 = 1000;
+
+/// Page of a listing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Page {
+    /// Maximum number of entries.
+    pub limit: i64,
+    /// Number of entries skipped.
+    pub offset: i64,
+}
+
+impl Page {
+    /// Parses the page requested by `limit` and `offset`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if `limit` is not between 1 and [`MAX_PAGE_SIZE`] or `offset` is
+    /// negative.
+    pub fn new(limit: Option<i64>, offset: Option<i64>) -> Result<Page, String> {
+        let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+            return Err(format!("Invalid limit {}, expected 1 to {}", limit, MAX_PAGE_SIZE));
+        }
+        let offset = offset.unwrap_or(0);
+        if offset < 0 {
+            return Err(format!("Invalid offset {}, expected 0 or more", offset));
+        }
+
+        Ok(Page { limit, offset })
+    }
+
+    /// Number of entries to load: one more than the page holds, to detect a next page.
+    pub fn fetch(&self) -> i64 {
+        self.limit + 1
+    }
+}
+
+/// Returns the URL of the page following `page`, keeping the other query parameters.
+pub fn next_page_url(req: &HttpRequest, page: &Page) -> String {
+    let mut parameters = req
+        .query_string()
+        .split('&')
+        .filter(|parameter| !parameter.is_empty())
+        .filter(|parameter| !parameter.starts_with("limit=") && !parameter.starts_with("offset="))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    parameters.push(format!("limit={}", page.limit));
+    parameters.push(format!("offset={}", page.offset + page.limit));
+
+    format!("{}?{}", req.path(), parameters.join("&"))
+}
+
+/// Responds with a page of a listing.
+///
+/// # Arguments
+///
+/// * `req` - The request; the next page link is derived from it.
+/// * `page` - The requested page.
+/// * `entries` - Up to [`Page::fetch`] entries starting at the page offset.
+pub fn page_response<T: Serialize>(req: &HttpRequest, page: &Page, mut entries: Vec<T>) -> HttpResponse {
+    let mut response = HttpResponse::Ok();
+    if entries.len() as i64 > page.limit {
+        entries.truncate(page.limit as usize);
+        response.insert_header(("Link", format!("<{}>; rel=\"next\"", next_page_url(req, page))));
+    }
+
+    response.json(entries)
+}
+
+#[test]
+fn test_page() {
+    use actix_web::test::TestRequest;
+
+    assert_eq!(Page::new(None, None), Ok(Page { limit: DEFAULT_PAGE_SIZE, offset: 0 }));
+    assert_eq!(Page::new(Some(10), Some(20)).map(|page| page.fetch()), Ok(11));
+    assert!(Page::new(Some(0), None).is_err());
+    assert!(Page::new(Some(MAX_PAGE_SIZE + 1), None).is_err());
+    assert!(Page::new(None, Some(-1)).is_err());
+
+    let req = TestRequest::get().uri("/jwks/expiring?within=2h&offset=10&limit=10").to_http_request();
+    let page = Page::new(Some(10), Some(10)).unwrap();
+    assert_eq!(next_page_url(&req, &page), "/jwks/expiring?within=2h&limit=10&offset=20");
+}
@@ -0,0 +1,563 @@
+//! This module contains deployment-level policies applied to key management requests.
+
+use crate::crypto::{curve_for_alg, oct_key_size, supported_algorithms};
+use crate::models::JwkData;
+use chrono::{NaiveDateTime, TimeDelta};
+use dotenv::dotenv;
+use serde_json::Value;
+use std::env;
+
+/// Largest RSA key size accepted regardless of configuration, to bound key generation time.
+pub const MAX_RSA_KEY_SIZE: u32 = 8192;
+
+/// Returns the algorithms permitted by the `ALLOWED_ALGORITHMS` environment variable.
+///
+/// # Returns
+///
+/// `None` if the variable is unset or empty (every supported algorithm is permitted),
+/// otherwise the list of permitted algorithm names.
+pub fn allowed_algorithms() -> Option<Vec<String>> {
+    dotenv().ok();
+
+    env::var("ALLOWED_ALGORITHMS")
+        .ok()
+        .and_then(|value| parse_algorithm_list(&value))
+}
+
+/// Returns the algorithms of the keys generated when the service starts with an empty key table
+/// (`BOOTSTRAP_ALGS`, e.g. `RS256,ES256`).
+///
+/// # Returns
+///
+/// An empty list if the variable is unset or empty (no keys are generated at startup).
+///
+/// # Panics
+///
+/// This function will panic if an algorithm is not supported.
+pub fn bootstrap_algorithms() -> Vec<String> {
+    dotenv().ok();
+
+    let algorithms = env::var("BOOTSTRAP_ALGS")
+        .ok()
+        .and_then(|value| parse_algorithm_list(&value))
+        .unwrap_or_default();
+    for algorithm in &algorithms {
+        if !supported_algorithms().contains(&algorithm.as_str()) {
+            panic!("Unsupported algorithm {} in BOOTSTRAP_ALGS", algorithm);
+        }
+    }
+
+    algorithms
+}
+
+/// Parses a comma-separated list of algorithm names (e.g. `RS256, ES256`).
+///
+/// # Returns
+///
+/// `None` if the list contains no algorithm names.
+pub fn parse_algorithm_list(value: &str) -> Option<Vec<String>> {
+    let algorithms = value
+        .split(',')
+        .map(str::trim)
+        .filter(|alg| !alg.is_empty())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+
+    if algorithms.is_empty() {
+        None
+    } else {
+        Some(algorithms)
+    }
+}
+
+/// Checks whether the algorithm is permitted by the allowlist.
+///
+/// # Arguments
+///
+/// * `alg` - Algorithm name from the request (e.g., "RS256").
+/// * `allowed` - Permitted algorithms, or `None` if every algorithm is permitted.
+pub fn is_algorithm_allowed(alg: &str, allowed: Option<&[String]>) -> bool {
+    match allowed {
+        Some(algorithms) => algorithms.iter().any(|allowed_alg| allowed_alg == alg),
+        None => true,
+    }
+}
+
+/// Returns the RSA key size used when the request does not specify one (`RSA_KEY_SIZE`).
+///
+/// # Panics
+///
+/// This function will panic if `RSA_KEY_SIZE` is not a number.
+pub fn default_rsa_key_size() -> u32 {
+    dotenv().ok();
+
+    env::var("RSA_KEY_SIZE")
+        .unwrap_or_else(|_| "2048".to_string())
+        .parse()
+        .expect("RSA_KEY_SIZE must be a number")
+}
+
+/// Returns the minimum permitted RSA key size (`MIN_RSA_KEY_SIZE`).
+///
+/// # Panics
+///
+/// This function will panic if `MIN_RSA_KEY_SIZE` is not a number.
+pub fn min_rsa_key_size() -> u32 {
+    dotenv().ok();
+
+    env::var("MIN_RSA_KEY_SIZE")
+        .unwrap_or_else(|_| "2048".to_string())
+        .parse()
+        .expect("MIN_RSA_KEY_SIZE must be a number")
+}
+
+/// Returns the curves approved by the `APPROVED_CURVES` environment variable.
+///
+/// # Returns
+///
+/// `None` if the variable is unset or empty (every supported curve is approved),
+/// otherwise the list of approved curve names (e.g., `P-256`, `Ed25519`).
+pub fn approved_curves() -> Option<Vec<String>> {
+    dotenv().ok();
+
+    env::var("APPROVED_CURVES")
+        .ok()
+        .and_then(|value| parse_algorithm_list(&value))
+}
+
+/// Returns whether key creation returns an existing usable key with the same parameters instead
+/// of creating a new one when the request does not decide (`REUSE_ACTIVE_KEYS`, default `0`).
+pub fn reuse_active_keys() -> bool {
+    dotenv().ok();
+
+    env::var("REUSE_ACTIVE_KEYS").map(|value| value == "1").unwrap_or(false)
+}
+
+/// Checks that the key requested for the algorithm meets the minimum strength requirements.
+///
+/// # Arguments
+///
+/// * `alg` - Algorithm name from the request (e.g., "RS256").
+/// * `rsa_key_size` - RSA key size in bits; only used for RSA algorithms.
+/// * `min_rsa_key_size` - Minimum permitted RSA key size in bits.
+/// * `approved_curves` - Approved curves, or `None` if every curve is approved.
+///
+/// # Errors
+///
+/// Returns a message describing the violated requirement.
+pub fn check_key_strength(
+    alg: &str,
+    rsa_key_size: u32,
+    min_rsa_key_size: u32,
+    approved_curves: Option<&[String]>,
+) -> Result<(), String> {
+    if alg.starts_with("RS") {
+        if rsa_key_size < min_rsa_key_size {
+            return Err(format!(
+                "RSA key size must be at least {} bits",
+                min_rsa_key_size
+            ));
+        }
+        if rsa_key_size > MAX_RSA_KEY_SIZE {
+            return Err(format!(
+                "RSA key size must not exceed {} bits",
+                MAX_RSA_KEY_SIZE
+            ));
+        }
+    }
+
+    if let (Some(crv), Some(curves)) = (curve_for_alg(alg), approved_curves) {
+        if !curves.iter().any(|approved| approved == crv) {
+            return Err(format!("Curve {} is not approved by policy", crv));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that an HMAC secret is at least as long as the output of the algorithm's hash
+/// (RFC 7518, section 3.2).
+///
+/// # Arguments
+///
+/// * `alg` - HMAC algorithm (e.g., "HS256"); other algorithms are not checked.
+/// * `secret_len` - Length of the secret in bytes.
+///
+/// # Errors
+///
+/// Returns a message if the secret is too short.
+pub fn check_secret_length(alg: &str, secret_len: usize) -> Result<(), String> {
+    match oct_key_size(alg) {
+        Some(min_len) if secret_len < min_len => Err(format!(
+            "{} secrets must be at least {} bytes",
+            alg, min_len
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the lifetime of private keys (`PRIVATE_KEY_EXPIRATION_SECONDS`, default 1 day).
+///
+/// # Panics
+///
+/// This function will panic if `PRIVATE_KEY_EXPIRATION_SECONDS` is not a number.
+pub fn private_key_expiration_seconds() -> i64 {
+    dotenv().ok();
+
+    env::var("PRIVATE_KEY_EXPIRATION_SECONDS")
+        .unwrap_or_else(|_| "86400".to_string())
+        .parse()
+        .expect("PRIVATE_KEY_EXPIRATION_SECONDS must be a number")
+}
+
+/// Returns the longest total extension of a private key beyond its regular lifetime
+/// (`MAX_PRIVATE_KEY_EXTENSION_SECONDS`, default 1 day).
+///
+/// # Panics
+///
+/// This function will panic if `MAX_PRIVATE_KEY_EXTENSION_SECONDS` is not a number.
+pub fn max_private_key_extension_seconds() -> i64 {
+    dotenv().ok();
+
+    env::var("MAX_PRIVATE_KEY_EXTENSION_SECONDS")
+        .unwrap_or_else(|_| "86400".to_string())
+        .parse()
+        .expect("MAX_PRIVATE_KEY_EXTENSION_SECONDS must be a number")
+}
+
+/// Returns how long keys stay published after their private key expired
+/// (`KEY_EXPIRATION_SECONDS`, default 2 days).
+///
+/// # Panics
+///
+/// This function will panic if `KEY_EXPIRATION_SECONDS` is not a number.
+pub fn key_expiration_seconds() -> i64 {
+    dotenv().ok();
+
+    env::var("KEY_EXPIRATION_SECONDS")
+        .unwrap_or_else(|_| "172800".to_string())
+        .parse()
+        .expect("KEY_EXPIRATION_SECONDS must be a number")
+}
+
+/// Purposes a key can be dedicated to, each with its own rotation policy.
+pub const KEY_PURPOSES: [&str; 6] = ["access-token", "refresh-token", "id-token", "webhook-signing", "ssh", "ssh-ca"];
+
+/// Purpose of the keys used for SSH authentication, exported in `authorized_keys` format.
+pub const SSH_PURPOSE: &str = "ssh";
+
+/// Purpose of the keys signing SSH certificates.
+pub const SSH_CA_PURPOSE: &str = "ssh-ca";
+
+/// Checks that a key purpose is one of [`KEY_PURPOSES`].
+///
+/// # Errors
+///
+/// Returns a message listing the known purposes.
+pub fn check_key_purpose(purpose: &str) -> Result<(), String> {
+    if KEY_PURPOSES.contains(&purpose) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Unknown key purpose {}, expected one of: {}",
+            purpose,
+            KEY_PURPOSES.join(", ")
+        ))
+    }
+}
+
+/// Checks that a key of an algorithm can serve a purpose: SSH CA keys must be Ed25519 keys, and
+/// SSH keys Ed25519 or RSA keys.
+///
+/// # Errors
+///
+/// Returns a message naming the required algorithms.
+pub fn check_purpose_algorithm(purpose: &str, algorithm: &str) -> Result<(), String> {
+    if purpose == SSH_CA_PURPOSE && algorithm != "Ed25519" {
+        return Err(format!("Keys of purpose {} must be Ed25519 keys", purpose));
+    }
+    if purpose == SSH_PURPOSE && !matches!(algorithm, "Ed25519" | "RS256" | "RS384" | "RS512") {
+        return Err(format!("Keys of purpose {} must be Ed25519 or RSA keys", purpose));
+    }
+    Ok(())
+}
+
+/// Returns the name of the environment variable overriding a lifetime setting for a purpose or an
+/// algorithm (e.g., `ACCESS_TOKEN_PRIVATE_KEY_EXPIRATION_SECONDS`, `ES256_KEY_EXPIRATION_SECONDS`).
+/// Characters other than letters and digits become underscores (e.g.,
+/// `ECDH_ES_A128KW_KEY_EXPIRATION_SECONDS`).
+pub fn purpose_variable(purpose: &str, setting: &str) -> String {
+    let name = purpose.to_uppercase().replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+    format!("{}_{}", name, setting)
+}
+
+/// Returns the lifetimes of keys of an algorithm and purpose: the lifetime of the private key and
+/// how long the key stays published afterwards.
+///
+/// Each purpose and algorithm rotates independently: `<PURPOSE>_PRIVATE_KEY_EXPIRATION_SECONDS`
+/// and `<PURPOSE>_KEY_EXPIRATION_SECONDS` override the settings for keys of that purpose, then
+/// `<ALG>_PRIVATE_KEY_EXPIRATION_SECONDS` and `<ALG>_KEY_EXPIRATION_SECONDS` (e.g.,
+/// `EDDSA_KEY_EXPIRATION_SECONDS`) for keys of that algorithm, and otherwise
+/// [`private_key_expiration_seconds`] and [`key_expiration_seconds`] apply.
+///
+/// # Arguments
+///
+/// * `algorithm` - JWA algorithm of the key (e.g., "EdDSA").
+/// * `purpose` - Purpose of the key, if any.
+///
+/// # Panics
+///
+/// This function will panic if an override is not a number.
+pub fn key_lifetimes(algorithm: &str, purpose: Option<&str>) -> (i64, i64) {
+    dotenv().ok();
+
+    let setting = |name: &str, default: fn() -> i64| {
+        let variables = purpose
+            .map(|purpose| purpose_variable(purpose, name))
+            .into_iter()
+            .chain([purpose_variable(algorithm, name)]);
+        for variable in variables {
+            if let Ok(value) = env::var(&variable) {
+                return value
+                    .parse()
+                    .unwrap_or_else(|_| panic!("{} must be a number", variable));
+            }
+        }
+        default()
+    };
+
+    (
+        setting("PRIVATE_KEY_EXPIRATION_SECONDS", private_key_expiration_seconds),
+        setting("KEY_EXPIRATION_SECONDS", key_expiration_seconds),
+    )
+}
+
+/// Computes the expiration dates of a key whose private key is extended.
+///
+/// The private key expiration is pushed out by `seconds`, but never more than `max_seconds`
+/// beyond the regular lifetime of the private key, so repeated extensions stay bounded. The key
+/// expiration is pushed out by the same amount, so tokens signed until the new private key
+/// expiration stay verifiable for as long as before.
+///
+/// # Arguments
+///
+/// * `jwk` - Key to extend.
+/// * `seconds` - Requested extension in seconds.
+/// * `lifetime_seconds` - Regular lifetime of private keys in seconds.
+/// * `max_seconds` - Longest total extension beyond the regular lifetime in seconds.
+/// * `now` - Current time.
+///
+/// # Returns
+///
+/// The new private key and key expiration dates.
+///
+/// # Errors
+///
+/// Returns a message if the extension is not positive, the private key has already expired or
+/// the new expiration would exceed the bound.
+pub fn extend_private_key_expiration(
+    jwk: &JwkData,
+    seconds: i64,
+    lifetime_seconds: i64,
+    max_seconds: i64,
+    now: NaiveDateTime,
+) -> Result<(NaiveDateTime, Option<NaiveDateTime>), String> {
+    if seconds <= 0 {
+        return Err("Extension must be a positive number of seconds".to_string());
+    }
+    let current = match jwk.private_key_expires_at {
+        Some(current) if current > now => current,
+        _ => return Err("Private key has already expired".to_string()),
+    };
+
+    let extension = TimeDelta::try_seconds(seconds)
+        .ok_or_else(|| "Extension is too long".to_string())?;
+    let extended = current
+        .checked_add_signed(extension)
+        .ok_or_else(|| "Extension is too long".to_string())?;
+    let bound = lifetime_seconds
+        .checked_add(max_seconds)
+        .and_then(TimeDelta::try_seconds)
+        .and_then(|bound| jwk.created_at.checked_add_signed(bound))
+        .unwrap_or(jwk.created_at);
+    if extended > bound {
+        return Err(format!(
+            "Private key must not be extended more than {} seconds beyond its lifetime",
+            max_seconds
+        ));
+    }
+
+    let key_expires = jwk
+        .key_expires_at
+        .map(|key_expires| key_expires.checked_add_signed(extension).unwrap_or(key_expires));
+
+    Ok((extended, key_expires))
+}
+
+#[test]
+fn test_parse_algorithm_list() {
+    assert_eq!(
+        parse_algorithm_list("RS256, ES256,,EdDSA "),
+        Some(vec!["RS256".to_string(), "ES256".to_string(), "EdDSA".to_string()])
+    );
+    assert_eq!(parse_algorithm_list(" , "), None);
+}
+
+#[test]
+fn test_is_algorithm_allowed() {
+    let allowed = vec!["RS256".to_string(), "ES256".to_string()];
+
+    assert!(is_algorithm_allowed("RS256", Some(&allowed)));
+    assert!(!is_algorithm_allowed("Ed448", Some(&allowed)));
+    assert!(!is_algorithm_allowed("ES512", Some(&allowed)));
+    assert!(is_algorithm_allowed("Ed448", None));
+}
+
+#[test]
+fn test_check_key_strength() {
+    let curves = vec!["P-256".to_string(), "Ed25519".to_string()];
+
+    assert!(check_key_strength("RS256", 2048, 2048, None).is_ok());
+    assert!(check_key_strength("RS256", 1024, 2048, None).is_err());
+    assert!(check_key_strength("RS512", 3072, 4096, None).is_err());
+    assert!(check_key_strength("RS256", 16384, 2048, None).is_err());
+    assert!(check_key_strength("ES256", 0, 2048, Some(&curves)).is_ok());
+    assert!(check_key_strength("ES512", 0, 2048, Some(&curves)).is_err());
+    assert!(check_key_strength("Ed448", 0, 2048, Some(&curves)).is_err());
+    assert!(check_key_strength("Ed448", 0, 2048, None).is_ok());
+}
+
+#[test]
+fn test_check_secret_length() {
+    assert!(check_secret_length("HS256", 32).is_ok());
+    assert!(check_secret_length("HS256", 31).is_err());
+    assert!(check_secret_length("HS384", 32).is_err());
+    assert!(check_secret_length("HS512", 64).is_ok());
+    assert!(check_secret_length("ES256", 0).is_ok());
+}
+
+/// Checks that the claims satisfy the issuer and audience constraints of the signing key.
+///
+/// # Arguments
+///
+/// * `jwk` - Key used for signing.
+/// * `claims` - JWT claims set to sign.
+///
+/// # Errors
+///
+/// Returns a message describing the violated constraint. A constrained claim that is missing
+/// from the claims set violates the constraint.
+pub fn check_token_constraints(jwk: &JwkData, claims: &Value) -> Result<(), String> {
+    if let Some(issuers) = &jwk.allowed_issuers {
+        match claims.get("iss").and_then(Value::as_str) {
+            Some(iss) if issuers.iter().any(|allowed| allowed == iss) => {}
+            Some(iss) => return Err(format!("Issuer {} is not allowed for this key", iss)),
+            None => return Err("Claim iss is required for this key".to_string()),
+        }
+    }
+
+    if let Some(audiences) = &jwk.allowed_audiences {
+        let requested = match claims.get("aud") {
+            Some(Value::String(aud)) => vec![aud.as_str()],
+            Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if requested.is_empty() {
+            return Err("Claim aud is required for this key".to_string());
+        }
+        if let Some(aud) = requested
+            .iter()
+            .find(|aud| !audiences.iter().any(|allowed| allowed == *aud))
+        {
+            return Err(format!("Audience {} is not allowed for this key", aud));
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_check_key_purpose() {
+    for purpose in KEY_PURPOSES {
+        assert!(check_key_purpose(purpose).is_ok());
+    }
+    assert!(check_key_purpose("session").is_err());
+    assert!(check_key_purpose("Access-Token").is_err());
+    assert!(check_purpose_algorithm(SSH_CA_PURPOSE, "Ed25519").is_ok());
+    assert!(check_purpose_algorithm(SSH_CA_PURPOSE, "ES256").is_err());
+    assert!(check_purpose_algorithm(SSH_PURPOSE, "RS256").is_ok());
+    assert!(check_purpose_algorithm(SSH_PURPOSE, "ES256").is_err());
+    assert!(check_purpose_algorithm("access-token", "ES256").is_ok());
+
+    assert_eq!(
+        purpose_variable("webhook-signing", "KEY_EXPIRATION_SECONDS"),
+        "WEBHOOK_SIGNING_KEY_EXPIRATION_SECONDS"
+    );
+    assert_eq!(purpose_variable("ML-DSA-65", "KEY_EXPIRATION_SECONDS"), "ML_DSA_65_KEY_EXPIRATION_SECONDS");
+    assert_eq!(
+        purpose_variable("ECDH-ES+A128KW", "KEY_EXPIRATION_SECONDS"),
+        "ECDH_ES_A128KW_KEY_EXPIRATION_SECONDS"
+    );
+}
+
+#[test]
+fn test_key_lifetimes() {
+    let defaults = (private_key_expiration_seconds(), key_expiration_seconds());
+    assert_eq!(key_lifetimes("TEST-LIFETIME", None), defaults);
+
+    // Algorithm overrides apply to every purpose without its own override
+    env::set_var("TEST_LIFETIME_PRIVATE_KEY_EXPIRATION_SECONDS", "600");
+    env::set_var("TEST_LIFETIME_PURPOSE_KEY_EXPIRATION_SECONDS", "60");
+    assert_eq!(key_lifetimes("TEST-LIFETIME", None), (600, defaults.1));
+    assert_eq!(key_lifetimes("TEST-LIFETIME", Some("test-lifetime-purpose")), (600, 60));
+    assert_eq!(key_lifetimes("TEST-OTHER", Some("test-lifetime-purpose")), (defaults.0, 60));
+}
+
+#[test]
+fn test_check_token_constraints() {
+    use serde_json::json;
+
+    let unconstrained = JwkData::default();
+    assert!(check_token_constraints(&unconstrained, &json!({})).is_ok());
+
+    let jwk = JwkData {
+        allowed_issuers: Some(vec!["https://auth.example.com".to_string()]),
+        allowed_audiences: Some(vec!["a".to_string(), "b".to_string()]),
+        ..Default::default()
+    };
+    let iss = "https://auth.example.com";
+
+    assert!(check_token_constraints(&jwk, &json!({"iss": iss, "aud": "a"})).is_ok());
+    assert!(check_token_constraints(&jwk, &json!({"iss": iss, "aud": ["a", "b"]})).is_ok());
+    assert!(check_token_constraints(&jwk, &json!({"iss": iss, "aud": ["a", "c"]})).is_err());
+    assert!(check_token_constraints(&jwk, &json!({"iss": "https://evil", "aud": "a"})).is_err());
+    assert!(check_token_constraints(&jwk, &json!({"aud": "a"})).is_err());
+    assert!(check_token_constraints(&jwk, &json!({"iss": iss})).is_err());
+    assert!(check_token_constraints(&jwk, &json!({"iss": iss, "aud": []})).is_err());
+}
+
+#[test]
+fn test_extend_private_key_expiration() {
+    use chrono::NaiveDate;
+
+    let now = NaiveDate::from_ymd_opt(2026, 10, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+    let hours = |hours: i64| now + TimeDelta::hours(hours);
+    let day = 86400;
+    let jwk = JwkData {
+        created_at: hours(-22),
+        private_key_expires_at: Some(hours(2)),
+        key_expires_at: Some(hours(50)),
+        ..Default::default()
+    };
+
+    assert_eq!(
+        extend_private_key_expiration(&jwk, 3600, day, day, now),
+        Ok((hours(3), Some(hours(51))))
+    );
+    assert!(extend_private_key_expiration(&jwk, day, day, day, now).is_ok());
+    assert!(extend_private_key_expiration(&jwk, day + 1, day, day, now).is_err());
+    assert!(extend_private_key_expiration(&jwk, 0, day, day, now).is_err());
+
+    let expired = JwkData { private_key_expires_at: Some(hours(-1)), ..jwk };
+    assert!(extend_private_key_expiration(&expired, 3600, day, day, now).is_err());
+}
@@ -0,0 +1,297 @@
+//! This module provides experimental post-quantum key generation.
+//!
+//! Keys are represented as Algorithm Key Pairs (`kty: "AKP"`) following the JOSE drafts for
+//! ML-DSA (draft-ietf-cose-dilithium) and ML-KEM (draft-ietf-jose-pqc-kem): the public key is
+//! published in the `pub` parameter and the private key is stored as the seed the key pair is
+//! derived from.
+//!
+//! ML-DSA signing keys are gated behind the `ml-dsa` cargo feature, ML-KEM encryption keys behind
+//! the `ml-kem` cargo feature.
+
+use std::error::Error;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+#[cfg(feature = "ml-dsa")]
+use ml_dsa::{Keypair, MlDsa44, MlDsa65, MlDsa87, MlDsaParams, Seed, SigningKey};
+use uuid::Uuid;
+use crate::models::JwkData;
+
+/// Generates an ML-DSA key pair and associated JWK data.
+///
+/// # Arguments
+///
+/// * `alg` - Signing algorithm to use. Supported values:
+///   - "ML-DSA-44"
+///   - "ML-DSA-65"
+///   - "ML-DSA-87"
+///
+/// # Returns
+///
+/// Returns a [`JwkData`] structure containing:
+/// - Public key (pub) in Base64URL format
+/// - Private key seed in Base64URL format
+/// - Generated key ID (kid)
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - Unsupported algorithm is specified
+/// - OpenSSL fails to provide random bytes for the seed
+#[cfg(feature = "ml-dsa")]
+pub fn generate_ml_dsa_jwk_data(alg: &str) -> Result<JwkData, Box<dyn Error>> {
+    let mut seed_bytes = [0u8; 32];
+    openssl::rand::rand_bytes(&mut seed_bytes)?;
+    let seed = Seed::from(seed_bytes);
+
+    let public_key = match alg {
+        "ML-DSA-44" => { encode_ml_dsa_public_key::<MlDsa44>(&seed) }
+        "ML-DSA-65" => { encode_ml_dsa_public_key::<MlDsa65>(&seed) }
+        "ML-DSA-87" => { encode_ml_dsa_public_key::<MlDsa87>(&seed) }
+        _ => { return Err(Box::from("Unsupported algorithm")) }
+    };
+
+    let kid = Uuid::new_v4().to_string();
+
+    Ok(JwkData {
+        kty: "AKP".to_string(),
+        alg: alg.to_string(),
+        kid,
+        crv: None,
+        x: None,
+        y: None,
+        n: None,
+        e: None,
+        x5c: None,
+        x5t: None,
+        private_key: URL_SAFE_NO_PAD.encode(seed_bytes),
+        pub_: Some(public_key),
+        ..Default::default()
+    })
+}
+
+/// Derives the ML-DSA key pair from `seed` and returns its Base64URL encoded public key.
+#[cfg(feature = "ml-dsa")]
+fn encode_ml_dsa_public_key<P: MlDsaParams>(seed: &Seed) -> String {
+    let signing_key = SigningKey::<P>::from_seed(seed);
+    URL_SAFE_NO_PAD.encode(signing_key.verifying_key().encode())
+}
+
+/// Signs `data` with an ML-DSA private key seed using an empty context string.
+///
+/// # Arguments
+///
+/// * `alg` - ML-DSA parameter set ("ML-DSA-44", "ML-DSA-65" or "ML-DSA-87").
+/// * `private_key` - Base64URL encoded 32-byte private key seed.
+/// * `data` - Data to sign.
+///
+/// # Errors
+///
+/// Returns an error if the algorithm is unsupported or the seed is malformed.
+#[cfg(feature = "ml-dsa")]
+pub fn sign_ml_dsa(alg: &str, private_key: &str, data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let seed_bytes: [u8; 32] = URL_SAFE_NO_PAD
+        .decode(private_key)?
+        .try_into()
+        .map_err(|_| "Invalid ML-DSA private key seed")?;
+    let seed = Seed::from(seed_bytes);
+
+    match alg {
+        "ML-DSA-44" => Ok(sign_ml_dsa_with::<MlDsa44>(&seed, data)),
+        "ML-DSA-65" => Ok(sign_ml_dsa_with::<MlDsa65>(&seed, data)),
+        "ML-DSA-87" => Ok(sign_ml_dsa_with::<MlDsa87>(&seed, data)),
+        _ => Err(Box::from("Unsupported algorithm")),
+    }
+}
+
+/// Verifies an ML-DSA `signature` over `data` with an empty context string.
+///
+/// # Arguments
+///
+/// * `alg` - ML-DSA parameter set ("ML-DSA-44", "ML-DSA-65" or "ML-DSA-87").
+/// * `public_key` - Base64URL encoded public key (the JWK `pub` parameter).
+/// * `data` - Signed data.
+/// * `signature` - Signature to verify.
+///
+/// # Errors
+///
+/// Returns an error if the algorithm is unsupported or the public key is malformed.
+#[cfg(feature = "ml-dsa")]
+pub fn verify_ml_dsa(
+    alg: &str,
+    public_key: &str,
+    data: &[u8],
+    signature: &[u8],
+) -> Result<bool, Box<dyn Error>> {
+    let public_key = URL_SAFE_NO_PAD.decode(public_key)?;
+
+    match alg {
+        "ML-DSA-44" => verify_ml_dsa_with::<MlDsa44>(&public_key, data, signature),
+        "ML-DSA-65" => verify_ml_dsa_with::<MlDsa65>(&public_key, data, signature),
+        "ML-DSA-87" => verify_ml_dsa_with::<MlDsa87>(&public_key, data, signature),
+        _ => Err(Box::from("Unsupported algorithm")),
+    }
+}
+
+#[cfg(feature = "ml-dsa")]
+fn sign_ml_dsa_with<P: MlDsaParams>(seed: &Seed, data: &[u8]) -> Vec<u8> {
+    use ml_dsa::Signer;
+
+    let signing_key = SigningKey::<P>::from_seed(seed);
+    signing_key.sign(data).encode().to_vec()
+}
+
+#[cfg(feature = "ml-dsa")]
+fn verify_ml_dsa_with<P: MlDsaParams>(
+    public_key: &[u8],
+    data: &[u8],
+    signature: &[u8],
+) -> Result<bool, Box<dyn Error>> {
+    use ml_dsa::{EncodedVerifyingKey, Signature, Verifier, VerifyingKey};
+
+    let encoded = EncodedVerifyingKey::<P>::try_from(public_key)
+        .map_err(|_| "Invalid ML-DSA public key")?;
+    let verifying_key = VerifyingKey::<P>::decode(&encoded);
+
+    match Signature::<P>::try_from(signature) {
+        Ok(signature) => Ok(verifying_key.verify(data, &signature).is_ok()),
+        Err(_) => Ok(false),
+    }
+}
+
+#[cfg(all(test, feature = "ml-dsa"))]
+fn assert_ml_dsa_key_valid<P: MlDsaParams>(alg: &str) {
+    use ml_dsa::{EncodedVerifyingKey, Signer, Verifier, VerifyingKey};
+
+    let jwk: JwkData = generate_ml_dsa_jwk_data(alg).unwrap();
+
+    let control_data = "CONTROL_TEXT";
+
+    let seed_bytes: [u8; 32] = URL_SAFE_NO_PAD.decode(jwk.private_key).unwrap().try_into().unwrap();
+    let signing_key = SigningKey::<P>::from_seed(&Seed::from(seed_bytes));
+    let signature = signing_key.sign(control_data.as_bytes());
+
+    let pub_bytes = URL_SAFE_NO_PAD.decode(jwk.pub_.unwrap()).unwrap();
+    let encoded = EncodedVerifyingKey::<P>::try_from(pub_bytes.as_slice()).unwrap();
+    let verifying_key = VerifyingKey::<P>::decode(&encoded);
+
+    assert_eq!(jwk.kty, "AKP");
+    assert!(verifying_key.verify(control_data.as_bytes(), &signature).is_ok());
+}
+
+#[cfg(feature = "ml-dsa")]
+#[test]
+fn test_is_ml_dsa_key_valid_ml_dsa_44() {
+    assert_ml_dsa_key_valid::<MlDsa44>("ML-DSA-44");
+}
+
+#[cfg(feature = "ml-dsa")]
+#[test]
+fn test_is_ml_dsa_key_valid_ml_dsa_65() {
+    assert_ml_dsa_key_valid::<MlDsa65>("ML-DSA-65");
+}
+
+#[cfg(feature = "ml-dsa")]
+#[test]
+fn test_is_ml_dsa_key_valid_ml_dsa_87() {
+    assert_ml_dsa_key_valid::<MlDsa87>("ML-DSA-87");
+}
+
+/// Generates an ML-KEM key pair and associated JWK data.
+///
+/// # Arguments
+///
+/// * `alg` - Key encapsulation algorithm to use. Supported values:
+///   - "ML-KEM-512"
+///   - "ML-KEM-768"
+///   - "ML-KEM-1024"
+///
+/// # Returns
+///
+/// Returns a [`JwkData`] structure containing:
+/// - Public encapsulation key (pub) in Base64URL format
+/// - Private decapsulation key seed in Base64URL format
+/// - Generated key ID (kid)
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - Unsupported algorithm is specified
+/// - OpenSSL fails to provide random bytes for the seed
+#[cfg(feature = "ml-kem")]
+pub fn generate_ml_kem_jwk_data(alg: &str) -> Result<JwkData, Box<dyn Error>> {
+    use ml_kem::{ml_kem_1024, ml_kem_512, ml_kem_768, KeyExport};
+
+    let mut seed_bytes = [0u8; 64];
+    openssl::rand::rand_bytes(&mut seed_bytes)?;
+    let seed = ml_kem::Seed::from(seed_bytes);
+
+    let public_key = match alg {
+        "ML-KEM-512" => {
+            let dk = ml_kem_512::DecapsulationKey::from_seed(seed);
+            URL_SAFE_NO_PAD.encode(dk.encapsulation_key().to_bytes())
+        }
+        "ML-KEM-768" => {
+            let dk = ml_kem_768::DecapsulationKey::from_seed(seed);
+            URL_SAFE_NO_PAD.encode(dk.encapsulation_key().to_bytes())
+        }
+        "ML-KEM-1024" => {
+            let dk = ml_kem_1024::DecapsulationKey::from_seed(seed);
+            URL_SAFE_NO_PAD.encode(dk.encapsulation_key().to_bytes())
+        }
+        _ => { return Err(Box::from("Unsupported algorithm")) }
+    };
+
+    let kid = Uuid::new_v4().to_string();
+
+    Ok(JwkData {
+        kty: "AKP".to_string(),
+        alg: alg.to_string(),
+        kid,
+        crv: None,
+        x: None,
+        y: None,
+        n: None,
+        e: None,
+        x5c: None,
+        x5t: None,
+        private_key: URL_SAFE_NO_PAD.encode(seed_bytes),
+        pub_: Some(public_key),
+        ..Default::default()
+    })
+}
+
+#[cfg(feature = "ml-kem")]
+#[test]
+fn test_is_ml_kem_key_valid_ml_kem_768() {
+    use ml_kem::{ml_kem_768, Decapsulate, Key};
+
+    let jwk: JwkData = generate_ml_kem_jwk_data("ML-KEM-768").unwrap();
+
+    let seed_bytes: [u8; 64] = URL_SAFE_NO_PAD.decode(jwk.private_key).unwrap().try_into().unwrap();
+    let dk = ml_kem_768::DecapsulationKey::from_seed(ml_kem::Seed::from(seed_bytes));
+
+    let pub_bytes = URL_SAFE_NO_PAD.decode(jwk.pub_.unwrap()).unwrap();
+    let ek_bytes = Key::<ml_kem_768::EncapsulationKey>::try_from(pub_bytes.as_slice()).unwrap();
+    let ek = ml_kem_768::EncapsulationKey::new(&ek_bytes).unwrap();
+
+    let mut m = [0u8; 32];
+    openssl::rand::rand_bytes(&mut m).unwrap();
+    let (ciphertext, shared_key) = ek.encapsulate_deterministic(&m.into());
+
+    assert_eq!(jwk.kty, "AKP");
+    assert_eq!(dk.decapsulate(&ciphertext), shared_key);
+}
+
+#[cfg(feature = "ml-dsa")]
+#[test]
+fn test_sign_and_verify_with_jwk_ml_dsa_65() {
+    use crate::crypto::{sign_with_jwk, verify_with_jwk};
+    use crate::models::Jwk;
+
+    let jwk: JwkData = generate_ml_dsa_jwk_data("ML-DSA-65").unwrap();
+
+    let signature = sign_with_jwk(&jwk, b"CONTROL_TEXT").unwrap();
+    let public_jwk = Jwk::from(jwk);
+
+    assert!(verify_with_jwk(&public_jwk, b"CONTROL_TEXT", &signature).unwrap());
+    assert!(!verify_with_jwk(&public_jwk, b"OTHER_TEXT", &signature).unwrap());
+}
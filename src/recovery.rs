@@ -0,0 +1,254 @@
+//! This module provides point-in-time recovery of the keystore with the `restore` command.
+//!
+//! Every mutation of the `jwks` table is recorded by a database trigger in the append-only
+//! `key_operations` log, together with the key row after the mutation. Replaying the log up to
+//! a point in time reconstructs the keystore as it was then, e.g. to undo an accidental bulk
+//! delete without restoring a database backup.
+//!
+//! Private keys are only logged encrypted (see [`crate::key_encryption`]), so keys removed from
+//! the table whose logged record has no private key cannot be brought back; they are reported
+//! instead.
+
+use crate::db::establish_connection;
+use crate::models::parse_timestamp;
+use crate::schema::key_operations;
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use diesel::sql_query;
+use diesel::sql_types::{Timestamp, Uuid as SqlUuid};
+use std::error::Error;
+use uuid::Uuid;
+
+/// Usage of the `restore` command.
+pub const RESTORE_USAGE: &str = "\
+Usage: jwks-service-app restore --at <TIME> [OPTIONS]
+
+Options:
+  --at <TIME>          Point in time to restore (RFC 3339 or Unix timestamp)
+  --dry-run            Report the changes without applying them
+  -h, --help           Print this help";
+
+/// Latest logged state of every key at the restore point (`$1`).
+const KEYS_AT_RESTORE_POINT: &str = "\
+WITH restore_point AS (
+  SELECT DISTINCT ON (key_id) key_id, record
+  FROM key_operations
+  WHERE occurred_at <= $1
+  ORDER BY key_id, seq DESC
+)";
+
+/// Options of the `restore` command.
+#[derive(Debug, PartialEq)]
+pub struct RestoreOptions {
+    /// Point in time the keystore is restored to (UTC).
+    pub at: NaiveDateTime,
+    /// Whether the changes are only reported.
+    pub dry_run: bool,
+}
+
+/// Changes made by a restore.
+#[derive(Debug, Default, PartialEq)]
+pub struct RestoreSummary {
+    /// Keys brought back to their state at the restore point (e.g. undeleted).
+    pub restored: usize,
+    /// Keys deleted because they did not exist at the restore point.
+    pub removed: usize,
+    /// Keys missing from the table that are not restored, because their logged record has no
+    /// private key (it was stored unencrypted).
+    pub unrecoverable: Vec<Uuid>,
+}
+
+/// Result row of the query of the keys that cannot be restored.
+#[derive(QueryableByName)]
+struct UnrecoverableKey {
+    #[diesel(sql_type = SqlUuid)]
+    key_id: Uuid,
+}
+
+impl RestoreOptions {
+    /// Parses the arguments following the `restore` command.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message describing the invalid argument. Help requests return the usage.
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut at = None;
+        let mut dry_run = false;
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--at" => {
+                    let value = args.next().ok_or("Missing value for --at".to_string())?;
+                    at = Some(parse_timestamp(value).ok_or_else(|| {
+                        format!("Invalid --at {}, expected an RFC 3339 or Unix timestamp", value)
+                    })?);
+                }
+                "--dry-run" => dry_run = true,
+                "-h" | "--help" => return Err(RESTORE_USAGE.to_string()),
+                _ => return Err(format!("Unknown argument {}\n\n{}", arg, RESTORE_USAGE)),
+            }
+        }
+
+        match at {
+            Some(at) => Ok(RestoreOptions { at, dry_run }),
+            None => Err(format!("--at is required\n\n{}", RESTORE_USAGE)),
+        }
+    }
+}
+
+/// Restores the keystore to its state at a point in time.
+///
+/// Keys are brought back to their logged lifecycle state (deletion, expiration and token
+/// constraints; key material never changes) and keys created later are soft deleted. Keys are
+/// never removed from the table, and the restore itself is recorded in the log, so it can be
+/// undone by restoring to a point in time before it. Primary key designations are not
+/// restored. Keys missing from the table are only inserted again if their logged record holds
+/// the private key; the others are reported as unrecoverable.
+///
+/// The caller is responsible for running the restore in a transaction.
+///
+/// # Arguments
+///
+/// * `connection` - Database connection.
+/// * `at` - Point in time to restore (UTC).
+/// * `now` - Deletion time recorded for removed keys.
+///
+/// # Errors
+///
+/// Returns an error if the log cannot be replayed.
+pub fn restore_keystore(
+    connection: &mut PgConnection,
+    at: NaiveDateTime,
+    now: NaiveDateTime,
+) -> QueryResult<RestoreSummary> {
+    let removed = sql_query(format!(
+        "{} UPDATE jwks SET deleted_at = $2, updated_at = $2 \
+         WHERE deleted_at IS NULL \
+         AND id NOT IN (SELECT key_id FROM restore_point WHERE record IS NOT NULL)",
+        KEYS_AT_RESTORE_POINT
+    ))
+    .bind::<Timestamp, _>(at)
+    .bind::<Timestamp, _>(now)
+    .execute(connection)?;
+
+    // Keys logged without their private key would be restored without key material
+    let unrecoverable = sql_query(format!(
+        "{} SELECT key_id FROM restore_point \
+         WHERE record IS NOT NULL AND NOT record ? 'private_key' \
+         AND key_id NOT IN (SELECT id FROM jwks) \
+         ORDER BY key_id",
+        KEYS_AT_RESTORE_POINT
+    ))
+    .bind::<Timestamp, _>(at)
+    .load::<UnrecoverableKey>(connection)?
+    .into_iter()
+    .map(|key| key.key_id)
+    .collect();
+
+    let restored = sql_query(format!(
+        "{} INSERT INTO jwks \
+         SELECT (jsonb_populate_record( \
+           NULL::jwks, \
+           jsonb_build_object('burn_after_read', false, 'sensitive', false, 'external', false) || record \
+             || jsonb_build_object('updated_at', $2, 'is_primary', false))).* \
+         FROM restore_point WHERE record IS NOT NULL \
+         AND (record ? 'private_key' OR key_id IN (SELECT id FROM jwks)) \
+         ON CONFLICT (id) DO UPDATE SET \
+         deleted_at = EXCLUDED.deleted_at, \
+         private_key_expires_at = EXCLUDED.private_key_expires_at, \
+         key_expires_at = EXCLUDED.key_expires_at, \
+         allowed_issuers = EXCLUDED.allowed_issuers, \
+         allowed_audiences = EXCLUDED.allowed_audiences, \
+         updated_at = EXCLUDED.updated_at, \
+         frozen_at = EXCLUDED.frozen_at \
+         WHERE (jwks.deleted_at, jwks.private_key_expires_at, jwks.key_expires_at, \
+         jwks.allowed_issuers, jwks.allowed_audiences, jwks.frozen_at) IS DISTINCT FROM \
+         (EXCLUDED.deleted_at, EXCLUDED.private_key_expires_at, EXCLUDED.key_expires_at, \
+         EXCLUDED.allowed_issuers, EXCLUDED.allowed_audiences, EXCLUDED.frozen_at)",
+        KEYS_AT_RESTORE_POINT
+    ))
+    .bind::<Timestamp, _>(at)
+    .bind::<Timestamp, _>(now)
+    .execute(connection)?;
+
+    Ok(RestoreSummary { restored, removed, unrecoverable })
+}
+
+/// Runs the `restore` command with the arguments following it.
+///
+/// # Errors
+///
+/// Returns an error if the arguments are invalid, the restore point precedes the operations log
+/// or the restore fails.
+pub fn run_restore(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let options = RestoreOptions::from_args(args)?;
+    let connection = &mut establish_connection();
+
+    // Keys are only known from the start of the log on
+    let log_start = key_operations::table
+        .select(diesel::dsl::min(key_operations::occurred_at))
+        .first::<Option<NaiveDateTime>>(connection)?;
+    match log_start {
+        Some(log_start) if log_start <= options.at => {}
+        Some(log_start) => {
+            return Err(Box::from(format!(
+                "Restore point precedes the operations log, which starts at {}",
+                log_start
+            )))
+        }
+        None => return Err(Box::from("The operations log is empty")),
+    }
+
+    let mut summary = RestoreSummary::default();
+    let result = connection.transaction(|connection| {
+        summary = restore_keystore(connection, options.at, Utc::now().naive_utc())?;
+        if options.dry_run {
+            Err(DieselError::RollbackTransaction)
+        } else {
+            Ok(())
+        }
+    });
+    match result {
+        Ok(()) | Err(DieselError::RollbackTransaction) => {}
+        Err(e) => return Err(Box::new(e)),
+    }
+
+    if options.dry_run {
+        println!(
+            "Would restore {} keys and remove {} keys as of {} (dry run, no changes made).",
+            summary.restored, summary.removed, options.at
+        );
+    } else {
+        println!(
+            "Restored {} keys and removed {} keys as of {}.",
+            summary.restored, summary.removed, options.at
+        );
+    }
+    if !summary.unrecoverable.is_empty() {
+        let keys = summary.unrecoverable.iter().map(Uuid::to_string).collect::<Vec<_>>();
+        eprintln!(
+            "Not restored, the operations log holds no private key for {} keys: {}",
+            keys.len(),
+            keys.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_restore_options_from_args() {
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+    let options = RestoreOptions::from_args(&args(&["--at", "2026-10-15T12:00:00Z", "--dry-run"])).unwrap();
+    assert_eq!(options.at, parse_timestamp("1792065600").unwrap());
+    assert!(options.dry_run);
+
+    assert!(RestoreOptions::from_args(&args(&[])).is_err());
+    assert!(RestoreOptions::from_args(&args(&["--at"])).is_err());
+    assert!(RestoreOptions::from_args(&args(&["--at", "yesterday"])).is_err());
+    assert!(RestoreOptions::from_args(&args(&["--at", "0", "--unknown"])).is_err());
+}
@@ -0,0 +1,460 @@
+//! This module replicates keys between independent regional deployments (active-active).
+//!
+//! Every deployment publishes the keys changed since a position in its operations log at
+//! `/replication/changes` and periodically pulls the changes of its peers (`REPLICATION_PEERS`),
+//! so every region serves the same `jwks.json` from its own database. Replicated keys are merged
+//! with the local copy:
+//!
+//! * Key material is immutable. A key whose material differs between regions is a conflict; it
+//!   is logged and skipped. So is a key whose key ID is used by another local key.
+//! * Deletion wins: a key deleted in any region is deleted everywhere, with the earliest
+//!   deletion time.
+//! * Expiration dates, token constraints and freezing are last-writer-wins by the time of the
+//!   last change.
+//! * Primary key designations are local to each deployment and not replicated.
+
+use crate::db::establish_connection;
+use crate::key_encryption::check_secret_storage;
+use crate::manager::kid_owner;
+use crate::log_error;
+use crate::models::{JwkData, ReplicatedKey, ReplicationBatch, ReplicationChangesQuery};
+use crate::policy::check_secret_length;
+use crate::schema::{jwks, replication_cursors};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Uuid as SqlUuid};
+use dotenv::dotenv;
+use std::env;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Maximum number of keys in a change feed batch.
+pub const REPLICATION_BATCH_SIZE: i64 = 500;
+
+/// Name of the scheduled replication job.
+pub const REPLICATION_JOB: &str = "replication";
+
+/// Key changed in the operations log.
+#[derive(QueryableByName)]
+struct ChangedKey {
+    #[diesel(sql_type = SqlUuid)]
+    key_id: Uuid,
+    #[diesel(sql_type = BigInt)]
+    seq: i64,
+}
+
+/// Outcome of merging a replicated key.
+#[derive(Debug, PartialEq)]
+pub enum MergeOutcome {
+    /// The key did not exist locally and was inserted.
+    Inserted,
+    /// The local key was updated.
+    Updated,
+    /// The local key already matched.
+    Unchanged,
+    /// The key material differs from the local key; the key was skipped.
+    Conflict(String),
+}
+
+/// Returns the base URLs of the peer deployments (`REPLICATION_PEERS`, comma-separated).
+pub fn replication_peers() -> Vec<String> {
+    dotenv().ok();
+
+    env::var("REPLICATION_PEERS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|peer| peer.trim().trim_end_matches('/').to_string())
+        .filter(|peer| !peer.is_empty())
+        .collect()
+}
+
+/// Returns the token authenticating replication requests between deployments
+/// (`REPLICATION_TOKEN`).
+///
+/// # Returns
+///
+/// `None` if replication is disabled.
+pub fn replication_token() -> Option<String> {
+    dotenv().ok();
+
+    env::var("REPLICATION_TOKEN")
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+}
+
+/// Returns the interval between pulls from the peers (`REPLICATION_INTERVAL_SECONDS`,
+/// default 30).
+///
+/// # Panics
+///
+/// This function will panic if `REPLICATION_INTERVAL_SECONDS` is not a positive number.
+pub fn replication_interval() -> Duration {
+    dotenv().ok();
+
+    let seconds: u64 = env::var("REPLICATION_INTERVAL_SECONDS")
+        .unwrap_or_else(|_| "30".to_string())
+        .parse()
+        .ok()
+        .filter(|seconds| *seconds > 0)
+        .expect("REPLICATION_INTERVAL_SECONDS must be a positive number");
+
+    Duration::from_secs(seconds)
+}
+
+/// Loads the keys changed after a position in the operations log, in log order.
+///
+/// # Arguments
+///
+/// * `connection` - Database connection.
+/// * `since` - Sequence number of the last operation already seen.
+///
+/// # Errors
+///
+/// Returns an error if the log or the keys cannot be loaded.
+pub fn changed_keys(connection: &mut PgConnection, since: i64) -> QueryResult<ReplicationBatch> {
+    let changes = sql_query(
+        "SELECT key_id, MAX(seq) AS seq FROM key_operations \
+         WHERE seq > $1 GROUP BY key_id ORDER BY MAX(seq) LIMIT $2",
+    )
+    .bind::<BigInt, _>(since)
+    .bind::<BigInt, _>(REPLICATION_BATCH_SIZE)
+    .load::<ChangedKey>(connection)?;
+
+    let key_ids = changes.iter().map(|change| change.key_id).collect::<Vec<_>>();
+    let mut stored = jwks::table
+        .filter(jwks::id.eq_any(&key_ids))
+        .load::<JwkData>(connection)?;
+
+    let mut keys = Vec::new();
+    for change in &changes {
+        if let Some(position) = stored.iter().position(|jwk| jwk.id == change.key_id) {
+            keys.push(ReplicatedKey::new(stored.swap_remove(position)));
+        }
+    }
+
+    Ok(ReplicationBatch {
+        keys,
+        cursor: changes.last().map(|change| change.seq).unwrap_or(since),
+    })
+}
+
+/// Merges a replicated key into the local copy.
+///
+/// # Arguments
+///
+/// * `local` - Local copy of the key.
+/// * `remote` - Replicated key.
+///
+/// # Returns
+///
+/// The merged key, `None` if the local copy already matches it.
+///
+/// # Errors
+///
+/// Returns a message if the key material differs.
+pub fn merge_key(local: &JwkData, remote: &ReplicatedKey) -> Result<Option<JwkData>, String> {
+    let remote_key = remote.to_jwk_data();
+    let material = |jwk: &JwkData| {
+        (
+            (jwk.kty.clone(), jwk.alg.clone(), jwk.kid.clone(), jwk.crv.clone()),
+            (jwk.x.clone(), jwk.y.clone(), jwk.n.clone(), jwk.e.clone(), jwk.pub_.clone()),
+            (jwk.x5c.clone(), jwk.x5t.clone(), jwk.private_key.clone()),
+            (jwk.created_at, jwk.predecessor_id),
+        )
+    };
+    if material(local) != material(&remote_key) {
+        return Err(format!("key material of key {} differs between regions", local.id));
+    }
+
+    // Deletion wins, with the earliest deletion time
+    let deleted = match (local.deleted_at, remote_key.deleted_at) {
+        (Some(local_time), Some(remote_time)) => Some(local_time.min(remote_time)),
+        (local_time, remote_time) => local_time.or(remote_time),
+    };
+    // A burn-after-read private key retrieved in any region stays retrieved
+    let retrieved = match (local.private_key_retrieved_at, remote_key.private_key_retrieved_at) {
+        (Some(local_time), Some(remote_time)) => Some(local_time.min(remote_time)),
+        (local_time, remote_time) => local_time.or(remote_time),
+    };
+
+    let mut merged = if remote_key.updated_at > local.updated_at {
+        remote_key
+    } else {
+        local.clone()
+    };
+    merged.deleted_at = deleted;
+    merged.private_key_retrieved_at = retrieved;
+    merged.updated_at = local.updated_at.max(remote.updated_at);
+    merged.is_primary = local.is_primary;
+
+    Ok(if &merged == local { None } else { Some(merged) })
+}
+
+/// Checks that a replicated symmetric key meets the minimum secret length.
+fn check_secret_strength(jwk: &JwkData) -> Result<(), String> {
+    if !jwk.is_symmetric() {
+        return Ok(());
+    }
+    let secret = URL_SAFE_NO_PAD
+        .decode(&jwk.private_key)
+        .map_err(|_| "the secret is not Base64URL encoded".to_string())?;
+    check_secret_length(&jwk.alg, secret.len())
+}
+
+/// Applies a replicated key to the local database.
+///
+/// # Errors
+///
+/// Returns an error if the key cannot be loaded or stored.
+pub fn apply_replicated_key(
+    connection: &mut PgConnection,
+    remote: &ReplicatedKey,
+) -> QueryResult<MergeOutcome> {
+    connection.transaction(|connection| {
+        let local = jwks::table
+            .find(remote.key.id)
+            .for_update()
+            .first::<JwkData>(connection)
+            .optional()?;
+        let local = match local {
+            Some(local) => local,
+            None => {
+                if let Some(owner) = kid_owner(connection, &remote.key.kid)? {
+                    return Ok(MergeOutcome::Conflict(format!(
+                        "key ID {} of key {} is already used by key {}",
+                        remote.key.kid, remote.key.id, owner
+                    )));
+                }
+                let inserted = JwkData { is_primary: false, ..remote.to_jwk_data() };
+                // Secrets of symmetric keys are never stored unencrypted
+                let checked = check_secret_strength(&inserted)
+                    .and_then(|()| if inserted.is_symmetric() { check_secret_storage() } else { Ok(()) });
+                if let Err(message) = checked {
+                    return Ok(MergeOutcome::Conflict(format!("key {} rejected: {}", inserted.id, message)));
+                }
+                diesel::insert_into(jwks::table).values(inserted).execute(connection)?;
+                return Ok(MergeOutcome::Inserted);
+            }
+        };
+
+        match merge_key(&local, remote) {
+            Err(message) => Ok(MergeOutcome::Conflict(message)),
+            Ok(None) => Ok(MergeOutcome::Unchanged),
+            Ok(Some(merged)) => {
+                diesel::update(jwks::table.find(merged.id))
+                    .set((
+                        jwks::deleted_at.eq(merged.deleted_at),
+                        jwks::private_key_expires_at.eq(merged.private_key_expires_at),
+                        jwks::key_expires_at.eq(merged.key_expires_at),
+                        jwks::allowed_issuers.eq(merged.allowed_issuers),
+                        jwks::allowed_audiences.eq(merged.allowed_audiences),
+                        jwks::updated_at.eq(merged.updated_at),
+                        jwks::frozen_at.eq(merged.frozen_at),
+                        jwks::private_key_retrieved_at.eq(merged.private_key_retrieved_at),
+                    ))
+                    .execute(connection)?;
+                Ok(MergeOutcome::Updated)
+            }
+        }
+    })
+}
+
+/// Pulls and applies the changes of a single peer, starting at the stored cursor.
+///
+/// The cursor only advances past keys that were applied (or skipped as conflicts), so a key
+/// that failed to apply is retried on the next run.
+///
+/// # Errors
+///
+/// Returns a message if the peer cannot be reached or a key cannot be applied.
+pub fn sync_from_peer(
+    connection: &mut PgConnection,
+    client: &reqwest::blocking::Client,
+    peer: &str,
+    token: &str,
+) -> Result<(), String> {
+    loop {
+        let since = replication_cursors::table
+            .find(peer)
+            .select(replication_cursors::cursor)
+            .first::<i64>(connection)
+            .optional()
+            .map_err(|e| e.to_string())?
+            .unwrap_or(0);
+
+        let batch = client
+            .get(format!("{}/replication/changes", peer))
+            .query(&[("since", since)])
+            .bearer_auth(token)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.json::<ReplicationBatch>())
+            .map_err(|e| format!("failed to fetch changes from {}: {}", peer, e))?;
+
+        for key in &batch.keys {
+            let outcome = apply_replicated_key(connection, key)
+                .map_err(|e| format!("failed to apply key {} from {}: {}", key.key.id, peer, e))?;
+            if let MergeOutcome::Conflict(message) = outcome {
+                log_error!("Replication conflict from {}: {}", peer, message);
+            }
+        }
+
+        diesel::insert_into(replication_cursors::table)
+            .values((
+                replication_cursors::peer.eq(peer),
+                replication_cursors::cursor.eq(batch.cursor),
+            ))
+            .on_conflict(replication_cursors::peer)
+            .do_update()
+            .set(replication_cursors::cursor.eq(batch.cursor))
+            .execute(connection)
+            .map_err(|e| e.to_string())?;
+
+        if (batch.keys.len() as i64) < REPLICATION_BATCH_SIZE {
+            return Ok(());
+        }
+    }
+}
+
+/// Pulls the changes of every peer. Used as the scheduled replication job.
+///
+/// # Errors
+///
+/// Returns the messages of every failed peer joined with `; `.
+pub fn sync_from_peers(connection: &mut PgConnection) -> Result<(), String> {
+    let token = replication_token().ok_or("REPLICATION_TOKEN is not set")?;
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let failures = replication_peers()
+        .iter()
+        .filter_map(|peer| sync_from_peer(connection, &client, peer, &token).err())
+        .collect::<Vec<_>>();
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures.join("; "))
+    }
+}
+
+/// Handles a peer's request for the keys changed since a position in the operations log.
+///
+/// The response contains private key material and requires the replication token as a bearer
+/// token.
+///
+/// # Returns
+///
+/// A JSON response containing the changed keys, `401 Unauthorized` for a missing or wrong
+/// token, or `404 Not Found` if replication is disabled.
+#[utoipa::path(
+    get,
+    path = "/replication/changes",
+    params(ReplicationChangesQuery),
+    responses(
+        (status = 200, description = "Keys changed since the position", body = ReplicationBatch),
+        (status = 401, description = "Missing or wrong replication token", body = String, content_type = "text/plain"),
+        (status = 404, description = "Replication is not configured", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to load the changes", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn replication_changes_handler(
+    req: HttpRequest,
+    query: web::Query<ReplicationChangesQuery>,
+) -> impl Responder {
+    let token = match replication_token() {
+        Some(token) => token,
+        None => return HttpResponse::NotFound().body("Replication is not configured"),
+    };
+
+    let presented = req
+        .headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let authorized = presented.is_some_and(|presented| {
+        presented.len() == token.len()
+            && openssl::memcmp::eq(presented.as_bytes(), token.as_bytes())
+    });
+    if !authorized {
+        return HttpResponse::Unauthorized().body("Invalid replication token");
+    }
+
+    let since = query.since.unwrap_or(0);
+    match web::block(move || changed_keys(&mut establish_connection(), since)).await {
+        Ok(Ok(batch)) => HttpResponse::Ok().json(batch),
+        _ => HttpResponse::InternalServerError().body("Failed to load the changes"),
+    }
+}
+
+#[test]
+fn test_merge_key() {
+    use chrono::{NaiveDate, TimeDelta};
+
+    let created = NaiveDate::from_ymd_opt(2026, 10, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+    let later = |days: i64| created + TimeDelta::days(days);
+    let local = JwkData {
+        id: Uuid::new_v4(),
+        kty: "EC".to_string(),
+        alg: "ES256".to_string(),
+        kid: "key-1".to_string(),
+        private_key: "private".to_string(),
+        created_at: created,
+        key_expires_at: Some(later(30)),
+        updated_at: created,
+        ..Default::default()
+    };
+    let remote = |jwk: JwkData| ReplicatedKey::new(jwk);
+
+    // Identical keys need no change
+    assert_eq!(merge_key(&local, &remote(local.clone())), Ok(None));
+
+    // Newer metadata wins, older metadata is ignored
+    let newer = JwkData { key_expires_at: Some(later(60)), updated_at: later(2), ..local.clone() };
+    let merged = merge_key(&local, &remote(newer)).unwrap().unwrap();
+    assert_eq!(merged.key_expires_at, Some(later(60)));
+    assert_eq!(merged.updated_at, later(2));
+
+    let current = JwkData { updated_at: later(3), ..local.clone() };
+    let older = JwkData { key_expires_at: Some(later(60)), updated_at: later(2), ..local.clone() };
+    assert_eq!(merge_key(&current, &remote(older)), Ok(None));
+
+    // Deletion wins even over newer metadata, with the earliest deletion time
+    let deleted = JwkData { deleted_at: Some(later(1)), updated_at: later(1), ..local.clone() };
+    let merged = merge_key(&current, &remote(deleted.clone())).unwrap().unwrap();
+    assert_eq!(merged.deleted_at, Some(later(1)));
+    let deleted_later =
+        JwkData { deleted_at: Some(later(5)), updated_at: later(5), ..local.clone() };
+    let merged = merge_key(&deleted, &remote(deleted_later)).unwrap().unwrap();
+    assert_eq!(merged.deleted_at, Some(later(1)));
+
+    // A retrieved burn-after-read private key stays retrieved even over newer metadata
+    let retrieved = JwkData { private_key_retrieved_at: Some(later(1)), updated_at: later(1), ..local.clone() };
+    let merged = merge_key(&current, &remote(retrieved)).unwrap().unwrap();
+    assert_eq!(merged.private_key_retrieved_at, Some(later(1)));
+
+    // Key material never changes
+    let other_material =
+        JwkData { private_key: "other".to_string(), updated_at: later(9), ..local.clone() };
+    assert!(merge_key(&local, &remote(other_material)).is_err());
+}
+
+#[test]
+fn test_check_secret_strength() {
+    let secret = |len: usize| JwkData {
+        kty: "oct".to_string(),
+        alg: "HS256".to_string(),
+        private_key: URL_SAFE_NO_PAD.encode(vec![7u8; len]),
+        ..Default::default()
+    };
+
+    assert!(check_secret_strength(&secret(32)).is_ok());
+    assert!(check_secret_strength(&secret(16)).is_err());
+    assert!(check_secret_strength(&JwkData { private_key: "!".to_string(), ..secret(32) }).is_err());
+    assert!(check_secret_strength(&JwkData { kty: "EC".to_string(), alg: "ES256".to_string(), ..Default::default() }).is_ok());
+}
@@ -0,0 +1,268 @@
+//! This module provides optional HMAC signing of mutating requests.
+//!
+//! With `REQUEST_SIGNING_SECRET` set, every `POST`, `PUT`, `PATCH` and `DELETE` request must
+//! carry a Unix timestamp in `X-Request-Timestamp` and an HMAC-SHA256 over the timestamp, the
+//! method, the path with query and the body in `X-Request-Signature`. A proxy holding only the
+//! API credentials can thus neither forge nor alter mutations. Requests older than
+//! `REQUEST_SIGNING_MAX_AGE_SECONDS` are rejected, and each signature is accepted once.
+//!
+//! Accepted signatures are remembered in the memory of the process only, so the replay
+//! protection holds for a single instance: with several replicas behind a load balancer, a
+//! captured request can be replayed once against each other replica within the maximum age.
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::web::Bytes;
+use actix_web::HttpResponse;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use dotenv::dotenv;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+
+/// Request header carrying the signing time as a Unix timestamp.
+pub const TIMESTAMP_HEADER: &str = "X-Request-Timestamp";
+
+/// Request header carrying the base64url encoded HMAC-SHA256 signature.
+pub const SIGNATURE_HEADER: &str = "X-Request-Signature";
+
+/// Signatures accepted within the maximum request age, with their timestamps; local to the
+/// process, not shared between replicas.
+static SEEN_SIGNATURES: Mutex<Option<HashMap<String, i64>>> = Mutex::new(None);
+
+/// Returns the shared secret of request signing (`REQUEST_SIGNING_SECRET`).
+///
+/// # Returns
+///
+/// `None` if requests are not signed.
+pub fn request_signing_secret() -> Option<String> {
+    dotenv().ok();
+
+    env::var("REQUEST_SIGNING_SECRET")
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+}
+
+/// Returns how far the timestamp of a signed request may deviate from the current time in
+/// seconds (`REQUEST_SIGNING_MAX_AGE_SECONDS`, default 300).
+///
+/// # Panics
+///
+/// This function will panic if `REQUEST_SIGNING_MAX_AGE_SECONDS` is not a positive number.
+pub fn request_signing_max_age_seconds() -> i64 {
+    dotenv().ok();
+
+    env::var("REQUEST_SIGNING_MAX_AGE_SECONDS")
+        .unwrap_or_else(|_| "300".to_string())
+        .parse()
+        .ok()
+        .filter(|seconds| *seconds > 0)
+        .expect("REQUEST_SIGNING_MAX_AGE_SECONDS must be a positive number")
+}
+
+/// Returns the signing input of a request: the timestamp, the method and the path with query,
+/// each followed by a newline, and the body.
+pub fn signing_input(timestamp: &str, method: &str, path_and_query: &str, body: &[u8]) -> Vec<u8> {
+    let mut input = format!("{}\n{}\n{}\n", timestamp, method, path_and_query).into_bytes();
+    input.extend_from_slice(body);
+    input
+}
+
+/// Signs a request with the shared secret.
+///
+/// # Returns
+///
+/// The base64url encoded HMAC-SHA256 of the signing input, see [`signing_input`].
+pub fn sign_request(secret: &str, timestamp: &str, method: &str, path_and_query: &str, body: &[u8]) -> String {
+    let key = PKey::hmac(secret.as_bytes()).expect("Error creating HMAC key");
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).expect("Error creating HMAC signer");
+    let signature = signer
+        .sign_oneshot_to_vec(&signing_input(timestamp, method, path_and_query, body))
+        .expect("Error computing HMAC");
+
+    URL_SAFE_NO_PAD.encode(signature)
+}
+
+/// Verifies the signature of a request and records it as used.
+///
+/// # Arguments
+///
+/// * `secret` - Shared secret.
+/// * `timestamp` - Value of `X-Request-Timestamp`.
+/// * `signature` - Value of `X-Request-Signature`.
+/// * `method`, `path_and_query`, `body` - The signed parts of the request.
+/// * `now` - Current time as a Unix timestamp.
+/// * `max_age` - Maximum deviation of the timestamp from `now` in seconds.
+///
+/// # Errors
+///
+/// Returns a message if the timestamp is invalid or out of range, the signature does not
+/// match, or the signature was already accepted.
+#[allow(clippy::too_many_arguments)]
+pub fn verify_request(
+    secret: &str,
+    timestamp: &str,
+    signature: &str,
+    method: &str,
+    path_and_query: &str,
+    body: &[u8],
+    now: i64,
+    max_age: i64,
+) -> Result<(), String> {
+    let signed_at: i64 = timestamp
+        .parse()
+        .map_err(|_| "Invalid request timestamp".to_string())?;
+    if (now - signed_at).abs() > max_age {
+        return Err("Request timestamp is too old or in the future".to_string());
+    }
+
+    let expected = sign_request(secret, timestamp, method, path_and_query, body);
+    if expected.len() != signature.len() || !openssl::memcmp::eq(expected.as_bytes(), signature.as_bytes()) {
+        return Err("Invalid request signature".to_string());
+    }
+
+    // Entries older than the maximum age are pruned, since such requests are rejected anyway
+    let mut seen = SEEN_SIGNATURES.lock().unwrap();
+    let seen = seen.get_or_insert_with(HashMap::new);
+    seen.retain(|_, seen_at| *seen_at >= now - max_age);
+    if seen.contains_key(signature) {
+        return Err("Request has already been used".to_string());
+    }
+    seen.insert(signature.to_string(), signed_at);
+
+    Ok(())
+}
+
+/// Middleware rejecting mutating requests without a valid signature with
+/// `401 Unauthorized`.
+///
+/// # Errors
+///
+/// Returns an error if the request body cannot be read.
+pub async fn verify_request_signature(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    if [Method::GET, Method::HEAD, Method::OPTIONS].contains(req.method()) {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    }
+    let secret = match request_signing_secret() {
+        Some(secret) => secret,
+        None => return next.call(req).await.map(ServiceResponse::map_into_left_body),
+    };
+
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let (timestamp, signature) = match (header(TIMESTAMP_HEADER), header(SIGNATURE_HEADER)) {
+        (Some(timestamp), Some(signature)) => (timestamp, signature),
+        _ => {
+            let response = HttpResponse::Unauthorized().body("Request signature is required");
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+    };
+
+    // Read the body for the signature and hand it on to the handler
+    let body = req.extract::<Bytes>().await?;
+    let path_and_query = req
+        .uri()
+        .path_and_query()
+        .map(|path_and_query| path_and_query.as_str().to_string())
+        .unwrap_or_default();
+    let verified = verify_request(
+        &secret,
+        &timestamp,
+        &signature,
+        req.method().as_str(),
+        &path_and_query,
+        &body,
+        Utc::now().timestamp(),
+        request_signing_max_age_seconds(),
+    );
+    if let Err(message) = verified {
+        return Ok(req.into_response(HttpResponse::Unauthorized().body(message)).map_into_right_body());
+    }
+    let (_, mut payload) = actix_http::h1::Payload::create(true);
+    payload.unread_data(body);
+    req.set_payload(Payload::from(payload));
+
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
+
+#[test]
+fn test_verify_request() {
+    let now = 1_700_000_000;
+    let body = br#"{"alg":"ES256"}"#;
+    let signature = sign_request("secret", "1700000000", "POST", "/jwks", body);
+
+    // Tampered or differently signed requests
+    assert!(verify_request("other", "1700000000", &signature, "POST", "/jwks", body, now, 300).is_err());
+    assert!(verify_request("secret", "1700000000", &signature, "DELETE", "/jwks", body, now, 300).is_err());
+    assert!(verify_request("secret", "1700000000", &signature, "POST", "/jwks", b"{}", now, 300).is_err());
+    assert!(verify_request("secret", "1700000001", &signature, "POST", "/jwks", body, now, 300).is_err());
+    assert!(verify_request("secret", "1700000000", &signature, "POST", "/jwks", body, now + 301, 300).is_err());
+    assert!(verify_request("secret", "yesterday", &signature, "POST", "/jwks", body, now, 300).is_err());
+
+    // Accepted once
+    assert!(verify_request("secret", "1700000000", &signature, "POST", "/jwks", body, now, 300).is_ok());
+    assert_eq!(
+        verify_request("secret", "1700000000", &signature, "POST", "/jwks", body, now, 300),
+        Err("Request has already been used".to_string())
+    );
+}
+
+#[cfg(feature = "test-util")]
+#[actix_web::test]
+async fn test_verify_request_signature() {
+    use actix_web::{middleware::from_fn, test, web, App};
+
+    let _environment = crate::test_support::EnvGuard::set(&[("REQUEST_SIGNING_SECRET", "middleware-secret")]);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(verify_request_signature))
+            .route("/echo", web::post().to(|body: Bytes| async move { HttpResponse::Ok().body(body) }))
+            .route("/echo", web::get().to(|| async { HttpResponse::Ok().finish() })),
+    )
+    .await;
+
+    // Reads are not signed
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/echo").to_request()).await;
+    assert_eq!(resp.status(), 200);
+
+    let req = test::TestRequest::post().uri("/echo?x=1").set_payload("payload").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+
+    // The handler receives the signed body
+    let timestamp = Utc::now().timestamp().to_string();
+    let signature = sign_request("middleware-secret", &timestamp, "POST", "/echo?x=1", b"payload");
+    let req = test::TestRequest::post()
+        .uri("/echo?x=1")
+        .insert_header((TIMESTAMP_HEADER, timestamp.as_str()))
+        .insert_header((SIGNATURE_HEADER, signature.as_str()))
+        .set_payload("payload")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(test::read_body(resp).await, Bytes::from_static(b"payload"));
+
+    // Replays are rejected
+    let req = test::TestRequest::post()
+        .uri("/echo?x=1")
+        .insert_header((TIMESTAMP_HEADER, timestamp.as_str()))
+        .insert_header((SIGNATURE_HEADER, signature.as_str()))
+        .set_payload("payload")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+}
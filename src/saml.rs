@@ -0,0 +1,163 @@
+//! This module generates SAML 2.0 metadata embedding the X.509 signing certificates of the
+//! managed keys, so that SAML relying parties can consume the same keys as JWT verifiers.
+
+use dotenv::dotenv;
+use std::env;
+
+/// SAML 2.0 metadata namespace.
+const METADATA_NS: &str = "urn:oasis:names:tc:SAML:2.0:metadata";
+
+/// XML Signature namespace.
+const DSIG_NS: &str = "http://www.w3.org/2000/09/xmldsig#";
+
+/// SAML 2.0 protocol identifier.
+const PROTOCOL: &str = "urn:oasis:names:tc:SAML:2.0:protocol";
+
+/// Role of the entity described by the metadata.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SamlRole {
+    /// Identity provider (`IDPSSODescriptor`).
+    IdentityProvider,
+    /// Service provider (`SPSSODescriptor`).
+    ServiceProvider,
+}
+
+/// Configuration of the SAML metadata document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SamlMetadataConfig {
+    /// Entity ID of the described entity (`SAML_ENTITY_ID`).
+    pub entity_id: String,
+    /// Role of the described entity (`SAML_ROLE`).
+    pub role: SamlRole,
+    /// Location of the single sign-on service (IdP) or assertion consumer service (SP)
+    /// (`SAML_SERVICE_URL`).
+    pub service_url: Option<String>,
+}
+
+/// Returns the SAML metadata configuration from the environment.
+///
+/// # Returns
+///
+/// `None` if `SAML_ENTITY_ID` is not set (SAML metadata is disabled).
+///
+/// # Panics
+///
+/// This function will panic if `SAML_ROLE` is neither `idp` nor `sp`.
+pub fn saml_metadata_config() -> Option<SamlMetadataConfig> {
+    dotenv().ok();
+
+    let entity_id = env::var("SAML_ENTITY_ID")
+        .ok()
+        .filter(|value| !value.trim().is_empty())?;
+    let role = match env::var("SAML_ROLE").unwrap_or_else(|_| "idp".to_string()).as_str() {
+        "idp" => SamlRole::IdentityProvider,
+        "sp" => SamlRole::ServiceProvider,
+        _ => panic!("SAML_ROLE must be idp or sp"),
+    };
+    let service_url = env::var("SAML_SERVICE_URL")
+        .ok()
+        .filter(|value| !value.trim().is_empty());
+
+    Some(SamlMetadataConfig {
+        entity_id,
+        role,
+        service_url,
+    })
+}
+
+/// Escapes text for use in XML content and attribute values.
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Builds the SAML 2.0 metadata document.
+///
+/// # Arguments
+///
+/// * `config` - Entity ID, role and service location.
+/// * `certificates` - Key IDs with their signing certificates as standard Base64 encoded DER.
+///
+/// # Returns
+///
+/// The `EntityDescriptor` XML document.
+pub fn saml_metadata(config: &SamlMetadataConfig, certificates: &[(String, String)]) -> String {
+    let descriptor = match config.role {
+        SamlRole::IdentityProvider => "IDPSSODescriptor",
+        SamlRole::ServiceProvider => "SPSSODescriptor",
+    };
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<md:EntityDescriptor xmlns:md=\"{}\" xmlns:ds=\"{}\" entityID=\"{}\">\n",
+        METADATA_NS,
+        DSIG_NS,
+        escape_xml(&config.entity_id)
+    ));
+    xml.push_str(&format!(
+        "  <md:{} protocolSupportEnumeration=\"{}\">\n",
+        descriptor, PROTOCOL
+    ));
+
+    for (key_id, certificate) in certificates {
+        xml.push_str("    <md:KeyDescriptor use=\"signing\">\n");
+        xml.push_str("      <ds:KeyInfo>\n");
+        xml.push_str(&format!("        <ds:KeyName>{}</ds:KeyName>\n", escape_xml(key_id)));
+        xml.push_str("        <ds:X509Data>\n");
+        xml.push_str(&format!(
+            "          <ds:X509Certificate>{}</ds:X509Certificate>\n",
+            certificate
+        ));
+        xml.push_str("        </ds:X509Data>\n");
+        xml.push_str("      </ds:KeyInfo>\n");
+        xml.push_str("    </md:KeyDescriptor>\n");
+    }
+
+    if let Some(service_url) = &config.service_url {
+        let service_url = escape_xml(service_url);
+        match config.role {
+            SamlRole::IdentityProvider => xml.push_str(&format!(
+                "    <md:SingleSignOnService Binding=\"urn:oasis:names:tc:SAML:2.0:bindings:HTTP-Redirect\" Location=\"{}\"/>\n",
+                service_url
+            )),
+            SamlRole::ServiceProvider => xml.push_str(&format!(
+                "    <md:AssertionConsumerService Binding=\"urn:oasis:names:tc:SAML:2.0:bindings:HTTP-POST\" Location=\"{}\" index=\"0\"/>\n",
+                service_url
+            )),
+        }
+    }
+
+    xml.push_str(&format!("  </md:{}>\n", descriptor));
+    xml.push_str("</md:EntityDescriptor>\n");
+    xml
+}
+
+#[test]
+fn test_saml_metadata() {
+    let config = SamlMetadataConfig {
+        entity_id: "https://idp.example.com/?a=1&b=2".to_string(),
+        role: SamlRole::IdentityProvider,
+        service_url: Some("https://idp.example.com/sso".to_string()),
+    };
+    let certificates = vec![("kid-1".to_string(), "MIIB".to_string())];
+
+    let xml = saml_metadata(&config, &certificates);
+    assert!(xml.contains("entityID=\"https://idp.example.com/?a=1&amp;b=2\""));
+    assert!(xml.contains("<md:IDPSSODescriptor protocolSupportEnumeration=\"urn:oasis:names:tc:SAML:2.0:protocol\">"));
+    assert!(xml.contains("<ds:KeyName>kid-1</ds:KeyName>"));
+    assert!(xml.contains("<ds:X509Certificate>MIIB</ds:X509Certificate>"));
+    assert!(xml.contains("<md:SingleSignOnService"));
+
+    let sp = SamlMetadataConfig {
+        role: SamlRole::ServiceProvider,
+        ..config
+    };
+    let xml = saml_metadata(&sp, &[]);
+    assert!(xml.contains("<md:SPSSODescriptor"));
+    assert!(xml.contains("<md:AssertionConsumerService"));
+    assert!(!xml.contains("KeyDescriptor"));
+}
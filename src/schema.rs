@@ -37,5 +37,212 @@ diesel::table! {
         private_key_expires_at -> Nullable<Timestamp>,
         /// Key expiration date.
         key_expires_at -> Nullable<Timestamp>,
+        /// Contain the public key of an Algorithm Key Pair (e.g., ML-DSA) encoded using base64url.
+        #[sql_name = "pub"]
+        pub_ -> Nullable<Text>,
+        /// Issuers (`iss` claim) the key may sign tokens for. If `NULL`, any issuer is allowed.
+        allowed_issuers -> Nullable<Array<Text>>,
+        /// Audiences (`aud` claim) the key may sign tokens for. If `NULL`, any audience is allowed.
+        allowed_audiences -> Nullable<Array<Text>>,
+        /// Key this key rotated, if it is a later version of a logical key.
+        predecessor_id -> Nullable<Uuid>,
+        /// Time of the last change of the key.
+        updated_at -> Timestamp,
+        /// Time the key was frozen. If set, the key is neither published nor used for signing.
+        frozen_at -> Nullable<Timestamp>,
+        /// Whether the key is the primary key of its algorithm, used by default for signing.
+        is_primary -> Bool,
+        /// Purpose the key is used for (e.g., `access-token`). If `NULL`, the key has no purpose.
+        purpose -> Nullable<Varchar>,
+        /// Whether the private key can only be retrieved once.
+        burn_after_read -> Bool,
+        /// Time the private key of a burn-after-read key was retrieved.
+        private_key_retrieved_at -> Nullable<Timestamp>,
+        /// Whether retrieving the private key requires a one-time approval token.
+        sensitive -> Bool,
+        /// Whether the key was registered from outside with its public part only.
+        external -> Bool,
+        /// Activation time. If set, the key is neither published nor used for signing before it.
+        not_before -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    /// Append-only log of key lifecycle events.
+    audit_log (id) {
+        /// Unique event identifier.
+        id -> Uuid,
+        /// Identifier of the affected key.
+        key_id -> Uuid,
+        /// Performed action (e.g., "create", "delete").
+        action -> Varchar,
+        /// Caller that performed the action, if known.
+        actor -> Nullable<Varchar>,
+        /// Time of the event.
+        occurred_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    /// Leases granting a replica ownership of a subsystem (e.g., the scheduler).
+    scheduler_lease (name) {
+        /// Name of the lease.
+        name -> Varchar,
+        /// Replica currently holding the lease.
+        holder -> Varchar,
+        /// Time the lease lapses unless renewed by its holder.
+        expires_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    /// Append-only log of every mutation of the `jwks` table.
+    key_operations (seq) {
+        /// Position of the operation in the log.
+        seq -> Int8,
+        /// Identifier of the affected key.
+        key_id -> Uuid,
+        /// Performed operation ("INSERT", "UPDATE", "DELETE" or "SNAPSHOT").
+        operation -> Varchar,
+        /// The key row after the operation, without `private_key` if it is stored unencrypted.
+        /// `NULL` if the key was removed.
+        record -> Nullable<Jsonb>,
+        /// Time of the operation.
+        occurred_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    /// Position in the change feed of each replication peer.
+    replication_cursors (peer) {
+        /// Base URL of the peer deployment.
+        peer -> Varchar,
+        /// Sequence number of the last replicated operation of the peer.
+        cursor -> Int8,
+    }
+}
+
+diesel::table! {
+    /// Named aliases resolving to the current version of a key.
+    key_aliases (name) {
+        /// Name of the alias (e.g., "access-token-signing").
+        name -> Varchar,
+        /// Key the alias currently resolves to.
+        key_id -> Uuid,
+        /// Time the alias was created.
+        created_at -> Timestamp,
+        /// Time the alias was last pointed to a key.
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    /// Append-only log of every response containing private key material.
+    private_key_access_log (id) {
+        /// Unique access record identifier.
+        id -> Uuid,
+        /// Identifier of the accessed key.
+        key_id -> Uuid,
+        /// Key ID of the accessed key.
+        kid -> Varchar,
+        /// Endpoint that returned the private key (e.g., "/jwks/{id}").
+        endpoint -> Varchar,
+        /// Caller that accessed the key, if known.
+        actor -> Nullable<Varchar>,
+        /// Address of the client the request came from, if known.
+        client_address -> Nullable<Varchar>,
+        /// Time of the access.
+        accessed_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    /// One-time approvals to retrieve the private key of a sensitive key.
+    private_key_approvals (id) {
+        /// Unique approval identifier.
+        id -> Uuid,
+        /// Identifier of the approved key.
+        key_id -> Uuid,
+        /// SHA-256 hash of the approval token.
+        token_hash -> Varchar,
+        /// Approver that issued the approval, if known.
+        approved_by -> Nullable<Varchar>,
+        /// Time the approval was issued.
+        created_at -> Timestamp,
+        /// Time the approval lapses unless used.
+        expires_at -> Timestamp,
+        /// Time the approval was used. If set, the approval cannot be used again.
+        used_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    /// Anomalies detected in accesses to private keys.
+    private_key_access_anomalies (id) {
+        /// Unique anomaly identifier.
+        id -> Uuid,
+        /// Access record the anomaly was detected in.
+        access_id -> Uuid,
+        /// Identifier of the accessed key.
+        key_id -> Uuid,
+        /// Kind of anomaly (e.g., "new_network").
+        kind -> Varchar,
+        /// Description of the anomaly.
+        detail -> Varchar,
+        /// Caller that accessed the key, if known.
+        actor -> Nullable<Varchar>,
+        /// Address of the client the access came from, if known.
+        client_address -> Nullable<Varchar>,
+        /// Time the anomaly was detected.
+        detected_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    /// Position of each SIEM sink in the audit log.
+    siem_cursors (sink) {
+        /// Name of the sink (e.g., "splunk").
+        sink -> Varchar,
+        /// Time of the last forwarded audit event.
+        occurred_at -> Timestamp,
+        /// Identifier of the last forwarded audit event.
+        event_id -> Uuid,
+    }
+}
+
+diesel::table! {
+    /// Expiry warnings that have been sent, so each is sent once per expiry date.
+    expiry_notifications (key_id, event, expires_at) {
+        /// Identifier of the expiring key.
+        key_id -> Uuid,
+        /// Kind of expiry (e.g., "key_expiring").
+        event -> Varchar,
+        /// Expiry date the warning was sent for.
+        expires_at -> Timestamp,
+        /// Time the warning was sent.
+        sent_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    /// Latest run of each scheduled background job.
+    job_runs (name) {
+        /// Name of the job (e.g., "replication").
+        name -> Varchar,
+        /// Replica that performed the latest run.
+        replica -> Varchar,
+        /// Outcome of the latest run: "running", "completed" or "failed".
+        outcome -> Varchar,
+        /// Time the latest run started.
+        started_at -> Timestamp,
+        /// Time the latest run finished.
+        finished_at -> Nullable<Timestamp>,
+        /// Error of the latest failed run.
+        last_error -> Nullable<Text>,
+        /// Time of the latest failed run.
+        last_error_at -> Nullable<Timestamp>,
+        /// Time of the next scheduled run, as planned by the replica that last ran the job on
+        /// schedule.
+        next_run_at -> Nullable<Timestamp>,
     }
 }
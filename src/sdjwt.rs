@@ -0,0 +1,158 @@
+//! This module provides Selective Disclosure JWT (SD-JWT) issuance.
+//!
+//! Selectively disclosable top-level claims are removed from the signed payload and replaced by
+//! the SHA-256 digests of their disclosures in the `_sd` claim. The issued SD-JWT is the signed
+//! JWT followed by every disclosure, each terminated by `~`. The holder reveals a claim by
+//! presenting its disclosure. Optional key binding embeds the holder's public key in `cnf.jwk`.
+
+use crate::jws::encode_jws;
+use crate::models::JwkData;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use serde_json::{json, Value};
+use std::error::Error;
+
+/// Default `typ` header of issued SD-JWTs (SD-JWT based verifiable credentials).
+pub const DEFAULT_SD_JWT_TYPE: &str = "dc+sd-jwt";
+
+/// Claims that must stay visible to allow validating the SD-JWT.
+const NON_DISCLOSABLE_CLAIMS: [&str; 9] =
+    ["iss", "exp", "nbf", "iat", "cnf", "vct", "status", "_sd", "_sd_alg"];
+
+/// Creates the disclosure of a claim: `BASE64URL([salt, name, value])`.
+pub fn create_disclosure(salt: &str, name: &str, value: &Value) -> Result<String, Box<dyn Error>> {
+    Ok(URL_SAFE_NO_PAD.encode(serde_json::to_vec(&json!([salt, name, value]))?))
+}
+
+/// Computes the `sha-256` digest of a disclosure as listed in the `_sd` claim.
+pub fn disclosure_digest(disclosure: &str) -> String {
+    URL_SAFE_NO_PAD.encode(openssl::sha::sha256(disclosure.as_bytes()))
+}
+
+/// Generates a random 128-bit salt for a disclosure.
+fn generate_salt() -> Result<String, Box<dyn Error>> {
+    let mut salt = [0u8; 16];
+    openssl::rand::rand_bytes(&mut salt)?;
+    Ok(URL_SAFE_NO_PAD.encode(salt))
+}
+
+/// Issues an SD-JWT.
+///
+/// # Arguments
+///
+/// * `jwk` - Key used for signing, including its private part.
+/// * `claims` - Claims of the SD-JWT.
+/// * `disclosable` - Names of the top-level claims that are selectively disclosable.
+/// * `holder_jwk` - Public key of the holder for key binding (`cnf.jwk`), if any.
+/// * `typ` - Value of the `typ` header.
+///
+/// # Errors
+///
+/// Returns an error if the claims are not an object, a disclosable claim is missing or must stay
+/// visible, the holder key contains private key material or signing fails.
+pub fn issue_sd_jwt(
+    jwk: &JwkData,
+    claims: &Value,
+    disclosable: &[String],
+    holder_jwk: Option<&Value>,
+    typ: &str,
+) -> Result<String, Box<dyn Error>> {
+    let mut payload = claims
+        .as_object()
+        .cloned()
+        .ok_or("Claims must be a JSON object")?;
+
+    let mut disclosures = Vec::new();
+    let mut digests = Vec::new();
+    for name in disclosable {
+        if NON_DISCLOSABLE_CLAIMS.contains(&name.as_str()) {
+            return Err(format!("Claim {} cannot be selectively disclosable", name).into());
+        }
+        let value = payload
+            .remove(name)
+            .ok_or(format!("Disclosable claim {} is missing", name))?;
+
+        let disclosure = create_disclosure(&generate_salt()?, name, &value)?;
+        digests.push(disclosure_digest(&disclosure));
+        disclosures.push(disclosure);
+    }
+
+    // Sorted digests do not reveal the original claim order
+    digests.sort();
+    if !digests.is_empty() {
+        payload.insert("_sd".to_string(), json!(digests));
+    }
+    payload.insert("_sd_alg".to_string(), json!("sha-256"));
+
+    if let Some(holder_jwk) = holder_jwk {
+        let holder = holder_jwk.as_object().ok_or("Holder key must be a JWK object")?;
+        if holder.contains_key("d") {
+            return Err(Box::from("Holder key must not contain a private key"));
+        }
+        payload.insert("cnf".to_string(), json!({ "jwk": holder_jwk }));
+    }
+
+    let token = encode_jws(jwk, typ, &serde_json::to_vec(&Value::Object(payload))?)?;
+
+    let mut sd_jwt = token;
+    sd_jwt.push('~');
+    for disclosure in disclosures {
+        sd_jwt.push_str(&disclosure);
+        sd_jwt.push('~');
+    }
+
+    Ok(sd_jwt)
+}
+
+#[test]
+fn test_disclosure_digest_matches_specification_example() {
+    // Disclosure of `given_name` and its digest from the SD-JWT specification example
+    assert_eq!(
+        disclosure_digest("WyIyR0xDNDJzS1F2ZUNmR2ZyeU5STjl3IiwgImdpdmVuX25hbWUiLCAiSm9obiJd"),
+        "jsu9yVulwQQlhFlM_3JlzMaSFzglhQG0DpfayQwLUK4"
+    );
+
+    let disclosure = create_disclosure("2GLC42sKQveCfGfryNRN9w", "given_name", &json!("John")).unwrap();
+    let decoded: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(disclosure).unwrap()).unwrap();
+    assert_eq!(decoded, json!(["2GLC42sKQveCfGfryNRN9w", "given_name", "John"]));
+}
+
+#[test]
+fn test_issue_sd_jwt() {
+    use crate::crypto::generate_jwk_data;
+    use crate::jws::decode_jws;
+
+    let jwk = generate_jwk_data("ES256", 2048).unwrap();
+    let claims = json!({
+        "iss": "https://issuer.example.com",
+        "given_name": "Erika",
+        "birthdate": "1963-08-12",
+        "vct": "https://credentials.example.com/identity"
+    });
+    let holder = json!({"kty": "OKP", "crv": "Ed25519", "x": "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"});
+    let disclosable = vec!["given_name".to_string(), "birthdate".to_string()];
+
+    let sd_jwt = issue_sd_jwt(&jwk, &claims, &disclosable, Some(&holder), DEFAULT_SD_JWT_TYPE).unwrap();
+    assert!(sd_jwt.ends_with('~'));
+
+    let parts = sd_jwt.split('~').collect::<Vec<_>>();
+    assert_eq!(parts.len(), 4);
+
+    let decoded = decode_jws(parts[0]).unwrap();
+    assert_eq!(decoded.header_str("typ"), Some("dc+sd-jwt"));
+    let payload = decoded.claims().unwrap();
+    assert!(payload.get("given_name").is_none());
+    assert_eq!(payload["iss"], "https://issuer.example.com");
+    assert_eq!(payload["_sd_alg"], "sha-256");
+    assert_eq!(payload["cnf"]["jwk"], holder);
+
+    let sd = payload["_sd"].as_array().unwrap();
+    for disclosure in &parts[1..3] {
+        assert!(sd.contains(&json!(disclosure_digest(disclosure))));
+        let decoded: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(disclosure).unwrap()).unwrap();
+        assert_eq!(decoded[2], claims[decoded[1].as_str().unwrap()]);
+    }
+
+    assert!(issue_sd_jwt(&jwk, &claims, &["iss".to_string()], None, DEFAULT_SD_JWT_TYPE).is_err());
+    assert!(issue_sd_jwt(&jwk, &claims, &["missing".to_string()], None, DEFAULT_SD_JWT_TYPE).is_err());
+    assert!(issue_sd_jwt(&jwk, &claims, &[], Some(&json!({"kty": "EC", "d": "x"})), DEFAULT_SD_JWT_TYPE).is_err());
+}
@@ -0,0 +1,161 @@
+//! This module provides the security headers added to every response, to pass standard
+//! web-security scans.
+//!
+//! Every response gets `X-Content-Type-Options: nosniff`, `Strict-Transport-Security` and
+//! `Referrer-Policy`; the Swagger UI page additionally gets a restrictive
+//! `Content-Security-Policy` that only allows the Swagger UI assets and its own inline script.
+//! Each header is configurable per deployment, and all of them can be disabled with
+//! `SECURITY_HEADERS_ENABLED=0` (e.g. when a gateway in front of the service sets them).
+
+use actix_web::middleware::DefaultHeaders;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use dotenv::dotenv;
+use std::env;
+
+/// Origin the Swagger UI assets are loaded from.
+const SWAGGER_UI_ASSET_ORIGIN: &str = "https://unpkg.com";
+
+/// Security headers of a deployment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SecurityHeaders {
+    /// Value of `Strict-Transport-Security`, if sent.
+    pub strict_transport_security: Option<String>,
+    /// Value of `Referrer-Policy`, if sent.
+    pub referrer_policy: Option<String>,
+    /// Value of the `Content-Security-Policy` of the Swagger UI page, if sent.
+    pub swagger_ui_csp: Option<String>,
+}
+
+/// Returns whether security headers are sent (`SECURITY_HEADERS_ENABLED`, default `1`).
+pub fn security_headers_enabled() -> bool {
+    dotenv().ok();
+
+    env::var("SECURITY_HEADERS_ENABLED").map(|value| value != "0").unwrap_or(true)
+}
+
+/// Returns the value of an optional header variable: the default if unset, `None` if empty.
+fn header_variable(name: &str, default: impl FnOnce() -> String) -> Option<String> {
+    match env::var(name) {
+        Ok(value) if value.trim().is_empty() => None,
+        Ok(value) => Some(value.trim().to_string()),
+        Err(_) => Some(default()),
+    }
+}
+
+/// Returns the `Strict-Transport-Security` value for a maximum age.
+///
+/// # Returns
+///
+/// `None` for a maximum age of 0 (HSTS disabled).
+pub fn strict_transport_security(max_age_seconds: u64, include_subdomains: bool) -> Option<String> {
+    match (max_age_seconds, include_subdomains) {
+        (0, _) => None,
+        (max_age, true) => Some(format!("max-age={}; includeSubDomains", max_age)),
+        (max_age, false) => Some(format!("max-age={}", max_age)),
+    }
+}
+
+/// Returns the `sha256-` source of the inline script of an HTML page, for use in `script-src`.
+///
+/// # Returns
+///
+/// `None` if the page has no inline script (a `<script>` element without attributes).
+pub fn inline_script_hash(html: &str) -> Option<String> {
+    let start = html.find("<script>")? + "<script>".len();
+    let length = html[start..].find("</script>")?;
+
+    Some(format!(
+        "'sha256-{}'",
+        STANDARD.encode(openssl::sha::sha256(&html.as_bytes()[start..start + length]))
+    ))
+}
+
+/// Returns the default `Content-Security-Policy` of a Swagger UI page: its assets from
+/// unpkg, its own inline script, and API requests to this service only.
+pub fn default_swagger_ui_csp(html: &str) -> String {
+    let inline_script = inline_script_hash(html).unwrap_or_default();
+
+    format!(
+        "default-src 'none'; script-src {origin} {inline_script}; \
+         style-src {origin} 'unsafe-inline'; img-src 'self' data: {origin}; connect-src 'self'; \
+         frame-ancestors 'none'; base-uri 'none'; form-action 'none'",
+        origin = SWAGGER_UI_ASSET_ORIGIN,
+        inline_script = inline_script,
+    )
+}
+
+/// Returns the security headers of the deployment.
+///
+/// * `HSTS_MAX_AGE_SECONDS` (default 31536000, 0 disables HSTS) and `HSTS_INCLUDE_SUBDOMAINS`
+///   (default `1`) configure `Strict-Transport-Security`.
+/// * `REFERRER_POLICY` (default `no-referrer`) configures `Referrer-Policy`.
+/// * `SWAGGER_UI_CSP` (default [`default_swagger_ui_csp`]) configures the
+///   `Content-Security-Policy` of the Swagger UI page.
+///
+/// An empty `REFERRER_POLICY` or `SWAGGER_UI_CSP` disables the header.
+///
+/// # Arguments
+///
+/// * `swagger_ui_html` - The Swagger UI page, whose inline script the default CSP allows.
+///
+/// # Panics
+///
+/// This function will panic if `HSTS_MAX_AGE_SECONDS` is not a number.
+pub fn security_headers(swagger_ui_html: &str) -> SecurityHeaders {
+    dotenv().ok();
+
+    let max_age: u64 = env::var("HSTS_MAX_AGE_SECONDS")
+        .unwrap_or_else(|_| "31536000".to_string())
+        .parse()
+        .expect("HSTS_MAX_AGE_SECONDS must be a number");
+    let include_subdomains = env::var("HSTS_INCLUDE_SUBDOMAINS")
+        .map(|value| value != "0")
+        .unwrap_or(true);
+
+    SecurityHeaders {
+        strict_transport_security: strict_transport_security(max_age, include_subdomains),
+        referrer_policy: header_variable("REFERRER_POLICY", || "no-referrer".to_string()),
+        swagger_ui_csp: header_variable("SWAGGER_UI_CSP", || default_swagger_ui_csp(swagger_ui_html)),
+    }
+}
+
+/// Builds the middleware adding the security headers sent with every response.
+///
+/// Headers already set by a handler are kept.
+pub fn default_headers(headers: &SecurityHeaders) -> DefaultHeaders {
+    let mut middleware = DefaultHeaders::new().add(("X-Content-Type-Options", "nosniff"));
+    if let Some(value) = &headers.strict_transport_security {
+        middleware = middleware.add(("Strict-Transport-Security", value.as_str()));
+    }
+    if let Some(value) = &headers.referrer_policy {
+        middleware = middleware.add(("Referrer-Policy", value.as_str()));
+    }
+    middleware
+}
+
+#[test]
+fn test_strict_transport_security() {
+    assert_eq!(
+        strict_transport_security(31536000, true).as_deref(),
+        Some("max-age=31536000; includeSubDomains")
+    );
+    assert_eq!(strict_transport_security(600, false).as_deref(), Some("max-age=600"));
+    assert_eq!(strict_transport_security(0, true), None);
+}
+
+#[test]
+fn test_default_swagger_ui_csp() {
+    let html = "<script src=\"https://unpkg.com/ui.js\"></script>\n<script>init();</script>";
+    let hash = inline_script_hash(html).unwrap();
+    assert_eq!(
+        hash,
+        format!("'sha256-{}'", STANDARD.encode(openssl::sha::sha256(b"init();")))
+    );
+    assert!(inline_script_hash("<p>no script</p>").is_none());
+
+    let csp = default_swagger_ui_csp(html);
+    assert!(csp.starts_with("default-src 'none';"));
+    assert!(csp.contains(&hash));
+    assert!(csp.contains("frame-ancestors 'none'"));
+}
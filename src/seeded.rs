@@ -0,0 +1,245 @@
+//! This module provides deterministic key generation from a seed for tests.
+//!
+//! The same seed, algorithm and key size always produce the same key, key ID and (for RSA keys)
+//! certificate, which keeps integration tests and golden files reproducible. Key material is
+//! derived from the seed with a SHA-512 based generator and is therefore only as secret as the
+//! seed. The module is available in unit tests and with the `seeded-keygen` feature, which
+//! cannot be enabled in release builds.
+
+use crate::crypto::{curve_for_alg, ec_jwk_data, eddsa_jwk_data, oct_jwk_data, oct_key_size, rsa_jwk_data};
+use crate::models::JwkData;
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::ec::{EcGroup, EcKey, EcPoint};
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey, Private};
+use openssl::rsa::Rsa;
+use std::error::Error;
+
+/// Public exponent of seeded RSA keys.
+const RSA_PUBLIC_EXPONENT: u32 = 65537;
+
+/// Miller-Rabin rounds used when searching seeded RSA primes.
+const PRIME_CHECKS: i32 = 64;
+
+/// Start of the validity period of certificates issued for seeded RSA keys (2025-01-01T00:00:00Z).
+const SEEDED_CERTIFICATE_NOT_BEFORE: i64 = 1735689600;
+
+/// Deterministic byte generator: `SHA-512(seed || label || counter)` blocks.
+struct SeededRng<'a> {
+    seed: &'a [u8],
+    label: &'static str,
+    counter: u64,
+}
+
+impl<'a> SeededRng<'a> {
+    fn new(seed: &'a [u8], label: &'static str) -> Self {
+        SeededRng { seed, label, counter: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        let mut out = Vec::with_capacity(len);
+        while out.len() < len {
+            let mut hasher = openssl::sha::Sha512::new();
+            hasher.update(self.seed);
+            hasher.update(self.label.as_bytes());
+            hasher.update(&self.counter.to_be_bytes());
+            self.counter += 1;
+            out.extend_from_slice(&hasher.finish());
+        }
+        out.truncate(len);
+        out
+    }
+}
+
+/// Derives the key ID of a seeded key.
+fn seeded_kid(seed: &[u8]) -> String {
+    let bytes = SeededRng::new(seed, "kid").bytes(16);
+    let mut random = [0u8; 16];
+    random.copy_from_slice(&bytes);
+    uuid::Builder::from_random_bytes(random).into_uuid().to_string()
+}
+
+/// Finds the first prime of `bits` bits at or after a seeded odd starting point with the two
+/// most significant bits set, so the product of two such primes has exactly twice the bits.
+fn seeded_prime(rng: &mut SeededRng, bits: u32, e: &BigNum) -> Result<BigNum, Box<dyn Error>> {
+    let mut ctx = BigNumContext::new()?;
+    let mut bytes = rng.bytes(bits.div_ceil(8) as usize);
+
+    // Drop the excess high bits and set the two top bits and the low bit
+    let excess = bytes.len() as u32 * 8 - bits;
+    bytes[0] &= 0xff >> excess;
+    bytes[0] |= 0xc0 >> excess;
+    if excess == 7 {
+        bytes[1] |= 0x80;
+    }
+    let last = bytes.len() - 1;
+    bytes[last] |= 1;
+
+    let mut candidate = BigNum::from_slice(&bytes)?;
+    let two = BigNum::from_u32(2)?;
+    let one = BigNum::from_u32(1)?;
+    loop {
+        let mut p_minus_1 = BigNum::new()?;
+        p_minus_1.checked_sub(&candidate, &one)?;
+        let mut gcd = BigNum::new()?;
+        gcd.gcd(&p_minus_1, e, &mut ctx)?;
+
+        if gcd == one && candidate.is_prime_fasttest(PRIME_CHECKS, &mut ctx, true)? {
+            return Ok(candidate);
+        }
+
+        let mut next = BigNum::new()?;
+        next.checked_add(&candidate, &two)?;
+        candidate = next;
+        if candidate.num_bits() as u32 > bits {
+            return Err(Box::from("Failed to find a prime"));
+        }
+    }
+}
+
+/// Generates a seeded RSA key pair from two seeded primes.
+fn seeded_rsa(seed: &[u8], key_size: u32) -> Result<Rsa<Private>, Box<dyn Error>> {
+    let mut ctx = BigNumContext::new()?;
+    let e = BigNum::from_u32(RSA_PUBLIC_EXPONENT)?;
+    let one = BigNum::from_u32(1)?;
+
+    let mut rng = SeededRng::new(seed, "rsa");
+    let p = seeded_prime(&mut rng, key_size - key_size / 2, &e)?;
+    let q = seeded_prime(&mut rng, key_size / 2, &e)?;
+    if p == q {
+        return Err(Box::from("Seeded primes must differ"));
+    }
+
+    let mut n = BigNum::new()?;
+    n.checked_mul(&p, &q, &mut ctx)?;
+
+    let mut p_minus_1 = BigNum::new()?;
+    p_minus_1.checked_sub(&p, &one)?;
+    let mut q_minus_1 = BigNum::new()?;
+    q_minus_1.checked_sub(&q, &one)?;
+    let mut phi = BigNum::new()?;
+    phi.checked_mul(&p_minus_1, &q_minus_1, &mut ctx)?;
+
+    let mut d = BigNum::new()?;
+    d.mod_inverse(&e, &phi, &mut ctx)?;
+    let mut dmp1 = BigNum::new()?;
+    dmp1.nnmod(&d, &p_minus_1, &mut ctx)?;
+    let mut dmq1 = BigNum::new()?;
+    dmq1.nnmod(&d, &q_minus_1, &mut ctx)?;
+    let mut iqmp = BigNum::new()?;
+    iqmp.mod_inverse(&q, &p, &mut ctx)?;
+
+    let rsa = Rsa::from_private_components(n, e, d, p, q, dmp1, dmq1, iqmp)?;
+    if !rsa.check_key()? {
+        return Err(Box::from("Seeded RSA key is invalid"));
+    }
+    Ok(rsa)
+}
+
+/// Generates a seeded EC key pair with a private scalar in `[1, order - 1]`.
+fn seeded_ec(seed: &[u8], alg: &str) -> Result<EcKey<Private>, Box<dyn Error>> {
+    let curve = match curve_for_alg(alg) {
+        Some("P-256") => Nid::X9_62_PRIME256V1,
+        Some("P-384") => Nid::SECP384R1,
+        Some("P-521") => Nid::SECP521R1,
+        Some("secp256k1") => Nid::SECP256K1,
+        _ => return Err(Box::from("Unsupported algorithm")),
+    };
+    let group = EcGroup::from_curve_name(curve)?;
+    let mut ctx = BigNumContext::new()?;
+    let one = BigNum::from_u32(1)?;
+
+    let mut order = BigNum::new()?;
+    group.order(&mut order, &mut ctx)?;
+    let mut order_minus_1 = BigNum::new()?;
+    order_minus_1.checked_sub(&order, &one)?;
+
+    // 64 extra bits make the modulo bias negligible
+    let bytes = SeededRng::new(seed, "ec").bytes(order.num_bytes() as usize + 8);
+    let random = BigNum::from_slice(&bytes)?;
+    let mut scalar = BigNum::new()?;
+    scalar.nnmod(&random, &order_minus_1, &mut ctx)?;
+    let mut d = BigNum::new()?;
+    d.checked_add(&scalar, &one)?;
+
+    let mut public = EcPoint::new(&group)?;
+    public.mul_generator(&group, &d, &ctx)?;
+
+    let ec_key = EcKey::from_private_components(&group, &d, &public)?;
+    ec_key.check_key()?;
+    Ok(ec_key)
+}
+
+/// Generates a key pair deterministically from a seed.
+///
+/// # Arguments
+///
+/// * `alg` - Algorithm as accepted by [`crate::crypto::generate_jwk_data`].
+/// * `rsa_key_size` - RSA key size in bits; only used for RSA algorithms.
+/// * `seed` - Seed the key material and key ID are derived from.
+///
+/// # Errors
+///
+/// Returns an error if the algorithm is unsupported (post-quantum keys cannot be seeded) or key
+/// generation fails.
+pub fn generate_seeded_jwk_data(alg: &str, rsa_key_size: u32, seed: &[u8]) -> Result<JwkData, Box<dyn Error>> {
+    // Different algorithms derive unrelated keys from the same seed
+    let seed = [seed, b":", alg.as_bytes(), b":", &rsa_key_size.to_be_bytes()].concat();
+    let kid = seeded_kid(&seed);
+
+    match alg {
+        "RS256" | "RS384" | "RS512" | "RSA-OAEP-256" => rsa_jwk_data(
+            seeded_rsa(&seed, rsa_key_size)?,
+            alg,
+            kid,
+            SEEDED_CERTIFICATE_NOT_BEFORE,
+        ),
+        "ES256" | "ES384" | "ES512" | "ES256K" | "ECDH-ES+A128KW" => ec_jwk_data(seeded_ec(&seed, alg)?, alg, kid),
+        "Ed25519" | "Ed448" => {
+            let (id, len) = if alg == "Ed25519" { (Id::ED25519, 32) } else { (Id::ED448, 57) };
+            let private = SeededRng::new(&seed, "okp").bytes(len);
+            eddsa_jwk_data(PKey::private_key_from_raw_bytes(&private, id)?, alg, kid)
+        }
+        "HS256" | "HS384" | "HS512" => {
+            let size = oct_key_size(alg).ok_or("Unsupported algorithm")?;
+            oct_jwk_data(&SeededRng::new(&seed, "oct").bytes(size), alg, kid)
+        }
+        _ => Err(Box::from("Unsupported algorithm")),
+    }
+}
+
+#[test]
+fn test_generate_seeded_jwk_data_is_deterministic() {
+    use crate::crypto::{sign_with_jwk, verify_with_jwk};
+    use crate::models::Jwk;
+
+    for alg in ["RS256", "ES256", "ES512", "Ed25519", "Ed448"] {
+        let first = generate_seeded_jwk_data(alg, 2048, b"seed").unwrap();
+        let second = generate_seeded_jwk_data(alg, 2048, b"seed").unwrap();
+        let other = generate_seeded_jwk_data(alg, 2048, b"other seed").unwrap();
+
+        assert_eq!(first.kid, second.kid, "{}", alg);
+        assert_eq!(first.private_key, second.private_key, "{}", alg);
+        assert_eq!((&first.n, &first.x, &first.y), (&second.n, &second.x, &second.y), "{}", alg);
+        assert_eq!(first.x5c, second.x5c, "{}", alg);
+        assert_ne!(first.private_key, other.private_key, "{}", alg);
+        assert_ne!(first.kid, other.kid, "{}", alg);
+
+        let signature = sign_with_jwk(&first, b"data").unwrap();
+        assert!(verify_with_jwk(&Jwk::from(first), b"data", &signature).unwrap(), "{}", alg);
+    }
+
+    let rsa = generate_seeded_jwk_data("RS256", 2048, b"seed").unwrap();
+    let n = base64::Engine::decode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, rsa.n.unwrap()).unwrap();
+    assert_eq!(n.len() * 8, 2048);
+
+    assert_ne!(
+        generate_seeded_jwk_data("ES256", 2048, b"seed").unwrap().kid,
+        generate_seeded_jwk_data("ES384", 2048, b"seed").unwrap().kid
+    );
+    assert_eq!(
+        generate_seeded_jwk_data("HS256", 2048, b"seed").unwrap().private_key,
+        generate_seeded_jwk_data("HS256", 2048, b"seed").unwrap().private_key
+    );
+    assert!(generate_seeded_jwk_data("PS256", 2048, b"seed").is_err());
+}
@@ -0,0 +1,397 @@
+//! This module forwards the audit log to a SIEM.
+//!
+//! With `SIEM_SINK` set, a scheduled job reads the audit events recorded since the last forwarded
+//! one and posts them in batches to Splunk (HTTP Event Collector), Elasticsearch (bulk API) or a
+//! generic HTTPS collector (JSON array). The position of the sink in the audit log is stored in
+//! the database, so no event is lost or sent twice when the service restarts or another replica
+//! takes over the scheduler. A batch that cannot be delivered is retried with exponential backoff
+//! and, if it keeps failing, again at the next run.
+
+use crate::audit::AuditEvent;
+use crate::jobs::replica_id;
+use crate::schema::{audit_log, jwks, siem_cursors};
+use chrono::NaiveDateTime;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use dotenv::dotenv;
+use serde::Serialize;
+use serde_json::json;
+use std::env;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Name of the scheduled SIEM forwarding job.
+pub const SIEM_JOB: &str = "siem-forwarding";
+
+/// Source reported with every forwarded event.
+const EVENT_SOURCE: &str = "jwks-service-app";
+
+/// Delay before the first retry of a failed batch; doubled for every further retry.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Kind of SIEM the audit log is forwarded to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SiemSink {
+    /// Splunk HTTP Event Collector.
+    Splunk,
+    /// Elasticsearch bulk API.
+    Elastic,
+    /// Generic HTTPS collector accepting a JSON array of events.
+    Https,
+}
+
+impl SiemSink {
+    /// Returns the name of the sink, as configured in `SIEM_SINK`.
+    pub fn name(self) -> &'static str {
+        match self {
+            SiemSink::Splunk => "splunk",
+            SiemSink::Elastic => "elastic",
+            SiemSink::Https => "https",
+        }
+    }
+}
+
+/// Settings of the SIEM forwarding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SiemSettings {
+    /// Kind of SIEM.
+    pub sink: SiemSink,
+    /// Endpoint the batches are posted to.
+    pub url: String,
+    /// Credential of the endpoint: the HEC token, the Elasticsearch API key or a bearer token.
+    pub token: Option<String>,
+    /// Elasticsearch index the events are written to.
+    pub index: String,
+    /// Maximum number of events per request.
+    pub batch_size: i64,
+    /// Number of retries of a failed batch within a run.
+    pub max_retries: u32,
+}
+
+/// Audit event as forwarded to the SIEM.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct SiemEvent {
+    /// Unique event identifier.
+    pub id: Uuid,
+    /// Identifier of the affected key.
+    pub key_id: Uuid,
+    /// Key ID of the affected key, if it still exists.
+    pub kid: Option<String>,
+    /// Performed action (e.g., "create").
+    pub action: String,
+    /// Caller that performed the action, if known.
+    pub actor: Option<String>,
+    /// Time of the event.
+    pub occurred_at: NaiveDateTime,
+    /// Service that recorded the event.
+    pub source: &'static str,
+    /// Replica that forwarded the event.
+    pub replica: String,
+}
+
+/// Returns the settings of the SIEM forwarding: `SIEM_SINK` (`splunk`, `elastic` or `https`),
+/// `SIEM_URL`, `SIEM_TOKEN`, `SIEM_ELASTIC_INDEX` (default `jwks-audit`), `SIEM_BATCH_SIZE`
+/// (default 100) and `SIEM_MAX_RETRIES` (default 3).
+///
+/// # Returns
+///
+/// `None` if `SIEM_SINK` is not set, so the audit log is not forwarded.
+///
+/// # Panics
+///
+/// This function will panic if the sink is unknown, `SIEM_URL` is missing or a number is invalid.
+pub fn siem_settings() -> Option<SiemSettings> {
+    dotenv().ok();
+
+    let sink = match env::var("SIEM_SINK").unwrap_or_default().trim() {
+        "" => return None,
+        "splunk" => SiemSink::Splunk,
+        "elastic" => SiemSink::Elastic,
+        "https" => SiemSink::Https,
+        other => panic!("Unknown SIEM_SINK {}, expected splunk, elastic or https", other),
+    };
+    let url = env::var("SIEM_URL")
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+        .expect("SIEM_URL must be set if SIEM_SINK is set");
+
+    Some(SiemSettings {
+        sink,
+        url,
+        token: env::var("SIEM_TOKEN").ok().filter(|value| !value.trim().is_empty()),
+        index: env::var("SIEM_ELASTIC_INDEX").unwrap_or_else(|_| "jwks-audit".to_string()),
+        batch_size: env::var("SIEM_BATCH_SIZE")
+            .unwrap_or_else(|_| "100".to_string())
+            .parse()
+            .ok()
+            .filter(|size| *size > 0)
+            .expect("SIEM_BATCH_SIZE must be a positive number"),
+        max_retries: env::var("SIEM_MAX_RETRIES")
+            .unwrap_or_else(|_| "3".to_string())
+            .parse()
+            .expect("SIEM_MAX_RETRIES must be a non-negative number"),
+    })
+}
+
+/// Returns the interval between SIEM forwarding runs (`SIEM_INTERVAL_SECONDS`, default 5).
+///
+/// # Panics
+///
+/// This function will panic if `SIEM_INTERVAL_SECONDS` is not a positive number.
+pub fn siem_interval() -> Duration {
+    dotenv().ok();
+
+    let seconds: u64 = env::var("SIEM_INTERVAL_SECONDS")
+        .unwrap_or_else(|_| "5".to_string())
+        .parse()
+        .ok()
+        .filter(|seconds| *seconds > 0)
+        .expect("SIEM_INTERVAL_SECONDS must be a positive number");
+
+    Duration::from_secs(seconds)
+}
+
+/// Formats a batch of events as the request body of a sink.
+///
+/// # Returns
+///
+/// The content type and the body.
+pub fn format_batch(settings: &SiemSettings, events: &[SiemEvent]) -> (&'static str, String) {
+    match settings.sink {
+        // HEC accepts concatenated event objects
+        SiemSink::Splunk => {
+            let body = events
+                .iter()
+                .map(|event| {
+                    json!({
+                        "time": event.occurred_at.and_utc().timestamp_millis() as f64 / 1000.0,
+                        "host": event.replica,
+                        "source": EVENT_SOURCE,
+                        "sourcetype": "jwks:audit",
+                        "event": event,
+                    })
+                    .to_string()
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            ("application/json", body)
+        }
+        // The event ID as document ID makes a resent batch idempotent
+        SiemSink::Elastic => {
+            let mut body = String::new();
+            for event in events {
+                body.push_str(&json!({ "create": { "_index": settings.index, "_id": event.id } }).to_string());
+                body.push('\n');
+                let mut document = serde_json::to_value(event).unwrap_or_default();
+                document["@timestamp"] = json!(event.occurred_at.and_utc().to_rfc3339());
+                body.push_str(&document.to_string());
+                body.push('\n');
+            }
+            ("application/x-ndjson", body)
+        }
+        SiemSink::Https => ("application/json", serde_json::to_string(events).unwrap_or_default()),
+    }
+}
+
+/// Returns the `Authorization` header value of a sink, if a token is configured.
+fn authorization(settings: &SiemSettings) -> Option<String> {
+    let token = settings.token.as_ref()?;
+    Some(match settings.sink {
+        SiemSink::Splunk => format!("Splunk {}", token),
+        SiemSink::Elastic => format!("ApiKey {}", token),
+        SiemSink::Https => format!("Bearer {}", token),
+    })
+}
+
+/// Posts a batch of events to the sink.
+///
+/// # Errors
+///
+/// Returns a message if the request fails, the sink responds with an error status or, for
+/// Elasticsearch, rejects an event other than one that was already indexed.
+pub fn send_batch(settings: &SiemSettings, events: &[SiemEvent]) -> Result<(), String> {
+    let (content_type, body) = format_batch(settings, events);
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut request = client
+        .post(&settings.url)
+        .header(reqwest::header::CONTENT_TYPE, content_type)
+        .body(body);
+    if let Some(value) = authorization(settings) {
+        request = request.header(reqwest::header::AUTHORIZATION, value);
+    }
+    let response = request
+        .send()
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?;
+
+    if settings.sink == SiemSink::Elastic {
+        let result: serde_json::Value = response.json().map_err(|e| e.to_string())?;
+        let rejected = result["items"]
+            .as_array()
+            .map(|items| {
+                items
+                    .iter()
+                    .filter(|item| item["create"]["status"].as_u64().is_some_and(|status| status >= 300 && status != 409))
+                    .count()
+            })
+            .unwrap_or(0);
+        if rejected > 0 {
+            return Err(format!("Elasticsearch rejected {} events", rejected));
+        }
+    }
+
+    Ok(())
+}
+
+/// Loads the audit events recorded after the position of a sink, oldest first.
+fn load_events(
+    connection: &mut PgConnection,
+    sink: SiemSink,
+    limit: i64,
+) -> QueryResult<Vec<AuditEvent>> {
+    let cursor = siem_cursors::table
+        .find(sink.name())
+        .select((siem_cursors::occurred_at, siem_cursors::event_id))
+        .first::<(NaiveDateTime, Uuid)>(connection)
+        .optional()?;
+
+    let mut query = audit_log::table.select(AuditEvent::as_select()).into_boxed();
+    if let Some((occurred_at, event_id)) = cursor {
+        query = query.filter(
+            audit_log::occurred_at
+                .gt(occurred_at)
+                .or(audit_log::occurred_at.eq(occurred_at).and(audit_log::id.gt(event_id))),
+        );
+    }
+    query
+        .order((audit_log::occurred_at.asc(), audit_log::id.asc()))
+        .limit(limit)
+        .load(connection)
+}
+
+/// Stores the position of a sink after the last forwarded event.
+fn advance_cursor(connection: &mut PgConnection, sink: SiemSink, event: &AuditEvent) -> QueryResult<()> {
+    diesel::insert_into(siem_cursors::table)
+        .values((
+            siem_cursors::sink.eq(sink.name()),
+            siem_cursors::occurred_at.eq(event.occurred_at),
+            siem_cursors::event_id.eq(event.id),
+        ))
+        .on_conflict(siem_cursors::sink)
+        .do_update()
+        .set((
+            siem_cursors::occurred_at.eq(event.occurred_at),
+            siem_cursors::event_id.eq(event.id),
+        ))
+        .execute(connection)
+        .map(|_| ())
+}
+
+/// Forwards the audit events recorded since the last run to the configured SIEM.
+///
+/// Batches are sent until the sink has caught up with the audit log. A failed batch is retried
+/// up to `SIEM_MAX_RETRIES` times, waiting twice as long before every retry.
+///
+/// # Errors
+///
+/// Returns a message if the events cannot be loaded, a batch cannot be delivered or the position
+/// cannot be stored; the remaining events are forwarded by the next run.
+pub fn forward_audit_events(connection: &mut PgConnection) -> Result<(), String> {
+    let Some(settings) = siem_settings() else {
+        return Ok(());
+    };
+
+    loop {
+        let events = load_events(connection, settings.sink, settings.batch_size).map_err(|e| e.to_string())?;
+        let Some(last) = events.last() else {
+            return Ok(());
+        };
+
+        let key_ids = events.iter().map(|event| event.key_id).collect::<Vec<_>>();
+        let kids = jwks::table
+            .filter(jwks::id.eq_any(&key_ids))
+            .select((jwks::id, jwks::kid))
+            .load::<(Uuid, String)>(connection)
+            .map_err(|e| e.to_string())?;
+        let batch = events
+            .iter()
+            .map(|event| SiemEvent {
+                id: event.id,
+                key_id: event.key_id,
+                kid: kids.iter().find(|(key_id, _)| *key_id == event.key_id).map(|(_, kid)| kid.clone()),
+                action: event.action.clone(),
+                actor: event.actor.clone(),
+                occurred_at: event.occurred_at,
+                source: EVENT_SOURCE,
+                replica: replica_id().to_string(),
+            })
+            .collect::<Vec<_>>();
+
+        let mut attempt = 0;
+        while let Err(message) = send_batch(&settings, &batch) {
+            if attempt >= settings.max_retries {
+                return Err(format!("failed to deliver {} events to {}: {}", batch.len(), settings.sink.name(), message));
+            }
+            std::thread::sleep(RETRY_DELAY * 2u32.pow(attempt));
+            attempt += 1;
+        }
+
+        advance_cursor(connection, settings.sink, last).map_err(|e| e.to_string())?;
+        if (events.len() as i64) < settings.batch_size {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_settings(sink: SiemSink) -> SiemSettings {
+    SiemSettings {
+        sink,
+        url: "https://siem.example.com".to_string(),
+        token: Some("secret".to_string()),
+        index: "jwks-audit".to_string(),
+        batch_size: 100,
+        max_retries: 3,
+    }
+}
+
+#[test]
+fn test_format_batch() {
+    let event = SiemEvent {
+        id: Uuid::new_v4(),
+        key_id: Uuid::new_v4(),
+        kid: Some("key-1".to_string()),
+        action: "create".to_string(),
+        actor: Some("ops@example.com".to_string()),
+        occurred_at: chrono::DateTime::from_timestamp(1_700_000_000, 500_000_000).unwrap().naive_utc(),
+        source: EVENT_SOURCE,
+        replica: "replica-1".to_string(),
+    };
+    let events = [event.clone(), event.clone()];
+
+    let (content_type, body) = format_batch(&test_settings(SiemSink::Splunk), &events);
+    assert_eq!(content_type, "application/json");
+    let lines = body.lines().map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()).collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["time"], json!(1_700_000_000.5));
+    assert_eq!(lines[0]["sourcetype"], "jwks:audit");
+    assert_eq!(lines[0]["event"]["kid"], "key-1");
+
+    let (content_type, body) = format_batch(&test_settings(SiemSink::Elastic), &events);
+    assert_eq!(content_type, "application/x-ndjson");
+    let lines = body.lines().map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()).collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0]["create"]["_id"], json!(event.id));
+    assert_eq!(lines[1]["action"], "create");
+    assert!(lines[1]["@timestamp"].as_str().unwrap().starts_with("2023-11-14T22:13:20.5"));
+
+    let (_, body) = format_batch(&test_settings(SiemSink::Https), &events);
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&body).unwrap().as_array().unwrap().len(), 2);
+
+    assert_eq!(authorization(&test_settings(SiemSink::Splunk)).unwrap(), "Splunk secret");
+    assert_eq!(authorization(&test_settings(SiemSink::Elastic)).unwrap(), "ApiKey secret");
+    assert_eq!(authorization(&test_settings(SiemSink::Https)).unwrap(), "Bearer secret");
+}
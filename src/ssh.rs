@@ -0,0 +1,577 @@
+//! This module signs OpenSSH user and host certificates with stored SSH CA keys and exports
+//! SSH keys in `authorized_keys` format.
+//!
+//! Ed25519 keys of purpose `ssh-ca` act as SSH certificate authorities. Certificates are built
+//! according to the OpenSSH certificate format (`PROTOCOL.certkeys`) and signed with the current
+//! CA key: the primary `ssh-ca` key, else the most recent one. CA keys are stored, rotated and
+//! audited like every other key, so rotating the CA only requires trusting the new key on the
+//! SSH servers before the old one expires.
+//!
+//! Keys of purpose `ssh` (Ed25519 or RSA) and `ssh-ca` are also served as OpenSSH public keys, so
+//! provisioning tooling can install them without converting from JWK. CA keys are marked with the
+//! `cert-authority` option.
+
+use crate::audit::{record_event, request_actor, ACTION_SSH_CERTIFY};
+use crate::crypto::sign_with_jwk;
+use crate::db::establish_connection;
+use crate::log_error;
+use crate::models::{JwkData, SshCertificate, SshCertificateInput};
+use crate::policy::{SSH_CA_PURPOSE, SSH_PURPOSE};
+use crate::schema::jwks;
+use crate::store::KeyStore;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use dotenv::dotenv;
+use std::env;
+use std::error::Error;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Public key types that can be certified.
+const SUBJECT_KEY_TYPES: [&str; 5] = [
+    "ssh-ed25519",
+    "ssh-rsa",
+    "ecdsa-sha2-nistp256",
+    "ecdsa-sha2-nistp384",
+    "ecdsa-sha2-nistp521",
+];
+
+/// Certificate type of user certificates.
+pub const SSH_CERT_TYPE_USER: u32 = 1;
+
+/// Certificate type of host certificates.
+pub const SSH_CERT_TYPE_HOST: u32 = 2;
+
+/// Extensions of user certificates if none are requested, as issued by `ssh-keygen`.
+const DEFAULT_USER_EXTENSIONS: [&str; 5] = [
+    "permit-X11-forwarding",
+    "permit-agent-forwarding",
+    "permit-port-forwarding",
+    "permit-pty",
+    "permit-user-rc",
+];
+
+/// How far the start of the validity is backdated to tolerate clock skew, in seconds.
+const CLOCK_SKEW_SECONDS: i64 = 60;
+
+/// Returns the default validity of certificates in seconds (`SSH_CERTIFICATE_TTL_SECONDS`,
+/// default 3600).
+///
+/// # Panics
+///
+/// This function will panic if `SSH_CERTIFICATE_TTL_SECONDS` is not a positive number.
+pub fn ssh_certificate_ttl_seconds() -> i64 {
+    dotenv().ok();
+
+    env::var("SSH_CERTIFICATE_TTL_SECONDS")
+        .unwrap_or_else(|_| "3600".to_string())
+        .parse()
+        .ok()
+        .filter(|seconds| *seconds > 0)
+        .expect("SSH_CERTIFICATE_TTL_SECONDS must be a positive number")
+}
+
+/// Returns the longest validity of certificates in seconds (`SSH_CERTIFICATE_MAX_TTL_SECONDS`,
+/// default 86400).
+///
+/// # Panics
+///
+/// This function will panic if `SSH_CERTIFICATE_MAX_TTL_SECONDS` is not a positive number.
+pub fn ssh_certificate_max_ttl_seconds() -> i64 {
+    dotenv().ok();
+
+    env::var("SSH_CERTIFICATE_MAX_TTL_SECONDS")
+        .unwrap_or_else(|_| "86400".to_string())
+        .parse()
+        .ok()
+        .filter(|seconds| *seconds > 0)
+        .expect("SSH_CERTIFICATE_MAX_TTL_SECONDS must be a positive number")
+}
+
+/// Appends an SSH `string` (RFC 4251, section 5): the length as `uint32`, then the bytes.
+pub fn put_string(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+/// Appends an SSH `mpint` (RFC 4251, section 5) of a non-negative big-endian integer.
+pub fn put_mpint(out: &mut Vec<u8>, bytes: &[u8]) {
+    let start = bytes.iter().position(|byte| *byte != 0).unwrap_or(bytes.len());
+    let magnitude = &bytes[start..];
+    // A set high bit would make the number negative
+    if magnitude.first().is_some_and(|byte| byte & 0x80 != 0) {
+        let mut padded = vec![0];
+        padded.extend_from_slice(magnitude);
+        put_string(out, &padded);
+    } else {
+        put_string(out, magnitude);
+    }
+}
+
+/// Reads an SSH `string` at the position, advancing the position past it.
+fn read_string<'a>(data: &'a [u8], position: &mut usize) -> Option<&'a [u8]> {
+    let length = u32::from_be_bytes(data.get(*position..*position + 4)?.try_into().ok()?) as usize;
+    let value = data.get(*position + 4..*position + 4 + length)?;
+    *position += 4 + length;
+    Some(value)
+}
+
+/// Parses an OpenSSH public key (`<type> <base64> [comment]`).
+///
+/// # Returns
+///
+/// The key type and the key blob.
+///
+/// # Errors
+///
+/// Returns a message if the key is malformed or of a type that cannot be certified.
+pub fn parse_public_key(line: &str) -> Result<(String, Vec<u8>), String> {
+    let mut fields = line.split_whitespace();
+    let key_type = fields.next().ok_or("Empty public key")?;
+    if !SUBJECT_KEY_TYPES.contains(&key_type) {
+        return Err(format!(
+            "Unsupported public key type {}, expected one of: {}",
+            key_type,
+            SUBJECT_KEY_TYPES.join(", ")
+        ));
+    }
+    let blob = fields
+        .next()
+        .and_then(|encoded| STANDARD.decode(encoded).ok())
+        .ok_or("Public key is not base64 encoded")?;
+
+    // The blob repeats the key type
+    let mut position = 0;
+    if read_string(&blob, &mut position) != Some(key_type.as_bytes()) {
+        return Err("Public key type does not match the key".to_string());
+    }
+
+    Ok((key_type.to_string(), blob))
+}
+
+/// Returns the OpenSSH public key blob of an Ed25519 key.
+///
+/// # Errors
+///
+/// Returns an error if the key is not an Ed25519 key.
+pub fn ed25519_public_key_blob(jwk: &JwkData) -> Result<Vec<u8>, Box<dyn Error>> {
+    if jwk.kty != "OKP" || jwk.crv.as_deref() != Some("Ed25519") {
+        return Err(Box::from("Key is not an Ed25519 key"));
+    }
+    let x = URL_SAFE_NO_PAD.decode(jwk.x.as_deref().ok_or("Missing JWK parameter x")?)?;
+
+    let mut blob = Vec::new();
+    put_string(&mut blob, b"ssh-ed25519");
+    put_string(&mut blob, &x);
+    Ok(blob)
+}
+
+/// Returns the OpenSSH public key blob of an RSA key.
+///
+/// # Errors
+///
+/// Returns an error if the key is not an RSA key.
+pub fn rsa_public_key_blob(jwk: &JwkData) -> Result<Vec<u8>, Box<dyn Error>> {
+    if jwk.kty != "RSA" {
+        return Err(Box::from("Key is not an RSA key"));
+    }
+    let n = URL_SAFE_NO_PAD.decode(jwk.n.as_deref().ok_or("Missing JWK parameter n")?)?;
+    let e = URL_SAFE_NO_PAD.decode(jwk.e.as_deref().ok_or("Missing JWK parameter e")?)?;
+
+    let mut blob = Vec::new();
+    put_string(&mut blob, b"ssh-rsa");
+    put_mpint(&mut blob, &e);
+    put_mpint(&mut blob, &n);
+    Ok(blob)
+}
+
+/// Formats the public part of an Ed25519 or RSA key as an `authorized_keys` line, with the key
+/// ID as comment. Lines of `ssh-ca` keys carry the `cert-authority` option.
+///
+/// # Errors
+///
+/// Returns an error if the key is neither an Ed25519 nor an RSA key.
+pub fn authorized_key_line(jwk: &JwkData) -> Result<String, Box<dyn Error>> {
+    let (key_type, blob) = match jwk.kty.as_str() {
+        "OKP" => ("ssh-ed25519", ed25519_public_key_blob(jwk)?),
+        "RSA" => ("ssh-rsa", rsa_public_key_blob(jwk)?),
+        _ => return Err(Box::from("Key type has no OpenSSH representation")),
+    };
+    let options = if jwk.purpose.as_deref() == Some(SSH_CA_PURPOSE) { "cert-authority " } else { "" };
+
+    Ok(format!("{}{} {} {}", options, key_type, STANDARD.encode(blob), jwk.kid))
+}
+
+/// Contents of a certificate, apart from the certified key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CertificateFields {
+    /// Serial number.
+    pub serial: u64,
+    /// [`SSH_CERT_TYPE_USER`] or [`SSH_CERT_TYPE_HOST`].
+    pub cert_type: u32,
+    /// Key identity.
+    pub identity: String,
+    /// Principals the certificate is valid for.
+    pub principals: Vec<String>,
+    /// Start of the validity as a Unix timestamp.
+    pub valid_after: u64,
+    /// End of the validity as a Unix timestamp.
+    pub valid_before: u64,
+    /// Names of the extensions (flags without data).
+    pub extensions: Vec<String>,
+}
+
+/// Builds and signs an OpenSSH certificate.
+///
+/// # Arguments
+///
+/// * `ca_key` - Ed25519 CA key, including its private part.
+/// * `subject_type` - Type of the certified key (e.g., "ssh-ed25519").
+/// * `subject_blob` - Public key blob of the certified key.
+/// * `fields` - Contents of the certificate.
+/// * `nonce` - Random nonce.
+///
+/// # Returns
+///
+/// The certificate in OpenSSH format, with the identity as comment.
+///
+/// # Errors
+///
+/// Returns an error if the CA key is not an Ed25519 key, the certified key is malformed or
+/// signing fails.
+pub fn sign_certificate(
+    ca_key: &JwkData,
+    subject_type: &str,
+    subject_blob: &[u8],
+    fields: &CertificateFields,
+    nonce: &[u8],
+) -> Result<String, Box<dyn Error>> {
+    let ca_blob = ed25519_public_key_blob(ca_key)?;
+    let certificate_type = format!("{}-cert-v01@openssh.com", subject_type);
+
+    // The public key fields of the certified key follow its type in the blob
+    let mut position = 0;
+    read_string(subject_blob, &mut position).ok_or("Malformed public key")?;
+
+    let mut certificate = Vec::new();
+    put_string(&mut certificate, certificate_type.as_bytes());
+    put_string(&mut certificate, nonce);
+    certificate.extend_from_slice(&subject_blob[position..]);
+    certificate.extend_from_slice(&fields.serial.to_be_bytes());
+    certificate.extend_from_slice(&fields.cert_type.to_be_bytes());
+    put_string(&mut certificate, fields.identity.as_bytes());
+    let mut principals = Vec::new();
+    for principal in &fields.principals {
+        put_string(&mut principals, principal.as_bytes());
+    }
+    put_string(&mut certificate, &principals);
+    certificate.extend_from_slice(&fields.valid_after.to_be_bytes());
+    certificate.extend_from_slice(&fields.valid_before.to_be_bytes());
+    // No critical options
+    put_string(&mut certificate, b"");
+    // Extensions must be sorted by name
+    let mut names = fields.extensions.clone();
+    names.sort();
+    names.dedup();
+    let mut extensions = Vec::new();
+    for name in &names {
+        put_string(&mut extensions, name.as_bytes());
+        put_string(&mut extensions, b"");
+    }
+    put_string(&mut certificate, &extensions);
+    // Reserved
+    put_string(&mut certificate, b"");
+    put_string(&mut certificate, &ca_blob);
+
+    let mut signature = Vec::new();
+    put_string(&mut signature, b"ssh-ed25519");
+    put_string(&mut signature, &sign_with_jwk(ca_key, &certificate)?);
+    put_string(&mut certificate, &signature);
+
+    Ok(format!(
+        "{} {} {}",
+        certificate_type,
+        STANDARD.encode(certificate),
+        fields.identity
+    ))
+}
+
+/// Loads the current SSH CA key: the primary active `ssh-ca` key, else the most recent one.
+fn find_ssh_ca_key(connection: &mut PgConnection) -> QueryResult<Option<JwkData>> {
+    let now = Utc::now().naive_utc();
+
+    jwks::table
+        .filter(jwks::purpose.eq(SSH_CA_PURPOSE))
+        .filter(jwks::deleted_at.is_null())
+        .filter(jwks::frozen_at.is_null())
+        .filter(jwks::key_expires_at.gt(now))
+        .filter(jwks::not_before.is_null().or(jwks::not_before.le(now)))
+        .filter(jwks::private_key_expires_at.is_null().or(jwks::private_key_expires_at.gt(now)))
+        .filter(jwks::external.eq(false))
+        .order((jwks::is_primary.desc(), jwks::created_at.desc()))
+        .first::<JwkData>(connection)
+        .optional()
+}
+
+/// Converts a Unix timestamp of a certificate to a date and time.
+fn certificate_time(timestamp: u64) -> NaiveDateTime {
+    DateTime::from_timestamp(timestamp as i64, 0)
+        .unwrap_or_default()
+        .naive_utc()
+}
+
+/// Handles the request to sign an SSH user or host certificate with the current SSH CA key.
+///
+/// # Arguments
+///
+/// * `req` - The request; its `X-Actor` header is recorded in the audit log.
+/// * `input` - The public key to certify, the certificate type, identity, principals, validity
+///   and extensions.
+///
+/// # Returns
+///
+/// A JSON response containing the certificate in OpenSSH format, or an error message.
+#[utoipa::path(
+    post,
+    path = "/ssh/certificates",
+    request_body = SshCertificateInput,
+    responses(
+        (status = 201, description = "Certificate signed", body = SshCertificate),
+        (status = 400, description = "Invalid public key, certificate type, principals or validity", body = String, content_type = "text/plain"),
+        (status = 404, description = "No active SSH CA key", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to sign the certificate", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn ssh_certificate_handler(req: HttpRequest, input: web::Json<SshCertificateInput>) -> impl Responder {
+    let (subject_type, subject_blob) = match parse_public_key(&input.public_key) {
+        Ok(subject) => subject,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+    let cert_type = match input.cert_type.as_deref().unwrap_or("user") {
+        "user" => SSH_CERT_TYPE_USER,
+        "host" => SSH_CERT_TYPE_HOST,
+        other => {
+            return HttpResponse::BadRequest().body(format!("Unknown certificate type {}, expected user or host", other))
+        }
+    };
+    // Certificates without principals are valid for every user or host
+    if input.principals.is_empty() || input.principals.iter().any(|principal| principal.trim().is_empty()) {
+        return HttpResponse::BadRequest().body("At least one non-empty principal is required");
+    }
+    let ttl = input.ttl_seconds.unwrap_or_else(ssh_certificate_ttl_seconds);
+    let max_ttl = ssh_certificate_max_ttl_seconds();
+    if ttl <= 0 || ttl > max_ttl {
+        return HttpResponse::BadRequest().body(format!("ttl_seconds must be between 1 and {}", max_ttl));
+    }
+    let extensions = match (cert_type, &input.extensions) {
+        (SSH_CERT_TYPE_HOST, _) => Vec::new(),
+        (_, Some(extensions)) => extensions.clone(),
+        (_, None) => DEFAULT_USER_EXTENSIONS.iter().map(|name| name.to_string()).collect(),
+    };
+
+    let connection = &mut establish_connection();
+    let ca_key = match find_ssh_ca_key(connection) {
+        Ok(Some(ca_key)) => ca_key,
+        Ok(None) => return HttpResponse::NotFound().body("No active SSH CA key"),
+        Err(_) => return HttpResponse::InternalServerError().body("Failed to load the SSH CA key"),
+    };
+
+    let mut random = [0u8; 40];
+    if openssl::rand::rand_bytes(&mut random).is_err() {
+        return HttpResponse::InternalServerError().body("Failed to generate the certificate nonce");
+    }
+    let (nonce, serial) = random.split_at(32);
+    let now = Utc::now().timestamp();
+    let fields = CertificateFields {
+        serial: u64::from_be_bytes(serial.try_into().unwrap()),
+        cert_type,
+        identity: input.identity.clone(),
+        principals: input.principals.clone(),
+        valid_after: (now - CLOCK_SKEW_SECONDS) as u64,
+        valid_before: (now + ttl) as u64,
+        extensions,
+    };
+
+    let certificate = match sign_certificate(&ca_key, &subject_type, &subject_blob, &fields, nonce) {
+        Ok(certificate) => certificate,
+        Err(_) => return HttpResponse::InternalServerError().body("Failed to sign the certificate"),
+    };
+
+    if let Err(error) = record_event(connection, ca_key.id, ACTION_SSH_CERTIFY, request_actor(&req)) {
+        log_error!("Failed to record audit event for key {}: {}", ca_key.id, error);
+    }
+
+    HttpResponse::Created().json(SshCertificate {
+        certificate,
+        serial: fields.serial,
+        valid_after: certificate_time(fields.valid_after),
+        valid_before: certificate_time(fields.valid_before),
+        ca_kid: ca_key.kid,
+    })
+}
+
+/// Loads the active SSH and SSH CA keys, oldest first.
+fn load_ssh_keys(store: &dyn KeyStore) -> Result<Vec<JwkData>, String> {
+    let mut keys = store.list_active(None, Some(SSH_PURPOSE.to_string()))?;
+    keys.extend(store.list_active(None, Some(SSH_CA_PURPOSE.to_string()))?);
+    keys.sort_by_key(|jwk| jwk.created_at);
+    Ok(keys)
+}
+
+/// Handles the request to retrieve the active SSH and SSH CA keys in `authorized_keys` format.
+///
+/// # Returns
+///
+/// One OpenSSH public key per line, commented with the key ID, or an error message.
+#[utoipa::path(
+    get,
+    path = "/ssh/authorized_keys",
+    responses(
+        (status = 200, description = "Active keys of purpose ssh and ssh-ca, one per line", body = String, content_type = "text/plain"),
+        (status = 500, description = "Failed to load the keys", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn authorized_keys_handler(store: web::Data<Arc<dyn KeyStore>>) -> impl Responder {
+    let keys = match web::block(move || load_ssh_keys(store.as_ref().as_ref())).await {
+        Ok(Ok(keys)) => keys,
+        _ => return HttpResponse::InternalServerError().body("Failed to load the SSH keys"),
+    };
+
+    let mut body = String::new();
+    for key in &keys {
+        match authorized_key_line(key) {
+            Ok(line) => {
+                body.push_str(&line);
+                body.push('\n');
+            }
+            Err(error) => log_error!("Failed to export SSH key {}: {}", key.id, error),
+        }
+    }
+
+    HttpResponse::Ok().content_type("text/plain; charset=utf-8").body(body)
+}
+
+/// Handles the request to retrieve the public part of an SSH key as an `authorized_keys` line.
+///
+/// # Arguments
+///
+/// * `key_id` - The unique identifier of the key.
+///
+/// # Returns
+///
+/// The OpenSSH public key, `404 Not Found` if the key does not exist or is not active, or
+/// `422 Unprocessable Entity` if it is not designated for SSH use.
+#[utoipa::path(
+    get,
+    path = "/jwks/{id}/ssh",
+    params(
+        ("id" = String, Path, description = "Unique key identifier")
+    ),
+    responses(
+        (status = 200, description = "OpenSSH public key in authorized_keys format", body = String, content_type = "text/plain"),
+        (status = 404, description = "Key not found", body = String, content_type = "text/plain"),
+        (status = 422, description = "Key is not of purpose ssh or ssh-ca", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn ssh_public_key_handler(store: web::Data<Arc<dyn KeyStore>>, key_id: web::Path<Uuid>) -> impl Responder {
+    let key_id = key_id.into_inner();
+    let key = match web::block(move || store.get_by_id(key_id)).await {
+        Ok(Ok(Some(key))) if key.is_published(None, Utc::now().naive_utc()) => key,
+        _ => return HttpResponse::NotFound().body("Key not found"),
+    };
+    if !matches!(key.purpose.as_deref(), Some(SSH_PURPOSE | SSH_CA_PURPOSE)) {
+        return HttpResponse::UnprocessableEntity().body("Key is not designated for SSH use");
+    }
+
+    match authorized_key_line(&key) {
+        Ok(line) => HttpResponse::Ok().content_type("text/plain; charset=utf-8").body(line + "\n"),
+        Err(error) => HttpResponse::UnprocessableEntity().body(error.to_string()),
+    }
+}
+
+#[test]
+fn test_parse_public_key() {
+    let line = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIHLv64DJzq/ZgpOZKvoMUF7a92uqLo5OGtXc+gFZ/JTy alice@laptop";
+    let (key_type, blob) = parse_public_key(line).unwrap();
+    assert_eq!(key_type, "ssh-ed25519");
+    assert_eq!(blob.len(), 4 + 11 + 4 + 32);
+
+    assert!(parse_public_key("ssh-dss AAAAB3NzaC1kc3M=").is_err());
+    assert!(parse_public_key("ssh-rsa AAAAC3NzaC1lZDI1NTE5AAAAIHLv64DJzq/ZgpOZKvoMUF7a92uqLo5OGtXc+gFZ/JTy").is_err());
+    assert!(parse_public_key("ssh-ed25519 not-base64!").is_err());
+}
+
+#[test]
+fn test_sign_certificate() {
+    use crate::crypto::{generate_jwk_data, verify_with_jwk};
+    use crate::models::Jwk;
+
+    let ca_key = generate_jwk_data("Ed25519", 2048).unwrap();
+    let subject = generate_jwk_data("Ed25519", 2048).unwrap();
+    let subject_blob = ed25519_public_key_blob(&subject).unwrap();
+    let fields = CertificateFields {
+        serial: 42,
+        cert_type: SSH_CERT_TYPE_USER,
+        identity: "alice@example.com".to_string(),
+        principals: vec!["alice".to_string()],
+        valid_after: 1_700_000_000,
+        valid_before: 1_700_003_600,
+        extensions: vec!["permit-pty".to_string()],
+    };
+
+    let line = sign_certificate(&ca_key, "ssh-ed25519", &subject_blob, &fields, &[7; 32]).unwrap();
+    let mut parts = line.split(' ');
+    assert_eq!(parts.next(), Some("ssh-ed25519-cert-v01@openssh.com"));
+    let certificate = STANDARD.decode(parts.next().unwrap()).unwrap();
+    assert_eq!(parts.next(), Some("alice@example.com"));
+
+    // Type, nonce and the certified key
+    let mut position = 0;
+    assert_eq!(read_string(&certificate, &mut position), Some(&b"ssh-ed25519-cert-v01@openssh.com"[..]));
+    assert_eq!(read_string(&certificate, &mut position), Some(&[7u8; 32][..]));
+    assert_eq!(read_string(&certificate, &mut position), Some(&subject_blob[19..]));
+    assert_eq!(&certificate[position..position + 8], &42u64.to_be_bytes());
+
+    // The signature covers everything before it and verifies with the CA key
+    let signature_start = certificate.len() - (4 + 4 + 11 + 4 + 64);
+    let mut position = signature_start;
+    let signature = read_string(&certificate, &mut position).unwrap();
+    let mut signature_position = 0;
+    assert_eq!(read_string(signature, &mut signature_position), Some(&b"ssh-ed25519"[..]));
+    let signature = read_string(signature, &mut signature_position).unwrap();
+    assert!(verify_with_jwk(&Jwk::from(ca_key.clone()), &certificate[..signature_start], signature).unwrap());
+
+    let rsa = generate_jwk_data("RS256", 2048).unwrap();
+    assert!(sign_certificate(&rsa, "ssh-ed25519", &subject_blob, &fields, &[7; 32]).is_err());
+}
+
+#[test]
+fn test_authorized_key_line() {
+    use crate::crypto::generate_jwk_data;
+
+    let mut ed25519 = generate_jwk_data("Ed25519", 2048).unwrap();
+    ed25519.purpose = Some(SSH_PURPOSE.to_string());
+    let line = authorized_key_line(&ed25519).unwrap();
+    assert!(line.starts_with("ssh-ed25519 "));
+    assert!(line.ends_with(&format!(" {}", ed25519.kid)));
+    let (_, blob) = parse_public_key(&line).unwrap();
+    assert_eq!(blob, ed25519_public_key_blob(&ed25519).unwrap());
+
+    // The exponent and modulus follow the key type as mpints
+    let rsa = generate_jwk_data("RS256", 2048).unwrap();
+    let (key_type, blob) = parse_public_key(&authorized_key_line(&rsa).unwrap()).unwrap();
+    assert_eq!(key_type, "ssh-rsa");
+    let mut position = 4 + 7;
+    assert_eq!(read_string(&blob, &mut position), Some(&[1u8, 0, 1][..]));
+    let modulus = read_string(&blob, &mut position).unwrap();
+    assert_eq!((modulus.len(), modulus[0]), (257, 0));
+    assert_eq!(position, blob.len());
+
+    ed25519.purpose = Some(SSH_CA_PURPOSE.to_string());
+    assert!(authorized_key_line(&ed25519).unwrap().starts_with("cert-authority ssh-ed25519 "));
+    assert!(authorized_key_line(&generate_jwk_data("ES256", 2048).unwrap()).is_err());
+}
@@ -0,0 +1,245 @@
+//! This module pushes the metrics of the JWK microservice to StatsD or DogStatsD.
+//!
+//! For environments without Prometheus scraping, `STATSD_HOST` enables an exporter sending the
+//! metrics recorded for `/metrics` over UDP as they happen: request counts and latencies, key
+//! generation durations, database query latencies and connection waits and, every
+//! `STATSD_INTERVAL_SECONDS`, the usage of the connection pools and the key counts per lifecycle
+//! status. DogStatsD (`STATSD_FLAVOR=dogstatsd`) receives the labels as tags; plain StatsD has no
+//! tags, so the label values are appended to the metric name instead.
+
+use crate::db::{establish_connection, pool_usage};
+use crate::log_error;
+use crate::metrics::key_counts;
+use chrono::Utc;
+use dotenv::dotenv;
+use std::env;
+use std::net::UdpSocket;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Dialect of the StatsD protocol.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatsdFlavor {
+    /// Plain StatsD, with the label values in the metric name.
+    Statsd,
+    /// DogStatsD, with the labels as tags.
+    DogStatsd,
+}
+
+/// Kind of a StatsD metric.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetricKind {
+    /// Counter increment (`c`).
+    Counter,
+    /// Duration in milliseconds (`ms`).
+    Timer,
+    /// Absolute value (`g`).
+    Gauge,
+}
+
+/// Client sending metrics to a StatsD server.
+#[derive(Debug)]
+pub struct StatsdClient {
+    socket: UdpSocket,
+    prefix: String,
+    flavor: StatsdFlavor,
+}
+
+impl StatsdClient {
+    /// Creates a client sending to a StatsD server.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - Address of the server (e.g., "127.0.0.1:8125"); host names are resolved once.
+    /// * `prefix` - Prefix of every metric name (e.g., "jwks").
+    /// * `flavor` - Dialect of the protocol.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the socket cannot be created or the host cannot be resolved.
+    pub fn new(host: &str, prefix: &str, flavor: StatsdFlavor) -> std::io::Result<StatsdClient> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(host)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(StatsdClient { socket, prefix: prefix.to_string(), flavor })
+    }
+
+    /// Sends a metric. Delivery is best effort: send errors are ignored, like lost datagrams.
+    pub fn send(&self, name: &str, labels: &[(&str, &str)], value: f64, kind: MetricKind) {
+        let line = format_metric(&self.prefix, self.flavor, name, labels, value, kind);
+        let _ = self.socket.send(line.as_bytes());
+    }
+}
+
+/// Replaces the characters that StatsD servers treat specially in names and tags.
+fn sanitize(value: &str) -> String {
+    let sanitized = value
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.' || c == '/' { c } else { '_' })
+        .collect::<String>();
+    sanitized.trim_matches('_').to_string()
+}
+
+/// Formats a metric as a StatsD line.
+///
+/// # Arguments
+///
+/// * `prefix` - Prefix of the metric name.
+/// * `flavor` - Dialect of the protocol.
+/// * `name` - Name of the metric (e.g., "http.requests").
+/// * `labels` - Label names and values.
+/// * `value` - Value of the metric.
+/// * `kind` - Kind of the metric.
+pub fn format_metric(
+    prefix: &str,
+    flavor: StatsdFlavor,
+    name: &str,
+    labels: &[(&str, &str)],
+    value: f64,
+    kind: MetricKind,
+) -> String {
+    let kind = match kind {
+        MetricKind::Counter => "c",
+        MetricKind::Timer => "ms",
+        MetricKind::Gauge => "g",
+    };
+    let mut metric = if prefix.is_empty() { name.to_string() } else { format!("{}.{}", prefix, name) };
+
+    match flavor {
+        StatsdFlavor::DogStatsd => {
+            let tags = labels
+                .iter()
+                .map(|(label, value)| format!("{}:{}", label, sanitize(value)))
+                .collect::<Vec<_>>();
+            if tags.is_empty() {
+                format!("{}:{}|{}", metric, value, kind)
+            } else {
+                format!("{}:{}|{}|#{}", metric, value, kind, tags.join(","))
+            }
+        }
+        // Dots separate name segments in Graphite, so they cannot appear in label values
+        StatsdFlavor::Statsd => {
+            for (_, value) in labels {
+                metric.push('.');
+                metric.push_str(&sanitize(&value.replace(['.', '/'], "_")));
+            }
+            format!("{}:{}|{}", metric, value, kind)
+        }
+    }
+}
+
+/// Returns the StatsD client configured by `STATSD_HOST`, `STATSD_PREFIX` (default `jwks`) and
+/// `STATSD_FLAVOR` (`statsd` or `dogstatsd`, default `statsd`).
+///
+/// # Returns
+///
+/// `None` if `STATSD_HOST` is not set or the client cannot be created (logged once).
+pub fn statsd_client() -> Option<&'static StatsdClient> {
+    static CLIENT: OnceLock<Option<StatsdClient>> = OnceLock::new();
+
+    CLIENT
+        .get_or_init(|| {
+            dotenv().ok();
+
+            let host = env::var("STATSD_HOST").ok().filter(|value| !value.trim().is_empty())?;
+            let prefix = env::var("STATSD_PREFIX").unwrap_or_else(|_| "jwks".to_string());
+            let flavor = match env::var("STATSD_FLAVOR").unwrap_or_default().as_str() {
+                "" | "statsd" => StatsdFlavor::Statsd,
+                "dogstatsd" => StatsdFlavor::DogStatsd,
+                other => panic!("Unknown STATSD_FLAVOR {}, expected statsd or dogstatsd", other),
+            };
+
+            match StatsdClient::new(host.trim(), &prefix, flavor) {
+                Ok(client) => Some(client),
+                Err(error) => {
+                    log_error!("Failed to create the StatsD client for {}: {}", host, error);
+                    None
+                }
+            }
+        })
+        .as_ref()
+}
+
+/// Returns the interval between pushes of the key counts (`STATSD_INTERVAL_SECONDS`, default 10).
+///
+/// # Panics
+///
+/// This function will panic if `STATSD_INTERVAL_SECONDS` is not a positive number.
+pub fn statsd_interval() -> Duration {
+    dotenv().ok();
+
+    let seconds: u64 = env::var("STATSD_INTERVAL_SECONDS")
+        .unwrap_or_else(|_| "10".to_string())
+        .parse()
+        .ok()
+        .filter(|seconds| *seconds > 0)
+        .expect("STATSD_INTERVAL_SECONDS must be a positive number");
+
+    Duration::from_secs(seconds)
+}
+
+/// Sends a metric if a StatsD client is configured.
+pub fn emit(name: &str, labels: &[(&str, &str)], value: f64, kind: MetricKind) {
+    if let Some(client) = statsd_client() {
+        client.send(name, labels, value, kind);
+    }
+}
+
+/// Counts the keys per lifecycle status and sends the counts as gauges.
+///
+/// # Errors
+///
+/// Returns a message if the keys cannot be counted.
+pub fn push_key_counts() -> Result<(), String> {
+    let connection = &mut establish_connection();
+    let counts = key_counts(connection, Utc::now().naive_utc()).map_err(|e| e.to_string())?;
+    for (status, count) in counts {
+        emit("keys", &[("status", &status)], count as f64, MetricKind::Gauge);
+    }
+    Ok(())
+}
+
+/// Sends the usage of the database connection pools as gauges.
+pub fn push_pool_usage() {
+    for usage in pool_usage() {
+        let in_use = [("pool", usage.pool), ("state", "in_use")];
+        emit("db.pool_connections", &in_use, usage.in_use as f64, MetricKind::Gauge);
+        let idle = [("pool", usage.pool), ("state", "idle")];
+        emit("db.pool_connections", &idle, usage.idle as f64, MetricKind::Gauge);
+        emit("db.pool_max_connections", &[("pool", usage.pool)], usage.max_size as f64, MetricKind::Gauge);
+    }
+}
+
+#[test]
+fn test_format_metric() {
+    let labels = [("method", "GET"), ("route", "/jwks/{id}"), ("status", "200")];
+    assert_eq!(
+        format_metric("jwks", StatsdFlavor::DogStatsd, "http.requests", &labels, 1.0, MetricKind::Counter),
+        "jwks.http.requests:1|c|#method:GET,route:/jwks/_id,status:200"
+    );
+    assert_eq!(
+        format_metric("jwks", StatsdFlavor::Statsd, "http.requests", &labels, 1.0, MetricKind::Counter),
+        "jwks.http.requests.GET.jwks__id.200:1|c"
+    );
+    assert_eq!(
+        format_metric("", StatsdFlavor::Statsd, "keygen.duration", &[("alg", "RS256")], 12.5, MetricKind::Timer),
+        "keygen.duration.RS256:12.5|ms"
+    );
+    assert_eq!(
+        format_metric("jwks", StatsdFlavor::DogStatsd, "keys", &[], 3.0, MetricKind::Gauge),
+        "jwks.keys:3|g"
+    );
+}
+
+#[test]
+fn test_statsd_client_sends_datagrams() {
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    server.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let client = StatsdClient::new(&server.local_addr().unwrap().to_string(), "jwks", StatsdFlavor::DogStatsd).unwrap();
+
+    client.send("keys", &[("status", "active")], 2.0, MetricKind::Gauge);
+    let mut buffer = [0u8; 512];
+    let length = server.recv(&mut buffer).unwrap();
+    assert_eq!(&buffer[..length], b"jwks.keys:2|g|#status:active");
+}
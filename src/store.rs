@@ -0,0 +1,854 @@
+//! This module defines the storage of the keys behind the [`KeyStore`] trait.
+//!
+//! The request handlers and the key manager create, load, list and delete keys through the store
+//! of the process ([`key_store`]) instead of querying the database directly, so that the storage
+//! can be swapped. [`PgKeyStore`] keeps the keys in PostgreSQL (`DATABASE_URL`), using the pooled
+//! connections of [`crate::db`]; keys are read from the read replica (`DATABASE_READ_URL`) if one
+//! is configured. The lifecycle changes of the keys (extension, freezing, primary keys) go
+//! through the store as well; bulk transitions, approvals and the audit log still use
+//! PostgreSQL directly.
+//!
+//! With the `test-util` feature, [`MemoryKeyStore`] keeps the keys in the memory of the process
+//! instead, for tests and demo deployments without a database: selected with
+//! `STORAGE_BACKEND=memory`, or passed to [`crate::app_config_with_store`] by services embedding
+//! the crate to test their clients. The keys are lost on restart and the endpoints that still use
+//! PostgreSQL are not available.
+
+use crate::aliases::{alias_key_id, move_aliases};
+use crate::audit::{
+    deleting_actors, record_event, ACTION_CREATE, ACTION_DELETE, ACTION_EXTEND, ACTION_FREEZE,
+    ACTION_UNFREEZE, ACTION_UNSET_PRIMARY,
+};
+use crate::crypto::key_use_for_alg;
+use crate::db::{checkout, database_pool, read_database_pool, snapshot_batches, Batches, DbPool};
+use crate::dual_write::mirror_key;
+use crate::log_error;
+use crate::manager::{designate_primary, is_kid_conflict, published_keys};
+use crate::models::JwkData;
+use crate::schema::jwks::dsl::*;
+use chrono::{NaiveDateTime, Utc};
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_types::{Nullable, Timestamp};
+use dotenv::dotenv;
+#[cfg(feature = "test-util")]
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+#[cfg(feature = "test-util")]
+use std::sync::{Mutex, MutexGuard};
+use uuid::Uuid;
+
+/// Key store of the process, created on first use.
+static KEY_STORE: OnceLock<Arc<dyn KeyStore>> = OnceLock::new();
+
+/// Number of keys loaded per batch by [`KeyStore::list_active`].
+const LIST_BATCH_SIZE: i64 = 500;
+
+/// Error of storing a key.
+#[derive(Debug)]
+pub enum StoreError {
+    /// The key ID is already used by another key.
+    KidConflict,
+    /// The key could not be stored.
+    Failed(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::KidConflict => f.write_str("Key ID is already in use"),
+            StoreError::Failed(message) => f.write_str(message),
+        }
+    }
+}
+
+/// Change of the lifecycle state of a key, applied by [`KeyStore::update_lifecycle`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LifecycleChange {
+    /// The private key and the key expire at the given times.
+    Extend {
+        /// New private key expiration date.
+        private_key_expires_at: NaiveDateTime,
+        /// New key expiration date.
+        key_expires_at: Option<NaiveDateTime>,
+    },
+    /// The key is frozen (`true`) or unfrozen (`false`).
+    Freeze(bool),
+    /// The primary designation of the key is removed.
+    UnsetPrimary,
+}
+
+impl LifecycleChange {
+    /// Returns the audit log action of the change.
+    fn action(&self) -> &'static str {
+        match self {
+            LifecycleChange::Extend { .. } => ACTION_EXTEND,
+            LifecycleChange::Freeze(true) => ACTION_FREEZE,
+            LifecycleChange::Freeze(false) => ACTION_UNFREEZE,
+            LifecycleChange::UnsetPrimary => ACTION_UNSET_PRIMARY,
+        }
+    }
+}
+
+/// Storage of the keys.
+///
+/// The operations block, so callers on an async runtime run them on the blocking thread pool
+/// (see [`crate::manager::run_blocking`]). Errors are described by a message.
+pub trait KeyStore: Send + Sync {
+    /// Stores a new key and records its creation by `actor` in the audit log.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::KidConflict`] if the `kid` is already used by another key.
+    fn insert(&self, jwk: &JwkData, actor: Option<String>) -> Result<(), StoreError>;
+
+    /// Returns whether the keys are kept at rest, e.g. in a database, where the secrets of
+    /// symmetric keys are only stored encrypted.
+    fn stores_at_rest(&self) -> bool {
+        true
+    }
+
+    /// Returns a key by its ID, deleted and expired keys included.
+    fn get_by_id(&self, key_id: Uuid) -> Result<Option<JwkData>, String>;
+
+    /// Returns a key by its ID like [`KeyStore::get_by_id`], for using its private part or
+    /// changing its lifecycle: the key is read where it is written, so it can be used as soon as
+    /// it is stored.
+    fn get_private(&self, key_id: Uuid) -> Result<Option<JwkData>, String> {
+        self.get_by_id(key_id)
+    }
+
+    /// Returns the key using a `kid`, deleted and expired keys included, read where it is
+    /// written like [`KeyStore::get_private`].
+    fn get_by_kid(&self, key_id: &str) -> Result<Option<JwkData>, String>;
+
+    /// Returns the batches of the keys published in the JWK Set, primary keys first and then
+    /// oldest first, loaded from a consistent view of the store.
+    ///
+    /// # Arguments
+    ///
+    /// * `at` - Instant to reconstruct the key set for, or `None` for the current key set.
+    /// * `key_purpose` - Purpose the key set is restricted to, if any.
+    fn active_batches(&self, at: Option<NaiveDateTime>, key_purpose: Option<String>) -> Result<Batches<JwkData>, String>;
+
+    /// Lists the keys published in the JWK Set, in the order of [`KeyStore::active_batches`].
+    fn list_active(&self, at: Option<NaiveDateTime>, key_purpose: Option<String>) -> Result<Vec<JwkData>, String> {
+        let mut batches = self.active_batches(at, key_purpose)?;
+        let mut keys = Vec::new();
+        loop {
+            let batch = batches(keys.len() as i64, LIST_BATCH_SIZE)?;
+            let last = (batch.len() as i64) < LIST_BATCH_SIZE;
+            keys.extend(batch);
+            if last {
+                return Ok(keys);
+            }
+        }
+    }
+
+    /// Returns the keys that are neither deleted nor expired, frozen keys and keys not active yet
+    /// included.
+    fn live_keys(&self) -> Result<Vec<JwkData>, String>;
+
+    /// Soft-deletes a key and records the deletion by `actor` in the audit log.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the key does not exist.
+    fn soft_delete(&self, key_id: Uuid, actor: Option<String>) -> Result<bool, String>;
+
+    /// Returns the keys that can sign now, primary keys first and then newest first: keys that
+    /// are neither deleted, frozen nor expired, whose activation time (if any) has been reached
+    /// and whose private key has not expired.
+    ///
+    /// # Arguments
+    ///
+    /// * `algorithm` - Algorithm the keys must use, or `None` for every signing algorithm.
+    fn signing_keys(&self, algorithm: Option<&str>) -> Result<Vec<JwkData>, String>;
+
+    /// Claims the single retrieval of the private key of a burn-after-read key.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the private key has already been retrieved or the key does not exist.
+    fn claim_retrieval(&self, key_id: Uuid) -> Result<bool, String>;
+
+    /// Returns the changes of the published key set in the interval from `since` to `now`: the
+    /// keys created, unfrozen or activated in it that are still published (oldest first) and the
+    /// `kid` of the keys known before it that were deleted, expired or frozen in it.
+    fn changes(&self, since: NaiveDateTime, now: NaiveDateTime) -> Result<(Vec<JwkData>, Vec<String>), String>;
+
+    /// Returns the next version of a key, the key rotated from it, if any.
+    fn successor(&self, key_id: Uuid) -> Result<Option<JwkData>, String>;
+
+    /// Returns the ID of the key an alias points to, if the alias exists.
+    fn resolve_alias(&self, alias: &str) -> Result<Option<Uuid>, String>;
+
+    /// Applies a lifecycle change to a key that is not deleted and records it by `actor` in the
+    /// audit log.
+    ///
+    /// # Returns
+    ///
+    /// `false` if no key was changed: the key does not exist, is deleted, or (for
+    /// [`LifecycleChange::UnsetPrimary`]) is not primary.
+    fn update_lifecycle(&self, key_id: Uuid, change: LifecycleChange, actor: Option<String>) -> Result<bool, String>;
+
+    /// Designates a key as the primary key of its algorithm, replacing the previous primary key,
+    /// and records the change by `actor` in the audit log.
+    fn designate_primary(&self, key: &JwkData, actor: Option<String>) -> Result<(), String>;
+
+    /// Hands the primary designation and the aliases of a rotated key over to its new version.
+    ///
+    /// Failures are logged; the rotation itself has already succeeded.
+    fn hand_over(&self, rotated: &JwkData, successor: &JwkData, actor: Option<String>);
+
+    /// Returns a page of the deleted keys, newest deletion first, with the caller that deleted
+    /// each key according to the audit log, if known.
+    fn deleted(&self, offset: i64, limit: i64) -> Result<Vec<(JwkData, Option<String>)>, String>;
+
+    /// Returns a page of the keys that are neither deleted nor expired and whose private key or
+    /// the key itself expires until `until`, soonest upcoming expiry first.
+    fn expiring(&self, until: NaiveDateTime, offset: i64, limit: i64) -> Result<Vec<JwkData>, String>;
+}
+
+/// Key store keeping the keys in the `jwks` table of PostgreSQL.
+pub struct PgKeyStore {
+    pool: DbPool,
+    read_pool: DbPool,
+}
+
+impl PgKeyStore {
+    /// Creates a store writing with connections of `pool` and reading with connections of
+    /// `read_pool`, which may be the same pool.
+    pub fn new(pool: DbPool, read_pool: DbPool) -> PgKeyStore {
+        PgKeyStore { pool, read_pool }
+    }
+}
+
+impl KeyStore for PgKeyStore {
+    fn insert(&self, jwk: &JwkData, actor: Option<String>) -> Result<(), StoreError> {
+        let connection = &mut checkout(&self.pool).map_err(StoreError::Failed)?;
+        match diesel::insert_into(jwks).values(jwk.clone()).execute(connection) {
+            Ok(_) => {}
+            Err(error) if is_kid_conflict(&error) => return Err(StoreError::KidConflict),
+            Err(error) => return Err(StoreError::Failed(error.to_string())),
+        }
+
+        if let Err(error) = record_event(connection, jwk.id, ACTION_CREATE, actor) {
+            log_error!("Failed to record audit event for key {}: {}", jwk.id, error);
+        }
+        mirror_key(connection, jwk.id);
+        Ok(())
+    }
+
+    fn get_by_id(&self, key_id: Uuid) -> Result<Option<JwkData>, String> {
+        let connection = &mut checkout(&self.read_pool)?;
+        jwks.filter(id.eq(key_id))
+            .first::<JwkData>(connection)
+            .optional()
+            .map_err(|error| error.to_string())
+    }
+
+    fn get_private(&self, key_id: Uuid) -> Result<Option<JwkData>, String> {
+        // The read replica may not have caught up with a key that was just created
+        let connection = &mut checkout(&self.pool)?;
+        jwks.filter(id.eq(key_id))
+            .first::<JwkData>(connection)
+            .optional()
+            .map_err(|error| error.to_string())
+    }
+
+    fn get_by_kid(&self, key_id: &str) -> Result<Option<JwkData>, String> {
+        let connection = &mut checkout(&self.pool)?;
+        jwks.filter(kid.eq(key_id))
+            .first::<JwkData>(connection)
+            .optional()
+            .map_err(|error| error.to_string())
+    }
+
+    fn active_batches(&self, at: Option<NaiveDateTime>, key_purpose: Option<String>) -> Result<Batches<JwkData>, String> {
+        let connection = checkout(&self.read_pool)?;
+        Ok(snapshot_batches(connection, move |connection, offset, limit| {
+            published_keys(at, key_purpose.clone())
+                .order((is_primary.desc(), created_at.asc(), id.asc()))
+                .offset(offset)
+                .limit(limit)
+                .load::<JwkData>(connection)
+        }))
+    }
+
+    fn list_active(&self, at: Option<NaiveDateTime>, key_purpose: Option<String>) -> Result<Vec<JwkData>, String> {
+        // A single query is consistent without a snapshot
+        let connection = &mut checkout(&self.read_pool)?;
+        published_keys(at, key_purpose)
+            .order((is_primary.desc(), created_at.asc(), id.asc()))
+            .load::<JwkData>(connection)
+            .map_err(|error| error.to_string())
+    }
+
+    fn live_keys(&self) -> Result<Vec<JwkData>, String> {
+        let connection = &mut checkout(&self.read_pool)?;
+        jwks.filter(deleted_at.is_null())
+            .filter(key_expires_at.gt(Utc::now().naive_utc()))
+            .load::<JwkData>(connection)
+            .map_err(|error| error.to_string())
+    }
+
+    fn soft_delete(&self, key_id: Uuid, actor: Option<String>) -> Result<bool, String> {
+        let connection = &mut checkout(&self.pool)?;
+
+        // Set deleted_at to the current date and time
+        let now = Utc::now().naive_utc();
+        let deleted = diesel::update(jwks.filter(id.eq(key_id)))
+            .set((deleted_at.eq(Some(now)), updated_at.eq(now)))
+            .execute(connection)
+            .map_err(|error| error.to_string())?;
+        if deleted == 0 {
+            return Ok(false);
+        }
+
+        if let Err(error) = record_event(connection, key_id, ACTION_DELETE, actor) {
+            log_error!("Failed to record audit event for key {}: {}", key_id, error);
+        }
+        mirror_key(connection, key_id);
+        Ok(true)
+    }
+
+    fn signing_keys(&self, algorithm: Option<&str>) -> Result<Vec<JwkData>, String> {
+        let connection = &mut checkout(&self.pool)?;
+        let now = Utc::now().naive_utc();
+
+        let mut query = jwks
+            .filter(deleted_at.is_null()) // Exclude deleted keys
+            .filter(frozen_at.is_null()) // Exclude frozen keys
+            .filter(key_expires_at.gt(now)) // Exclude expired keys
+            .filter(not_before.is_null().or(not_before.le(now))) // Exclude keys not active yet
+            .filter(private_key_expires_at.gt(now)) // Exclude keys that can no longer sign
+            .order((is_primary.desc(), created_at.desc()))
+            .into_boxed();
+        if let Some(algorithm) = algorithm {
+            query = query.filter(alg.eq(algorithm.to_string()));
+        }
+        let results = query.load::<JwkData>(connection).map_err(|error| error.to_string())?;
+
+        Ok(results.into_iter().filter(|jwk| key_use_for_alg(&jwk.alg) == "sig").collect())
+    }
+
+    fn claim_retrieval(&self, key_id: Uuid) -> Result<bool, String> {
+        let connection = &mut checkout(&self.pool)?;
+        let now = Utc::now().naive_utc();
+        let claimed = diesel::update(jwks.filter(id.eq(key_id)).filter(private_key_retrieved_at.is_null()))
+            .set((private_key_retrieved_at.eq(now), updated_at.eq(now)))
+            .execute(connection)
+            .map_err(|error| error.to_string())?;
+        if claimed > 0 {
+            mirror_key(connection, key_id);
+        }
+        Ok(claimed > 0)
+    }
+
+    fn changes(&self, since: NaiveDateTime, now: NaiveDateTime) -> Result<(Vec<JwkData>, Vec<String>), String> {
+        let connection = &mut checkout(&self.read_pool)?;
+
+        // Keys created, unfrozen or activated in the interval and still active
+        let added = jwks
+            .filter(created_at.gt(since).or(updated_at.gt(since)).or(not_before.gt(since)))
+            .filter(created_at.le(now))
+            .filter(kty.ne("oct")) // Symmetric keys are never published
+            .filter(deleted_at.is_null())
+            .filter(frozen_at.is_null())
+            .filter(key_expires_at.gt(now))
+            .filter(not_before.is_null().or(not_before.le(now)))
+            .order(created_at.asc())
+            .load::<JwkData>(connection)
+            .map_err(|error| error.to_string())?;
+
+        // Keys known before the interval that were deleted, expired or frozen in it
+        let removed = jwks
+            .filter(created_at.le(since))
+            .filter(kty.ne("oct"))
+            .filter(
+                deleted_at
+                    .gt(since)
+                    .and(deleted_at.le(now))
+                    .or(deleted_at.is_null().and(key_expires_at.gt(since)).and(key_expires_at.le(now)))
+                    .or(deleted_at.is_null().and(frozen_at.gt(since)).and(frozen_at.le(now))),
+            )
+            .select(kid)
+            .load::<String>(connection)
+            .map_err(|error| error.to_string())?;
+
+        Ok((added, removed))
+    }
+
+    fn successor(&self, key_id: Uuid) -> Result<Option<JwkData>, String> {
+        let connection = &mut checkout(&self.pool)?;
+        jwks.filter(predecessor_id.eq(key_id))
+            .first::<JwkData>(connection)
+            .optional()
+            .map_err(|error| error.to_string())
+    }
+
+    fn resolve_alias(&self, alias: &str) -> Result<Option<Uuid>, String> {
+        let connection = &mut checkout(&self.read_pool)?;
+        alias_key_id(connection, alias).map_err(|error| error.to_string())
+    }
+
+    fn update_lifecycle(&self, key_id: Uuid, change: LifecycleChange, actor: Option<String>) -> Result<bool, String> {
+        let connection = &mut checkout(&self.pool)?;
+        let now = Utc::now().naive_utc();
+
+        let target = jwks.filter(id.eq(key_id)).filter(deleted_at.is_null());
+        let updated = match change {
+            LifecycleChange::Extend { private_key_expires_at: private_expires, key_expires_at: key_expires } => {
+                diesel::update(target)
+                    .set((
+                        private_key_expires_at.eq(Some(private_expires)),
+                        key_expires_at.eq(key_expires),
+                        updated_at.eq(now),
+                    ))
+                    .execute(connection)
+            }
+            LifecycleChange::Freeze(frozen) => diesel::update(target)
+                .set((frozen_at.eq(frozen.then_some(now)), updated_at.eq(now)))
+                .execute(connection),
+            // Designations are local to the deployment and not replicated, so the change time is kept
+            LifecycleChange::UnsetPrimary => diesel::update(target.filter(is_primary.eq(true)))
+                .set(is_primary.eq(false))
+                .execute(connection),
+        }
+        .map_err(|error| error.to_string())?;
+        if updated == 0 {
+            return Ok(false);
+        }
+
+        if let Err(error) = record_event(connection, key_id, change.action(), actor) {
+            log_error!("Failed to record audit event for key {}: {}", key_id, error);
+        }
+        mirror_key(connection, key_id);
+        Ok(true)
+    }
+
+    fn designate_primary(&self, key: &JwkData, actor: Option<String>) -> Result<(), String> {
+        let connection = &mut checkout(&self.pool)?;
+        designate_primary(connection, key, actor).map_err(|error| error.to_string())
+    }
+
+    fn hand_over(&self, rotated: &JwkData, successor: &JwkData, actor: Option<String>) {
+        let connection = &mut match checkout(&self.pool) {
+            Ok(connection) => connection,
+            Err(error) => {
+                log_error!("Failed to hand key {} over to {}: {}", rotated.id, successor.id, error);
+                return;
+            }
+        };
+
+        if rotated.is_primary {
+            if let Err(error) = designate_primary(connection, successor, actor) {
+                log_error!("Failed to designate key {} as primary: {}", successor.id, error);
+            }
+        }
+        if let Err(error) = move_aliases(connection, rotated.id, successor.id) {
+            log_error!("Failed to move aliases of key {} to {}: {}", rotated.id, successor.id, error);
+        }
+    }
+
+    fn deleted(&self, offset: i64, limit: i64) -> Result<Vec<(JwkData, Option<String>)>, String> {
+        let connection = &mut checkout(&self.read_pool)?;
+        let results = jwks
+            .filter(deleted_at.is_not_null())
+            .order((deleted_at.desc(), id.asc()))
+            .offset(offset)
+            .limit(limit)
+            .load::<JwkData>(connection)
+            .map_err(|error| error.to_string())?;
+
+        let key_ids = results.iter().map(|jwk| jwk.id).collect::<Vec<_>>();
+        let mut actors = deleting_actors(connection, &key_ids).map_err(|error| error.to_string())?;
+        Ok(results.into_iter().map(|jwk| {
+            let actor = actors.remove(&jwk.id);
+            (jwk, actor)
+        }).collect())
+    }
+
+    fn expiring(&self, until: NaiveDateTime, offset: i64, limit: i64) -> Result<Vec<JwkData>, String> {
+        let connection = &mut checkout(&self.read_pool)?;
+        let now = Utc::now().naive_utc();
+
+        // Soonest upcoming expiry first: the private key expiration until it passed, then the key's
+        let next_expiry = sql::<Nullable<Timestamp>>("CASE WHEN private_key_expires_at > ")
+            .bind::<Timestamp, _>(now)
+            .sql(" THEN private_key_expires_at ELSE key_expires_at END");
+
+        jwks.filter(deleted_at.is_null())
+            .filter(key_expires_at.gt(now))
+            .filter(
+                private_key_expires_at
+                    .gt(now)
+                    .and(private_key_expires_at.le(until))
+                    .or(key_expires_at.le(until)),
+            )
+            .order((next_expiry.asc(), id.asc()))
+            .offset(offset)
+            .limit(limit)
+            .load::<JwkData>(connection)
+            .map_err(|error| error.to_string())
+    }
+}
+
+/// Key store keeping the keys in the memory of the process, available with the `test-util`
+/// feature.
+///
+/// Nothing is recorded in the audit log or mirrored to a dual-write target, and keys have no
+/// aliases.
+#[cfg(feature = "test-util")]
+#[derive(Default)]
+pub struct MemoryKeyStore {
+    keys: Mutex<HashMap<Uuid, JwkData>>,
+}
+
+#[cfg(feature = "test-util")]
+impl MemoryKeyStore {
+    /// Creates an empty store.
+    pub fn new() -> MemoryKeyStore {
+        MemoryKeyStore::default()
+    }
+
+    /// Locks the keys; a panic while they were locked leaves them consistent.
+    fn keys(&self) -> MutexGuard<'_, HashMap<Uuid, JwkData>> {
+        self.keys.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(feature = "test-util")]
+impl KeyStore for MemoryKeyStore {
+    fn stores_at_rest(&self) -> bool {
+        false
+    }
+
+    fn insert(&self, jwk: &JwkData, _actor: Option<String>) -> Result<(), StoreError> {
+        let mut keys = self.keys();
+        if keys.values().any(|key| key.kid == jwk.kid) {
+            return Err(StoreError::KidConflict);
+        }
+        if keys.contains_key(&jwk.id) {
+            return Err(StoreError::Failed(format!("Key {} already exists", jwk.id)));
+        }
+
+        keys.insert(jwk.id, jwk.clone());
+        Ok(())
+    }
+
+    fn get_by_id(&self, key_id: Uuid) -> Result<Option<JwkData>, String> {
+        Ok(self.keys().get(&key_id).cloned())
+    }
+
+    fn get_by_kid(&self, key_id: &str) -> Result<Option<JwkData>, String> {
+        Ok(self.keys().values().find(|jwk| jwk.kid == key_id).cloned())
+    }
+
+    fn active_batches(&self, at: Option<NaiveDateTime>, key_purpose: Option<String>) -> Result<Batches<JwkData>, String> {
+        // The batches are taken from a copy of the keys published now
+        let now = Utc::now().naive_utc();
+        let mut published = self
+            .keys()
+            .values()
+            .filter(|jwk| jwk.is_published(at, now))
+            .filter(|jwk| key_purpose.is_none() || jwk.purpose == key_purpose)
+            .cloned()
+            .collect::<Vec<_>>();
+        published.sort_by_key(|jwk| (!jwk.is_primary, jwk.created_at, jwk.id));
+
+        Ok(Box::new(move |offset, limit| {
+            Ok(published.iter().skip(offset as usize).take(limit as usize).cloned().collect())
+        }))
+    }
+
+    fn live_keys(&self) -> Result<Vec<JwkData>, String> {
+        let now = Utc::now().naive_utc();
+        Ok(self
+            .keys()
+            .values()
+            .filter(|jwk| jwk.deleted_at.is_none())
+            .filter(|jwk| jwk.key_expires_at.is_some_and(|expires_at| expires_at > now))
+            .cloned()
+            .collect())
+    }
+
+    fn soft_delete(&self, key_id: Uuid, _actor: Option<String>) -> Result<bool, String> {
+        let now = Utc::now().naive_utc();
+        match self.keys().get_mut(&key_id) {
+            Some(jwk) => {
+                jwk.deleted_at = Some(now);
+                jwk.updated_at = now;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn signing_keys(&self, algorithm: Option<&str>) -> Result<Vec<JwkData>, String> {
+        let now = Utc::now().naive_utc();
+        let mut results = self
+            .keys()
+            .values()
+            .filter(|jwk| jwk.deleted_at.is_none() && jwk.frozen_at.is_none())
+            .filter(|jwk| jwk.key_expires_at.is_some_and(|expires_at| expires_at > now))
+            .filter(|jwk| jwk.not_before.is_none_or(|activation| activation <= now))
+            .filter(|jwk| jwk.private_key_expires_at.is_some_and(|expires_at| expires_at > now))
+            .filter(|jwk| algorithm.is_none_or(|algorithm| jwk.alg == algorithm))
+            .filter(|jwk| key_use_for_alg(&jwk.alg) == "sig")
+            .cloned()
+            .collect::<Vec<_>>();
+        results.sort_by_key(|jwk| (!jwk.is_primary, std::cmp::Reverse(jwk.created_at)));
+        Ok(results)
+    }
+
+    fn claim_retrieval(&self, key_id: Uuid) -> Result<bool, String> {
+        let now = Utc::now().naive_utc();
+        match self.keys().get_mut(&key_id).filter(|jwk| jwk.private_key_retrieved_at.is_none()) {
+            Some(jwk) => {
+                jwk.private_key_retrieved_at = Some(now);
+                jwk.updated_at = now;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn changes(&self, since: NaiveDateTime, now: NaiveDateTime) -> Result<(Vec<JwkData>, Vec<String>), String> {
+        let keys = self.keys();
+        let within = |time: Option<NaiveDateTime>| time.is_some_and(|time| time > since && time <= now);
+
+        let mut added = keys
+            .values()
+            .filter(|jwk| jwk.created_at > since || jwk.updated_at > since || jwk.not_before.is_some_and(|activation| activation > since))
+            .filter(|jwk| jwk.created_at <= now && jwk.kty != "oct")
+            .filter(|jwk| jwk.deleted_at.is_none() && jwk.frozen_at.is_none())
+            .filter(|jwk| jwk.key_expires_at.is_some_and(|expires_at| expires_at > now))
+            .filter(|jwk| jwk.not_before.is_none_or(|activation| activation <= now))
+            .cloned()
+            .collect::<Vec<_>>();
+        added.sort_by_key(|jwk| jwk.created_at);
+
+        let removed = keys
+            .values()
+            .filter(|jwk| jwk.created_at <= since && jwk.kty != "oct")
+            .filter(|jwk| {
+                within(jwk.deleted_at)
+                    || jwk.deleted_at.is_none() && (within(jwk.key_expires_at) || within(jwk.frozen_at))
+            })
+            .map(|jwk| jwk.kid.clone())
+            .collect();
+
+        Ok((added, removed))
+    }
+
+    fn successor(&self, key_id: Uuid) -> Result<Option<JwkData>, String> {
+        Ok(self.keys().values().find(|jwk| jwk.predecessor_id == Some(key_id)).cloned())
+    }
+
+    fn resolve_alias(&self, _alias: &str) -> Result<Option<Uuid>, String> {
+        // Aliases are kept in PostgreSQL only
+        Ok(None)
+    }
+
+    fn update_lifecycle(&self, key_id: Uuid, change: LifecycleChange, _actor: Option<String>) -> Result<bool, String> {
+        let now = Utc::now().naive_utc();
+        let mut keys = self.keys();
+        let Some(jwk) = keys.get_mut(&key_id).filter(|jwk| jwk.deleted_at.is_none()) else {
+            return Ok(false);
+        };
+
+        match change {
+            LifecycleChange::Extend { private_key_expires_at: private_expires, key_expires_at: key_expires } => {
+                jwk.private_key_expires_at = Some(private_expires);
+                jwk.key_expires_at = key_expires;
+                jwk.updated_at = now;
+            }
+            LifecycleChange::Freeze(frozen) => {
+                jwk.frozen_at = frozen.then_some(now);
+                jwk.updated_at = now;
+            }
+            LifecycleChange::UnsetPrimary if jwk.is_primary => jwk.is_primary = false,
+            LifecycleChange::UnsetPrimary => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn designate_primary(&self, key: &JwkData, _actor: Option<String>) -> Result<(), String> {
+        for jwk in self.keys().values_mut().filter(|jwk| jwk.alg == key.alg) {
+            jwk.is_primary = jwk.id == key.id;
+        }
+        Ok(())
+    }
+
+    fn hand_over(&self, rotated: &JwkData, successor: &JwkData, actor: Option<String>) {
+        if rotated.is_primary {
+            let _ = self.designate_primary(successor, actor);
+        }
+    }
+
+    fn deleted(&self, offset: i64, limit: i64) -> Result<Vec<(JwkData, Option<String>)>, String> {
+        let mut deleted = self.keys().values().filter(|jwk| jwk.deleted_at.is_some()).cloned().collect::<Vec<_>>();
+        deleted.sort_by_key(|jwk| (std::cmp::Reverse(jwk.deleted_at), jwk.id));
+
+        Ok(deleted.into_iter().skip(offset as usize).take(limit as usize).map(|jwk| (jwk, None)).collect())
+    }
+
+    fn expiring(&self, until: NaiveDateTime, offset: i64, limit: i64) -> Result<Vec<JwkData>, String> {
+        let now = Utc::now().naive_utc();
+        let next_expiry = |jwk: &JwkData| match jwk.private_key_expires_at {
+            Some(expires_at) if expires_at > now => Some(expires_at),
+            _ => jwk.key_expires_at,
+        };
+
+        let mut expiring = self
+            .keys()
+            .values()
+            .filter(|jwk| jwk.deleted_at.is_none())
+            .filter(|jwk| jwk.key_expires_at.is_some_and(|expires_at| expires_at > now))
+            .filter(|jwk| {
+                jwk.private_key_expires_at.is_some_and(|expires_at| expires_at > now && expires_at <= until)
+                    || jwk.key_expires_at.is_some_and(|expires_at| expires_at <= until)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        expiring.sort_by_key(|jwk| (next_expiry(jwk), jwk.id));
+
+        Ok(expiring.into_iter().skip(offset as usize).take(limit as usize).collect())
+    }
+}
+
+/// Returns whether the keys are kept in memory (`STORAGE_BACKEND=memory`) instead of PostgreSQL
+/// (`STORAGE_BACKEND=postgres`, the default).
+///
+/// # Panics
+///
+/// This function will panic if `STORAGE_BACKEND` is set to another value, or to `memory` in a
+/// build without the `test-util` feature.
+pub fn memory_storage() -> bool {
+    dotenv().ok();
+
+    match env::var("STORAGE_BACKEND").as_deref() {
+        Err(_) | Ok("postgres") => false,
+        Ok("memory") if cfg!(feature = "test-util") => true,
+        Ok("memory") => panic!("STORAGE_BACKEND=memory requires a build with the test-util feature"),
+        Ok(backend) => panic!("Unknown STORAGE_BACKEND {}, expected postgres or memory", backend),
+    }
+}
+
+/// Returns the key store of the process, created on first use.
+pub fn key_store() -> Arc<dyn KeyStore> {
+    KEY_STORE
+        .get_or_init(|| -> Arc<dyn KeyStore> {
+            #[cfg(feature = "test-util")]
+            if memory_storage() {
+                return Arc::new(MemoryKeyStore::new());
+            }
+            #[cfg(not(feature = "test-util"))]
+            memory_storage();
+
+            Arc::new(PgKeyStore::new(database_pool(), read_database_pool()))
+        })
+        .clone()
+}
+
+#[cfg(feature = "test-util")]
+#[test]
+fn test_memory_key_store() {
+    use crate::crypto::generate_ec_jwk_data;
+
+    let store = MemoryKeyStore::new();
+    let now = Utc::now().naive_utc();
+    let key = |created: i64, primary: bool| JwkData {
+        id: Uuid::new_v4(),
+        created_at: now - chrono::Duration::seconds(created),
+        key_expires_at: Some(now + chrono::Duration::hours(1)),
+        is_primary: primary,
+        ..generate_ec_jwk_data("ES256").unwrap()
+    };
+    let (older, newer, primary) = (key(20, false), key(10, false), key(5, true));
+    for jwk in [&newer, &primary, &older] {
+        store.insert(jwk, None).unwrap();
+    }
+
+    // A kid is used by a single key
+    let duplicate = JwkData { kid: older.kid.clone(), ..key(0, false) };
+    assert!(matches!(store.insert(&duplicate, None), Err(StoreError::KidConflict)));
+
+    // Primary keys first, then oldest first, batch by batch
+    let listed = store.list_active(None, None).unwrap().into_iter().map(|jwk| jwk.id).collect::<Vec<_>>();
+    assert_eq!(listed, vec![primary.id, older.id, newer.id]);
+    let mut batches = store.active_batches(None, None).unwrap();
+    assert_eq!(batches(1, 1).unwrap()[0].id, older.id);
+    assert!(batches(3, 1).unwrap().is_empty());
+    assert!(store.list_active(None, Some("other".to_string())).unwrap().is_empty());
+
+    // Deleted keys are kept but no longer published
+    assert!(store.soft_delete(older.id, None).unwrap());
+    assert!(!store.soft_delete(Uuid::new_v4(), None).unwrap());
+    assert!(store.get_by_id(older.id).unwrap().unwrap().deleted_at.is_some());
+    assert_eq!(store.list_active(None, None).unwrap().len(), 2);
+    let before = now - chrono::Duration::seconds(1);
+    assert_eq!(store.list_active(Some(before), None).unwrap().len(), 3);
+    assert_eq!(store.deleted(0, 10).unwrap().into_iter().map(|(jwk, _)| jwk.id).collect::<Vec<_>>(), vec![older.id]);
+}
+
+#[cfg(feature = "test-util")]
+#[test]
+fn test_memory_key_store_lifecycle() {
+    use crate::crypto::generate_ec_jwk_data;
+
+    let store = MemoryKeyStore::new();
+    let now = Utc::now().naive_utc();
+    let key = |created: i64| JwkData {
+        id: Uuid::new_v4(),
+        created_at: now - chrono::Duration::seconds(created),
+        private_key_expires_at: Some(now + chrono::Duration::hours(1)),
+        key_expires_at: Some(now + chrono::Duration::hours(2)),
+        ..generate_ec_jwk_data("ES256").unwrap()
+    };
+    let (first, second) = (key(20), key(10));
+    let third = JwkData { predecessor_id: Some(second.id), ..key(5) };
+    for jwk in [&first, &second, &third] {
+        store.insert(jwk, None).unwrap();
+    }
+
+    // Newest first, until a key is designated as primary
+    let signing = |store: &MemoryKeyStore| store.signing_keys(Some("ES256")).unwrap().into_iter().map(|jwk| jwk.id).collect::<Vec<_>>();
+    assert_eq!(signing(&store), vec![third.id, second.id, first.id]);
+    store.designate_primary(&first, None).unwrap();
+    assert_eq!(signing(&store)[0], first.id);
+    assert!(store.signing_keys(Some("RS256")).unwrap().is_empty());
+    assert_eq!(store.successor(second.id).unwrap().map(|jwk| jwk.id), Some(third.id));
+    assert!(store.successor(third.id).unwrap().is_none());
+
+    // Frozen keys no longer sign; unsetting a designation a key does not have changes nothing
+    assert!(store.update_lifecycle(second.id, LifecycleChange::Freeze(true), None).unwrap());
+    assert_eq!(signing(&store), vec![first.id, third.id]);
+    assert!(store.update_lifecycle(first.id, LifecycleChange::UnsetPrimary, None).unwrap());
+    assert!(!store.update_lifecycle(first.id, LifecycleChange::UnsetPrimary, None).unwrap());
+    assert!(!store.update_lifecycle(Uuid::new_v4(), LifecycleChange::Freeze(true), None).unwrap());
+
+    // Extended keys leave the expiry window
+    let later = now + chrono::Duration::hours(5);
+    let extension = LifecycleChange::Extend { private_key_expires_at: later, key_expires_at: Some(later) };
+    assert!(store.update_lifecycle(first.id, extension, None).unwrap());
+    let expiring = store.expiring(now + chrono::Duration::hours(3), 0, 10).unwrap();
+    assert_eq!(expiring.len(), 2);
+    assert!(expiring.iter().all(|jwk| jwk.id != first.id));
+    assert_eq!(store.expiring(now + chrono::Duration::hours(3), 1, 10).unwrap().len(), 1);
+
+    // The private key of a key is retrieved once if it is claimed
+    assert_eq!(store.get_by_kid(&third.kid).unwrap().map(|jwk| jwk.id), Some(third.id));
+    assert!(store.claim_retrieval(third.id).unwrap());
+    assert!(!store.claim_retrieval(third.id).unwrap());
+
+    // Keys frozen in the interval are removed, keys created or changed in it are added
+    let (added, removed) = store.changes(now - chrono::Duration::seconds(7), Utc::now().naive_utc()).unwrap();
+    assert_eq!(added.into_iter().map(|jwk| jwk.id).collect::<Vec<_>>(), vec![first.id, third.id]);
+    assert_eq!(removed, vec![second.kid.clone()]);
+    assert_eq!(store.live_keys().unwrap().len(), 3);
+}
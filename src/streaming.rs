@@ -0,0 +1,169 @@
+//! This module streams responses listing many keys.
+//!
+//! Deployments hosting thousands of keys would otherwise load every key and build the whole body
+//! in memory for each request. A streamed listing loads its rows in batches of
+//! [`STREAM_BATCH_SIZE`], e.g. from a read-only snapshot (see [`crate::db::snapshot_batches`]), and
+//! sends every batch as a chunk as soon as it is serialized. Listings that fit in a single
+//! batch are sent in one piece with a `Content-Length`, as before.
+//!
+//! Batches are loaded on the blocking thread pool, so the queries do not stall the actix worker
+//! threads.
+
+use crate::db::Batches;
+use crate::log_error;
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::rt::task::{self, JoinHandle};
+use actix_web::web::Bytes;
+use actix_web::HttpResponse;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Number of rows loaded and sent per chunk.
+pub const STREAM_BATCH_SIZE: i64 = 500;
+
+/// Loads the serialized items of a batch, given the offset and the maximum number of rows.
+pub type LoadBatch = Batches<String>;
+
+/// Batch loading on the blocking thread pool, handing the loader back with the items.
+type LoadingBatch = JoinHandle<(LoadBatch, Result<Vec<String>, String>)>;
+
+/// Layout of a streamed listing: the items are sent between `prefix` and `suffix`, separated by
+/// `separator`.
+pub struct Listing {
+    /// Content type of the response.
+    pub content_type: &'static str,
+    /// Text before the first item.
+    pub prefix: String,
+    /// Text between two items.
+    pub separator: &'static str,
+    /// Text after the last item.
+    pub suffix: &'static str,
+}
+
+/// Loads a batch on the blocking thread pool.
+fn load_batch(mut load: LoadBatch, offset: i64) -> LoadingBatch {
+    task::spawn_blocking(move || {
+        let items = load(offset, STREAM_BATCH_SIZE);
+        (load, items)
+    })
+}
+
+/// Body sending the batches after the first one, which is loaded before the response starts.
+struct BatchedBody {
+    /// Loader of the next batch; `None` while a batch loads.
+    cursor: Option<LoadBatch>,
+    loading: Option<LoadingBatch>,
+    separator: &'static str,
+    suffix: &'static str,
+    offset: i64,
+    first: Option<Bytes>,
+    done: bool,
+}
+
+impl MessageBody for BatchedBody {
+    type Error = String;
+
+    fn size(&self) -> BodySize {
+        BodySize::Stream
+    }
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let body = self.get_mut();
+        if let Some(first) = body.first.take() {
+            return Poll::Ready(Some(Ok(first)));
+        }
+        if body.done {
+            return Poll::Ready(None);
+        }
+
+        let loading = match (&mut body.loading, body.cursor.take()) {
+            (Some(loading), _) => loading,
+            (loading, Some(cursor)) => loading.insert(load_batch(cursor, body.offset)),
+            (None, None) => return Poll::Ready(None),
+        };
+        let loaded = match Pin::new(loading).poll(cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(loaded) => loaded,
+        };
+        body.loading = None;
+
+        let items = match loaded {
+            Ok((cursor, Ok(items))) => {
+                body.cursor = Some(cursor);
+                Ok(items)
+            }
+            Ok((_, Err(error))) => Err(error),
+            Err(error) => Err(error.to_string()),
+        };
+        let items = match items {
+            Ok(items) => items,
+            Err(error) => {
+                // The status is already sent, so the client sees a truncated body
+                log_error!("Failed to load a batch of a streamed listing: {}", error);
+                body.done = true;
+                return Poll::Ready(Some(Err(error)));
+            }
+        };
+        body.offset += items.len() as i64;
+
+        let mut chunk = String::new();
+        for item in &items {
+            chunk.push_str(body.separator);
+            chunk.push_str(item);
+        }
+        if (items.len() as i64) < STREAM_BATCH_SIZE {
+            chunk.push_str(body.suffix);
+            body.done = true;
+            body.cursor = None;
+        }
+
+        Poll::Ready(Some(Ok(Bytes::from(chunk))))
+    }
+}
+
+impl Drop for BatchedBody {
+    /// Drops the loader of a listing that was not sent to the end on the blocking thread pool,
+    /// since it may end a database snapshot. A batch still loading drops it when it completes.
+    fn drop(&mut self) {
+        if let Some(load) = self.cursor.take() {
+            task::spawn_blocking(move || drop(load));
+        }
+    }
+}
+
+/// Responds with a listing loaded batch by batch.
+///
+/// The first batch is loaded before responding, so a listing that cannot be loaded at all is
+/// reported by the caller with an error status. Later failures abort the response.
+///
+/// # Arguments
+///
+/// * `listing` - Layout of the response.
+/// * `load` - Loads the serialized items of a batch, in a stable order; it is dropped when the
+///   response is sent or dropped.
+///
+/// # Errors
+///
+/// Returns a message if the first batch cannot be loaded.
+pub async fn stream_listing(listing: Listing, load: LoadBatch) -> Result<HttpResponse, String> {
+    let (cursor, items) = load_batch(load, 0).await.map_err(|e| e.to_string())?;
+    let items = items?;
+
+    let mut first = listing.prefix;
+    first.push_str(&items.join(listing.separator));
+    if (items.len() as i64) < STREAM_BATCH_SIZE {
+        first.push_str(listing.suffix);
+        return Ok(HttpResponse::Ok().content_type(listing.content_type).body(first));
+    }
+
+    Ok(HttpResponse::Ok().content_type(listing.content_type).body(BatchedBody {
+        cursor: Some(cursor),
+        loading: None,
+        separator: listing.separator,
+        suffix: listing.suffix,
+        offset: items.len() as i64,
+        first: Some(Bytes::from(first)),
+        done: false,
+    }))
+}
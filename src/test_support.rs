@@ -0,0 +1,195 @@
+//! This module provides reusable test utilities for the JWK microservice.
+//!
+//! It is available with the `test-util` feature. [`setup_database`] prepares a migrated
+//! PostgreSQL database: the one configured by `DATABASE_URL`, or a throwaway container started
+//! with testcontainers if the variable is not set. [`init_test_service`] builds the application
+//! on top of it, so `cargo test` works without any manually provisioned database.
+//!
+//! With `STORAGE_BACKEND=memory`, [`init_test_service`] serves the keys from the in-memory store
+//! of the process and no database is prepared, so `STORAGE_BACKEND=memory cargo test` runs
+//! without PostgreSQL and Docker; tests of the features kept in PostgreSQL only are skipped with
+//! [`skip_without_database`]. [`init_memory_test_service`] serves the keys from a fresh in-memory
+//! store in any case.
+//!
+//! Tests changing environment variables set them through [`EnvGuard`], which serializes them and
+//! restores the previous values.
+
+use crate::store::{memory_storage, MemoryKeyStore};
+use crate::{app_config, app_config_with_store, MIGRATIONS};
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::{test, App};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel_migrations::MigrationHarness;
+use dotenv::dotenv;
+use std::env;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+use testcontainers_modules::postgres::Postgres;
+use testcontainers_modules::testcontainers::runners::SyncRunner;
+use testcontainers_modules::testcontainers::Container;
+
+/// Port PostgreSQL listens on inside the container.
+const POSTGRES_PORT: u16 = 5432;
+
+/// Key-encryption key of the test database (base64url encoded), unless `KEY_ENCRYPTION_KEY` is set.
+const TEST_KEY_ENCRYPTION_KEY: &str = "dGVzdC1rZXktZW5jcnlwdGlvbi1rZXktMzItYnl0ZXM";
+
+/// Database shared by all tests of the process.
+struct TestDatabase {
+    url: String,
+    // Keeps the container running for the lifetime of the process
+    _container: Option<Container<Postgres>>,
+}
+
+static TEST_DATABASE: OnceLock<TestDatabase> = OnceLock::new();
+
+/// Lock held by the tests changing environment variables.
+static ENVIRONMENT: Mutex<()> = Mutex::new(());
+
+/// Starts a PostgreSQL container and returns it with its connection URL.
+///
+/// The synchronous runner drives its own Tokio runtime, so the container is started on a
+/// separate thread to allow calling this from async tests.
+fn start_postgres_container() -> (String, Container<Postgres>) {
+    std::thread::spawn(|| {
+        let container = Postgres::default()
+            .start()
+            .expect("Failed to start the PostgreSQL container (is Docker running?)");
+        let host = container.get_host().expect("Failed to get the container host");
+        let port = container
+            .get_host_port_ipv4(POSTGRES_PORT)
+            .expect("Failed to get the container port");
+
+        (format!("postgres://postgres:postgres@{}:{}/postgres", host, port), container)
+    })
+    .join()
+    .expect("Failed to start the PostgreSQL container")
+}
+
+/// Prepares the database used by the tests and returns its URL.
+///
+/// Uses `DATABASE_URL` if it is set (in the environment or the `.env` file); otherwise starts a
+/// PostgreSQL container and points `DATABASE_URL` at it. Private keys are encrypted with a test
+/// key-encryption key unless `KEY_ENCRYPTION_KEY` is set. Pending migrations are run once per
+/// process. Subsequent calls return the same database.
+///
+/// # Panics
+///
+/// This function will panic if the container cannot be started, the database is unreachable or
+/// the migrations fail.
+pub fn setup_database() -> &'static str {
+    &TEST_DATABASE
+        .get_or_init(|| {
+            dotenv().ok();
+
+            let (url, container) = match env::var("DATABASE_URL") {
+                Ok(url) => (url, None),
+                Err(_) => {
+                    let (url, container) = start_postgres_container();
+                    env::set_var("DATABASE_URL", &url);
+                    (url, Some(container))
+                }
+            };
+            if env::var("KEY_ENCRYPTION_KEY").is_err() {
+                env::set_var("KEY_ENCRYPTION_KEY", TEST_KEY_ENCRYPTION_KEY);
+            }
+
+            let connection = &mut PgConnection::establish(&url)
+                .unwrap_or_else(|_| panic!("Error connecting to {}", url));
+            crate::migrate::rename_applied_versions(connection).expect("Failed to rename migrations");
+            connection
+                .run_pending_migrations(MIGRATIONS)
+                .expect("Failed to run migrations");
+
+            TestDatabase { url, _container: container }
+        })
+        .url
+}
+
+/// Returns whether a test needing PostgreSQL is skipped because the keys are kept in memory
+/// (`STORAGE_BACKEND=memory`) and no database is prepared.
+///
+/// # Arguments
+///
+/// * `test` - Name of the test, reported when it is skipped.
+pub fn skip_without_database(test: &str) -> bool {
+    let skipped = memory_storage();
+    if skipped {
+        eprintln!("Skipping {}: it needs PostgreSQL and STORAGE_BACKEND=memory is set", test);
+    }
+    skipped
+}
+
+/// Prepares the test database and initializes the application as a test service.
+///
+/// With `STORAGE_BACKEND=memory`, no database is prepared and the keys are served from the
+/// in-memory store of the process.
+///
+/// # Returns
+///
+/// A service that can be called with `actix_web::test::call_service`.
+pub async fn init_test_service() -> impl Service<
+    actix_http::Request,
+    Response = ServiceResponse<impl MessageBody>,
+    Error = actix_web::Error,
+> {
+    if !memory_storage() {
+        setup_database();
+    }
+    test::init_service(App::new().configure(app_config)).await
+}
+
+/// Initializes the application as a test service serving the keys from a fresh in-memory store,
+/// without a database.
+///
+/// # Returns
+///
+/// The service, which can be called with `actix_web::test::call_service`, and its store.
+pub async fn init_memory_test_service() -> (
+    impl Service<actix_http::Request, Response = ServiceResponse<impl MessageBody>, Error = actix_web::Error>,
+    Arc<MemoryKeyStore>,
+) {
+    let store = Arc::new(MemoryKeyStore::new());
+    let app = test::init_service(App::new().configure(app_config_with_store(store.clone()))).await;
+    (app, store)
+}
+
+/// Environment variables set by a test, restored to their previous values when the guard is
+/// dropped.
+///
+/// The guard holds a process-wide lock, so tests changing the environment run one at a time and
+/// do not see each other's values.
+pub struct EnvGuard {
+    previous: Vec<(String, Option<String>)>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl EnvGuard {
+    /// Waits for the tests holding the lock, then sets `variables` (name and value).
+    pub fn set(variables: &[(&str, &str)]) -> Self {
+        // A failed test must not fail the tests waiting for the lock
+        let lock = ENVIRONMENT.lock().unwrap_or_else(PoisonError::into_inner);
+        let previous = variables
+            .iter()
+            .map(|(name, value)| {
+                let previous = env::var(name).ok();
+                env::set_var(name, value);
+                (name.to_string(), previous)
+            })
+            .collect();
+
+        EnvGuard { previous, _lock: lock }
+    }
+}
+
+impl Drop for EnvGuard {
+    fn drop(&mut self) {
+        for (name, previous) in self.previous.iter().rev() {
+            match previous {
+                Some(value) => env::set_var(name, value),
+                None => env::remove_var(name),
+            }
+        }
+    }
+}
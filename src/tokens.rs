@@ -0,0 +1,551 @@
+//! This module builds the claims sets of tokens issued according to standard token profiles.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use dotenv::dotenv;
+use serde_json::{Map, Value};
+use std::env;
+use uuid::Uuid;
+
+/// Lifetime of access tokens whose request sets neither `exp` nor `expires_in`.
+pub const DEFAULT_ACCESS_TOKEN_LIFETIME_SECONDS: i64 = 3600;
+
+/// Returns the claims object or an error if the claims set is not a JSON object.
+fn claims_object(claims: &Value) -> Result<Map<String, Value>, String> {
+    claims
+        .as_object()
+        .cloned()
+        .ok_or_else(|| "Claims must be a JSON object".to_string())
+}
+
+/// Checks that a claim is present and is a non-empty string.
+fn require_string_claim(claims: &Map<String, Value>, name: &str) -> Result<(), String> {
+    match claims.get(name).and_then(Value::as_str) {
+        Some(value) if !value.is_empty() => Ok(()),
+        _ => Err(format!("Claim {} is required and must be a string", name)),
+    }
+}
+
+/// Checks that the `aud` claim is a string or a non-empty array of strings.
+fn require_audience_claim(claims: &Map<String, Value>) -> Result<(), String> {
+    let valid = match claims.get("aud") {
+        Some(Value::String(aud)) => !aud.is_empty(),
+        Some(Value::Array(values)) => {
+            !values.is_empty() && values.iter().all(|value| value.is_string())
+        }
+        _ => false,
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err("Claim aud is required and must be a string or an array of strings".to_string())
+    }
+}
+
+/// Validates and completes the claims of a JWT access token (RFC 9068).
+///
+/// The caller must supply `iss`, `sub`, `aud` and `client_id`. `iat` defaults to `now`, `exp`
+/// to `iat + expires_in` and `jti` to a random UUID.
+///
+/// # Arguments
+///
+/// * `claims` - Claims requested by the caller.
+/// * `now` - Current time in seconds since the Unix epoch.
+/// * `expires_in` - Token lifetime in seconds used when `exp` is not supplied.
+///
+/// # Errors
+///
+/// Returns a message describing the first missing or invalid claim.
+pub fn access_token_claims(claims: &Value, now: i64, expires_in: i64) -> Result<Value, String> {
+    let mut claims = claims_object(claims)?;
+
+    for name in ["iss", "sub", "client_id"] {
+        require_string_claim(&claims, name)?;
+    }
+    require_audience_claim(&claims)?;
+
+    if expires_in <= 0 {
+        return Err("expires_in must be positive".to_string());
+    }
+
+    let iat = match claims.get("iat") {
+        None => now,
+        Some(value) => value.as_i64().ok_or("Claim iat must be a number")?,
+    };
+    let exp = match claims.get("exp") {
+        None => iat + expires_in,
+        Some(value) => value.as_i64().ok_or("Claim exp must be a number")?,
+    };
+    if exp <= iat {
+        return Err("Claim exp must be after iat".to_string());
+    }
+
+    claims.insert("iat".to_string(), Value::from(iat));
+    claims.insert("exp".to_string(), Value::from(exp));
+    if !claims.contains_key("jti") {
+        claims.insert("jti".to_string(), Value::from(Uuid::new_v4().to_string()));
+    }
+
+    Ok(Value::Object(claims))
+}
+
+/// Returns the ID of the key designated for signing software statements
+/// (`SOFTWARE_STATEMENT_KEY_ID`).
+///
+/// # Returns
+///
+/// `None` if no key is designated.
+///
+/// # Panics
+///
+/// This function will panic if `SOFTWARE_STATEMENT_KEY_ID` is not a UUID.
+pub fn software_statement_key_id() -> Option<Uuid> {
+    dotenv().ok();
+
+    env::var("SOFTWARE_STATEMENT_KEY_ID")
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+        .map(|value| {
+            value
+                .trim()
+                .parse()
+                .expect("SOFTWARE_STATEMENT_KEY_ID must be a UUID")
+        })
+}
+
+/// Returns the software statement claims template (`SOFTWARE_STATEMENT_TEMPLATE`).
+///
+/// # Returns
+///
+/// `None` if no template is configured.
+///
+/// # Panics
+///
+/// This function will panic if `SOFTWARE_STATEMENT_TEMPLATE` is not a JSON object.
+pub fn software_statement_template() -> Option<Value> {
+    dotenv().ok();
+
+    env::var("SOFTWARE_STATEMENT_TEMPLATE")
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+        .map(|value| {
+            serde_json::from_str::<Value>(&value)
+                .ok()
+                .filter(Value::is_object)
+                .expect("SOFTWARE_STATEMENT_TEMPLATE must be a JSON object")
+        })
+}
+
+/// Validates and completes the claims of a software statement (RFC 7591).
+///
+/// Every claim of the template is required. A template value is used when the caller does not
+/// supply the claim; a `null` template value must be supplied by the caller. `iss` is always
+/// required and `iat` defaults to `now`.
+///
+/// # Arguments
+///
+/// * `claims` - Claims requested by the caller.
+/// * `template` - Claims template, or `None` if no template is configured.
+/// * `now` - Current time in seconds since the Unix epoch.
+///
+/// # Errors
+///
+/// Returns a message describing the first missing or invalid claim.
+pub fn software_statement_claims(
+    claims: &Value,
+    template: Option<&Value>,
+    now: i64,
+) -> Result<Value, String> {
+    let mut claims = claims_object(claims)?;
+
+    if let Some(template) = template {
+        for (name, default) in claims_object(template)? {
+            if claims.get(&name).is_none_or(Value::is_null) {
+                if default.is_null() {
+                    return Err(format!("Claim {} is required", name));
+                }
+                claims.insert(name, default);
+            }
+        }
+    }
+
+    require_string_claim(&claims, "iss")?;
+    if !claims.contains_key("iat") {
+        claims.insert("iat".to_string(), Value::from(now));
+    }
+
+    Ok(Value::Object(claims))
+}
+
+/// Token type identifier of JWTs (RFC 8693).
+pub const TOKEN_TYPE_JWT: &str = "urn:ietf:params:oauth:token-type:jwt";
+
+/// Token type identifier of access tokens (RFC 8693).
+pub const TOKEN_TYPE_ACCESS_TOKEN: &str = "urn:ietf:params:oauth:token-type:access_token";
+
+/// Grant type of token exchange requests (RFC 8693).
+pub const GRANT_TYPE_TOKEN_EXCHANGE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+
+/// Checks the validity period of a token (`exp` and `nbf` claims).
+///
+/// # Arguments
+///
+/// * `claims` - Verified token claims.
+/// * `now` - Current time in seconds since the Unix epoch.
+///
+/// # Errors
+///
+/// Returns a message if the token is expired, not yet valid or has malformed time claims.
+pub fn check_token_times(claims: &Value, now: i64) -> Result<(), String> {
+    if let Some(exp) = claims.get("exp") {
+        let exp = exp.as_i64().ok_or("Claim exp must be a number")?;
+        if now >= exp {
+            return Err("Token has expired".to_string());
+        }
+    }
+
+    if let Some(nbf) = claims.get("nbf") {
+        let nbf = nbf.as_i64().ok_or("Claim nbf must be a number")?;
+        if now < nbf {
+            return Err("Token is not yet valid".to_string());
+        }
+    }
+
+    Ok(())
+}
+
+/// Maps the claims of a verified subject token to the claims of an exchanged token (RFC 8693).
+///
+/// `iss`, `sub` and `client_id` are taken from the subject token. `aud` and `scope` are taken from
+/// the request if given and from the subject token otherwise. If an actor token is given, its
+/// `sub` becomes the `act` claim (delegation), nesting any `act` claim of the subject token.
+/// The exchanged token never outlives the subject token.
+///
+/// # Arguments
+///
+/// * `subject` - Verified subject token claims.
+/// * `actor` - Verified actor token claims, if any.
+/// * `audience` - Requested audience, if any.
+/// * `scope` - Requested scope, if any.
+/// * `now` - Current time in seconds since the Unix epoch.
+/// * `lifetime` - Maximum lifetime of the exchanged token in seconds.
+///
+/// # Errors
+///
+/// Returns a message describing the first missing or invalid claim.
+pub fn exchanged_token_claims(
+    subject: &Value,
+    actor: Option<&Value>,
+    audience: Option<&str>,
+    scope: Option<&str>,
+    now: i64,
+    lifetime: i64,
+) -> Result<Value, String> {
+    let subject = claims_object(subject)?;
+    require_string_claim(&subject, "iss")?;
+    require_string_claim(&subject, "sub")?;
+
+    let mut claims = Map::new();
+    for name in ["iss", "sub", "client_id"] {
+        if let Some(value) = subject.get(name) {
+            claims.insert(name.to_string(), value.clone());
+        }
+    }
+
+    match audience.map(Value::from).or_else(|| subject.get("aud").cloned()) {
+        Some(aud) => claims.insert("aud".to_string(), aud),
+        None => return Err("Audience is required".to_string()),
+    };
+    if let Some(scope) = scope.map(Value::from).or_else(|| subject.get("scope").cloned()) {
+        claims.insert("scope".to_string(), scope);
+    }
+
+    if let Some(actor) = actor {
+        let actor = claims_object(actor)?;
+        require_string_claim(&actor, "sub")?;
+        let mut act = Map::new();
+        act.insert("sub".to_string(), actor["sub"].clone());
+        if let Some(previous) = subject.get("act") {
+            act.insert("act".to_string(), previous.clone());
+        }
+        claims.insert("act".to_string(), Value::Object(act));
+    } else if let Some(previous) = subject.get("act") {
+        claims.insert("act".to_string(), previous.clone());
+    }
+
+    let mut exp = now + lifetime;
+    if let Some(subject_exp) = subject.get("exp").and_then(Value::as_i64) {
+        exp = exp.min(subject_exp);
+    }
+
+    claims.insert("iat".to_string(), Value::from(now));
+    claims.insert("exp".to_string(), Value::from(exp));
+    claims.insert("jti".to_string(), Value::from(Uuid::new_v4().to_string()));
+
+    Ok(Value::Object(claims))
+}
+
+/// Lifetime of request objects whose request does not set `exp`.
+pub const DEFAULT_REQUEST_OBJECT_LIFETIME_SECONDS: i64 = 300;
+
+/// Longest validity period (`exp - nbf`) of a request object permitted by FAPI.
+pub const MAX_REQUEST_OBJECT_LIFETIME_SECONDS: i64 = 3600;
+
+/// Validates and completes the claims of a signed authorization request object (RFC 9101).
+///
+/// The caller must supply `iss`, `aud`, `client_id`, `response_type`, `redirect_uri` and `scope`,
+/// and `iss` must equal `client_id`. `nbf` and `iat` default to `now`, `exp` to
+/// `nbf + DEFAULT_REQUEST_OBJECT_LIFETIME_SECONDS` and `jti` to a random UUID. As required by
+/// FAPI, the validity period must not exceed `MAX_REQUEST_OBJECT_LIFETIME_SECONDS`.
+///
+/// # Arguments
+///
+/// * `claims` - Authorization request parameters supplied by the caller.
+/// * `now` - Current time in seconds since the Unix epoch.
+///
+/// # Errors
+///
+/// Returns a message describing the first missing or invalid claim.
+pub fn request_object_claims(claims: &Value, now: i64) -> Result<Value, String> {
+    let mut claims = claims_object(claims)?;
+
+    for name in ["iss", "client_id", "response_type", "redirect_uri", "scope"] {
+        require_string_claim(&claims, name)?;
+    }
+    require_audience_claim(&claims)?;
+    if claims["iss"] != claims["client_id"] {
+        return Err("Claim iss must equal client_id".to_string());
+    }
+
+    let nbf = match claims.get("nbf") {
+        None => now,
+        Some(value) => value.as_i64().ok_or("Claim nbf must be a number")?,
+    };
+    let exp = match claims.get("exp") {
+        None => nbf + DEFAULT_REQUEST_OBJECT_LIFETIME_SECONDS,
+        Some(value) => value.as_i64().ok_or("Claim exp must be a number")?,
+    };
+    if exp <= nbf {
+        return Err("Claim exp must be after nbf".to_string());
+    }
+    if exp - nbf > MAX_REQUEST_OBJECT_LIFETIME_SECONDS {
+        return Err(format!(
+            "Request object must not be valid for more than {} seconds",
+            MAX_REQUEST_OBJECT_LIFETIME_SECONDS
+        ));
+    }
+
+    claims.insert("nbf".to_string(), Value::from(nbf));
+    claims.insert("exp".to_string(), Value::from(exp));
+    if !claims.contains_key("iat") {
+        claims.insert("iat".to_string(), Value::from(now));
+    }
+    if !claims.contains_key("jti") {
+        claims.insert("jti".to_string(), Value::from(Uuid::new_v4().to_string()));
+    }
+
+    Ok(Value::Object(claims))
+}
+
+/// Binds the token to a client certificate by adding the `cnf.x5t#S256` confirmation claim
+/// (RFC 8705).
+///
+/// Other members of an existing `cnf` claim are kept.
+///
+/// # Arguments
+///
+/// * `claims` - Token claims.
+/// * `thumbprint` - Base64URL encoded SHA-256 thumbprint of the client certificate.
+///
+/// # Errors
+///
+/// Returns a message if the claims are not an object, the thumbprint is malformed or the
+/// existing `cnf` claim is not an object.
+pub fn bind_certificate(claims: &Value, thumbprint: &str) -> Result<Value, String> {
+    let mut claims = claims_object(claims)?;
+
+    match URL_SAFE_NO_PAD.decode(thumbprint) {
+        Ok(digest) if digest.len() == 32 => {}
+        _ => return Err("Certificate thumbprint must be a Base64URL encoded SHA-256 digest".to_string()),
+    }
+
+    let cnf = claims
+        .entry("cnf")
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()
+        .ok_or("Claim cnf must be a JSON object")?;
+    cnf.insert("x5t#S256".to_string(), Value::from(thumbprint));
+
+    Ok(Value::Object(claims))
+}
+
+#[test]
+fn test_access_token_claims() {
+    use serde_json::json;
+
+    let claims = json!({
+        "iss": "https://auth.example.com",
+        "sub": "user-1",
+        "aud": "https://api.example.com",
+        "client_id": "client-1"
+    });
+
+    let completed = access_token_claims(&claims, 1_000, 60).unwrap();
+    assert_eq!(completed["iat"], 1_000);
+    assert_eq!(completed["exp"], 1_060);
+    assert!(completed["jti"].is_string());
+    assert_eq!(completed["sub"], "user-1");
+
+    let mut explicit = claims.clone();
+    explicit["exp"] = json!(5_000);
+    explicit["jti"] = json!("fixed");
+    let completed = access_token_claims(&explicit, 1_000, 60).unwrap();
+    assert_eq!(completed["exp"], 5_000);
+    assert_eq!(completed["jti"], "fixed");
+
+    let mut expired = claims.clone();
+    expired["exp"] = json!(500);
+    assert!(access_token_claims(&expired, 1_000, 60).is_err());
+
+    let mut no_client = claims.clone();
+    no_client.as_object_mut().unwrap().remove("client_id");
+    assert!(access_token_claims(&no_client, 1_000, 60).is_err());
+
+    let mut empty_aud = claims.clone();
+    empty_aud["aud"] = json!([]);
+    assert!(access_token_claims(&empty_aud, 1_000, 60).is_err());
+
+    assert!(access_token_claims(&json!("not an object"), 1_000, 60).is_err());
+    assert!(access_token_claims(&claims, 1_000, 0).is_err());
+}
+
+#[test]
+fn test_software_statement_claims() {
+    use serde_json::json;
+
+    let template = json!({
+        "iss": "https://partners.example.com",
+        "software_id": null,
+        "grant_types": ["client_credentials"]
+    });
+
+    let completed = software_statement_claims(
+        &json!({"software_id": "partner-app", "client_name": "Partner"}),
+        Some(&template),
+        1_000,
+    )
+    .unwrap();
+    assert_eq!(completed["iss"], "https://partners.example.com");
+    assert_eq!(completed["software_id"], "partner-app");
+    assert_eq!(completed["grant_types"], json!(["client_credentials"]));
+    assert_eq!(completed["client_name"], "Partner");
+    assert_eq!(completed["iat"], 1_000);
+
+    let overridden = software_statement_claims(
+        &json!({"software_id": "partner-app", "grant_types": ["authorization_code"]}),
+        Some(&template),
+        1_000,
+    )
+    .unwrap();
+    assert_eq!(overridden["grant_types"], json!(["authorization_code"]));
+
+    assert!(software_statement_claims(&json!({}), Some(&template), 1_000).is_err());
+    assert!(software_statement_claims(&json!({"software_id": "x"}), None, 1_000).is_err());
+    assert!(software_statement_claims(&json!({"iss": "https://partners.example.com"}), None, 1_000).is_ok());
+}
+
+#[test]
+fn test_check_token_times() {
+    use serde_json::json;
+
+    assert!(check_token_times(&json!({}), 1_000).is_ok());
+    assert!(check_token_times(&json!({"exp": 1_001, "nbf": 1_000}), 1_000).is_ok());
+    assert!(check_token_times(&json!({"exp": 1_000}), 1_000).is_err());
+    assert!(check_token_times(&json!({"nbf": 1_001}), 1_000).is_err());
+    assert!(check_token_times(&json!({"exp": "soon"}), 1_000).is_err());
+}
+
+#[test]
+fn test_exchanged_token_claims() {
+    use serde_json::json;
+
+    let subject = json!({
+        "iss": "https://auth.example.com",
+        "sub": "user-1",
+        "aud": "https://frontend.example.com",
+        "scope": "read write",
+        "client_id": "client-1",
+        "exp": 1_100,
+        "email": "user@example.com"
+    });
+
+    let exchanged = exchanged_token_claims(&subject, None, Some("https://api.example.com"), Some("read"), 1_000, 3600).unwrap();
+    assert_eq!(exchanged["sub"], "user-1");
+    assert_eq!(exchanged["aud"], "https://api.example.com");
+    assert_eq!(exchanged["scope"], "read");
+    assert_eq!(exchanged["client_id"], "client-1");
+    assert_eq!(exchanged["exp"], 1_100);
+    assert!(exchanged.get("email").is_none());
+    assert!(exchanged.get("act").is_none());
+
+    let actor = json!({"iss": "https://auth.example.com", "sub": "service-a"});
+    let delegated = exchanged_token_claims(&subject, Some(&actor), None, None, 1_000, 60).unwrap();
+    assert_eq!(delegated["aud"], "https://frontend.example.com");
+    assert_eq!(delegated["act"], json!({"sub": "service-a"}));
+    assert_eq!(delegated["exp"], 1_060);
+
+    let chained = exchanged_token_claims(&delegated, Some(&json!({"sub": "service-b"})), None, None, 1_000, 60).unwrap();
+    assert_eq!(chained["act"], json!({"sub": "service-b", "act": {"sub": "service-a"}}));
+
+    assert!(exchanged_token_claims(&json!({"iss": "x", "sub": "y"}), None, None, None, 1_000, 60).is_err());
+    assert!(exchanged_token_claims(&json!({"sub": "y", "aud": "z"}), None, None, None, 1_000, 60).is_err());
+}
+
+#[test]
+fn test_request_object_claims() {
+    use serde_json::json;
+
+    let claims = json!({
+        "iss": "client-1",
+        "client_id": "client-1",
+        "aud": "https://auth.example.com",
+        "response_type": "code",
+        "redirect_uri": "https://client.example.com/callback",
+        "scope": "openid accounts"
+    });
+
+    let completed = request_object_claims(&claims, 1_000).unwrap();
+    assert_eq!(completed["nbf"], 1_000);
+    assert_eq!(completed["iat"], 1_000);
+    assert_eq!(completed["exp"], 1_000 + DEFAULT_REQUEST_OBJECT_LIFETIME_SECONDS);
+    assert!(completed["jti"].is_string());
+
+    let mut too_long = claims.clone();
+    too_long["exp"] = json!(1_000 + MAX_REQUEST_OBJECT_LIFETIME_SECONDS + 1);
+    assert!(request_object_claims(&too_long, 1_000).is_err());
+
+    let mut other_issuer = claims.clone();
+    other_issuer["iss"] = json!("client-2");
+    assert!(request_object_claims(&other_issuer, 1_000).is_err());
+
+    let mut no_redirect = claims.clone();
+    no_redirect.as_object_mut().unwrap().remove("redirect_uri");
+    assert!(request_object_claims(&no_redirect, 1_000).is_err());
+}
+
+#[test]
+fn test_bind_certificate() {
+    use serde_json::json;
+
+    let thumbprint = URL_SAFE_NO_PAD.encode([7u8; 32]);
+
+    let bound = bind_certificate(&json!({"sub": "user-1", "cnf": {"jkt": "abc"}}), &thumbprint).unwrap();
+    assert_eq!(bound["cnf"]["x5t#S256"], thumbprint.as_str());
+    assert_eq!(bound["cnf"]["jkt"], "abc");
+
+    let bound = bind_certificate(&json!({"sub": "user-1"}), &thumbprint).unwrap();
+    assert_eq!(bound["cnf"], json!({"x5t#S256": thumbprint}));
+
+    assert!(bind_certificate(&json!({}), "short").is_err());
+    assert!(bind_certificate(&json!({"cnf": "x"}), &thumbprint).is_err());
+}
@@ -0,0 +1,315 @@
+//! This module serves cached copies of upstream JWK Sets (read-through proxy).
+//!
+//! A deployment can front the `jwks_uri` endpoints of other services (`UPSTREAM_JWKS`), so
+//! relying parties in its cluster fetch them from a local cache at `/upstream/{name}/jwks.json`.
+//! Documents are fetched on the first request and cached in memory:
+//!
+//! * For `UPSTREAM_JWKS_TTL_SECONDS` a cached document is served without contacting the upstream.
+//! * For `UPSTREAM_JWKS_STALE_WHILE_REVALIDATE_SECONDS` after that, the stale document is served
+//!   while a background refresh fetches a new copy.
+//! * If the upstream fails, the last good document is served for up to
+//!   `UPSTREAM_JWKS_STALE_IF_ERROR_SECONDS` after it expired, so an upstream outage does not
+//!   break token validation in the cluster.
+//!
+//! Upstream documents must be JSON objects with a `keys` array of JWK objects; anything else is
+//! treated as a failed fetch. Valid documents are served as fetched.
+
+use crate::log_error;
+use actix_web::{web, HttpResponse, Responder};
+use dotenv::dotenv;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Maximum size of an upstream document.
+pub const MAX_UPSTREAM_DOCUMENT_BYTES: usize = 4 * 1024 * 1024;
+
+/// Timeout of an upstream request.
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Cached upstream documents by upstream name.
+static CACHE: Mutex<Option<HashMap<String, CachedDocument>>> = Mutex::new(None);
+
+/// Upstream document with the time it was fetched.
+#[derive(Debug, Clone)]
+struct CachedDocument {
+    url: String,
+    body: String,
+    fetched_at: Instant,
+    refreshing: bool,
+}
+
+/// Cache lifetimes of the upstream documents.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CacheSettings {
+    /// Time a document is served without contacting the upstream.
+    pub ttl: Duration,
+    /// Time after the TTL a document is served while it is refreshed in the background.
+    pub stale_while_revalidate: Duration,
+    /// Time after the TTL a document is served if the upstream fails.
+    pub stale_if_error: Duration,
+}
+
+/// State of a cached document.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Freshness {
+    /// Within the TTL.
+    Fresh,
+    /// Past the TTL, but within the stale-while-revalidate window.
+    Stale,
+    /// Past both; the document is only served if the upstream fails.
+    Expired,
+}
+
+impl CacheSettings {
+    /// Returns the state of a document of the given age.
+    pub fn freshness(&self, age: Duration) -> Freshness {
+        if age < self.ttl {
+            Freshness::Fresh
+        } else if age < self.ttl + self.stale_while_revalidate {
+            Freshness::Stale
+        } else {
+            Freshness::Expired
+        }
+    }
+
+    /// Returns whether a document of the given age may be served because the upstream failed.
+    pub fn usable_on_error(&self, age: Duration) -> bool {
+        age < self.ttl + self.stale_if_error
+    }
+}
+
+/// Returns the upstream JWK Set URLs by name (`UPSTREAM_JWKS`, comma-separated `name=url`
+/// entries).
+///
+/// # Panics
+///
+/// This function will panic if an entry is not of the form `name=url`.
+pub fn upstream_sources() -> HashMap<String, String> {
+    dotenv().ok();
+
+    env::var("UPSTREAM_JWKS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (name, url) = entry
+                .split_once('=')
+                .filter(|(name, url)| !name.trim().is_empty() && !url.trim().is_empty())
+                .expect("UPSTREAM_JWKS entries must be of the form name=url");
+            (name.trim().to_string(), url.trim().to_string())
+        })
+        .collect()
+}
+
+/// Returns the cache lifetimes of the upstream documents (`UPSTREAM_JWKS_TTL_SECONDS`, default
+/// 300; `UPSTREAM_JWKS_STALE_WHILE_REVALIDATE_SECONDS`, default 60;
+/// `UPSTREAM_JWKS_STALE_IF_ERROR_SECONDS`, default 86400).
+///
+/// # Panics
+///
+/// This function will panic if a setting is not a non-negative number.
+pub fn cache_settings() -> CacheSettings {
+    dotenv().ok();
+
+    let seconds = |name: &str, default: u64| {
+        env::var(name)
+            .map(|value| value.trim().parse().unwrap_or_else(|_| panic!("{} must be a non-negative number", name)))
+            .unwrap_or(default)
+    };
+
+    CacheSettings {
+        ttl: Duration::from_secs(seconds("UPSTREAM_JWKS_TTL_SECONDS", 300)),
+        stale_while_revalidate: Duration::from_secs(seconds("UPSTREAM_JWKS_STALE_WHILE_REVALIDATE_SECONDS", 60)),
+        stale_if_error: Duration::from_secs(seconds("UPSTREAM_JWKS_STALE_IF_ERROR_SECONDS", 86400)),
+    }
+}
+
+/// Checks that an upstream document is a JWK Set.
+///
+/// # Errors
+///
+/// Returns a message if the document is not a JSON object with a `keys` array of objects.
+pub fn check_upstream_document(body: &[u8]) -> Result<(), String> {
+    let document: Value = serde_json::from_slice(body).map_err(|e| format!("invalid JSON: {}", e))?;
+    let keys = document
+        .get("keys")
+        .and_then(Value::as_array)
+        .ok_or("the document has no keys array")?;
+    if !keys.iter().all(Value::is_object) {
+        return Err("the keys array contains a non-object entry".to_string());
+    }
+
+    Ok(())
+}
+
+/// Fetches and checks an upstream document. The document is served as fetched.
+///
+/// # Errors
+///
+/// Returns a message if the request fails, the response is not successful, too large or not a
+/// JWK Set.
+pub fn fetch_upstream_document(url: &str) -> Result<String, String> {
+    let response = reqwest::blocking::Client::builder()
+        .timeout(UPSTREAM_TIMEOUT)
+        .build()
+        .and_then(|client| client.get(url).header("Accept", "application/json").send())
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?;
+    if response.content_length().is_some_and(|length| length > MAX_UPSTREAM_DOCUMENT_BYTES as u64) {
+        return Err("the document is too large".to_string());
+    }
+    let body = response.bytes().map_err(|e| e.to_string())?;
+    if body.len() > MAX_UPSTREAM_DOCUMENT_BYTES {
+        return Err("the document is too large".to_string());
+    }
+
+    check_upstream_document(&body)?;
+    String::from_utf8(body.to_vec()).map_err(|_| "the document is not UTF-8".to_string())
+}
+
+/// Stores a fetched document, or clears the refresh flag of the cached one if the fetch failed.
+fn store_document(name: &str, url: &str, result: &Result<String, String>) {
+    let mut cache = CACHE.lock().unwrap();
+    let cache = cache.get_or_insert_with(HashMap::new);
+    match result {
+        Ok(body) => {
+            cache.insert(
+                name.to_string(),
+                CachedDocument {
+                    url: url.to_string(),
+                    body: body.clone(),
+                    fetched_at: Instant::now(),
+                    refreshing: false,
+                },
+            );
+        }
+        Err(_) => {
+            if let Some(cached) = cache.get_mut(name) {
+                cached.refreshing = false;
+            }
+        }
+    }
+}
+
+/// Refreshes a stale document in the background, unless a refresh is already running.
+fn refresh_in_background(name: &str, url: &str) {
+    {
+        let mut cache = CACHE.lock().unwrap();
+        match cache.get_or_insert_with(HashMap::new).get_mut(name) {
+            Some(cached) if !cached.refreshing => cached.refreshing = true,
+            _ => return,
+        }
+    }
+
+    let (name, url) = (name.to_string(), url.to_string());
+    std::thread::spawn(move || {
+        let result = fetch_upstream_document(&url);
+        if let Err(error) = &result {
+            log_error!("Failed to refresh the upstream JWK Set {}: {}", name, error);
+        }
+        store_document(&name, &url, &result);
+    });
+}
+
+/// Builds the response serving a document of the given age.
+fn document_response(body: String, age: Duration, settings: &CacheSettings) -> HttpResponse {
+    let max_age = settings.ttl.saturating_sub(age).as_secs();
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header(("Cache-Control", format!("public, max-age={}", max_age)))
+        .insert_header(("Age", age.as_secs().to_string()))
+        .body(body)
+}
+
+/// Handles the request to retrieve the cached copy of an upstream JWK Set.
+///
+/// # Arguments
+///
+/// * `name` - The name of the upstream in `UPSTREAM_JWKS`.
+///
+/// # Returns
+///
+/// The upstream JWK Set, with its time in the cache in the `Age` header.
+#[utoipa::path(
+    get,
+    path = "/upstream/{name}/jwks.json",
+    params(("name" = String, Path, description = "Name of the upstream in UPSTREAM_JWKS")),
+    responses(
+        (status = 200, description = "Cached upstream JWK Set", body = Object,
+            headers(("Age" = String, description = "Seconds since the document was fetched from the upstream"))),
+        (status = 404, description = "Unknown upstream", body = String, content_type = "text/plain"),
+        (status = 502, description = "The upstream failed and no usable copy is cached", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn upstream_jwks_handler(name: web::Path<String>) -> impl Responder {
+    let name = name.into_inner();
+    let url = match upstream_sources().remove(&name) {
+        Some(url) => url,
+        None => return HttpResponse::NotFound().body("Unknown upstream"),
+    };
+    let settings = cache_settings();
+
+    // A document cached for a previous URL of the upstream is not served
+    let cached = CACHE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|cache| cache.get(&name).cloned())
+        .filter(|cached| cached.url == url);
+    if let Some(cached) = &cached {
+        let age = cached.fetched_at.elapsed();
+        match settings.freshness(age) {
+            Freshness::Fresh => return document_response(cached.body.clone(), age, &settings),
+            Freshness::Stale => {
+                refresh_in_background(&name, &url);
+                return document_response(cached.body.clone(), age, &settings);
+            }
+            Freshness::Expired => {}
+        }
+    }
+
+    let fetch_url = url.clone();
+    let result = web::block(move || fetch_upstream_document(&fetch_url))
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+    store_document(&name, &url, &result);
+    match result {
+        Ok(body) => document_response(body, Duration::ZERO, &settings),
+        Err(error) => {
+            log_error!("Failed to fetch the upstream JWK Set {}: {}", name, error);
+            match cached {
+                Some(cached) if settings.usable_on_error(cached.fetched_at.elapsed()) => {
+                    document_response(cached.body, cached.fetched_at.elapsed(), &settings)
+                }
+                _ => HttpResponse::BadGateway().body("Failed to fetch the upstream JWK Set"),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_cache_settings_freshness() {
+    let settings = CacheSettings {
+        ttl: Duration::from_secs(300),
+        stale_while_revalidate: Duration::from_secs(60),
+        stale_if_error: Duration::from_secs(3600),
+    };
+    assert_eq!(settings.freshness(Duration::from_secs(0)), Freshness::Fresh);
+    assert_eq!(settings.freshness(Duration::from_secs(300)), Freshness::Stale);
+    assert_eq!(settings.freshness(Duration::from_secs(360)), Freshness::Expired);
+    assert!(settings.usable_on_error(Duration::from_secs(3899)));
+    assert!(!settings.usable_on_error(Duration::from_secs(3900)));
+}
+
+#[test]
+fn test_check_upstream_document() {
+    assert!(check_upstream_document(br#"{ "keys": [ { "kty": "EC", "kid": "k1" } ] }"#).is_ok());
+    assert!(check_upstream_document(br#"{"keys": []}"#).is_ok());
+    assert!(check_upstream_document(br#"{"keys": {}}"#).is_err());
+    assert!(check_upstream_document(br#"{"keys": ["k1"]}"#).is_err());
+    assert!(check_upstream_document(b"<html>").is_err());
+}
@@ -0,0 +1,462 @@
+//! This module validates externally supplied JWKs before they are imported.
+//!
+//! A JWK is checked for compliance with RFC 7517 and RFC 7518 (required and well-formed
+//! parameters, consistent `alg`, `use` and `key_ops`), for weak parameters and against the
+//! deployment policy. Every problem is reported as a finding instead of stopping at the first.
+
+use crate::crypto::{curve_for_alg, public_key_from_jwk};
+use crate::models::{Jwk, JwkFinding};
+use crate::policy::{is_algorithm_allowed, MAX_RSA_KEY_SIZE};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine as _;
+use openssl::bn::BigNum;
+use openssl::x509::X509;
+use serde_json::{Map, Value};
+
+/// Finding severity that makes a JWK invalid.
+pub const SEVERITY_ERROR: &str = "error";
+
+/// Finding severity for a weakness that does not make a JWK invalid.
+pub const SEVERITY_WARNING: &str = "warning";
+
+/// Recommended RSA public exponent.
+const RSA_RECOMMENDED_EXPONENT: u32 = 65537;
+
+/// Private key parameters of RSA, EC, OKP and symmetric keys.
+const PRIVATE_PARAMETERS: [&str; 8] = ["d", "p", "q", "dp", "dq", "qi", "oth", "k"];
+
+/// Maximum length of a key ID.
+pub const MAX_KID_LENGTH: usize = 256;
+
+/// Operations permitted in `key_ops` (RFC 7517, section 4.3).
+const KEY_OPERATIONS: [&str; 8] = [
+    "sign", "verify", "encrypt", "decrypt", "wrapKey", "unwrapKey", "deriveKey", "deriveBits",
+];
+
+/// Deployment policy applied by [`validate_jwk`].
+#[derive(Debug, Clone, Default)]
+pub struct ValidationPolicy {
+    /// Permitted algorithms, or `None` if every algorithm is permitted.
+    pub allowed_algorithms: Option<Vec<String>>,
+    /// Minimum permitted RSA key size in bits.
+    pub min_rsa_key_size: u32,
+    /// Approved curves, or `None` if every curve is approved.
+    pub approved_curves: Option<Vec<String>>,
+}
+
+/// Collects the findings of a validation run.
+struct Findings(Vec<JwkFinding>);
+
+impl Findings {
+    fn push(&mut self, severity: &str, code: &str, message: String) {
+        self.0.push(JwkFinding {
+            severity: severity.to_string(),
+            code: code.to_string(),
+            message,
+        });
+    }
+
+    fn error(&mut self, code: &str, message: impl Into<String>) {
+        self.push(SEVERITY_ERROR, code, message.into());
+    }
+
+    fn warning(&mut self, code: &str, message: impl Into<String>) {
+        self.push(SEVERITY_WARNING, code, message.into());
+    }
+}
+
+/// Checks that a key ID can be stored and matched reliably: 1 to [`MAX_KID_LENGTH`] characters
+/// without control characters or surrounding whitespace.
+///
+/// # Errors
+///
+/// Returns a message describing the invalid key ID.
+pub fn validate_kid(kid: &str) -> Result<(), String> {
+    if kid.is_empty() || kid.len() > MAX_KID_LENGTH {
+        return Err(format!("kid must be 1 to {} characters long", MAX_KID_LENGTH));
+    }
+    if kid.chars().any(char::is_control) {
+        return Err("kid must not contain control characters".to_string());
+    }
+    if kid.trim() != kid {
+        return Err("kid must not start or end with whitespace".to_string());
+    }
+
+    Ok(())
+}
+
+/// Returns a string parameter of the JWK, reporting it if it is present but not a string.
+fn string_parameter<'a>(jwk: &'a Map<String, Value>, name: &str, findings: &mut Findings) -> Option<&'a str> {
+    match jwk.get(name) {
+        None | Some(Value::Null) => None,
+        Some(Value::String(value)) => Some(value),
+        Some(_) => {
+            findings.error("invalid_parameter", format!("{} must be a string", name));
+            None
+        }
+    }
+}
+
+/// Decodes a required Base64URL parameter of the JWK, reporting it if missing or malformed.
+fn base64url_parameter(jwk: &Map<String, Value>, name: &str, findings: &mut Findings) -> Option<Vec<u8>> {
+    let Some(value) = string_parameter(jwk, name, findings) else {
+        findings.error("missing_parameter", format!("{} is required", name));
+        return None;
+    };
+
+    match URL_SAFE_NO_PAD.decode(value) {
+        Ok(bytes) if !bytes.is_empty() => Some(bytes),
+        _ => {
+            findings.error(
+                "invalid_encoding",
+                format!("{} must be unpadded Base64URL (RFC 7515, section 2)", name),
+            );
+            None
+        }
+    }
+}
+
+/// Returns the algorithm name the deployment policy refers to for the JWK.
+fn policy_algorithm(kty: &str, alg: &str, crv: Option<&str>) -> String {
+    match (kty, crv) {
+        ("OKP", Some(crv)) if alg == "EdDSA" => crv.to_string(),
+        _ => alg.to_string(),
+    }
+}
+
+fn check_rsa(jwk: &Map<String, Value>, policy: &ValidationPolicy, findings: &mut Findings) {
+    if let Some(n) = base64url_parameter(jwk, "n", findings) {
+        if n[0] == 0 {
+            findings.error("non_minimal_encoding", "n must not have leading zero octets (RFC 7518, section 6.3.1.1)");
+        }
+
+        let bits = BigNum::from_slice(&n).map(|n| n.num_bits() as u32).unwrap_or_default();
+        if bits < policy.min_rsa_key_size {
+            findings.error(
+                "weak_key",
+                format!("RSA key size {} is below the minimum of {} bits", bits, policy.min_rsa_key_size),
+            );
+        } else if bits > MAX_RSA_KEY_SIZE {
+            findings.error(
+                "key_too_large",
+                format!("RSA key size {} exceeds the maximum of {} bits", bits, MAX_RSA_KEY_SIZE),
+            );
+        }
+    }
+
+    if let Some(e) = base64url_parameter(jwk, "e", findings) {
+        if e[0] == 0 {
+            findings.error("non_minimal_encoding", "e must not have leading zero octets (RFC 7518, section 6.3.1.2)");
+        }
+
+        match BigNum::from_slice(&e) {
+            Ok(e) if e.num_bits() < 2 || !e.is_bit_set(0) => {
+                findings.error("weak_key", "RSA public exponent must be odd and at least 3");
+            }
+            Ok(e) if e != BigNum::from_u32(RSA_RECOMMENDED_EXPONENT).unwrap() => {
+                findings.warning("weak_key", format!("RSA public exponent should be {}", RSA_RECOMMENDED_EXPONENT));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn check_ec(jwk: &Map<String, Value>, crv: Option<&str>, policy: &ValidationPolicy, findings: &mut Findings) {
+    let coordinate_size = match crv {
+        Some("P-256") => Some(32),
+        Some("P-384") => Some(48),
+        Some("P-521") => Some(66),
+        Some("secp256k1") => Some(32),
+        Some(crv) => {
+            findings.error("unsupported_curve", format!("Unsupported EC curve {}", crv));
+            None
+        }
+        None => {
+            findings.error("missing_parameter", "crv is required");
+            None
+        }
+    };
+
+    for name in ["x", "y"] {
+        if let (Some(value), Some(size)) = (base64url_parameter(jwk, name, findings), coordinate_size) {
+            if value.len() != size {
+                findings.error(
+                    "invalid_length",
+                    format!("{} must be {} octets for {} (RFC 7518, section 6.2.1)", name, size, crv.unwrap_or_default()),
+                );
+            }
+        }
+    }
+
+    check_curve_approved(crv, coordinate_size.is_some(), policy, findings);
+}
+
+fn check_okp(jwk: &Map<String, Value>, crv: Option<&str>, policy: &ValidationPolicy, findings: &mut Findings) {
+    let key_size = match crv {
+        Some("Ed25519") => Some(32),
+        Some("Ed448") => Some(57),
+        Some(crv) => {
+            findings.error("unsupported_curve", format!("Unsupported OKP curve {}", crv));
+            None
+        }
+        None => {
+            findings.error("missing_parameter", "crv is required");
+            None
+        }
+    };
+
+    if let (Some(x), Some(size)) = (base64url_parameter(jwk, "x", findings), key_size) {
+        if x.len() != size {
+            findings.error(
+                "invalid_length",
+                format!("x must be {} octets for {} (RFC 8037, section 2)", size, crv.unwrap_or_default()),
+            );
+        }
+    }
+
+    check_curve_approved(crv, key_size.is_some(), policy, findings);
+}
+
+fn check_curve_approved(crv: Option<&str>, supported: bool, policy: &ValidationPolicy, findings: &mut Findings) {
+    if let (Some(crv), Some(curves), true) = (crv, &policy.approved_curves, supported) {
+        if !curves.iter().any(|approved| approved == crv) {
+            findings.error("curve_not_approved", format!("Curve {} is not approved by policy", crv));
+        }
+    }
+}
+
+/// Checks that `alg` is consistent with the key type and curve.
+fn check_alg(kty: &str, alg: &str, crv: Option<&str>, findings: &mut Findings) {
+    let consistent = match kty {
+        "RSA" => matches!(alg, "RS256" | "RS384" | "RS512" | "PS256" | "PS384" | "PS512" | "RSA-OAEP" | "RSA-OAEP-256"),
+        "EC" => alg.starts_with("ECDH-ES") || (alg.starts_with("ES") && curve_for_alg(alg) == crv),
+        "OKP" => alg == "EdDSA" || curve_for_alg(alg).is_some_and(|curve| Some(curve) == crv),
+        _ => true,
+    };
+
+    if !consistent {
+        findings.error(
+            "alg_mismatch",
+            format!("Algorithm {} cannot be used with a {} key{}", alg, kty, crv.map(|crv| format!(" on {}", crv)).unwrap_or_default()),
+        );
+    }
+}
+
+/// Checks the `use` and `key_ops` parameters.
+fn check_usage(jwk: &Map<String, Value>, findings: &mut Findings) {
+    let key_use = string_parameter(jwk, "use", findings);
+    if let Some(key_use) = key_use {
+        if key_use != "sig" && key_use != "enc" {
+            findings.error("invalid_use", format!("use must be sig or enc, got {}", key_use));
+        }
+    }
+
+    match jwk.get("key_ops") {
+        None | Some(Value::Null) => {}
+        Some(Value::Array(operations)) => {
+            for operation in operations {
+                if !operation.as_str().is_some_and(|operation| KEY_OPERATIONS.contains(&operation)) {
+                    findings.error("invalid_key_ops", format!("Unknown key operation {}", operation));
+                }
+            }
+            if key_use.is_some() {
+                findings.warning("use_and_key_ops", "use and key_ops should not be used together (RFC 7517, section 4.3)");
+            }
+        }
+        Some(_) => findings.error("invalid_key_ops", "key_ops must be an array"),
+    }
+}
+
+/// Checks that the first certificate of `x5c` parses and certifies the JWK's public key.
+fn check_certificate(jwk: &Map<String, Value>, public_key: Option<&openssl::pkey::PKeyRef<openssl::pkey::Public>>, findings: &mut Findings) {
+    let Some(x5c) = jwk.get("x5c") else {
+        return;
+    };
+    let Some(first) = x5c.as_array().and_then(|x5c| x5c.first()).and_then(Value::as_str) else {
+        findings.error("invalid_certificate", "x5c must be a non-empty array of strings");
+        return;
+    };
+
+    let der = match (STANDARD.decode(first), URL_SAFE_NO_PAD.decode(first)) {
+        (Ok(der), _) => der,
+        (Err(_), Ok(der)) => {
+            findings.warning("invalid_encoding", "x5c entries should use standard Base64 (RFC 7517, section 4.7)");
+            der
+        }
+        _ => {
+            findings.error("invalid_certificate", "x5c entries must be Base64 encoded DER certificates");
+            return;
+        }
+    };
+
+    let certificate_key = match X509::from_der(&der).and_then(|cert| cert.public_key()) {
+        Ok(certificate_key) => certificate_key,
+        Err(_) => {
+            findings.error("invalid_certificate", "The first x5c certificate cannot be parsed");
+            return;
+        }
+    };
+    if let Some(public_key) = public_key {
+        if !certificate_key.public_eq(public_key) {
+            findings.error("certificate_key_mismatch", "The first x5c certificate does not certify the key");
+        }
+    }
+}
+
+/// Validates a JWK for RFC compliance, weak parameters and policy violations.
+///
+/// # Arguments
+///
+/// * `value` - The JWK as posted.
+/// * `policy` - Deployment policy the key is checked against.
+///
+/// # Returns
+///
+/// Every finding; the JWK is valid if none has severity [`SEVERITY_ERROR`].
+pub fn validate_jwk(value: &Value, policy: &ValidationPolicy) -> Vec<JwkFinding> {
+    let mut findings = Findings(Vec::new());
+
+    let Some(jwk) = value.as_object() else {
+        findings.error("invalid_json", "JWK must be a JSON object");
+        return findings.0;
+    };
+
+    for name in PRIVATE_PARAMETERS {
+        if jwk.contains_key(name) {
+            findings.error("private_key_material", format!("{} is private key material and must not be shared", name));
+        }
+    }
+
+    let kty = string_parameter(jwk, "kty", &mut findings);
+    let alg = string_parameter(jwk, "alg", &mut findings);
+    let crv = string_parameter(jwk, "crv", &mut findings);
+    let kid = string_parameter(jwk, "kid", &mut findings);
+
+    match kid.filter(|kid| !kid.is_empty()).map(validate_kid) {
+        None => findings.warning("missing_kid", "kid should be set so the key can be selected"),
+        Some(Err(message)) => findings.error("invalid_kid", message),
+        Some(Ok(())) => {}
+    }
+    check_usage(jwk, &mut findings);
+
+    let kty = match kty {
+        Some(kty @ ("RSA" | "EC" | "OKP")) => kty,
+        Some(kty) => {
+            findings.error("unsupported_key_type", format!("Unsupported key type {}", kty));
+            return findings.0;
+        }
+        None => {
+            findings.error("missing_parameter", "kty is required");
+            return findings.0;
+        }
+    };
+
+    let errors_before = findings.0.len();
+    match kty {
+        "RSA" => check_rsa(jwk, policy, &mut findings),
+        "EC" => check_ec(jwk, crv, policy, &mut findings),
+        _ => check_okp(jwk, crv, policy, &mut findings),
+    }
+
+    let parameter = |name: &str| jwk.get(name).and_then(Value::as_str).map(str::to_string);
+    let public = Jwk {
+        kty: kty.to_string(),
+        use_: String::new(),
+        alg: alg.unwrap_or_default().to_string(),
+        kid: kid.unwrap_or_default().to_string(),
+        crv: parameter("crv"),
+        x: parameter("x"),
+        y: parameter("y"),
+        n: parameter("n"),
+        e: parameter("e"),
+        x5c: None,
+        x5t: None,
+        pub_: None,
+    };
+    let public_key = public_key_from_jwk(&public).ok();
+    // Malformed parameters are already reported; otherwise the key itself is invalid
+    if public_key.is_none() && findings.0.len() == errors_before {
+        findings.error("invalid_public_key", "The public key parameters do not form a valid key");
+    }
+
+    match alg {
+        Some(alg) => {
+            check_alg(kty, alg, crv, &mut findings);
+            let policy_alg = policy_algorithm(kty, alg, crv);
+            if !is_algorithm_allowed(&policy_alg, policy.allowed_algorithms.as_deref()) {
+                findings.error("algorithm_not_allowed", format!("Algorithm {} is not permitted by policy", policy_alg));
+            }
+        }
+        None => findings.warning("missing_alg", "alg should be set to pin the key to one algorithm"),
+    }
+
+    check_certificate(jwk, public_key.as_deref(), &mut findings);
+
+    findings.0
+}
+
+#[test]
+fn test_validate_kid() {
+    assert!(validate_kid("884bd577-a3be-430c-b915-522124544ad0").is_ok());
+    assert!(validate_kid("partner key 1").is_ok());
+    assert!(validate_kid("").is_err());
+    assert!(validate_kid(" partner").is_err());
+    assert!(validate_kid("partner\n").is_err());
+    assert!(validate_kid(&"k".repeat(MAX_KID_LENGTH + 1)).is_err());
+}
+
+#[cfg(test)]
+fn codes(findings: &[JwkFinding], severity: &str) -> Vec<String> {
+    findings.iter().filter(|finding| finding.severity == severity).map(|finding| finding.code.clone()).collect()
+}
+
+#[test]
+fn test_validate_generated_jwks() {
+    use crate::crypto::generate_jwk_data;
+
+    let policy = ValidationPolicy { min_rsa_key_size: 2048, ..Default::default() };
+    for alg in ["RS256", "ES256", "ES512", "Ed25519", "Ed448"] {
+        let jwk = serde_json::to_value(Jwk::from(generate_jwk_data(alg, 2048).unwrap())).unwrap();
+        let findings = validate_jwk(&jwk, &policy);
+        let errors = codes(&findings, SEVERITY_ERROR);
+        assert!(errors.is_empty(), "{}: {:?}", alg, findings);
+    }
+}
+
+#[test]
+fn test_validate_jwk_reports_findings() {
+    use crate::crypto::generate_jwk_data;
+
+    let policy = ValidationPolicy {
+        allowed_algorithms: Some(vec!["ES256".to_string()]),
+        min_rsa_key_size: 3072,
+        approved_curves: Some(vec!["P-256".to_string()]),
+    };
+
+    let mut rsa = serde_json::to_value(Jwk::from(generate_jwk_data("RS256", 2048).unwrap())).unwrap();
+    rsa["e"] = Value::from("Aw");
+    rsa["d"] = Value::from("AQAB");
+    rsa["key_ops"] = serde_json::json!(["sign", "fly"]);
+    let findings = validate_jwk(&rsa, &policy);
+    assert_eq!(
+        codes(&findings, SEVERITY_ERROR),
+        ["private_key_material", "invalid_key_ops", "weak_key", "algorithm_not_allowed", "certificate_key_mismatch"]
+    );
+    assert_eq!(codes(&findings, SEVERITY_WARNING), ["use_and_key_ops", "weak_key", "invalid_encoding"]);
+
+    let mut ec = serde_json::to_value(Jwk::from(generate_jwk_data("ES384", 2048).unwrap())).unwrap();
+    ec["alg"] = Value::from("ES256");
+    ec["x"] = Value::from("AAAA");
+    ec.as_object_mut().unwrap().remove("kid");
+    let findings = validate_jwk(&ec, &policy);
+    assert_eq!(codes(&findings, SEVERITY_ERROR), ["invalid_length", "curve_not_approved", "alg_mismatch"]);
+    assert_eq!(codes(&findings, SEVERITY_WARNING), ["missing_kid"]);
+
+    let mut ec = serde_json::to_value(Jwk::from(generate_jwk_data("ES256", 2048).unwrap())).unwrap();
+    let y = ec["x"].clone();
+    ec["y"] = y;
+    assert_eq!(codes(&validate_jwk(&ec, &policy), SEVERITY_ERROR), ["invalid_public_key"]);
+
+    assert_eq!(codes(&validate_jwk(&serde_json::json!({"kty": "oct"}), &policy), SEVERITY_ERROR), ["unsupported_key_type"]);
+    assert_eq!(codes(&validate_jwk(&serde_json::json!([]), &policy), SEVERITY_ERROR), ["invalid_json"]);
+}
@@ -0,0 +1,48 @@
+//! This module warms the service up before its listeners bind.
+//!
+//! The first requests after a deploy would otherwise pay for work that is done once: resolving
+//! and connecting to the database (including TLS), loading the key table into the Postgres buffer
+//! cache, and planning the JWK Set query. The warm-up does this work at startup by opening the
+//! first pooled database connection and serving the JWK Set once in-process, so the first real
+//! requests are as fast as the following ones. Key generation is warmed by the crypto self-check, which runs before.
+
+use crate::db::{checkout, database_pool};
+use crate::handlers::jwks_handler;
+use crate::log_info;
+use crate::models::JwksQuery;
+use crate::store::key_store;
+use actix_web::web;
+use dotenv::dotenv;
+use std::env;
+use std::time::Instant;
+
+/// Returns whether the service warms up before accepting traffic (`WARMUP_ON_START`, default `1`).
+pub fn warmup_enabled() -> bool {
+    dotenv().ok();
+
+    env::var("WARMUP_ON_START").map(|value| value != "0").unwrap_or(true)
+}
+
+/// Runs the warm-up steps and logs how long each took.
+///
+/// # Errors
+///
+/// Returns a message if the database cannot be reached or the JWK Set cannot be served; the
+/// steps after a failed step are skipped.
+pub async fn run_warmup() -> Result<(), String> {
+    let started = Instant::now();
+    web::block(|| checkout(&database_pool()).map(drop))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|message| format!("database connection: {}", message))?;
+    log_info!("Warm-up: database connection took {} ms.", started.elapsed().as_millis());
+
+    let started = Instant::now();
+    let response = jwks_handler(web::Data::new(key_store()), web::Query(JwksQuery { at: None, purpose: None })).await;
+    if !response.status().is_success() {
+        return Err(format!("JWK Set: served with status {}", response.status()));
+    }
+    log_info!("Warm-up: JWK Set took {} ms.", started.elapsed().as_millis());
+
+    Ok(())
+}
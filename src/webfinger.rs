@@ -0,0 +1,219 @@
+//! This module answers WebFinger (RFC 7033) queries, so that federation partners discovering
+//! issuers via WebFinger are pointed at the issuer and `jwks_uri` of a resource's tenant.
+//!
+//! Tenants are identified by the host of the queried resource (`acct:alice@tenant.example.com`
+//! or `https://tenant.example.com/...`).
+
+use crate::models::{WebFingerLink, WebFingerResponse};
+use actix_web::{web, HttpResponse, Responder};
+use dotenv::dotenv;
+use std::env;
+
+/// Link relation of the OpenID Connect issuer (OpenID Connect Discovery 1.0, section 2).
+pub const ISSUER_REL: &str = "http://openid.net/specs/connect/1.0/issuer";
+
+/// Link relation of the JWK Set of the issuer.
+pub const JWKS_URI_REL: &str = "jwks_uri";
+
+/// Media type of WebFinger responses.
+const JRD_CONTENT_TYPE: &str = "application/jrd+json";
+
+/// Issuer and JWK Set of a tenant.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebFingerTenant {
+    /// Host of the resources of the tenant (e.g., "tenant.example.com").
+    pub host: String,
+    /// Issuer identifier of the tenant.
+    pub issuer: String,
+    /// URL of the JWK Set of the tenant.
+    pub jwks_uri: String,
+}
+
+/// Parses the tenants of `WEBFINGER_TENANTS`: comma-separated `host=issuer|jwks_uri` entries.
+///
+/// # Errors
+///
+/// Returns a message naming the malformed entry.
+pub fn parse_tenants(value: &str) -> Result<Vec<WebFingerTenant>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (host, urls) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid WebFinger tenant {}, expected host=issuer|jwks_uri", entry))?;
+            let (issuer, jwks_uri) = urls
+                .split_once('|')
+                .ok_or_else(|| format!("Invalid WebFinger tenant {}, expected host=issuer|jwks_uri", entry))?;
+            Ok(WebFingerTenant {
+                host: host.trim().to_lowercase(),
+                issuer: issuer.trim().to_string(),
+                jwks_uri: jwks_uri.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Returns the tenants answered by WebFinger (`WEBFINGER_TENANTS`).
+///
+/// # Returns
+///
+/// `None` if the variable is unset or empty (WebFinger is disabled).
+///
+/// # Panics
+///
+/// This function will panic if an entry of `WEBFINGER_TENANTS` is malformed.
+pub fn webfinger_tenants() -> Option<Vec<WebFingerTenant>> {
+    dotenv().ok();
+
+    let value = env::var("WEBFINGER_TENANTS")
+        .ok()
+        .filter(|value| !value.trim().is_empty())?;
+
+    Some(parse_tenants(&value).unwrap_or_else(|message| panic!("{}", message)))
+}
+
+/// Returns the host identifying the tenant of a resource.
+///
+/// # Returns
+///
+/// `None` for resources other than `acct:` and `http(s):` URIs.
+pub fn resource_host(resource: &str) -> Option<String> {
+    if let Some(account) = resource.strip_prefix("acct:") {
+        let (_, host) = account.rsplit_once('@')?;
+        return Some(host.to_lowercase()).filter(|host| !host.is_empty());
+    }
+
+    let url = reqwest::Url::parse(resource).ok()?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+    url.host_str().map(str::to_lowercase)
+}
+
+/// Builds the WebFinger response of a resource of a tenant.
+///
+/// # Arguments
+///
+/// * `resource` - Queried resource, returned as the subject.
+/// * `tenant` - Tenant of the resource.
+/// * `rels` - Requested link relations; every link is returned if empty.
+pub fn webfinger_response(resource: &str, tenant: &WebFingerTenant, rels: &[String]) -> WebFingerResponse {
+    let links = [
+        WebFingerLink {
+            rel: ISSUER_REL.to_string(),
+            type_: None,
+            href: tenant.issuer.clone(),
+        },
+        WebFingerLink {
+            rel: JWKS_URI_REL.to_string(),
+            type_: Some("application/jwk-set+json".to_string()),
+            href: tenant.jwks_uri.clone(),
+        },
+    ];
+
+    WebFingerResponse {
+        subject: resource.to_string(),
+        links: links
+            .into_iter()
+            .filter(|link| rels.is_empty() || rels.contains(&link.rel))
+            .collect(),
+    }
+}
+
+/// Handles a WebFinger query for a resource.
+///
+/// # Arguments
+///
+/// * `query` - The `resource` and optional, repeatable `rel` query parameters.
+///
+/// # Returns
+///
+/// A JRD response linking the issuer and `jwks_uri` of the resource's tenant, or `404 Not Found`
+/// if WebFinger is not configured or the resource belongs to no tenant.
+#[utoipa::path(
+    get,
+    path = "/.well-known/webfinger",
+    params(
+        ("resource" = String, Query, description = "Queried resource (e.g., \"acct:alice@tenant.example.com\")"),
+        ("rel" = Option<Vec<String>>, Query, description = "Link relations to return (repeatable)")
+    ),
+    responses(
+        (status = 200, description = "Links of the resource", body = WebFingerResponse, content_type = "application/jrd+json"),
+        (status = 400, description = "Missing resource", body = String, content_type = "text/plain"),
+        (status = 404, description = "WebFinger is not configured or the resource is unknown", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn webfinger_handler(query: web::Query<Vec<(String, String)>>) -> impl Responder {
+    let tenants = match webfinger_tenants() {
+        Some(tenants) => tenants,
+        None => return HttpResponse::NotFound().body("WebFinger is not configured"),
+    };
+
+    let parameters = query.into_inner();
+    let Some(resource) = parameters
+        .iter()
+        .find(|(name, _)| name == "resource")
+        .map(|(_, value)| value.clone())
+    else {
+        return HttpResponse::BadRequest().body("Missing resource");
+    };
+    let rels = parameters
+        .into_iter()
+        .filter(|(name, _)| name == "rel")
+        .map(|(_, value)| value)
+        .collect::<Vec<_>>();
+
+    let tenant = resource_host(&resource)
+        .and_then(|host| tenants.into_iter().find(|tenant| tenant.host == host));
+    match tenant {
+        // WebFinger resources are queried cross-origin by browser-based clients (RFC 7033, 5)
+        Some(tenant) => HttpResponse::Ok()
+            .content_type(JRD_CONTENT_TYPE)
+            .insert_header(("Access-Control-Allow-Origin", "*"))
+            .json(webfinger_response(&resource, &tenant, &rels)),
+        None => HttpResponse::NotFound().body("Unknown resource"),
+    }
+}
+
+#[test]
+fn test_parse_tenants() {
+    let tenants = parse_tenants(
+        "Tenant-A.example.com=https://a.example.com|https://keys.example.com/a/jwks.json, \
+         b.example.com=https://b.example.com|https://keys.example.com/b/jwks.json",
+    )
+    .unwrap();
+    assert_eq!(tenants.len(), 2);
+    assert_eq!(tenants[0].host, "tenant-a.example.com");
+    assert_eq!(tenants[0].issuer, "https://a.example.com");
+    assert_eq!(tenants[1].jwks_uri, "https://keys.example.com/b/jwks.json");
+
+    assert!(parse_tenants("a.example.com=https://a.example.com").is_err());
+    assert!(parse_tenants("a.example.com").is_err());
+}
+
+#[test]
+fn test_resource_host() {
+    assert_eq!(resource_host("acct:alice@Tenant.example.com").as_deref(), Some("tenant.example.com"));
+    assert_eq!(resource_host("https://tenant.example.com/alice").as_deref(), Some("tenant.example.com"));
+    assert_eq!(resource_host("acct:alice"), None);
+    assert_eq!(resource_host("mailto:alice@tenant.example.com"), None);
+}
+
+#[test]
+fn test_webfinger_response() {
+    let tenant = WebFingerTenant {
+        host: "tenant.example.com".to_string(),
+        issuer: "https://tenant.example.com".to_string(),
+        jwks_uri: "https://keys.example.com/jwks.json".to_string(),
+    };
+
+    let response = webfinger_response("acct:alice@tenant.example.com", &tenant, &[]);
+    assert_eq!(response.subject, "acct:alice@tenant.example.com");
+    assert_eq!(response.links.len(), 2);
+
+    let response = webfinger_response("acct:alice@tenant.example.com", &tenant, &[ISSUER_REL.to_string()]);
+    assert_eq!(response.links.len(), 1);
+    assert_eq!(response.links[0].href, "https://tenant.example.com");
+}
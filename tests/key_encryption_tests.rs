@@ -0,0 +1,33 @@
+// Tests run without a key-encryption key, in a process of their own: the integration tests
+// encrypt the stored keys with the test key-encryption key of `test_support`.
+
+use actix_web::http::StatusCode;
+use actix_web::test;
+use jwks_service_app::*;
+use serde_json::json;
+
+#[actix_rt::test]
+async fn test_symmetric_key_requires_key_encryption_key() {
+    if test_support::skip_without_database("test_symmetric_key_requires_key_encryption_key") {
+        return;
+    }
+
+    // An empty variable keeps the test database from setting the test key-encryption key
+    let _environment = test_support::EnvGuard::set(&[("KEY_ENCRYPTION_KEY", "")]);
+    assert!(key_encryption::key_encryption_key().unwrap().is_none());
+
+    // Start the application
+    let app = test_support::init_test_service().await;
+
+    // The secret of an HMAC key is not stored unencrypted
+    for algorithm in ["HS256", "HS384", "HS512"] {
+        let req = test::TestRequest::post()
+            .uri("/jwks")
+            .set_json(json!({ "alg": algorithm, "reuse_active": false }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = test::read_body(resp).await;
+        assert!(std::str::from_utf8(&body).unwrap().contains("KEY_ENCRYPTION_KEY"));
+    }
+}